@@ -1,3 +1,70 @@
+use std::{
+    env,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 fn main() {
     lalrpop::process_root().unwrap();
+
+    // What --version and the footer of runtime errors report, see src/frontend/version.rs
+    println!("cargo:rustc-env=WRENCH_GIT_HASH={}", git_hash());
+    println!("cargo:rustc-env=WRENCH_BUILD_DATE={}", build_date());
+    println!("cargo:rustc-env=WRENCH_FEATURES={}", enabled_features());
+
+    // Telling cargo what to watch replaces its default of rebuilding on any change, so the grammar is listed too.
+    // The git files are only listed if they exist, as a missing file would rebuild every time
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/grammar.lalrpop");
+    println!("cargo:rerun-if-changed=src/backend/civil_date.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for git_file in [".git/HEAD", ".git/refs", ".git/packed-refs"] {
+        if Path::new(git_file).exists() {
+            println!("cargo:rerun-if-changed={}", git_file);
+        }
+    }
+}
+
+// The short hash of the commit being built, or "unknown" when not built from a git checkout
+fn git_hash() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// The date of the build as YYYY-MM-DD in UTC. SOURCE_DATE_EPOCH is used if set, so builds can be reproduced
+fn build_date() -> String {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// civil_from_days, shared with the timestamps of the log, see src/backend/civil_date.rs
+include!("src/backend/civil_date.rs");
+
+// The cargo features the build was made with, comma separated, from the CARGO_FEATURE_ variables cargo sets for build scripts
+fn enabled_features() -> String {
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    features.join(",")
 }
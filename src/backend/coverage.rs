@@ -0,0 +1,389 @@
+use crate::frontend::{
+    ast::{Counter, Declaration, Expr, Parameter, Statement},
+    diagnostics::json_string,
+};
+
+/*
+ * This file deals with counting how many times the statements of a program run, for wrench --coverage
+ */
+
+// A statement of the program and how many times it ran. Statements are numbered in the order they are written
+#[derive(Debug)]
+pub struct CoveredStatement {
+    pub id: usize,
+    // The statement the block this one is in belongs to, None for statements at the top level
+    pub parent: Option<usize>,
+    // Which block of the parent this statement is in: then, else or body
+    pub branch: Option<&'static str>,
+    // The function this statement is in, if any
+    pub function: Option<String>,
+    // What the statement is, e.g. assignment to 'x'
+    pub description: String,
+    counter: Counter,
+}
+
+impl CoveredStatement {
+    pub fn count(&self) -> usize {
+        self.counter.count()
+    }
+}
+
+// A function declared in the program and how many times it was called
+#[derive(Debug)]
+pub struct CoveredFunction {
+    pub name: String,
+    counter: Counter,
+}
+
+impl CoveredFunction {
+    pub fn calls(&self) -> usize {
+        self.counter.count()
+    }
+}
+
+// The statements and functions of an instrumented program, whose counts go up as it runs
+#[derive(Debug, Default)]
+pub struct Coverage {
+    pub statements: Vec<CoveredStatement>,
+    pub functions: Vec<CoveredFunction>,
+}
+
+// Adds a counter to every statement of the program, and to the body of every function to count its calls
+// Programs run without --coverage are never instrumented, so they do not pay for the counting
+pub fn instrument(program: Statement) -> (Statement, Coverage) {
+    let mut coverage = Coverage::default();
+    let program = coverage.instrument_block(program, None, None, None);
+    (program, coverage)
+}
+
+impl Coverage {
+    // Instruments each statement of a chain of statements, walking the chain in a loop so long programs do not grow the stack
+    fn instrument_block(
+        &mut self,
+        block: Statement,
+        parent: Option<usize>,
+        branch: Option<&'static str>,
+        function: Option<&str>,
+    ) -> Statement {
+        let mut statements = Vec::new();
+        let mut rest = block;
+        loop {
+            match rest {
+                Statement::Compound(first, next) => {
+                    statements.push(*first);
+                    rest = *next;
+                }
+                Statement::Skip => break,
+                last => {
+                    statements.push(last);
+                    break;
+                }
+            }
+        }
+        let statements: Vec<Statement> = statements
+            .into_iter()
+            .map(|statement| match statement {
+                Statement::Skip => Statement::Skip,
+                statement => self.instrument_statement(statement, parent, branch, function),
+            })
+            .collect();
+        statements
+            .into_iter()
+            .rev()
+            .fold(Statement::Skip, |rest, statement| {
+                Statement::Compound(Box::new(statement), Box::new(rest))
+            })
+    }
+
+    fn instrument_statement(
+        &mut self,
+        statement: Statement,
+        parent: Option<usize>,
+        branch: Option<&'static str>,
+        function: Option<&str>,
+    ) -> Statement {
+        // The span stays outside the counter, so the statement counted is the one it is the span of
+        if let Statement::Located(span, statement) = statement {
+            let statement = self.instrument_statement(*statement, parent, branch, function);
+            return Statement::Located(span, Box::new(statement));
+        }
+        let id = self.statements.len();
+        let counter = Counter::default();
+        self.statements.push(CoveredStatement {
+            id,
+            parent,
+            branch,
+            function: function.map(str::to_string),
+            description: describe(&statement),
+            counter: counter.clone(),
+        });
+        let statement = match statement {
+            Statement::If(condition, body, else_body) => Statement::If(
+                condition,
+                Box::new(self.instrument_block(*body, Some(id), Some("then"), function)),
+                Box::new(self.instrument_block(*else_body, Some(id), Some("else"), function)),
+            ),
+            Statement::For(index, parameter, iterable, body) => Statement::For(
+                index,
+                parameter,
+                iterable,
+                Box::new(self.instrument_block(*body, Some(id), Some("body"), function)),
+            ),
+            Statement::While(condition, body) => Statement::While(
+                condition,
+                Box::new(self.instrument_block(*body, Some(id), Some("body"), function)),
+            ),
+            Statement::Defer(body) => Statement::Defer(Box::new(self.instrument_block(
+                *body,
+                Some(id),
+                Some("body"),
+                function,
+            ))),
+            Statement::Declaration(Declaration::Function(return_type, name, params, body)) => {
+                let calls = Counter::default();
+                self.functions.push(CoveredFunction {
+                    name: name.clone(),
+                    counter: calls.clone(),
+                });
+                let body = self.instrument_block(*body, Some(id), Some("body"), Some(&name));
+                Statement::Declaration(Declaration::Function(
+                    return_type,
+                    name,
+                    params,
+                    Box::new(Statement::Counted(calls, Box::new(body))),
+                ))
+            }
+            statement => statement,
+        };
+        Statement::Counted(counter, Box::new(statement))
+    }
+
+    // How many statements ran at least once, and how many there are
+    pub fn executed(&self) -> (usize, usize) {
+        let executed = self
+            .statements
+            .iter()
+            .filter(|statement| statement.count() > 0)
+            .count();
+        (executed, self.statements.len())
+    }
+
+    // The functions that were never called, in the order they are declared
+    pub fn never_called(&self) -> Vec<&str> {
+        self.functions
+            .iter()
+            .filter(|function| function.calls() == 0)
+            .map(|function| function.name.as_str())
+            .collect()
+    }
+
+    // The line written after the program has run, e.g. 73/90 statements executed; 3 functions never called
+    pub fn summary(&self) -> String {
+        let (executed, total) = self.executed();
+        let never_called = self.never_called().len();
+        format!(
+            "{}/{} statements executed; {} function{} never called",
+            executed,
+            total,
+            never_called,
+            if never_called == 1 { "" } else { "s" }
+        )
+    }
+
+    // The counts as JSON, an object with the statements, the functions and the totals of the summary
+    pub fn to_json(&self) -> String {
+        let or_null = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        let statements: Vec<String> = self
+            .statements
+            .iter()
+            .map(|statement| {
+                format!(
+                    "{{\"id\":{},\"parent\":{},\"branch\":{},\"function\":{},\"statement\":{},\"count\":{}}}",
+                    statement.id,
+                    or_null(statement.parent.map(|parent| parent.to_string())),
+                    or_null(statement.branch.map(json_string)),
+                    or_null(statement.function.as_deref().map(json_string)),
+                    json_string(&statement.description),
+                    statement.count()
+                )
+            })
+            .collect();
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|function| {
+                format!(
+                    "{{\"name\":{},\"calls\":{}}}",
+                    json_string(&function.name),
+                    function.calls()
+                )
+            })
+            .collect();
+        let (executed, total) = self.executed();
+        format!(
+            "{{\"statements\":[{}],\"functions\":[{}],\"executed\":{},\"total\":{},\"functions_never_called\":{}}}",
+            statements.join(","),
+            functions.join(","),
+            executed,
+            total,
+            self.never_called().len()
+        )
+    }
+}
+
+// What a statement is, as written in the coverage report
+fn describe(statement: &Statement) -> String {
+    match statement {
+        Statement::Expr(expr) => match &**expr {
+            Expr::FunctionCall(name, _) => format!("call to '{}'", name),
+            Expr::Pipe(_, name, _) => format!("pipe into '{}'", name),
+            _ => "expression".to_string(),
+        },
+        Statement::VariableAssignment(name, _) => format!("assignment to '{}'", name),
+        Statement::IndexAssignment(name, _, _) => format!("assignment to an element of '{}'", name),
+        Statement::FieldAssignment(name, column, _) => {
+            format!("assignment to '{}.{}'", name, column)
+        }
+        Statement::Declaration(Declaration::Variable(_, name, _)) => {
+            format!("declaration of '{}'", name)
+        }
+        Statement::Declaration(Declaration::Constant(_, name, _)) => {
+            format!("declaration of constant '{}'", name)
+        }
+        Statement::Declaration(Declaration::Function(_, name, _, _)) => {
+            format!("declaration of function '{}'", name)
+        }
+        Statement::Return(_) => "return".to_string(),
+        Statement::Break => "break".to_string(),
+        Statement::Continue => "continue".to_string(),
+        Statement::If(_, _, _) => "if".to_string(),
+        Statement::For(_, Parameter::Parameter(_, name), _, _) => format!("for '{}'", name),
+        Statement::While(_, _) => "while".to_string(),
+        Statement::Defer(_) => "defer".to_string(),
+        Statement::EnumDeclaration(name, _) => format!("declaration of enum '{}'", name),
+        Statement::Compound(_, _) | Statement::Skip => "block".to_string(),
+        Statement::Counted(_, statement) | Statement::Located(_, statement) => describe(statement),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::evaluate::interpret_to;
+    use crate::frontend::main::create_syntax_tree;
+
+    // Runs the program instrumented, returning the coverage of it
+    fn run_covered(program: &str) -> Coverage {
+        let (program, coverage) = instrument(create_syntax_tree(program).unwrap());
+        interpret_to(program, vec![], false, &mut Vec::new());
+        coverage
+    }
+
+    fn count_of(coverage: &Coverage, description: &str) -> usize {
+        coverage
+            .statements
+            .iter()
+            .find(|statement| statement.description == description)
+            .unwrap_or_else(|| panic!("no statement '{}'", description))
+            .count()
+    }
+
+    #[test]
+    fn test_else_that_never_runs_is_counted_zero() {
+        let coverage = run_covered(
+            "var int x = 5;
+            var int y = 0;
+            if (x < 10) { y = 1; } else { x = 0; }",
+        );
+        assert_eq!(count_of(&coverage, "assignment to 'y'"), 1);
+        let else_statement = coverage
+            .statements
+            .iter()
+            .find(|statement| statement.description == "assignment to 'x'")
+            .unwrap();
+        assert_eq!(else_statement.count(), 0);
+        assert_eq!(else_statement.branch, Some("else"));
+        assert_eq!(else_statement.parent, Some(2));
+        assert_eq!(
+            coverage.summary(),
+            "4/5 statements executed; 0 functions never called"
+        );
+    }
+
+    #[test]
+    fn test_loop_bodies_and_calls_are_counted() {
+        let coverage = run_covered(
+            "fn int twice(int n) { return n * 2; };
+            fn int unused() { return 0; };
+            var int total = 0;
+            for (int x in [1, 2, 3]) { total = total + twice(x); }
+            var int i = 0;
+            while (i < 4) { i = i + 1; }",
+        );
+        assert_eq!(count_of(&coverage, "assignment to 'total'"), 3);
+        assert_eq!(count_of(&coverage, "assignment to 'i'"), 4);
+        assert_eq!(count_of(&coverage, "return"), 3);
+        let calls: Vec<(&str, usize)> = coverage
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.calls()))
+            .collect();
+        assert_eq!(calls, [("twice", 3), ("unused", 0)]);
+        assert_eq!(coverage.never_called(), ["unused"]);
+        assert_eq!(
+            coverage.summary(),
+            "9/10 statements executed; 1 function never called"
+        );
+    }
+
+    #[test]
+    fn test_stage_functions_on_their_own_threads_are_counted() {
+        let coverage = run_covered(
+            "fn row(int id) make(int i) { return row(int id = i); };
+            fn bool even(row(int id) r) { return r.id % 2 == 0; };
+            var table(int id) t = rows_from_range(10, \"make\") pipe even();",
+        );
+        let calls: Vec<usize> = coverage
+            .functions
+            .iter()
+            .map(CoveredFunction::calls)
+            .collect();
+        assert_eq!(calls, [10, 10]);
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let coverage = run_covered(
+            "fn null greet(string name) { print(name); };
+            var bool loud = false;
+            if (loud) { greet(\"hi\"); }",
+        );
+        assert_eq!(
+            coverage.to_json(),
+            "{\"statements\":[\
+             {\"id\":0,\"parent\":null,\"branch\":null,\"function\":null,\"statement\":\"declaration of function 'greet'\",\"count\":1},\
+             {\"id\":1,\"parent\":0,\"branch\":\"body\",\"function\":\"greet\",\"statement\":\"call to 'print'\",\"count\":0},\
+             {\"id\":2,\"parent\":null,\"branch\":null,\"function\":null,\"statement\":\"declaration of 'loud'\",\"count\":1},\
+             {\"id\":3,\"parent\":null,\"branch\":null,\"function\":null,\"statement\":\"if\",\"count\":1},\
+             {\"id\":4,\"parent\":3,\"branch\":\"then\",\"function\":null,\"statement\":\"call to 'greet'\",\"count\":0}],\
+             \"functions\":[{\"name\":\"greet\",\"calls\":0}],\"executed\":3,\"total\":5,\"functions_never_called\":1}"
+        );
+    }
+
+    #[test]
+    fn test_instrumented_program_gives_the_same_result() {
+        let program =
+            "fn int f(int n) { if (n < 2) { return n; } return f(n - 1) + f(n - 2); }; f(10);";
+        let plain = interpret_to(
+            create_syntax_tree(program).unwrap(),
+            vec![],
+            true,
+            &mut Vec::new(),
+        );
+        let (instrumented, _) = instrument(create_syntax_tree(program).unwrap());
+        let mut out = Vec::new();
+        let code = interpret_to(instrumented, vec![], true, &mut out);
+        assert_eq!(code, plain);
+        assert_eq!(String::from_utf8(out).unwrap(), "55\n");
+    }
+}
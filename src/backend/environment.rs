@@ -1,6 +1,6 @@
 use core::panic;
 
-use crate::frontend::ast::{Parameter, Statement, TypeConstruct};
+use crate::frontend::ast::{Parameter, Statement, TypeConstruct, parameter_list};
 
 use super::evaluate::ExpressionValue;
 /*
@@ -8,7 +8,8 @@ use super::evaluate::ExpressionValue;
  */
 
 // Represents a function in the Wrench language, with it's closure that represents the functions in the environment at the time of declaration
-#[derive(Clone)]
+// A lambda has no name, and is found by the name of the variable or parameter holding it
+#[derive(Clone, Debug, PartialEq)]
 pub struct WrenchFunction {
     pub return_type: TypeConstruct,
     pub name: String,
@@ -43,26 +44,87 @@ impl WrenchFunction {
         }
         env
     }
+
+    //The environment the body of the function runs in: its closure, then its parameters, then the function itself so it can recurse
+    pub fn call_env(&self, args: Vec<ExpressionValue>) -> Vec<Vec<EnvironmentCell>> {
+        let mut env = self.get_closure_as_env();
+        for (param, arg) in self.parameters.iter().zip(args) {
+            let Parameter::Parameter(_, param_name) = param;
+            env_add(&mut env, EnvironmentCell::Variable(param_name.clone(), arg));
+        }
+        env_add(&mut env, EnvironmentCell::Function(self.clone()));
+        env
+    }
+
+    //The signature as the function is declared, e.g. fn int add(int a, int b), or fn (int a) int for a lambda
+    pub fn signature(&self) -> String {
+        if self.name.is_empty() {
+            return format!(
+                "fn ({}) {}",
+                parameter_list(&self.parameters),
+                self.return_type
+            );
+        }
+        format!(
+            "fn {} {}({})",
+            self.return_type,
+            self.name,
+            parameter_list(&self.parameters)
+        )
+    }
+}
+
+//Lists everything in the environment, innermost scope first, used by debug_env
+//Within a scope, cells are listed in the order they were declared. Variables are shown by their kind and a short rendering of the value
+pub fn format_env(env: &[Vec<EnvironmentCell>]) -> String {
+    let mut out = String::new();
+    for (depth, scope) in env.iter().enumerate().rev() {
+        out.push_str(&format!("scope {}:\n", depth));
+        if scope.is_empty() {
+            out.push_str("  (empty)\n");
+        }
+        for declaration in scope.iter() {
+            match declaration {
+                EnvironmentCell::Variable(name, value) => {
+                    out.push_str(&format!("  {}: {}\n", name, value.short_description()))
+                }
+                EnvironmentCell::Function(function) => {
+                    out.push_str(&format!("  {}\n", function.signature()))
+                }
+                EnvironmentCell::Deferred(_) => out.push_str("  (deferred statement)\n"),
+            }
+        }
+    }
+    out
 }
 
 //Helper function to convert the environment to a closure
+//A variable holding a function is in it by the name of the variable, so the function can be called by that name
 pub fn env_to_closure(env: &[Vec<EnvironmentCell>]) -> Vec<WrenchFunction> {
     let mut closure = Vec::new();
     for scope in env.iter() {
         for declaration in scope.iter() {
-            if let EnvironmentCell::Function(function) = declaration {
-                closure.push(function.clone());
+            match declaration {
+                EnvironmentCell::Function(function) => closure.push(function.clone()),
+                EnvironmentCell::Variable(name, ExpressionValue::Function(function)) => closure
+                    .push(WrenchFunction {
+                        name: name.clone(),
+                        ..function.clone()
+                    }),
+                EnvironmentCell::Variable(_, _) | EnvironmentCell::Deferred(_) => {}
             }
         }
     }
     closure
 }
 
-//Represents a cell in the environment. Only variables and functions can be defined and stored in the environment
+//Represents a cell in the environment. Only variables and functions can be defined and stored in the environment,
+//besides the statements deferred until the scope exits, which have no name and are never found by looking one up
 #[derive(Clone)]
 pub enum EnvironmentCell {
     Variable(String, ExpressionValue),
     Function(WrenchFunction),
+    Deferred(Statement),
 }
 
 //Helper function to retrieve a referrence to an environment cell from an environment. Returns None if the cell is not found
@@ -83,6 +145,7 @@ pub fn env_get_optional<'a>(
                         return Some(declaration);
                     }
                 }
+                EnvironmentCell::Deferred(_) => {}
             }
         }
     }
@@ -94,21 +157,28 @@ pub fn env_new() -> Vec<Vec<EnvironmentCell>> {
     Vec::new()
 }
 
-//Helper function to retrieve a referrence to an environment cell from an environment. Panics if the cell is not found
+//Helper function to retrieve a copy of an environment cell from an environment. Panics if the cell is not found
+//Prefer env_get_ref where a reference is enough, as copying the cell copies all of an array held by it
 pub fn env_get(env: &[Vec<EnvironmentCell>], name: &str) -> EnvironmentCell {
+    env_get_ref(env, name).clone()
+}
+
+//Helper function to retrieve a referrence to an environment cell from an environment. Panics if the cell is not found
+pub fn env_get_ref<'a>(env: &'a [Vec<EnvironmentCell>], name: &str) -> &'a EnvironmentCell {
     for scope in env.iter().rev() {
         for declaration in scope.iter() {
             match declaration {
                 EnvironmentCell::Variable(var_name, _) => {
                     if var_name == name {
-                        return declaration.clone();
+                        return declaration;
                     }
                 }
                 EnvironmentCell::Function(function) => {
                     if function.name == name {
-                        return declaration.clone();
+                        return declaration;
                     }
                 }
+                EnvironmentCell::Deferred(_) => {}
             }
         }
     }
@@ -118,14 +188,17 @@ pub fn env_get(env: &[Vec<EnvironmentCell>], name: &str) -> EnvironmentCell {
     );
 }
 
-//Helper function to add a new environment cell to the environment. Panics if the cell is already declared
+//Helper function to add a new environment cell to the innermost scope. Panics if the cell is already declared
 pub fn env_add(env: &mut [Vec<EnvironmentCell>], declaration: EnvironmentCell) {
     let name = match &declaration {
-        EnvironmentCell::Variable(var_name, _) => var_name,
-        EnvironmentCell::Function(function) => function.name.as_str(),
+        EnvironmentCell::Variable(var_name, _) => Some(var_name.as_str()),
+        EnvironmentCell::Function(function) => Some(function.name.as_str()),
+        EnvironmentCell::Deferred(_) => None,
     };
 
-    if env_get_optional(env, name).is_some() {
+    if let Some(name) = name
+        && env_get_optional(env, name).is_some()
+    {
         panic!(
             "Interpretation error. The identifier '{:?}' is already declared",
             name
@@ -154,6 +227,29 @@ pub fn env_update(env: &mut [Vec<EnvironmentCell>], name: &str, expression: Expr
     );
 }
 
+//Helper function to take the statements deferred in the innermost scope out of it, the last deferred first
+pub fn env_take_deferred(env: &mut [Vec<EnvironmentCell>]) -> Vec<Statement> {
+    let Some(scope) = env.last_mut() else {
+        return Vec::new();
+    };
+    if !scope
+        .iter()
+        .any(|cell| matches!(cell, EnvironmentCell::Deferred(_)))
+    {
+        return Vec::new();
+    }
+    let mut deferred = Vec::new();
+    scope.retain(|cell| match cell {
+        EnvironmentCell::Deferred(statement) => {
+            deferred.push(statement.clone());
+            false
+        }
+        _ => true,
+    });
+    deferred.reverse();
+    deferred
+}
+
 pub fn env_expand_scope(env: &mut Vec<Vec<EnvironmentCell>>) {
     env.push(Vec::new());
 }
@@ -161,6 +257,20 @@ pub fn env_expand_scope(env: &mut Vec<Vec<EnvironmentCell>>) {
 pub fn env_shrink_scope(env: &mut Vec<Vec<EnvironmentCell>>) {
     env.pop();
 }
+
+//The environment as it was at some point, which env_restore goes back to
+//Tables are shared, not copied, as they are when assigned: rows added to a table after the snapshot are still in it after
+//the restore. Everything else, from which names are declared in which scope to the values of variables, is restored
+pub struct EnvSnapshot(Vec<Vec<EnvironmentCell>>);
+
+pub fn env_snapshot(env: &[Vec<EnvironmentCell>]) -> EnvSnapshot {
+    EnvSnapshot(env.to_vec())
+}
+
+//Helper function to put the environment back as it was when the snapshot was taken, with the scopes added since removed
+pub fn env_restore(env: &mut Vec<Vec<EnvironmentCell>>, snapshot: EnvSnapshot) {
+    *env = snapshot.0;
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,7 +285,7 @@ mod tests {
         )
     }
 
-    fn dummy_variable(name: &str, value: i32) -> EnvironmentCell {
+    fn dummy_variable(name: &str, value: i64) -> EnvironmentCell {
         EnvironmentCell::Variable(name.to_string(), ExpressionValue::Number(value))
     }
 
@@ -286,4 +396,40 @@ mod tests {
         assert!(closure_from_env.iter().any(|f| f.name == "f1"));
         assert!(closure_from_env.iter().any(|f| f.name == "f2"));
     }
+
+    #[test]
+    fn test_format_env_lists_innermost_scope_first() {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        env_add(&mut env, dummy_variable("x", 1));
+        env_add(&mut env, EnvironmentCell::Function(dummy_function("foo")));
+        env_expand_scope(&mut env);
+        env_expand_scope(&mut env);
+        env_add(&mut env, dummy_variable("y", 2));
+        assert_eq!(
+            format_env(&env),
+            "scope 2:\n  y: int 2\nscope 1:\n  (empty)\nscope 0:\n  x: int 1\n  fn int foo()\n"
+        );
+    }
+
+    #[test]
+    fn test_env_restore_undoes_declarations_and_scopes() {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        env_add(&mut env, dummy_variable("x", 1));
+        let snapshot = env_snapshot(&env);
+        env_update(&mut env, "x", ExpressionValue::Number(2));
+        env_add(&mut env, dummy_variable("y", 2));
+        env_expand_scope(&mut env);
+        env_add(&mut env, EnvironmentCell::Function(dummy_function("f")));
+
+        env_restore(&mut env, snapshot);
+        assert_eq!(env.len(), 1);
+        assert!(matches!(
+            env_get(&env, "x"),
+            EnvironmentCell::Variable(_, ExpressionValue::Number(1))
+        ));
+        assert!(env_get_optional(&mut env, "y").is_none());
+        assert!(env_get_optional(&mut env, "f").is_none());
+    }
 }
@@ -1,52 +1,396 @@
 use core::panic;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::Write,
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
+    rc::Rc,
+    sync::Arc,
+};
 
 use crate::frontend::ast::{
-    ColumnAssignmentEnum, Declaration, Expr, Operator, Parameter, Statement, TypeConstruct,
+    ColumnAssignmentEnum, Declaration, Expr, Operator, Parameter, Span, Statement, TypeConstruct,
 };
 
 use super::{
     environment::{
-        EnvironmentCell, WrenchFunction, env_add, env_expand_scope, env_get, env_new,
-        env_shrink_scope, env_to_closure, env_update,
+        EnvironmentCell, WrenchFunction, env_add, env_expand_scope, env_get, env_get_optional,
+        env_get_ref, env_new, env_restore, env_shrink_scope, env_snapshot, env_take_deferred,
+        env_to_closure, env_update, format_env,
+    },
+    library::{char_at, check_output, lookup_builtin, output},
+    pipes::{evaluate_pipes, panic_message},
+    table::{
+        ColumnFill, PRINT_ROW_LIMIT, Row, Table, TableCell, TableCellType, coerce_cell,
+        format_double,
     },
-    library::{wrench_import, wrench_print, wrench_table_add_row},
-    pipes::evaluate_pipes,
-    table::{Row, Table, TableCell, TableCellType},
 };
 
 // Represents the value of an evaluated expression in the Wrench language
+// Tables have reference semantics: assigning a table or passing it to a function shares it, so
+// mutations through table_add_row are visible to every holder. Use table_clone to get a copy.
+// The exception is pipes, where tables are copied into the stage threads (see PipeValue)
 #[derive(Clone, Debug, PartialEq)]
 pub enum ExpressionValue {
-    Number(i32),
+    Number(i64),
     Double(f64),
-    String(String),
+    String(Arc<str>),
+    Char(char),
     Bool(bool),
     Table(Rc<RefCell<Table>>),
     Row(Row),
     Array(Vec<ExpressionValue>),
+    Function(WrenchFunction),
     Null,
 }
 
+// Longest string shown in a value description before it is cut off
+const DESCRIPTION_STRING_LIMIT: usize = 20;
+
+impl ExpressionValue {
+    //The name of the kind of value, as the type is written in wrench
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            ExpressionValue::Number(_) => "int",
+            ExpressionValue::Double(_) => "double",
+            ExpressionValue::String(_) => "string",
+            ExpressionValue::Char(_) => "char",
+            ExpressionValue::Bool(_) => "bool",
+            ExpressionValue::Table(_) => "table",
+            ExpressionValue::Row(_) => "row",
+            ExpressionValue::Array(_) => "array",
+            ExpressionValue::Function(_) => "function",
+            ExpressionValue::Null => "null",
+        }
+    }
+
+    //The type of the value as it would be declared in wrench. An empty array has no element to take its type from
+    pub fn value_type(&self) -> Option<TypeConstruct> {
+        match self {
+            ExpressionValue::Number(_) => Some(TypeConstruct::Int),
+            ExpressionValue::Double(_) => Some(TypeConstruct::Double),
+            ExpressionValue::String(_) => Some(TypeConstruct::String),
+            ExpressionValue::Char(_) => Some(TypeConstruct::Char),
+            ExpressionValue::Bool(_) => Some(TypeConstruct::Bool),
+            ExpressionValue::Table(t) => Some(TypeConstruct::Table(
+                Table::structure_to_parameters(t.borrow().get_structure()),
+            )),
+            ExpressionValue::Row(r) => Some(TypeConstruct::Row(
+                r.iter()
+                    .map(|(name, cell)| {
+                        Parameter::Parameter(cell.get_type().type_construct(), name.clone())
+                    })
+                    .collect(),
+            )),
+            ExpressionValue::Array(a) => a
+                .first()
+                .and_then(|first| first.value_type())
+                .map(|element| TypeConstruct::Array(Box::new(element))),
+            ExpressionValue::Function(f) => Some(TypeConstruct::Function(
+                Box::new(f.return_type.clone()),
+                f.parameters
+                    .iter()
+                    .map(|Parameter::Parameter(t, _)| t.clone())
+                    .collect(),
+            )),
+            ExpressionValue::Null => Some(TypeConstruct::Null),
+        }
+    }
+
+    //A short description of the value for error messages, e.g. int 5 or table (3 columns, 15204 rows)
+    //Tables, rows and arrays are described by their size, and long strings are cut off
+    pub fn short_description(&self) -> String {
+        let kind = self.kind_name();
+        match self {
+            ExpressionValue::Number(n) => format!("{} {}", kind, n),
+            ExpressionValue::Double(d) => format!("{} {}", kind, format_double(*d)),
+            ExpressionValue::String(s) if s.chars().count() > DESCRIPTION_STRING_LIMIT => {
+                let truncated: String = s.chars().take(DESCRIPTION_STRING_LIMIT).collect();
+                format!("{} \"{}…\"", kind, truncated)
+            }
+            ExpressionValue::String(s) => format!("{} \"{}\"", kind, s),
+            ExpressionValue::Char(c) => format!("{} {:?}", kind, c),
+            ExpressionValue::Bool(b) => format!("{} {}", kind, b),
+            ExpressionValue::Table(t) => {
+                let t = t.borrow();
+                format!(
+                    "{} ({} columns, {} rows)",
+                    kind,
+                    t.get_structure().len(),
+                    t.iter().count()
+                )
+            }
+            ExpressionValue::Row(r) => format!("{} ({} columns)", kind, r.iter().count()),
+            ExpressionValue::Array(a) => format!("{} ({} elements)", kind, a.len()),
+            ExpressionValue::Function(f) => f.signature(),
+            ExpressionValue::Null => kind.to_string(),
+        }
+    }
+}
+
+// Default for how many values an array or table can hold, see set_max_values
+pub const DEFAULT_MAX_VALUES: usize = 50_000_000;
+// Default for how many elements of an array print writes, see set_max_printed_values
+pub const DEFAULT_MAX_PRINTED_VALUES: usize = 100_000;
+
+thread_local! {
+    static MAX_VALUES: Cell<usize> = const { Cell::new(DEFAULT_MAX_VALUES) };
+    static MAX_PRINTED_VALUES: Cell<usize> = const { Cell::new(DEFAULT_MAX_PRINTED_VALUES) };
+    static CURRENT_SPAN: Cell<Option<Span>> = const { Cell::new(None) };
+}
+
+//Sets how many values an array or table made by programs run on this thread can hold, counting every element of nested
+//arrays and every cell of a table. Beyond it the program stops with an error, instead of running out of memory
+pub fn set_max_values(values: usize) {
+    MAX_VALUES.with(|limit| limit.set(values));
+}
+
+//Sets how many elements of an array print writes, for programs run on this thread. The rest is cut off with a note
+pub fn set_max_printed_values(values: usize) {
+    MAX_PRINTED_VALUES.with(|limit| limit.set(values));
+}
+
+//The span of the innermost statement being evaluated on this thread. After a runtime error it is the statement the error
+//happened in, as the statements it stopped are not left
+pub fn current_span() -> Option<Span> {
+    CURRENT_SPAN.with(Cell::get)
+}
+
+//Stops the program if an array or table is about to hold more values than allowed, see set_max_values
+pub fn check_value_count(count: usize, what: &str) {
+    let limit = MAX_VALUES.with(Cell::get);
+    if count > limit {
+        panic!(
+            "Interpretation error: {} would hold {} values, more than the limit of {} (raise it with --max-values)",
+            what, count, limit
+        );
+    }
+}
+
+impl ExpressionValue {
+    //The value as print writes it. Strings are written as they are, and arrays as [1, 2, 3]
+    //An array with more elements than set by set_max_printed_values is cut off
+    pub fn display_string(&self) -> String {
+        self.display_string_limited(Some(MAX_PRINTED_VALUES.with(Cell::get)))
+    }
+
+    //The value as print_full writes it, like display_string with every element of an array
+    pub fn full_display_string(&self) -> String {
+        self.display_string_limited(None)
+    }
+
+    fn display_string_limited(&self, limit: Option<usize>) -> String {
+        match self {
+            ExpressionValue::Number(n) => n.to_string(),
+            ExpressionValue::Double(d) => format_double(*d),
+            ExpressionValue::String(s) => s.to_string(),
+            ExpressionValue::Char(c) => c.to_string(),
+            ExpressionValue::Bool(b) => b.to_string(),
+            ExpressionValue::Null => "Null".to_string(),
+            ExpressionValue::Row(row) => row.print_line(),
+            ExpressionValue::Table(table) => table.borrow().render(Some(PRINT_ROW_LIMIT)),
+            ExpressionValue::Function(f) => f.signature(),
+            ExpressionValue::Array(_) => {
+                let mut out = String::new();
+                let mut budget = limit;
+                if !self.write_nested(&mut out, &mut budget) {
+                    out.push_str(&format!(
+                        "… output truncated at {} elements (use print_full / raise the limit with --max-print-values)",
+                        limit.unwrap_or_default()
+                    ));
+                }
+                out
+            }
+        }
+    }
+
+    //The value as it is written inside an array. Strings are quoted, so ["1", 1] and [1, 1] can be told apart
+    pub fn nested_string(&self) -> String {
+        let mut out = String::new();
+        self.write_nested(&mut out, &mut None);
+        out
+    }
+
+    //Writes the value as it is written inside an array. Each element other than an array takes one from the budget,
+    //and when there is none left, writing stops and false is returned
+    fn write_nested(&self, out: &mut String, budget: &mut Option<usize>) -> bool {
+        match self {
+            ExpressionValue::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    if !item.write_nested(out, budget) {
+                        return false;
+                    }
+                }
+                out.push(']');
+                true
+            }
+            other => {
+                if let Some(remaining) = budget {
+                    if *remaining == 0 {
+                        return false;
+                    }
+                    *remaining -= 1;
+                }
+                match other {
+                    ExpressionValue::String(s) => out.push_str(&format!("{:?}", s)),
+                    ExpressionValue::Char(c) => out.push_str(&format!("{:?}", c)),
+                    ExpressionValue::Row(row) => out.push_str(&row.nested_string()),
+                    ExpressionValue::Table(_) => out.push_str(&other.short_description()),
+                    other => out.push_str(&other.display_string_limited(None)),
+                }
+                true
+            }
+        }
+    }
+
+    //How many values the value holds, counting every element of nested arrays and every cell of a table
+    pub fn value_count(&self) -> usize {
+        match self {
+            ExpressionValue::Array(items) => items.iter().map(ExpressionValue::value_count).sum(),
+            ExpressionValue::Table(table) => {
+                let table = table.borrow();
+                table.row_count() * table.get_structure().len()
+            }
+            ExpressionValue::Row(row) => row.iter().count(),
+            _ => 1,
+        }
+    }
+}
+
 //Represents the value of a statement in the Wrench language. Either the statement returns something or nothing
+//An expression statement gives its value without returning it, so the last one of a program can be echoed, see interpret
+//A break or continue is passed up to the loop it is in, which stops there or goes on with the next iteration
 #[derive(Debug, PartialEq)]
 pub enum StatementValue {
     None,
     Return(ExpressionValue),
+    Value(ExpressionValue),
+    Break,
+    Continue,
 }
 
 /*
  * This file deals with evaluating the AST
  */
 
-pub fn interpret(input: Statement) {
+//Runs a program and returns its exit code
+//When the program declares main(), it is called after the top level statements and what it returns is the exit code.
+//An exit code is from 0 to 255, and main returning any other int is a runtime error
+//A return at the top level ends the program before main is called
+//Variables given by the host are declared in the global scope before the program runs
+//With echo_last_value, the value of a last top level expression statement is printed, like 3 * 14 in wrench -e "3 * 14;"
+pub fn interpret(
+    input: Statement,
+    host_vars: Vec<(String, ExpressionValue)>,
+    echo_last_value: bool,
+) -> i32 {
+    interpret_to(input, host_vars, echo_last_value, &mut output())
+}
+
+//Evaluates a statement in an environment kept from one input to the next, e.g. by an interactive session
+//When the statement stops with a runtime error, the environment is restored to what it was before it, so a declaration
+//that failed halfway leaves nothing behind and what was declared before can still be used. See env_snapshot for tables
+pub fn evaluate_input(
+    input: Statement,
+    env: &mut Vec<Vec<EnvironmentCell>>,
+) -> Result<StatementValue, String> {
+    let snapshot = env_snapshot(env);
+    match catch_unwind(AssertUnwindSafe(|| evaluate_statement(input, env))) {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            env_restore(env, snapshot);
+            Err(panic_message(e))
+        }
+    }
+}
+
+//Like interpret, with the echoed value written to out
+pub fn interpret_to<W: Write>(
+    input: Statement,
+    host_vars: Vec<(String, ExpressionValue)>,
+    echo_last_value: bool,
+    out: &mut W,
+) -> i32 {
+    let echo = echo_last_value && ends_with_echoed_expression(&input);
+    CURRENT_SPAN.with(|span| span.set(None));
     let mut env = env_new();
     env_expand_scope(&mut env);
-    evaluate_statement(input, &mut env);
+    for (name, value) in host_vars {
+        env_add(&mut env, EnvironmentCell::Variable(name, value));
+    }
+    //What is deferred at the top level runs when the program ends, after main
+    with_deferred(&mut env, |env| {
+        match evaluate_statement(input, env) {
+            StatementValue::Return(_) => return 0,
+            StatementValue::Value(value) if echo && value != ExpressionValue::Null => {
+                check_output(writeln!(out, "{}", value.display_string()));
+            }
+            _ => {}
+        }
+        match env_get_optional(env, "main") {
+            Some(EnvironmentCell::Function(main)) if main.parameters.is_empty() => {
+                let main = main.clone();
+                match evaluate_custom_function_call(&main, vec![]) {
+                    // Only the low byte of an exit code reaches the shell, so a larger one would exit with another code
+                    ExpressionValue::Number(code @ 0..=255) => code as i32,
+                    ExpressionValue::Number(code) => panic!(
+                        "Interpretation error: main() returned {}, but an exit code must be from 0 to 255",
+                        code
+                    ),
+                    _ => 0,
+                }
+            }
+            _ => 0,
+        }
+    })
+}
+
+//Whether the last top level statement of a program is an expression whose value is echoed
+//A pipe into print has printed its rows already, so it is not echoed. Calls to print give null, which is never echoed
+fn ends_with_echoed_expression(program: &Statement) -> bool {
+    let mut last = None;
+    let mut statements = vec![program];
+    while let Some(statement) = statements.pop() {
+        match statement {
+            Statement::Compound(first, rest) => {
+                statements.push(rest);
+                statements.push(first);
+            }
+            Statement::Skip => {}
+            Statement::Counted(_, statement) | Statement::Located(_, statement) => {
+                statements.push(statement)
+            }
+            other => last = Some(other),
+        }
+    }
+    match last {
+        Some(Statement::Expr(expr)) => {
+            !matches!(&**expr, Expr::Pipe(_, name, _) if name == "print")
+        }
+        _ => false,
+    }
+}
+
+//Declares the index of a for-loop, if it has one, in the scope of the iteration
+fn add_loop_index(env: &mut [Vec<EnvironmentCell>], index_name: &Option<String>, index: usize) {
+    if let Some(index_name) = index_name {
+        env_add(
+            env,
+            EnvironmentCell::Variable(index_name.clone(), ExpressionValue::Number(index as i64)),
+        );
+    }
 }
 
 //Evaluate S in Stmt
-fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>) -> StatementValue {
+pub fn evaluate_statement(
+    statement: Statement,
+    env: &mut Vec<Vec<EnvironmentCell>>,
+) -> StatementValue {
     match statement {
         //Matches D
         Statement::Declaration(declaration) => {
@@ -54,10 +398,7 @@ fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>)
             StatementValue::None
         }
         //Matches e
-        Statement::Expr(expression) => {
-            evaluate_expression(*expression, env);
-            StatementValue::None
-        }
+        Statement::Expr(expression) => StatementValue::Value(evaluate_expression(*expression, env)),
         //Matches x = e
         Statement::VariableAssignment(variable, expression) => {
             let evaluated_value = evaluate_expression(*expression, env);
@@ -65,22 +406,59 @@ fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>)
             StatementValue::None
         }
         //Matches S1;S2
+        //The chain of statements in a program is evaluated in a loop, so long programs do not grow the stack
+        //The value is that of the last statement, not counting the skip the chain ends with
         Statement::Compound(s1, s2) => {
-            let s1v = evaluate_statement(*s1, env);
-
-            if let StatementValue::Return(_) = s1v {
-                return s1v;
-            }
+            let mut first = s1;
+            let mut rest = s2;
+            let mut value = StatementValue::None;
+            loop {
+                let is_skip = *first == Statement::Skip;
+                let s1v = evaluate_statement(*first, env);
 
-            let s2v: StatementValue = evaluate_statement(*s2, env);
+                if let StatementValue::Return(_)
+                | StatementValue::Break
+                | StatementValue::Continue = s1v
+                {
+                    return s1v;
+                }
+                if !is_skip {
+                    value = s1v;
+                }
 
-            match s2v {
-                StatementValue::Return(_) => s2v,
-                StatementValue::None => StatementValue::None,
+                match *rest {
+                    Statement::Compound(next, next_rest) => {
+                        first = next;
+                        rest = next_rest;
+                    }
+                    Statement::Skip => return value,
+                    last => return evaluate_statement(last, env),
+                }
             }
         }
-        //Matches skip
-        Statement::Skip => StatementValue::None,
+        //Matches skip, and enum declarations, which only the type checker needs
+        Statement::Skip | Statement::EnumDeclaration(_, _) => StatementValue::None,
+        //Matches defer {S}, which runs S when the scope it is in exits, see with_deferred
+        Statement::Defer(body) => {
+            env_add(env, EnvironmentCell::Deferred(*body));
+            StatementValue::None
+        }
+        //A statement counted for --coverage, see instrument
+        Statement::Counted(counter, statement) => {
+            counter.hit();
+            evaluate_statement(*statement, env)
+        }
+        //A statement with its span, which is the current span while it runs, see current_span
+        Statement::Located(span, statement) => {
+            let outer = CURRENT_SPAN.with(|current| current.replace(Some(span)));
+            let value = evaluate_statement(*statement, env);
+            CURRENT_SPAN.with(|current| current.set(outer));
+            value
+        }
+        //Matches break, which stops the loop it is in
+        Statement::Break => StatementValue::Break,
+        //Matches continue, which skips the rest of the body of the loop it is in
+        Statement::Continue => StatementValue::Continue,
         //Matches return e
         Statement::Return(expression) => {
             let return_value = evaluate_expression(*expression, env);
@@ -92,47 +470,101 @@ fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>)
             match condition {
                 ExpressionValue::Bool(true) => evaluate_statement(*s1, env),
                 ExpressionValue::Bool(false) => evaluate_statement(*s2, env),
-                _ => {
-                    panic!("Interpretation error: Condition is not a boolean")
+                other => {
+                    panic!(
+                        "Interpretation error: Condition is not a boolean, found {}",
+                        other.short_description()
+                    )
                 }
             }
         }
-        //Matches for (T x in e) {S}
-        Statement::For(parameter, expression, body) => {
-            let iterator = evaluate_expression(*expression, env);
+        //Matches for (T x in e) {S} and for (int i, T x in e) {S}
+        //The rows of a table are copies. The table is not borrowed while the body runs, so it can write rows back with table_update_row
+        Statement::For(index, parameter, expression, body) => {
             let Parameter::Parameter(_, n) = parameter;
+            //A range is counted through one int at a time, rather than made into an array first
+            if let Expr::Range(start, end, inclusive) = *expression {
+                let (ExpressionValue::Number(start), ExpressionValue::Number(end)) = (
+                    evaluate_expression(*start, env),
+                    evaluate_expression(*end, env),
+                ) else {
+                    panic!("Interpretation error: The bounds of a range must be ints")
+                };
+                let ints: Box<dyn Iterator<Item = i64>> = if inclusive {
+                    Box::new(start..=end)
+                } else {
+                    Box::new(start..end)
+                };
+                for i in ints {
+                    env_expand_scope(env);
+                    env_add(
+                        env,
+                        EnvironmentCell::Variable(n.clone(), ExpressionValue::Number(i)),
+                    );
+                    let statement_value =
+                        with_deferred(env, |env| evaluate_statement(*body.clone(), env));
+                    env_shrink_scope(env);
+                    match statement_value {
+                        StatementValue::Return(value) => return StatementValue::Return(value),
+                        StatementValue::Break => break,
+                        StatementValue::None
+                        | StatementValue::Value(_)
+                        | StatementValue::Continue => {}
+                    }
+                }
+                return StatementValue::None;
+            }
+            let iterator = evaluate_expression(*expression, env);
+            let index_name = index.map(|Parameter::Parameter(_, index_name)| index_name);
             match iterator {
                 ExpressionValue::Table(table) => {
-                    let table = table.borrow();
-                    for row in table.iter() {
+                    let row_count = table.borrow().row_count();
+                    for i in 0..row_count {
+                        let row = table.borrow().get_row(i);
                         env_expand_scope(env);
+                        add_loop_index(env, &index_name, i);
                         env_add(
                             env,
-                            EnvironmentCell::Variable(n.clone(), ExpressionValue::Row(row.clone())),
+                            EnvironmentCell::Variable(n.clone(), ExpressionValue::Row(row)),
                         );
-                        let statement_value = evaluate_statement(*body.clone(), env);
+                        let statement_value =
+                            with_deferred(env, |env| evaluate_statement(*body.clone(), env));
                         match statement_value {
                             StatementValue::Return(value) => {
                                 env_shrink_scope(env);
                                 return StatementValue::Return(value);
                             }
-                            StatementValue::None => {}
+                            StatementValue::Break => {
+                                env_shrink_scope(env);
+                                break;
+                            }
+                            StatementValue::None
+                            | StatementValue::Value(_)
+                            | StatementValue::Continue => {}
                         }
                         env_shrink_scope(env);
                     }
                     StatementValue::None
                 }
                 ExpressionValue::Array(array) => {
-                    for element in array {
+                    for (i, element) in array.into_iter().enumerate() {
                         env_expand_scope(env);
+                        add_loop_index(env, &index_name, i);
                         env_add(env, EnvironmentCell::Variable(n.clone(), element));
-                        let statement_value = evaluate_statement(*body.clone(), env);
+                        let statement_value =
+                            with_deferred(env, |env| evaluate_statement(*body.clone(), env));
                         match statement_value {
                             StatementValue::Return(value) => {
                                 env_shrink_scope(env);
                                 return StatementValue::Return(value);
                             }
-                            StatementValue::None => {}
+                            StatementValue::Break => {
+                                env_shrink_scope(env);
+                                break;
+                            }
+                            StatementValue::None
+                            | StatementValue::Value(_)
+                            | StatementValue::Continue => {}
                         }
                         env_shrink_scope(env);
                     }
@@ -143,6 +575,49 @@ fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>)
                 }
             }
         }
+        //Matches x[e1] = e2
+        //The element is changed in the array held by the variable, which is a value like any other, so no other variable sees it
+        Statement::IndexAssignment(variable, index, expression) => {
+            let index = evaluate_expression(*index, env);
+            let value = evaluate_expression(*expression, env);
+            let ExpressionValue::Number(index) = index else {
+                panic!(
+                    "Interpretation error: Index must be a integer, found {}",
+                    index.short_description()
+                )
+            };
+            match env_get_optional(env, &variable) {
+                Some(EnvironmentCell::Variable(_, ExpressionValue::Array(array))) => {
+                    let length = array.len();
+                    match usize::try_from(index).ok().and_then(|i| array.get_mut(i)) {
+                        Some(element) => *element = value,
+                        None => index_out_of_bounds(index, Some(&variable), "an array", length),
+                    }
+                }
+                _ => panic!(
+                    "Interpretation error: Cannot assign to an element of '{}', '{}' is not an array variable",
+                    variable, variable
+                ),
+            }
+            StatementValue::None
+        }
+        //Matches x.c = e
+        Statement::FieldAssignment(variable, column, expression) => {
+            let cell = table_cell(evaluate_expression(*expression, env))
+                .unwrap_or_else(|e| panic!("Interpretation error: A row column {}", e));
+            match env_get_optional(env, &variable) {
+                Some(EnvironmentCell::Variable(_, ExpressionValue::Row(row))) => {
+                    if let Err(e) = row.set(&column, cell) {
+                        panic!("Interpretation error: {}", e);
+                    }
+                }
+                _ => panic!(
+                    "Interpretation error: Cannot assign to '{}.{}', '{}' is not a row variable",
+                    variable, column, variable
+                ),
+            }
+            StatementValue::None
+        }
         //Matches while(e){S}
         Statement::While(e, body) => {
             loop {
@@ -150,21 +625,31 @@ fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>)
                 env_expand_scope(env);
                 match condition {
                     ExpressionValue::Bool(true) => {
-                        let statement_value = evaluate_statement(*body.clone(), env);
+                        let statement_value =
+                            with_deferred(env, |env| evaluate_statement(*body.clone(), env));
                         match statement_value {
                             StatementValue::Return(value) => {
                                 env_shrink_scope(env);
                                 return StatementValue::Return(value);
                             }
-                            StatementValue::None => {}
+                            StatementValue::Break => {
+                                env_shrink_scope(env);
+                                break;
+                            }
+                            StatementValue::None
+                            | StatementValue::Value(_)
+                            | StatementValue::Continue => {}
                         }
                     }
                     ExpressionValue::Bool(false) => {
                         env_shrink_scope(env);
                         break;
                     }
-                    _ => {
-                        panic!("Interpretation error: Condition is not a boolean")
+                    other => {
+                        panic!(
+                            "Interpretation error: Condition is not a boolean, found {}",
+                            other.short_description()
+                        )
                     }
                 }
                 env_shrink_scope(env);
@@ -174,6 +659,52 @@ fn evaluate_statement(statement: Statement, env: &mut Vec<Vec<EnvironmentCell>>)
     }
 }
 
+//Runs f in the innermost scope of env, then the statements deferred in that scope, the last deferred first.
+//They also run when f stops with a runtime error, which is passed on after them. An error in a deferred statement is
+//reported as it happens and does not stop the other deferred statements. It is passed on only when f had no error
+fn with_deferred<R>(
+    env: &mut Vec<Vec<EnvironmentCell>>,
+    f: impl FnOnce(&mut Vec<Vec<EnvironmentCell>>) -> R,
+) -> R {
+    let depth = env.len();
+    let result = catch_unwind(AssertUnwindSafe(|| f(env)));
+    //A runtime error leaves the scopes opened inside f behind, the statements deferred in them have already run
+    env.truncate(depth);
+    let deferred_error = run_deferred(env);
+    match (result, deferred_error) {
+        (Ok(value), None) => value,
+        (Err(error), _) | (Ok(_), Some(error)) => resume_unwind(error),
+    }
+}
+
+//Runs the statements deferred in the innermost scope of env, each in a scope of its own. Returns the first error
+fn run_deferred(env: &mut Vec<Vec<EnvironmentCell>>) -> Option<Box<dyn Any + Send>> {
+    let depth = env.len();
+    let mut first_error = None;
+    for statement in env_take_deferred(env) {
+        env_expand_scope(env);
+        let deferred = catch_unwind(AssertUnwindSafe(|| {
+            with_deferred(env, |env| evaluate_statement(statement, env))
+        }));
+        env.truncate(depth);
+        if let Err(error) = deferred {
+            first_error.get_or_insert(error);
+        }
+    }
+    first_error
+}
+
+//The cell a value is stored as in a table or row. Only ints, doubles, strings and bools can be
+fn table_cell(value: ExpressionValue) -> Result<TableCell, String> {
+    match value {
+        ExpressionValue::Number(n) => Ok(TableCell::Int(n)),
+        ExpressionValue::Double(d) => Ok(TableCell::Double(d)),
+        ExpressionValue::String(s) => Ok(TableCell::String(s)),
+        ExpressionValue::Bool(b) => Ok(TableCell::Bool(b)),
+        other => Err(format!("cannot hold {}", other.short_description())),
+    }
+}
+
 //Evaluate D in Decl
 fn evaluate_declaration(declaration: Declaration, env: &mut Vec<Vec<EnvironmentCell>>) {
     match declaration {
@@ -182,6 +713,16 @@ fn evaluate_declaration(declaration: Declaration, env: &mut Vec<Vec<EnvironmentC
             let evaluated_value = evaluate_expression(*value, env);
             env_add(env, EnvironmentCell::Variable(var_name, evaluated_value));
         }
+        //Matches const T x = bind(f, e1, ..., en)
+        Declaration::Constant(_, var_name, value) if matches!(&*value, Expr::FunctionCall(f, _) if f == "bind") =>
+        {
+            let Expr::FunctionCall(_, args) = *value else {
+                unreachable!()
+            };
+            let args = args.into_iter().map(|arg| *arg).collect();
+            let function = bind_function(var_name, args, env);
+            env_add(env, EnvironmentCell::Function(function));
+        }
         //Matches const T x = e
         Declaration::Constant(_, var_name, value) => {
             let evaluated_value = evaluate_expression(*value, env);
@@ -200,7 +741,107 @@ fn evaluate_declaration(declaration: Declaration, env: &mut Vec<Vec<EnvironmentC
     }
 }
 
+//Makes the function declared by const T name = bind(f, e1, ..., en)
+//It takes the parameters of f that are not bound, and calls f with them followed by the values of e1 to en
+fn bind_function(
+    name: String,
+    args: Vec<Expr>,
+    env: &mut Vec<Vec<EnvironmentCell>>,
+) -> WrenchFunction {
+    let mut args = args.into_iter();
+    let function_name = match args.next().map(|arg| evaluate_expression(arg, env)) {
+        Some(ExpressionValue::String(function_name)) => function_name.to_string(),
+        _ => panic!("Interpretation error: bind expects the name of a function"),
+    };
+    let EnvironmentCell::Function(function) = env_get(env, &function_name) else {
+        panic!(
+            "Interpretation error: Identifier '{:?}' is not a function",
+            function_name
+        );
+    };
+    let bound: Vec<Box<Expr>> = args
+        .map(|arg| Box::new(value_to_literal(evaluate_expression(arg, env))))
+        .collect();
+    let unbound = function.parameters.len() - bound.len();
+    let parameters = function.parameters[..unbound].to_vec();
+    let call_args = parameters
+        .iter()
+        .map(|Parameter::Parameter(_, param_name)| Box::new(Expr::Identifier(param_name.clone())))
+        .chain(bound)
+        .collect();
+    WrenchFunction::new(
+        function.return_type.clone(),
+        name,
+        parameters,
+        Box::new(Statement::Return(Box::new(Expr::FunctionCall(
+            function_name,
+            call_args,
+        )))),
+        env_to_closure(env),
+    )
+}
+
+//The body of a lambda, after constants of the ints, doubles, strings and bools in scope where it is made
+//The values are captured when the lambda is made, so assigning the variables later does not change them. Tables and other
+//values are not captured, as the type checker only lets lambdas see these, see lambda_captures. Parameters hide variables
+fn capture_values(
+    env: &[Vec<EnvironmentCell>],
+    parameters: &[Parameter],
+    body: Box<Statement>,
+) -> Box<Statement> {
+    let mut captured: Vec<(String, TypeConstruct, Expr)> = Vec::new();
+    for cell in env.iter().flatten() {
+        let EnvironmentCell::Variable(name, value) = cell else {
+            continue;
+        };
+        // A variable hides one of the same name further out
+        captured.retain(|(captured_name, _, _)| captured_name != name);
+        let (value_type, literal) = match value {
+            ExpressionValue::Number(n) => (TypeConstruct::Int, Expr::Number(*n)),
+            ExpressionValue::Double(d) => (TypeConstruct::Double, Expr::Double(*d)),
+            ExpressionValue::String(s) => (TypeConstruct::String, Expr::StringLiteral(s.clone())),
+            ExpressionValue::Bool(b) => (TypeConstruct::Bool, Expr::Bool(*b)),
+            _ => continue,
+        };
+        captured.push((name.clone(), value_type, literal));
+    }
+    captured
+        .into_iter()
+        .rev()
+        .filter(|(name, _, _)| {
+            !parameters
+                .iter()
+                .any(|Parameter::Parameter(_, param_name)| param_name == name)
+        })
+        .fold(body, |body, (name, value_type, literal)| {
+            Box::new(Statement::Compound(
+                Box::new(Statement::Declaration(Declaration::Constant(
+                    value_type,
+                    name,
+                    Box::new(literal),
+                ))),
+                body,
+            ))
+        })
+}
+
+//The literal expression of a bound value
+fn value_to_literal(value: ExpressionValue) -> Expr {
+    match value {
+        ExpressionValue::Number(n) => Expr::Number(n),
+        ExpressionValue::Double(d) => Expr::Double(d),
+        ExpressionValue::String(s) => Expr::StringLiteral(s),
+        ExpressionValue::Bool(b) => Expr::Bool(b),
+        other => panic!(
+            "Interpretation error: bind can only fix int, double, string or bool arguments, found {}",
+            other.short_description()
+        ),
+    }
+}
+
 //Evaluate e in Expr
+//Subexpressions are always evaluated left to right: call arguments, array elements, row columns, operands, and the source
+//of a pipe before the arguments of its stages
 pub fn evaluate_expression(
     expression: Expr,
     env: &mut Vec<Vec<EnvironmentCell>>,
@@ -216,19 +857,36 @@ pub fn evaluate_expression(
         Expr::Bool(b) => ExpressionValue::Bool(b),
         //Matches s
         Expr::StringLiteral(s) => ExpressionValue::String(s),
+        //Matches 'c'
+        Expr::Char(c) => ExpressionValue::Char(c),
         //Matches e1 o e2
+        //Left leaning chains like 1 + 1 + 1 + ... are evaluated in a loop, so long chains do not grow the stack
         Expr::Operation(e1, op, e2) => {
-            let left = evaluate_expression(*e1, env);
-            let right = evaluate_expression(*e2, env);
-            evaluate_operation(left, op, right)
+            let mut operations = vec![(op, e2)];
+            let mut base = e1;
+            let base = loop {
+                match *base {
+                    Expr::Operation(left, op, right) => {
+                        operations.push((op, right));
+                        base = left;
+                    }
+                    other => break other,
+                }
+            };
+            let mut left = evaluate_expression(base, env);
+            for (op, right) in operations.into_iter().rev() {
+                let right = evaluate_expression(*right, env);
+                left = evaluate_operation(left, op, right);
+            }
+            left
         }
 
         //Matches x
-        Expr::Identifier(ref name) => match env_get(env, name) {
-            EnvironmentCell::Variable(_, ref value) => value.clone(),
-            EnvironmentCell::Function(..) => {
-                panic!("Interpretation error: Function identifier not allowed as expression")
-            }
+        //The name of a function gives the function, so it can be passed to another function or held in a variable
+        Expr::Identifier(ref name) => match env_get_ref(env, name) {
+            EnvironmentCell::Variable(_, value) => value.clone(),
+            EnvironmentCell::Function(function) => ExpressionValue::Function(function.clone()),
+            EnvironmentCell::Deferred(_) => unreachable!("deferred statements have no name"),
         },
         //Matches x(e)
         Expr::FunctionCall(name, expressions) => {
@@ -239,11 +897,16 @@ pub fn evaluate_expression(
             evaluate_function_call(name, args, env)
         }
         //Matches row(T x = e)
+        //Each cell is converted to the type declared for its column
         Expr::Row(column_assignment) => {
             let mut row: Vec<(String, TableCell)> = Vec::new();
+            let mut structure: HashMap<String, TableCellType> = HashMap::new();
             for assignment in column_assignment {
                 match assignment {
-                    ColumnAssignmentEnum::ColumnAssignment(_, name, expression) => {
+                    ColumnAssignmentEnum::ColumnAssignment(column_type, name, expression) => {
+                        structure.extend(Table::parameters_to_structure(vec![
+                            Parameter::Parameter(column_type, name.clone()),
+                        ]));
                         let evaluated_value = evaluate_expression(*expression, env);
                         match evaluated_value {
                             ExpressionValue::Number(n) => {
@@ -265,33 +928,58 @@ pub fn evaluate_expression(
                     }
                 }
             }
-            ExpressionValue::Row(Row::new(row))
+            match Row::new(row).coerce(&structure) {
+                Ok(row) => ExpressionValue::Row(row),
+                Err(e) => panic!("Interpretation error: {}", e),
+            }
         }
-        //Matches table(T x)
-        Expr::Table(params) => {
+        //Matches table(T x), where a column can be nullable, T x ?, or have a default, T x = e
+        Expr::Table(params, defaults, rows) => {
             let mut structure: HashMap<String, TableCellType> = HashMap::new();
-            for param in params {
-                match param {
-                    Parameter::Parameter(t, name) => match t {
-                        TypeConstruct::Bool => {
-                            structure.insert(name.clone(), TableCellType::Bool);
-                        }
-                        TypeConstruct::Int => {
-                            structure.insert(name.clone(), TableCellType::Int);
-                        }
-                        TypeConstruct::String => {
-                            structure.insert(name.clone(), TableCellType::String);
-                        }
-                        TypeConstruct::Double => {
-                            structure.insert(name.clone(), TableCellType::Double);
-                        }
-                        _ => {
-                            panic!("Interpretation error: Unsupported type in table declaration")
-                        }
-                    },
+            let mut fills: HashMap<String, ColumnFill> = HashMap::new();
+            for Parameter::Parameter(t, name) in params {
+                let t = match t {
+                    TypeConstruct::Nullable(t) => {
+                        fills.insert(name.clone(), ColumnFill::Null);
+                        *t
+                    }
+                    t => t,
+                };
+                let cell_type = match t {
+                    TypeConstruct::Bool => TableCellType::Bool,
+                    TypeConstruct::Int => TableCellType::Int,
+                    TypeConstruct::String => TableCellType::String,
+                    TypeConstruct::Double => TableCellType::Double,
+                    TypeConstruct::Enum(name, values) => TableCellType::Enum(name, values),
+                    _ => panic!("Interpretation error: Unsupported type in table declaration"),
+                };
+                structure.insert(name, cell_type);
+            }
+            //The defaults are evaluated once, when the table is made
+            for (name, default) in defaults {
+                let value = evaluate_expression(*default, env);
+                let cell = table_cell(value)
+                    .and_then(|cell| coerce_cell(cell, &structure[&name]))
+                    .unwrap_or_else(|e| {
+                        panic!(
+                            "Interpretation error: The default of column '{}' {}",
+                            name, e
+                        )
+                    });
+                fills.insert(name, ColumnFill::Default(cell));
+            }
+            let mut table = Table::new(structure).with_fills(fills);
+            //The rows given after the table are added in order, like with table_add_row
+            for row in rows {
+                match evaluate_expression(*row, env) {
+                    ExpressionValue::Row(row) => table.add_row(row),
+                    other => panic!(
+                        "Interpretation error: A table can only start with rows, found {}",
+                        other.short_description()
+                    ),
                 }
             }
-            ExpressionValue::Table(Rc::new(RefCell::new(Table::new(structure))))
+            ExpressionValue::Table(Rc::new(RefCell::new(table)))
         }
         //Matches e1 pipe x(e2)
         Expr::Pipe(expression, function_name, args) => {
@@ -303,65 +991,152 @@ pub fn evaluate_expression(
             let evaluated_value = evaluate_expression(*expr, env);
             match evaluated_value {
                 ExpressionValue::Bool(b) => ExpressionValue::Bool(!b),
-                _ => {
+                other => {
                     panic!(
-                        "Interpretation error: Not operator can only be applied to boolean values"
+                        "Interpretation error: Not operator can only be applied to boolean values, found {}",
+                        other.short_description()
                     )
                 }
             }
         }
+        //Matches -e
+        Expr::Negation(expr) => negate(evaluate_expression(*expr, env)),
         //Matches e.x
+        Expr::Range(_, _, _) => {
+            panic!("Interpretation error: A range can only be iterated by a for loop")
+        }
         Expr::ColumnIndexing(expr, column) => {
             let evaluated_value = evaluate_expression(*expr, env);
             match evaluated_value {
                 ExpressionValue::Row(row) => row.get(&column),
                 ExpressionValue::Table(table) => table.borrow().get_column(&column),
-                _ => {
+                // Like a table column, the column of an array of rows is an array with a value per row
+                ExpressionValue::Array(array) => ExpressionValue::Array(
+                    array
+                        .into_iter()
+                        .map(|element| match element {
+                            ExpressionValue::Row(row) => row.get(&column),
+                            other => panic!(
+                                "Interpretation error: Column indexing on an array requires an array of rows, found {}",
+                                other.short_description()
+                            ),
+                        })
+                        .collect(),
+                ),
+                other => {
                     panic!(
-                        "Interpretation error: Column indexing can only be applied to rows or tables"
+                        "Interpretation error: Column indexing can only be applied to rows, tables or arrays of rows, found {}",
+                        other.short_description()
                     )
                 }
             }
         }
+        //Matches fn (T x) T {S}, which is a function with the functions declared before it, like a declared function,
+        //and the values of the variables it captures
+        Expr::Lambda(parameters, return_type, body) => {
+            let body = capture_values(env, &parameters, body);
+            ExpressionValue::Function(WrenchFunction::new(
+                return_type,
+                String::new(),
+                parameters,
+                body,
+                env_to_closure(env),
+            ))
+        }
         //Matches [e]
         Expr::Array(elements) => {
             let mut evaluated_elements: Vec<ExpressionValue> = Vec::new();
+            let mut value_count = 0;
             for element in elements {
-                evaluated_elements.push(evaluate_expression(*element, env));
+                let element = evaluate_expression(*element, env);
+                value_count += element.value_count();
+                check_value_count(value_count, "An array");
+                evaluated_elements.push(element);
             }
             ExpressionValue::Array(evaluated_elements)
         }
         //Matches e1[e2]
         Expr::Indexing(expr, index) => {
-            let evaluated_value = evaluate_expression(*expr, env);
-            match evaluated_value {
-                ExpressionValue::Array(array) => {
-                    let int_index = match evaluate_expression(*index, env) {
-                        ExpressionValue::Number(n) => n as usize,
-                        _ => {
-                            panic!("Interpretation error: Index must be a integer")
-                        }
-                    };
-                    if int_index < array.len() {
-                        array[int_index].clone()
-                    } else {
-                        panic!("Interpretation error: Index out of bounds");
+            //An indexed variable is borrowed from the environment, so only the element is cloned and not the whole array
+            if let Expr::Identifier(ref name) = *expr {
+                let index = evaluate_expression(*index, env);
+                return match env_get_ref(env, name) {
+                    EnvironmentCell::Variable(_, value) => index_value(value, index, Some(name)),
+                    EnvironmentCell::Function(..) => panic!(
+                        "Interpretation error: Function identifier not allowed as expression"
+                    ),
+                    EnvironmentCell::Deferred(_) => {
+                        unreachable!("deferred statements have no name")
                     }
-                }
-                ExpressionValue::Table(table) => {
-                    let int_index = match evaluate_expression(*index, env) {
-                        ExpressionValue::Number(n) => n as usize,
-                        _ => {
-                            panic!("Interpretation error: Index must be a integer")
-                        }
-                    };
-                    return ExpressionValue::Row(table.borrow().get_row(int_index).clone());
-                }
-                _ => {
-                    panic!("Interpretation error: Indexing can only be applied to arrays")
-                }
+                };
+            }
+            let evaluated_value = evaluate_expression(*expr, env);
+            let index = evaluate_expression(*index, env);
+            index_value(&evaluated_value, index, None)
+        }
+    }
+}
+
+//The element of an array, row of a table or character of a string at the index, the name is the indexed variable if there is one
+fn index_value(
+    value: &ExpressionValue,
+    index: ExpressionValue,
+    name: Option<&str>,
+) -> ExpressionValue {
+    let int_index = match index {
+        ExpressionValue::Number(n) => n,
+        other => {
+            panic!(
+                "Interpretation error: Index must be a integer, found {}",
+                other.short_description()
+            )
+        }
+    };
+    match value {
+        ExpressionValue::Array(array) => {
+            match usize::try_from(int_index).ok().and_then(|i| array.get(i)) {
+                Some(element) => element.clone(),
+                None => index_out_of_bounds(int_index, name, "an array", array.len()),
+            }
+        }
+        ExpressionValue::Table(table) => {
+            let table = table.borrow();
+            let row_count = table.row_count();
+            // A negative index wraps around when cast, so it is checked before the row is fetched
+            match usize::try_from(int_index) {
+                Ok(i) if i < row_count => ExpressionValue::Row(table.get_row(i)),
+                _ => index_out_of_bounds(int_index, name, "a table", row_count),
             }
         }
+        // Strings are indexed by character, see the string builtins
+        ExpressionValue::String(s) => match char_at(s, int_index) {
+            Some(c) => ExpressionValue::String(c.into()),
+            None => panic!(
+                "Interpretation error: Index {} is out of bounds for a string of length {}",
+                int_index,
+                s.chars().count()
+            ),
+        },
+        other => {
+            panic!(
+                "Interpretation error: Indexing can only be applied to arrays, tables and strings, found {}",
+                other.short_description()
+            )
+        }
+    }
+}
+
+//Panics for an index outside an array or table, naming the indexed variable when there is one
+fn index_out_of_bounds(index: i64, name: Option<&str>, kind: &str, length: usize) -> ! {
+    match name {
+        Some(name) => panic!(
+            "Interpretation error: Index {} is out of bounds for '{}', which has length {}",
+            index, name, length
+        ),
+        None => panic!(
+            "Interpretation error: Index {} is out of bounds for {} of length {}",
+            index, kind, length
+        ),
     }
 }
 
@@ -370,39 +1145,24 @@ pub fn evaluate_function_call(
     args: Vec<ExpressionValue>,
     env: &[Vec<EnvironmentCell>],
 ) -> ExpressionValue {
-    match name.as_str() {
-        "print" => wrench_print(args),
-        "import" => wrench_import(args),
-        "table_add_row" => wrench_table_add_row(args),
-        _ => {
-            let function = env_get(env, &name);
-            if let EnvironmentCell::Function(wrench_function) = function {
-                let mut fun_env = wrench_function.get_closure_as_env();
-                for (param, arg) in wrench_function.parameters.iter().zip(args.into_iter()) {
-                    let Parameter::Parameter(_, param_name) = param;
-                    env_add(
-                        &mut fun_env,
-                        EnvironmentCell::Variable(param_name.clone(), arg),
-                    );
-                }
-                env_add(
-                    &mut fun_env,
-                    EnvironmentCell::Function(wrench_function.clone()),
-                );
-
-                let statement_value =
-                    evaluate_statement(*wrench_function.body.clone(), &mut fun_env);
-                match statement_value {
-                    StatementValue::Return(value) => value,
-                    StatementValue::None => ExpressionValue::Null,
-                }
-            } else {
-                panic!(
-                    "Interpretation error: Identifier '{:?}' is not a function",
-                    name
-                );
-            }
+    match lookup_builtin(&name) {
+        // debug_env is the only builtin that sees the environment of the call
+        Some(builtin) if builtin.name == "debug_env" => {
+            builtin.check_arguments(&args);
+            check_output(write!(output(), "{}", format_env(env)));
+            ExpressionValue::Null
         }
+        Some(builtin) => builtin.call(args),
+        None => match env_get_ref(env, &name) {
+            EnvironmentCell::Function(wrench_function)
+            | EnvironmentCell::Variable(_, ExpressionValue::Function(wrench_function)) => {
+                evaluate_custom_function_call(wrench_function, args)
+            }
+            _ => panic!(
+                "Interpretation error: Identifier '{:?}' is not a function",
+                name
+            ),
+        },
     }
 }
 
@@ -410,20 +1170,70 @@ pub fn evaluate_custom_function_call(
     function: &WrenchFunction,
     args: Vec<ExpressionValue>,
 ) -> ExpressionValue {
-    let mut fun_env = function.get_closure_as_env();
-    for (param, arg) in function.parameters.iter().zip(args.into_iter()) {
-        let Parameter::Parameter(_, param_name) = param;
-        env_add(
-            &mut fun_env,
-            EnvironmentCell::Variable(param_name.clone(), arg),
+    let mut fun_env = function.call_env(args);
+    let statement_value = with_deferred(&mut fun_env, |env| {
+        evaluate_statement(*function.body.clone(), env)
+    });
+    match statement_value {
+        StatementValue::Return(value) => value,
+        //The type checker only allows break and continue inside a loop, so none reaches the end of a function
+        StatementValue::None
+        | StatementValue::Value(_)
+        | StatementValue::Break
+        | StatementValue::Continue => ExpressionValue::Null,
+    }
+}
+
+//Wraps the result of an operation on doubles, which must be a finite number
+//Infinity and NaN are not values in wrench, so 0.0 / 0.0 is an error where it happens instead of spreading
+fn finite_double(result: f64, operator: &Operator, left: f64, right: f64) -> ExpressionValue {
+    if !result.is_finite() {
+        panic!(
+            "Interpretation error: '{}' on double {} and double {} does not give a finite number",
+            operator.symbol(),
+            format_double(left),
+            format_double(right)
         );
     }
-    env_add(&mut fun_env, EnvironmentCell::Function(function.clone()));
+    ExpressionValue::Double(result)
+}
 
-    let statement_value = evaluate_statement(*function.body.clone(), &mut fun_env);
-    match statement_value {
-        StatementValue::Return(value) => value,
-        StatementValue::None => ExpressionValue::Null,
+//The result of an operator on two ints, which is None when it does not fit in an int or divides by zero
+fn checked_int(result: Option<i64>, operator: &Operator, left: i64, right: i64) -> ExpressionValue {
+    match result {
+        Some(result) => ExpressionValue::Number(result),
+        None if right == 0 && matches!(operator, Operator::Division | Operator::Modulo) => panic!(
+            "Interpretation error: '{}' on int {} and int 0 divides by zero",
+            operator.symbol(),
+            left
+        ),
+        None => panic!(
+            "Interpretation error: Integer overflow, {} {} {} does not fit in an int",
+            left,
+            operator.symbol(),
+            right
+        ),
+    }
+}
+
+//Unary minus. An int stays an int, a double stays a double, and an array is negated element-wise
+fn negate(value: ExpressionValue) -> ExpressionValue {
+    match value {
+        ExpressionValue::Number(n) => match n.checked_neg() {
+            Some(negated) => ExpressionValue::Number(negated),
+            None => panic!(
+                "Interpretation error: Integer overflow, -({}) does not fit in an int",
+                n
+            ),
+        },
+        ExpressionValue::Double(d) => ExpressionValue::Double(-d),
+        ExpressionValue::Array(items) => {
+            ExpressionValue::Array(items.into_iter().map(negate).collect())
+        }
+        other => panic!(
+            "Interpretation error: Unary minus can only be applied to ints and doubles, found {}",
+            other.short_description()
+        ),
     }
 }
 
@@ -432,24 +1242,73 @@ fn evaluate_operation(
     operator: Operator,
     right: ExpressionValue,
 ) -> ExpressionValue {
+    // Arithmetic with an array, e.g. a table column, is done element-wise and gives an array
+    match (left, right) {
+        (ExpressionValue::Array(l), ExpressionValue::Array(r)) => {
+            if l.len() != r.len() {
+                panic!(
+                    "Interpretation error: cannot apply '{}' element-wise to arrays of length {} and {}",
+                    operator.symbol(),
+                    l.len(),
+                    r.len()
+                );
+            }
+            ExpressionValue::Array(
+                l.into_iter()
+                    .zip(r)
+                    .map(|(l, r)| evaluate_operation(l, operator.clone(), r))
+                    .collect(),
+            )
+        }
+        (ExpressionValue::Array(l), right) => ExpressionValue::Array(
+            l.into_iter()
+                .map(|l| evaluate_operation(l, operator.clone(), right.clone()))
+                .collect(),
+        ),
+        (left, ExpressionValue::Array(r)) => ExpressionValue::Array(
+            r.into_iter()
+                .map(|r| evaluate_operation(left.clone(), operator.clone(), r))
+                .collect(),
+        ),
+        (left, right) => evaluate_scalar_operation(left, operator, right),
+    }
+}
+
+fn evaluate_scalar_operation(
+    left: ExpressionValue,
+    operator: Operator,
+    right: ExpressionValue,
+) -> ExpressionValue {
+    // An int next to a double is widened to a double, whichever side it is on
+    let (left, right) = match (left, right) {
+        (ExpressionValue::Number(l), ExpressionValue::Double(r)) => (
+            ExpressionValue::Double(l as f64),
+            ExpressionValue::Double(r),
+        ),
+        (ExpressionValue::Double(l), ExpressionValue::Number(r)) => (
+            ExpressionValue::Double(l),
+            ExpressionValue::Double(r as f64),
+        ),
+        operands => operands,
+    };
     match operator {
         Operator::Addition => {
             if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right) {
-                return ExpressionValue::Number(l + r);
+                return checked_int(l.checked_add(*r), &operator, *l, *r);
             } else if let (ExpressionValue::String(l), ExpressionValue::String(r)) = (&left, &right)
             {
-                return ExpressionValue::String(format!("{}{}", l, r));
+                return ExpressionValue::String(format!("{}{}", l, r).into());
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
-                return ExpressionValue::Double(l + r);
+                return finite_double(l + r, &operator, *l, *r);
             }
         }
         Operator::Subtraction => {
             if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right) {
-                return ExpressionValue::Number(l - r);
+                return checked_int(l.checked_sub(*r), &operator, *l, *r);
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
-                return ExpressionValue::Double(l - r);
+                return finite_double(l - r, &operator, *l, *r);
             }
         }
         Operator::Or => {
@@ -463,6 +1322,8 @@ fn evaluate_operation(
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
                 return ExpressionValue::Bool(l < r);
+            } else if let (ExpressionValue::Char(l), ExpressionValue::Char(r)) = (&left, &right) {
+                return ExpressionValue::Bool(l < r);
             }
         }
         Operator::LessThanOrEqual => {
@@ -471,26 +1332,31 @@ fn evaluate_operation(
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
                 return ExpressionValue::Bool(l <= r);
+            } else if let (ExpressionValue::Char(l), ExpressionValue::Char(r)) = (&left, &right) {
+                return ExpressionValue::Bool(l <= r);
             }
         }
         Operator::Multiplication => {
             if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right) {
-                return ExpressionValue::Number(l * r);
+                return checked_int(l.checked_mul(*r), &operator, *l, *r);
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
-                return ExpressionValue::Double(l * r);
+                return finite_double(l * r, &operator, *l, *r);
             }
         }
         Operator::Modulo => {
             if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right) {
-                return ExpressionValue::Number(l % r);
+                return checked_int(l.checked_rem(*r), &operator, *l, *r);
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
-                return ExpressionValue::Double(l % r);
+                return finite_double(l % r, &operator, *l, *r);
             }
         }
         Operator::Equals => {
-            if let (ExpressionValue::Bool(l), ExpressionValue::Bool(r)) = (&left, &right) {
+            //Only the value of a nullable column is compared with null, see infer_operation_type
+            if left == ExpressionValue::Null || right == ExpressionValue::Null {
+                return ExpressionValue::Bool(left == right);
+            } else if let (ExpressionValue::Bool(l), ExpressionValue::Bool(r)) = (&left, &right) {
                 return ExpressionValue::Bool(l == r);
             } else if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right)
             {
@@ -498,6 +1364,8 @@ fn evaluate_operation(
             } else if let (ExpressionValue::String(l), ExpressionValue::String(r)) = (&left, &right)
             {
                 return ExpressionValue::Bool(l == r);
+            } else if let (ExpressionValue::Char(l), ExpressionValue::Char(r)) = (&left, &right) {
+                return ExpressionValue::Bool(l == r);
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
                 return ExpressionValue::Bool(l == r);
@@ -505,24 +1373,27 @@ fn evaluate_operation(
         }
         Operator::Division => {
             if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right) {
-                return ExpressionValue::Number(l / r);
+                return checked_int(l.checked_div(*r), &operator, *l, *r);
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
-                return ExpressionValue::Double(l / r);
+                return finite_double(l / r, &operator, *l, *r);
             }
         }
         Operator::Exponent => {
             if let (ExpressionValue::Number(l), ExpressionValue::Number(r)) = (&left, &right) {
-                return ExpressionValue::Number(l.pow(*r as u32));
+                let result = u32::try_from(*r).ok().and_then(|r| l.checked_pow(r));
+                return checked_int(result, &operator, *l, *r);
             } else if let (ExpressionValue::Double(l), ExpressionValue::Double(r)) = (&left, &right)
             {
-                return ExpressionValue::Double(l.powf(*r));
+                return finite_double(l.powf(*r), &operator, *l, *r);
             }
         }
     }
     panic!(
-        "Interpretation error: Unsupported operation for {:?} {:?} {:?}",
-        &left, &operator, &right,
+        "Interpretation error: cannot apply '{}' to {} and {}",
+        operator.symbol(),
+        left.short_description(),
+        right.short_description(),
     );
 }
 
@@ -614,6 +1485,18 @@ mod tests {
         assert_eq!(result, StatementValue::Return(ExpressionValue::Number(1)));
     }
 
+    #[test]
+    fn test_if_without_else_does_nothing_when_false() {
+        let (result, printed) = printed_by(
+            "var int x = 1;
+            if (x > 5) { x = 10; print(\"big\"); }
+            if (x == 1) { print(\"one\"); }
+            print(x);",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "one\n1\n");
+    }
+
     #[test]
     fn test_while_loop() {
         let mut env = env_new();
@@ -627,30 +1510,303 @@ mod tests {
     }
 
     #[test]
-    fn test_equals_operator_number() {
-        let left = ExpressionValue::Number(5);
-        let right = ExpressionValue::Number(5);
-        let operator = Operator::Equals;
-        let result = evaluate_operation(left, operator, right);
-        assert_eq!(result, ExpressionValue::Bool(true));
+    fn test_while_loop_with_increment_and_decrement() {
+        assert_eq!(
+            run_program("var int i = 0; while (i < 10) { i++; } return i;"),
+            ExpressionValue::Number(10)
+        );
+        assert_eq!(
+            run_program(
+                "var int i = 5; var int steps = 0; while (i > 0) { i--; steps++; } return steps;"
+            ),
+            ExpressionValue::Number(5)
+        );
+    }
+
+    //What the program prints when interpreted, and the error it stops with if any
+    fn printed_by(program: &str) -> (Result<i32, String>, String) {
+        let syntax_tree = crate::frontend::main::create_syntax_tree(program).unwrap();
+        crate::backend::library::capture_output(AssertUnwindSafe(|| {
+            interpret(syntax_tree, vec![], false)
+        }))
     }
 
     #[test]
-    fn test_equals_operator_string() {
-        let left = ExpressionValue::String("abc".to_string());
-        let right = ExpressionValue::String("abc".to_string());
-        let operator = Operator::Equals;
-        let result = evaluate_operation(left, operator, right);
-        assert_eq!(result, ExpressionValue::Bool(true));
+    fn test_defer_runs_after_normal_completion_and_early_return() {
+        let (result, printed) = printed_by(
+            "fn int f(int n) {
+                defer { print(\"deferred\"); }
+                if (n > 0) { return n; }
+                print(\"end of f\");
+                return 0;
+            };
+            print(f(1));
+            print(f(0));
+            for (int x in [1, 2]) { defer { print(x * 10); } print(x); }
+            defer { print(\"program ends\"); }
+            print(\"last\");",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(
+            printed,
+            "deferred\n1\nend of f\ndeferred\n0\n1\n10\n2\n20\nlast\nprogram ends\n"
+        );
     }
 
     #[test]
-    fn test_or_operator() {
-        let left = ExpressionValue::Bool(true);
-        let right = ExpressionValue::Bool(false);
-        let operator = Operator::Or;
-        let result = evaluate_operation(left, operator, right);
-        assert_eq!(result, ExpressionValue::Bool(true));
+    fn test_nested_ranges_compute_a_multiplication_table() {
+        let (result, printed) = printed_by(
+            "for (int i in 1..=3) {
+                for (int j in 1..=4) { print(i * j); }
+            }",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "1\n2\n3\n4\n2\n4\n6\n8\n3\n6\n9\n12\n");
+    }
+
+    #[test]
+    fn test_range_end_is_exclusive() {
+        assert_eq!(
+            run_program("var int sum = 0; for (int i in 0..10) { sum = sum + i; } return sum;"),
+            ExpressionValue::Number(45)
+        );
+        assert_eq!(
+            run_program("var int sum = 0; for (int i in 0..=10) { sum = sum + i; } return sum;"),
+            ExpressionValue::Number(55)
+        );
+        // A range ending before it starts is empty
+        assert_eq!(
+            run_program("var int n = 0; for (int i in 5..2) { n++; } return n;"),
+            ExpressionValue::Number(0)
+        );
+        assert_eq!(
+            run_program(
+                "for (int i in 3..1000000000000) { if (i * i > 50) { return i; } } return 0;"
+            ),
+            ExpressionValue::Number(8)
+        );
+    }
+
+    #[test]
+    fn test_bare_return_leaves_a_null_function() {
+        let (result, printed) = printed_by(
+            "fn null print_positive(int x) {
+                if (x < 0) { return; }
+                print(x);
+            };
+            print_positive(-1);
+            print_positive(2);",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "2\n");
+        assert_eq!(
+            run_program("fn null f() { return; }; return f();"),
+            ExpressionValue::Null
+        );
+    }
+
+    #[test]
+    fn test_nested_function_calls_sibling_helpers() {
+        assert_eq!(
+            run_program(
+                "fn int outer(int x) {
+                    fn int twice(int y) { return y * 2; };
+                    fn int quadruple(int y) { return twice(twice(y)); };
+                    return quadruple(x) + twice(1);
+                };
+                return outer(3) + outer(1);"
+            ),
+            ExpressionValue::Number(20)
+        );
+    }
+
+    #[test]
+    fn test_break_stops_a_while_loop() {
+        assert_eq!(
+            run_program("var int i = 0; while (true) { i++; if (i == 5) { break; } } return i;"),
+            ExpressionValue::Number(5)
+        );
+        // A break stops the innermost loop only
+        let (result, printed) = printed_by(
+            "for (int i in 1..=3) {
+                for (int j in 1..=3) { if (j > i) { break; } print(i * 10 + j); }
+            }",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "11\n21\n22\n31\n32\n33\n");
+    }
+
+    #[test]
+    fn test_continue_skips_to_the_next_iteration() {
+        let (result, printed) = printed_by(
+            "for (int x in [1, 2, 3, 4, 5, 6]) { if (x % 2 == 1) { continue; } print(x); }",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "2\n4\n6\n");
+        // A continue goes on with the innermost loop only
+        let (_, printed) = printed_by(
+            "for (int i in 1..=2) {
+                for (int j in 1..=3) { if (j == 2) { continue; } print(i * 10 + j); }
+                print(i);
+            }",
+        );
+        assert_eq!(printed, "11\n13\n1\n21\n23\n2\n");
+        // The scope of the body is left on each continue, so its declarations are made again in the next iteration
+        assert_eq!(
+            run_program(
+                "var int i = 0; var int sum = 0;
+                while (i < 10) { var int next = i + 1; i = next; if (next > 3) { continue; } sum = sum + next; }
+                return sum;"
+            ),
+            ExpressionValue::Number(6)
+        );
+    }
+
+    #[test]
+    fn test_return_in_a_loop_wins_over_continue() {
+        assert_eq!(
+            run_program(
+                "fn int first_even(int[] xs) {
+                    for (int x in xs) { if (x % 2 == 1) { continue; } return x; }
+                    return -1;
+                };
+                return first_even([3, 5, 8, 10]);"
+            ),
+            ExpressionValue::Number(8)
+        );
+    }
+
+    #[test]
+    fn test_break_stops_a_for_loop_over_a_table_at_the_matching_row() {
+        let (result, printed) = printed_by(
+            "var table(int id, string name) t = table(int id, string name);
+            table_add_row(t, row(int id = 1, string name = \"ada\"));
+            table_add_row(t, row(int id = 2, string name = \"bob\"));
+            table_add_row(t, row(int id = 3, string name = \"cy\"));
+            var int visited = 0;
+            for (row(int id, string name) r in t) {
+                defer { visited++; }
+                if (r.name == \"bob\") { print(r.id); break; }
+            }
+            print(visited);",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "2\n2\n");
+    }
+
+    #[test]
+    fn test_lambda_assigned_to_a_variable_and_called() {
+        assert_eq!(
+            run_program("var fn int(int) inc = fn (int x) int { return x + 1; }; return inc(41);"),
+            ExpressionValue::Number(42)
+        );
+        // A lambda sees the functions declared before it, and can be given to a function like a declared one
+        assert_eq!(
+            run_program(
+                "fn int twice(int x) { return x * 2; };
+                fn int apply(fn int(int) f, int x) { return f(x); };
+                return apply(fn (int x) int { return twice(x) + 1; }, apply(twice, 10));"
+            ),
+            ExpressionValue::Number(41)
+        );
+        let (result, printed) = printed_by("print(fn (int x, string s) bool { return x > 0; });");
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "fn (int x, string s) bool\n");
+    }
+
+    #[test]
+    fn test_defers_run_in_reverse_order() {
+        let (_, printed) =
+            printed_by("fn null f() { defer { print(1); } defer { print(2); } print(0); }; f();");
+        assert_eq!(printed, "0\n2\n1\n");
+    }
+
+    #[test]
+    fn test_defer_runs_after_a_runtime_error() {
+        let (result, printed) = printed_by(
+            "fn int f() { defer { print(\"cleanup\"); } var int[] a = [1]; return a[5]; }; f();",
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index 5 is out of bounds for 'a', which has length 1"
+        );
+        assert_eq!(printed, "cleanup\n");
+    }
+
+    #[test]
+    fn test_error_in_a_defer_does_not_mask_the_original_error() {
+        let (result, printed) = printed_by(
+            "fn int f() {
+                defer { print(\"cleanup\"); }
+                defer { var double zero = 0.0; print(1.0 / zero); }
+                var int[] a = [1];
+                return a[5];
+            };
+            f();",
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index 5 is out of bounds for 'a', which has length 1"
+        );
+        assert_eq!(printed, "cleanup\n");
+        // Without an error of its own, the scope stops with the error of the defer
+        let (result, printed) = printed_by(
+            "fn null f() { defer { print(\"cleanup\"); } defer { var double zero = 0.0; print(1.0 / zero); } }; f();",
+        );
+        assert!(
+            result
+                .unwrap_err()
+                .contains("does not give a finite number")
+        );
+        assert_eq!(printed, "cleanup\n");
+    }
+
+    #[test]
+    fn test_char_declaration_and_comparison() {
+        assert_eq!(
+            run_program("var char c = 'b'; if (c > 'a' and c <= 'b') { return c; } return 'x';"),
+            ExpressionValue::Char('b')
+        );
+        assert_eq!(
+            run_program("var char q = '\\''; return q == '\\'' and q != '\\n';"),
+            ExpressionValue::Bool(true)
+        );
+        assert_eq!(
+            ExpressionValue::Array(vec![
+                ExpressionValue::Char('a'),
+                ExpressionValue::Char('\'')
+            ])
+            .display_string(),
+            "['a', '\\'']"
+        );
+        assert_eq!(ExpressionValue::Char('\n').display_string(), "\n");
+    }
+
+    #[test]
+    fn test_equals_operator_number() {
+        let left = ExpressionValue::Number(5);
+        let right = ExpressionValue::Number(5);
+        let operator = Operator::Equals;
+        let result = evaluate_operation(left, operator, right);
+        assert_eq!(result, ExpressionValue::Bool(true));
+    }
+
+    #[test]
+    fn test_equals_operator_string() {
+        let left = ExpressionValue::String("abc".into());
+        let right = ExpressionValue::String("abc".into());
+        let operator = Operator::Equals;
+        let result = evaluate_operation(left, operator, right);
+        assert_eq!(result, ExpressionValue::Bool(true));
+    }
+
+    #[test]
+    fn test_or_operator() {
+        let left = ExpressionValue::Bool(true);
+        let right = ExpressionValue::Bool(false);
+        let operator = Operator::Or;
+        let result = evaluate_operation(left, operator, right);
+        assert_eq!(result, ExpressionValue::Bool(true));
     }
 
     #[test]
@@ -662,6 +1818,54 @@ mod tests {
         assert_eq!(result, ExpressionValue::Bool(true));
     }
 
+    #[test]
+    fn test_failed_input_leaves_earlier_declarations_usable() {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        let input = |source: &str| crate::frontend::main::create_syntax_tree(source).unwrap();
+        evaluate_input(input("var int x = 1;"), &mut env).unwrap();
+        let error = evaluate_input(
+            input("var int y = 2; var int[] xs = [1]; var int z = xs[3];"),
+            &mut env,
+        )
+        .unwrap_err();
+        assert!(error.contains("out of bounds"), "{}", error);
+        // y was declared before the error, and is gone again, so it can be declared by the next input
+        assert_eq!(
+            evaluate_input(input("var int y = 5; return x + y;"), &mut env),
+            Ok(StatementValue::Return(ExpressionValue::Number(6)))
+        );
+    }
+
+    #[test]
+    fn test_rows_added_by_a_failed_input_stay_in_the_table() {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        let input = |source: &str| crate::frontend::main::create_syntax_tree(source).unwrap();
+        evaluate_input(input("var table(int id) t = table(int id);"), &mut env).unwrap();
+        evaluate_input(
+            input("table_add_row(t, row(int id = 1)); var int[] xs = [1]; xs[3];"),
+            &mut env,
+        )
+        .unwrap_err();
+        // Tables are shared by the snapshot rather than copied, see env_snapshot
+        assert_eq!(
+            evaluate_input(input("return t;"), &mut env).map(|value| match value {
+                StatementValue::Return(t) => table_length(t),
+                _ => 0,
+            }),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_greater_than_operators() {
+        assert_eq!(run_program("return 5 > 3;"), ExpressionValue::Bool(true));
+        assert_eq!(run_program("return 3 >= 3;"), ExpressionValue::Bool(true));
+        assert_eq!(run_program("return 3 > 3;"), ExpressionValue::Bool(false));
+        assert_eq!(run_program("return 2 >= 3;"), ExpressionValue::Bool(false));
+    }
+
     #[test]
     fn test_addition_double() {
         let left = ExpressionValue::Double(1.5);
@@ -673,11 +1877,11 @@ mod tests {
 
     #[test]
     fn test_string_concatenation() {
-        let left = ExpressionValue::String("foo".to_string());
-        let right = ExpressionValue::String("bar".to_string());
+        let left = ExpressionValue::String("foo".into());
+        let right = ExpressionValue::String("bar".into());
         let operator = Operator::Addition;
         let result = evaluate_operation(left, operator, right);
-        assert_eq!(result, ExpressionValue::String("foobar".to_string()));
+        assert_eq!(result, ExpressionValue::String("foobar".into()));
     }
 
     #[test]
@@ -704,6 +1908,80 @@ mod tests {
         assert_eq!(result, ExpressionValue::Number(20));
     }
 
+    #[test]
+    fn test_nested_array_declared_with_its_type() {
+        assert_eq!(
+            run_program(
+                "var int[][] grid = [[], [1, 2], [3]];
+                var int[] empty = [];
+                grid = [[4], empty, [5, 6]];
+                return grid[2][1] + grid[0][0];"
+            ),
+            ExpressionValue::Number(10)
+        );
+    }
+
+    #[test]
+    fn test_assignment_to_array_elements() {
+        assert_eq!(
+            run_program(
+                "var int[] squares = [0, 0, 0, 0, 0];
+                var int i = 0;
+                while (i < 5) { squares[i] = i * i; i++; }
+                return squares[4] + squares[2];"
+            ),
+            ExpressionValue::Number(20)
+        );
+        // Arrays are values, so the copy in another variable is not changed
+        assert_eq!(
+            run_program(
+                "var int[][] grid = [[1, 2], [3]]; var int[] first = grid[0]; first[1] = 7; grid[1] = first; return grid[0][1] * 10 + grid[1][1];"
+            ),
+            ExpressionValue::Number(27)
+        );
+        let (result, _) = printed_by("var int[] xs = [1, 2, 3]; xs[3] = 4;");
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index 3 is out of bounds for 'xs', which has length 3"
+        );
+        let (result, _) = printed_by("var int[] xs = [1, 2, 3]; var int i = -1; xs[i] = 4;");
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index -1 is out of bounds for 'xs', which has length 3"
+        );
+    }
+
+    #[test]
+    fn test_out_of_bounds_reads_name_the_index_and_length() {
+        let (result, _) = printed_by("var int[] xs = [1, 2, 3]; return xs[3];");
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index 3 is out of bounds for 'xs', which has length 3"
+        );
+        let (result, _) = printed_by("var int[] xs = [1, 2, 3]; var int i = -1; return xs[i];");
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index -1 is out of bounds for 'xs', which has length 3"
+        );
+        let (result, _) = printed_by("return [1, 2][2];");
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index 2 is out of bounds for an array of length 2"
+        );
+        let (result, _) = printed_by(
+            "var table(int id) t = table(int id); table_add_row(t, row(int id = 1)); var int i = -1; print(t[i]);",
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index -1 is out of bounds for 't', which has length 1"
+        );
+        let (result, _) = printed_by("var table(int id) t = table(int id); print(t[0]);");
+        assert_eq!(
+            result.unwrap_err(),
+            "Interpretation error: Index 0 is out of bounds for 't', which has length 0"
+        );
+    }
+
     #[test]
     fn test_variable_assignment_and_lookup() {
         let mut env = env_new();
@@ -739,4 +2017,1056 @@ mod tests {
         let result = evaluate_expression(call_expr, &mut env);
         assert_eq!(result, ExpressionValue::Number(99));
     }
+
+    // Runs a program and returns the value of its top level return statement
+    fn run_program(input: &str) -> ExpressionValue {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        match evaluate_statement(
+            crate::frontend::main::create_syntax_tree(input).unwrap(),
+            &mut env,
+        ) {
+            StatementValue::Return(value) => value,
+            _ => ExpressionValue::Null,
+        }
+    }
+
+    fn table_length(value: ExpressionValue) -> usize {
+        match value {
+            ExpressionValue::Table(t) => t.borrow().iter().count(),
+            _ => self::panic!("Expected a table"),
+        }
+    }
+
+    #[test]
+    fn test_raise_for_everyone_writes_rows_back() {
+        let result = run_program(
+            "
+            var table(int id, double salary) staff = table(int id, double salary);
+            table_add_row(staff, row(int id = 1, double salary = 1000.0));
+            table_add_row(staff, row(int id = 2, double salary = 2000));
+            for (int i, row(int id, double salary) r in staff) {
+                r.salary = r.salary * 1.1;
+                table_update_row(staff, i, r);
+            }
+            return [staff[0].salary, staff[1].salary];
+            ",
+        );
+        let ExpressionValue::Array(salaries) = result else {
+            self::panic!("Expected an array");
+        };
+        let salaries: Vec<f64> = salaries
+            .into_iter()
+            .map(|salary| match salary {
+                ExpressionValue::Double(d) => d,
+                other => self::panic!("Expected a double, found {:?}", other),
+            })
+            .collect();
+        assert_eq!(salaries.len(), 2);
+        assert!((salaries[0] - 1100.0).abs() < 1e-9);
+        assert!((salaries[1] - 2200.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_not_equals_in_if_condition() {
+        let program = |x: &str| {
+            format!(
+                "var int x = {}; var string s = \"a\"; if (x != 5) {{ return 1; }} if (s != \"a\") {{ return 2; }} return 3;",
+                x
+            )
+        };
+        assert_eq!(run_program(&program("4")), ExpressionValue::Number(1));
+        assert_eq!(run_program(&program("5")), ExpressionValue::Number(3));
+        assert_eq!(
+            run_program("if (2.5 != 2.5) { return 1; } if (true != false) { return 2; } return 3;"),
+            ExpressionValue::Number(2)
+        );
+    }
+
+    #[test]
+    fn test_indexing_a_large_array_does_not_copy_it() {
+        // Copying the array on each access would take some 10^10 element copies here
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        env_add(
+            &mut env,
+            EnvironmentCell::Variable(
+                "xs".to_string(),
+                ExpressionValue::Array((0..100_000).map(ExpressionValue::Number).collect()),
+            ),
+        );
+        let program = crate::frontend::main::create_syntax_tree(
+            "var int sum = 0;
+            var int i = 0;
+            while (i < 100000) { sum = sum + xs[i] % 7; i = i + 1; }
+            return sum;",
+        )
+        .unwrap();
+        let started = std::time::Instant::now();
+        let result = evaluate_statement(program, &mut env);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(matches!(
+            result,
+            StatementValue::Return(ExpressionValue::Number(299995))
+        ));
+    }
+
+    #[test]
+    fn test_printing_a_huge_array_is_cut_off_quickly() {
+        let values = ExpressionValue::Array((0..10_000_000).map(ExpressionValue::Number).collect());
+        let started = std::time::Instant::now();
+        let printed = values.display_string();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(printed.starts_with("[0, 1, 2, "));
+        assert!(
+            printed.ends_with(&format!(
+                "99999, … output truncated at {} elements (use print_full / raise the limit with --max-print-values)",
+                DEFAULT_MAX_PRINTED_VALUES
+            )),
+            "{}",
+            &printed[printed.len() - 200..]
+        );
+        set_max_printed_values(3);
+        let nested = ExpressionValue::Array(vec![numbers(&[1, 2]), numbers(&[3, 4])]);
+        let printed = nested.display_string();
+        set_max_printed_values(DEFAULT_MAX_PRINTED_VALUES);
+        assert!(
+            printed.starts_with("[[1, 2], [3, … output truncated at 3 elements"),
+            "{}",
+            printed
+        );
+        assert_eq!(nested.full_display_string(), "[[1, 2], [3, 4]]");
+    }
+
+    #[test]
+    fn test_runaway_array_and_table_hit_the_value_limit() {
+        let limited = |program: &str| {
+            set_max_values(1000);
+            let result = std::panic::catch_unwind(|| run_program(program));
+            set_max_values(DEFAULT_MAX_VALUES);
+            crate::backend::pipes::panic_message(result.expect_err("the limit is reached"))
+        };
+        assert_eq!(
+            limited("var int[] xs = [1]; while (true) { xs = [xs, xs]; }"),
+            "Interpretation error: An array would hold 1024 values, more than the limit of 1000 (raise it with --max-values)"
+        );
+        assert_eq!(
+            limited(
+                "var table(int a, int b) t = table(int a, int b); while (true) { table_add_row(t, row(int a = 1, int b = 2)); }"
+            ),
+            "Interpretation error: A table would hold 1002 values, more than the limit of 1000 (raise it with --max-values)"
+        );
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        let cases = [
+            ("return -5;", ExpressionValue::Number(-5)),
+            ("return -2.5;", ExpressionValue::Double(-2.5)),
+            ("var int a = 3; return -a;", ExpressionValue::Number(-3)),
+            ("var int a = 3; return --a;", ExpressionValue::Number(3)),
+            (
+                "var int a = 3; return -(a - 5);",
+                ExpressionValue::Number(2),
+            ),
+            (
+                "var int a = 3; return -(a - 0.5);",
+                ExpressionValue::Double(-2.5),
+            ),
+            ("var int a = 3; return 1 - -a;", ExpressionValue::Number(4)),
+            (
+                "var int a = 3; return -a ** 2;",
+                ExpressionValue::Number(-9),
+            ),
+            (
+                "var row(double score) r = row(double score = 1.5); return -r.score;",
+                ExpressionValue::Double(-1.5),
+            ),
+            (
+                "fn int three() { return 3; }; return -three() * 2;",
+                ExpressionValue::Number(-6),
+            ),
+            ("return -[1, 2];", numbers(&[-1, -2])),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(run_program(program), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_unary_minus_overflow_is_an_error() {
+        let result = std::panic::catch_unwind(|| {
+            run_program("var int min = 0 - 9223372036854775807 - 1; return -min;")
+        });
+        assert_eq!(
+            crate::backend::pipes::panic_message(result.expect_err("-min does not fit")),
+            "Interpretation error: Integer overflow, -(-9223372036854775808) does not fit in an int"
+        );
+    }
+
+    #[test]
+    fn test_int_arithmetic_near_the_limits() {
+        let max = "var int max = 9223372036854775807;";
+        assert_eq!(
+            run_program(&format!("{} return max - 1 + 1;", max)),
+            ExpressionValue::Number(i64::MAX)
+        );
+        for (operation, message) in [
+            (
+                "max + 1",
+                "Integer overflow, 9223372036854775807 + 1 does not fit in an int",
+            ),
+            (
+                "0 - max - 2",
+                "Integer overflow, -9223372036854775807 - 2 does not fit in an int",
+            ),
+            (
+                "max * 2",
+                "Integer overflow, 9223372036854775807 * 2 does not fit in an int",
+            ),
+            (
+                "2 ** 63",
+                "Integer overflow, 2 ** 63 does not fit in an int",
+            ),
+            (
+                "max % (max - max)",
+                "'%' on int 9223372036854775807 and int 0 divides by zero",
+            ),
+        ] {
+            let program = format!("{} return {};", max, operation);
+            let result = std::panic::catch_unwind(|| run_program(&program));
+            assert_eq!(
+                crate::backend::pipes::panic_message(result.expect_err(operation)),
+                format!("Interpretation error: {}", message)
+            );
+        }
+    }
+
+    #[test]
+    fn test_row_column_assigned_in_a_function() {
+        let result = run_program(
+            "
+            fn row(string name, int age) birthday(row(string name, int age) r) {
+                r.age = r.age + 1;
+                return r;
+            };
+            var row(string name, int age) ada = row(string name = \"ada\", int age = 36);
+            var row(string name, int age) older = birthday(ada);
+            return [older.age, ada.age];
+            ",
+        );
+        assert_eq!(
+            result,
+            ExpressionValue::Array(vec![
+                ExpressionValue::Number(37),
+                ExpressionValue::Number(36)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_loop_row_is_a_copy() {
+        let result = run_program(
+            "
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 1));
+            var int seen = 0;
+            for (int i, row(int id) r in t) {
+                r.id = 5;
+                seen = seen + r.id + i;
+            }
+            return seen * 10 + t[0].id;
+            ",
+        );
+        assert_eq!(result, ExpressionValue::Number(51));
+    }
+
+    #[test]
+    fn test_table_mutation_in_function_is_visible_to_caller() {
+        let result = run_program(
+            "
+            fn null add(table(int id) t) {
+                table_add_row(t, row(int id = 1));
+            };
+            var table(int id) t = table(int id);
+            add(t);
+            return t;
+            ",
+        );
+        assert_eq!(table_length(result), 1);
+    }
+
+    #[test]
+    fn test_table_mutation_in_nested_function_is_visible_to_caller() {
+        let result = run_program(
+            "
+            fn null inner(table(int id) t) {
+                table_add_row(t, row(int id = 1));
+            };
+            fn null outer(table(int id) t) {
+                inner(t);
+                inner(t);
+            };
+            var table(int id) t = table(int id);
+            outer(t);
+            return t;
+            ",
+        );
+        assert_eq!(table_length(result), 2);
+    }
+
+    #[test]
+    fn test_table_clone_is_not_mutated() {
+        let result = run_program(
+            "
+            fn null add(table(int id) t) {
+                table_add_row(t, row(int id = 1));
+            };
+            var table(int id) t = table(int id);
+            add(table_clone(t));
+            return t;
+            ",
+        );
+        assert_eq!(table_length(result), 0);
+    }
+
+    #[test]
+    fn test_column_access_on_indexed_array_of_rows() {
+        let result = run_program(
+            "
+            var row(int id, string name)[] rows = [row(int id = 1, string name = \"a\"), row(int id = 2, string name = \"b\")];
+            return rows[1].name;
+            ",
+        );
+        assert_eq!(result, ExpressionValue::String("b".into()));
+    }
+
+    #[test]
+    fn test_column_access_maps_over_array_of_rows() {
+        let result = run_program(
+            "
+            var row(int id, string name)[] rows = [row(int id = 1, string name = \"a\"), row(int id = 2, string name = \"b\")];
+            return rows.id;
+            ",
+        );
+        assert_eq!(
+            result,
+            ExpressionValue::Array(vec![ExpressionValue::Number(1), ExpressionValue::Number(2)])
+        );
+    }
+
+    #[test]
+    fn test_unknown_column_at_runtime_matches_type_checker() {
+        let mut csv = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut csv, b"id,name\n1,Ann\n").unwrap();
+        let program = format!(
+            "var table(int id, string name) t = import({:?}, table(int id, string name));
+            return t[0].nmae;",
+            csv.path().to_str().unwrap()
+        );
+        let expected =
+            "Column 'nmae' not found, the columns are id: int, name: string. Did you mean 'name'?";
+        assert_eq!(
+            crate::frontend::main::check(&program).unwrap_err(),
+            expected
+        );
+        // Run without the type checker, the imported row reports the column the same way
+        let error = std::panic::catch_unwind(|| run_program(&program)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<String>().unwrap(),
+            &format!("Interpretation error: {}", expected)
+        );
+    }
+
+    // Runs a checked program, and returns what it echoes
+    fn echoed(program: &str, echo_last_value: bool) -> String {
+        let mut out = Vec::new();
+        interpret_to(
+            crate::frontend::main::check(program).unwrap(),
+            vec![],
+            echo_last_value,
+            &mut out,
+        );
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_echo_last_value() {
+        assert_eq!(echoed("3 * 14;", true), "42\n");
+        assert_eq!(echoed("var int x = 2; x * 21; x;", true), "2\n");
+        // Only the last statement is echoed, and only when it is an expression
+        assert_eq!(echoed("3 * 14; var int x = 1;", true), "");
+    }
+
+    #[test]
+    fn test_echo_renders_table() {
+        let program = "var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 1));
+            t;";
+        let mut table = Table::new(HashMap::from([("id".to_string(), TableCellType::Int)]));
+        table.add_row(Row::new(vec![("id".to_string(), TableCell::Int(1))]));
+        assert_eq!(
+            echoed(program, true),
+            format!("{}\n", table.render(Some(PRINT_ROW_LIMIT)))
+        );
+    }
+
+    #[test]
+    fn test_file_mode_and_print_are_not_echoed() {
+        assert_eq!(echoed("3 * 14;", false), "");
+        assert_eq!(echoed("print(42);", true), "");
+        assert_eq!(
+            echoed(
+                "var table(int id) t = table(int id);
+                t pipe print();",
+                true
+            ),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_string_indexing_is_by_character() {
+        let program = "var string s = \"æble 🎉\";
+            return s[0] + s[5] + substring(s, 1, 4);";
+        assert_eq!(
+            run_program(program),
+            ExpressionValue::String("æ🎉ble".into())
+        );
+    }
+
+    #[test]
+    fn test_string_literal_indexing() {
+        assert_eq!(
+            run_program("return \"hej\"[1];"),
+            ExpressionValue::String("e".into())
+        );
+        assert_eq!(
+            run_program("return \"blåbærgrød\"[4] + \"blåbærgrød\"[8];"),
+            ExpressionValue::String("æø".into())
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: Index 1 is out of bounds for a string of length 1"
+    )]
+    fn test_string_index_out_of_bounds() {
+        run_program("var string s = \"ø\"; return s[1];");
+    }
+
+    #[test]
+    fn test_long_operation_chain_evaluates() {
+        let mut expr = Expr::Number(1);
+        for _ in 0..50_000 {
+            expr = Expr::Operation(
+                Box::new(expr),
+                Operator::Addition,
+                Box::new(Expr::Number(1)),
+            );
+        }
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        assert_eq!(
+            evaluate_expression(expr, &mut env),
+            ExpressionValue::Number(50_001)
+        );
+    }
+
+    #[test]
+    fn test_long_sum_from_source_evaluates() {
+        let source = format!("return {};", vec!["1"; 10_000].join(" + "));
+        assert_eq!(run_program(&source), ExpressionValue::Number(10_000));
+    }
+
+    #[test]
+    fn test_long_program_evaluates() {
+        let source = "var int x = 0;\n".to_string() + &"x = x + 1;\n".repeat(20_000) + "return x;";
+        assert_eq!(run_program(&source), ExpressionValue::Number(20_000));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: cannot apply '+' to table (1 columns, 2 rows) and int 5"
+    )]
+    fn test_operation_error_describes_operands() {
+        run_program(
+            "
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 1));
+            table_add_row(t, row(int id = 2));
+            return t + 5;
+            ",
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: Condition is not a boolean, found string \"yes\""
+    )]
+    fn test_if_condition_error_describes_value() {
+        run_program("if (\"yes\") { return 1; } else { return 2; }");
+    }
+
+    #[test]
+    fn test_short_description_truncates_strings() {
+        let value = ExpressionValue::String("a".repeat(30).into());
+        assert_eq!(
+            value.short_description(),
+            format!("string \"{}…\"", "a".repeat(20))
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: '/' on double 0.0 and double 0.0 does not give a finite number"
+    )]
+    fn test_zero_divided_by_zero_is_an_error() {
+        run_program("var double z = 0.0; return z / z;");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: '/' on double 1.0 and double 0.0 does not give a finite number"
+    )]
+    fn test_division_by_zero_double_is_an_error() {
+        run_program("var double z = 0.0; return 1.0 / z;");
+    }
+
+    #[test]
+    #[should_panic(expected = "does not give a finite number")]
+    fn test_comparison_never_sees_nan() {
+        // The division fails before the comparison could silently be false
+        run_program("var double z = 0.0; if (z / z < 1.0) { return 1; } else { return 2; }");
+    }
+
+    #[test]
+    fn test_finite_double_operations() {
+        assert_eq!(
+            run_program("var double z = 0.5; return 1.0 / z;"),
+            ExpressionValue::Double(2.0)
+        );
+    }
+
+    // Calls a builtin directly, and returns the message it fails with
+    fn builtin_error(name: &str, args: Vec<ExpressionValue>) -> String {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            evaluate_function_call(name.to_string(), args, &[]);
+        }));
+        let error = result.expect_err(&format!("Expected {} to fail", name));
+        error.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+
+    fn empty_table() -> ExpressionValue {
+        ExpressionValue::Table(Rc::new(RefCell::new(Table::new(HashMap::new()))))
+    }
+
+    #[test]
+    fn test_builtins_with_too_few_arguments() {
+        let cases = [
+            ("print", "print expects 1 argument (value), got 0"),
+            ("print_full", "print_full expects 1 argument (value), got 0"),
+            (
+                "import",
+                "import expects 2 to 3 arguments (file name, table, options), got 0",
+            ),
+            (
+                "async_import",
+                "async_import expects 2 to 3 arguments (file name, table, options), got 0",
+            ),
+            (
+                "rows_from_range",
+                "rows_from_range expects 2 arguments (count, maker), got 0",
+            ),
+            ("describe", "describe expects 1 argument (table), got 0"),
+            (
+                "table_add_row",
+                "table_add_row expects 2 arguments (table, row), got 0",
+            ),
+            (
+                "table_clone",
+                "table_clone expects 1 argument (table), got 0",
+            ),
+        ];
+        for (name, message) in cases {
+            assert_eq!(
+                builtin_error(name, vec![]),
+                format!("Interpretation error: {}", message)
+            );
+        }
+        assert_eq!(
+            builtin_error("import", vec![ExpressionValue::String("f.csv".into())]),
+            "Interpretation error: import expects 2 to 3 arguments (file name, table, options), got 1"
+        );
+    }
+
+    #[test]
+    fn test_builtins_with_too_many_arguments() {
+        let table = empty_table();
+        let row = ExpressionValue::Row(Row::new(vec![]));
+        let file = ExpressionValue::String("f.csv".into());
+        let cases = [
+            (
+                "print",
+                vec![ExpressionValue::Number(1), ExpressionValue::Number(2)],
+            ),
+            (
+                "print_full",
+                vec![ExpressionValue::Number(1), ExpressionValue::Number(2)],
+            ),
+            (
+                "import",
+                vec![file.clone(), table.clone(), row.clone(), row.clone()],
+            ),
+            (
+                "async_import",
+                vec![file.clone(), table.clone(), row.clone(), row.clone()],
+            ),
+            (
+                "rows_from_range",
+                vec![ExpressionValue::Number(1), file.clone(), file.clone()],
+            ),
+            ("describe", vec![table.clone(), table.clone()]),
+            (
+                "table_add_row",
+                vec![table.clone(), row.clone(), row.clone()],
+            ),
+            ("table_clone", vec![table.clone(), table.clone()]),
+        ];
+        for (name, args) in cases {
+            let count = args.len();
+            let message = builtin_error(name, args);
+            assert!(
+                message.starts_with(&format!("Interpretation error: {} expects", name))
+                    && message.ends_with(&format!("got {}", count)),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_builtins_with_wrongly_typed_arguments() {
+        let table = empty_table();
+        let five = ExpressionValue::Number(5);
+        let cases = [
+            (
+                "import",
+                vec![five.clone(), table.clone()],
+                "import expects file name to be a string, found int 5",
+            ),
+            (
+                "import",
+                vec![ExpressionValue::String("f.csv".into()), five.clone()],
+                "import expects table to be a table, found int 5",
+            ),
+            (
+                "import",
+                vec![
+                    ExpressionValue::String("f.csv".into()),
+                    table.clone(),
+                    five.clone(),
+                ],
+                "import expects options to be a row, found int 5",
+            ),
+            (
+                "async_import",
+                vec![ExpressionValue::Bool(true), table.clone()],
+                "async_import expects file name to be a string, found bool true",
+            ),
+            (
+                "rows_from_range",
+                vec![
+                    ExpressionValue::String("10".into()),
+                    ExpressionValue::String("make".into()),
+                ],
+                "rows_from_range expects count to be an int, found string \"10\"",
+            ),
+            (
+                "describe",
+                vec![five.clone()],
+                "describe expects table to be a table, found int 5",
+            ),
+            (
+                "table_add_row",
+                vec![table.clone(), five.clone()],
+                "table_add_row expects row to be a row, found int 5",
+            ),
+            (
+                "table_clone",
+                vec![five.clone()],
+                "table_clone expects table to be a table, found int 5",
+            ),
+        ];
+        for (name, args, message) in cases {
+            assert_eq!(
+                builtin_error(name, args),
+                format!("Interpretation error: {}", message)
+            );
+        }
+    }
+
+    thread_local! {
+        // The labels passed to test_order, in the order the calls were evaluated
+        static ORDER: RefCell<Vec<i64>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // test_order(n) records n and returns it, so a program can observe the order its expressions are evaluated in
+    fn wrench_test_order(args: Vec<ExpressionValue>) -> ExpressionValue {
+        if let ExpressionValue::Number(n) = args[0] {
+            ORDER.with(|order| order.borrow_mut().push(n));
+        }
+        args[0].clone()
+    }
+
+    // Makes test_order callable from the programs run on this thread, and forgets the labels recorded so far
+    fn reset_order() {
+        let mut builtins = crate::backend::library::Builtins::default();
+        builtins.register(crate::backend::library::Builtin::new(
+            "test_order",
+            TypeConstruct::Int,
+            vec![("label", TypeConstruct::Int)],
+            wrench_test_order,
+        ));
+        crate::backend::library::set_builtins(std::sync::Arc::new(builtins));
+        ORDER.with(|order| order.borrow_mut().clear());
+    }
+
+    // Runs a program, and returns the labels recorded by test_order while it ran
+    fn evaluation_order(input: &str) -> Vec<i64> {
+        reset_order();
+        run_program(input);
+        ORDER.with(|order| order.take())
+    }
+
+    #[test]
+    fn test_call_arguments_evaluated_left_to_right() {
+        let program = "fn int sum(int a, int b, int c) { return a + b + c; };
+            return sum(test_order(1), test_order(2), test_order(3));";
+        assert_eq!(evaluation_order(program), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_array_elements_evaluated_left_to_right() {
+        assert_eq!(
+            evaluation_order("return [test_order(1), test_order(2), test_order(3)];"),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_row_columns_evaluated_left_to_right() {
+        let program =
+            "return row(int b = test_order(1), int a = test_order(2), int c = test_order(3));";
+        assert_eq!(evaluation_order(program), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_operands_evaluated_left_to_right() {
+        // Precedence and right associativity decide how operands are combined, not the order they are evaluated in
+        let program = "return test_order(1) + test_order(2) * test_order(3) - test_order(4);";
+        assert_eq!(evaluation_order(program), vec![1, 2, 3, 4]);
+        let program = "return test_order(1) ** test_order(2) ** test_order(3);";
+        assert_eq!(evaluation_order(program), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_pipe_evaluated_left_to_right() {
+        // The source is evaluated before the arguments of the stages, which are evaluated in the order of the stages
+        let program = "fn table(int id) source(int n) {
+                var table(int id) t = table(int id);
+                table_add_row(t, row(int id = n));
+                return t;
+            };
+            fn bool keep(row(int id) r, int n) { return true; };
+            return source(test_order(1)) pipe keep(test_order(2)) pipe keep(test_order(3));";
+        assert_eq!(evaluation_order(program), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_table_with_rows_as_pipe_source() {
+        let (result, printed) = printed_by(
+            "fn bool adult(row(int id, int age) r) { return r.age >= 18; }
+            table(int id, int age) [
+                row(int id = 1, int age = 30),
+                row(int id = 2, int age = 12),
+                row(int id = 3, int age = 45),
+            ] pipe adult() pipe print();",
+        );
+        assert_eq!(result, Ok(0));
+        assert_eq!(
+            printed,
+            "age      | id\n---------+---------\n30       | 1\n45       | 3\n2 rows\n"
+        );
+        // The rows are added in order, with the defaults filled in
+        assert_eq!(
+            run_program(
+                "var table(int id, string name) t = table(int id, string name = \"none\") [row(int id = 7), row(int id = 8, string name = \"b\")];
+                return t[0].name + t[1].name;"
+            ),
+            ExpressionValue::String("noneb".into())
+        );
+    }
+
+    #[test]
+    fn test_column_access_on_indexed_call_result() {
+        let program = "fn row(int id, string name)[] people(int n) {
+                return [row(int id = n, string name = \"Ann\"), row(int id = n + 1, string name = \"Bo\")];
+            };
+            return people(1)[1].name;";
+        crate::frontend::main::check(program).unwrap();
+        assert_eq!(run_program(program), ExpressionValue::String("Bo".into()));
+        assert_eq!(
+            run_program(
+                "var row(int id)[] rows = [row(int id = 4)]; return (rows[0]).id + rows[0].id;"
+            ),
+            ExpressionValue::Number(8)
+        );
+    }
+
+    #[test]
+    fn test_int_widened_next_to_double() {
+        assert_eq!(run_program("return 1.5 + 2;"), ExpressionValue::Double(3.5));
+        assert_eq!(run_program("return 2 * 1.5;"), ExpressionValue::Double(3.0));
+        assert_eq!(run_program("return 2 >= 1.5;"), ExpressionValue::Bool(true));
+        assert_eq!(run_program("return 1.0 == 1;"), ExpressionValue::Bool(true));
+    }
+
+    #[test]
+    fn test_main_called_after_top_level_statements() {
+        reset_order();
+        let program = "fn int main() {
+                test_order(2);
+                return 3;
+            };
+            test_order(1);";
+        let exit_code = interpret(
+            crate::frontend::main::check(program).unwrap(),
+            vec![],
+            false,
+        );
+        assert_eq!(exit_code, 3);
+        assert_eq!(ORDER.with(|order| order.take()), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_main_returning_null_exits_with_zero() {
+        reset_order();
+        let program = "fn null main() { test_order(1); };";
+        assert_eq!(
+            interpret(
+                crate::frontend::main::check(program).unwrap(),
+                vec![],
+                false
+            ),
+            0
+        );
+        assert_eq!(ORDER.with(|order| order.take()), vec![1]);
+    }
+
+    // The environment left after running the top level of a program
+    fn top_level_env(program: &str) -> Vec<Vec<EnvironmentCell>> {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        evaluate_statement(crate::frontend::main::check(program).unwrap(), &mut env);
+        env
+    }
+
+    #[test]
+    fn test_debug_env_inside_function_shows_parameters_and_closure() {
+        let mut env = top_level_env(
+            "fn int helper() { return 1; };
+            fn int f(int a, string s) { debug_env(); return a; };
+            var int later = 2;",
+        );
+        let Some(EnvironmentCell::Function(f)) = env_get_optional(&mut env, "f") else {
+            unreachable!("f is declared");
+        };
+        let shown = format_env(&f.call_env(vec![
+            ExpressionValue::Number(5),
+            ExpressionValue::String("x".into()),
+        ]));
+        assert_eq!(
+            shown,
+            "scope 0:\n  fn int helper()\n  a: int 5\n  s: string \"x\"\n  fn int f(int a, string s)\n"
+        );
+        // Globals are not part of the closure
+        assert!(!shown.contains("later"));
+    }
+
+    #[test]
+    fn test_debug_env_at_top_level_shows_globals() {
+        let env = top_level_env(
+            "var int x = 5;
+            const string name = \"a name that is longer than twenty characters\";
+            fn table(int id) make(int id) { return table(int id); };
+            debug_env();",
+        );
+        assert_eq!(
+            format_env(&env),
+            "scope 0:\n  x: int 5\n  name: string \"a name that is longe…\"\n  fn table(int id) make(int id)\n"
+        );
+    }
+
+    #[test]
+    fn test_debug_env_output_is_deterministic() {
+        let program = "var int a = 1; var double b = 2.5; var bool c = true; var int[] d = [1, 2];";
+        let first = format_env(&top_level_env(program));
+        for _ in 0..5 {
+            assert_eq!(format_env(&top_level_env(program)), first);
+        }
+    }
+
+    #[test]
+    fn test_bind_all_arguments_and_call_with_none() {
+        let program = "
+            fn int add(int a, int b) {
+                return a + b;
+            };
+            const fn int() seven = bind(\"add\", 3, 4);
+            const fn int(int) plus_ten = bind(\"add\", 10);
+            fn int main() {
+                return seven() * plus_ten(1);
+            };";
+        assert_eq!(
+            interpret(
+                crate::frontend::main::check(program).unwrap(),
+                vec![],
+                false
+            ),
+            77
+        );
+    }
+
+    #[test]
+    fn test_row_literal_cells_converted_to_declared_columns() {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        let row = |expr: &str, env: &mut Vec<Vec<EnvironmentCell>>| {
+            let Statement::Compound(statement, _) =
+                crate::frontend::main::create_syntax_tree(expr).unwrap()
+            else {
+                unreachable!()
+            };
+            let Statement::Expr(expr) = statement.unlocated().clone() else {
+                unreachable!()
+            };
+            evaluate_expression(*expr, env)
+        };
+        let ExpressionValue::Row(widened) = row("row(double x = 1);", &mut env) else {
+            unreachable!()
+        };
+        assert_eq!(widened.get_cell("x"), Some(&TableCell::Double(1.0)));
+        // Not type checked, so the string column can be given an int
+        let error = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            row("row(string x = 1);", &mut env)
+        }))
+        .expect_err("an int is not a string");
+        assert_eq!(
+            error.downcast_ref::<String>().unwrap(),
+            "Interpretation error: column 'x' expected string, found int 1"
+        );
+    }
+
+    fn numbers(values: &[i64]) -> ExpressionValue {
+        ExpressionValue::Array(values.iter().map(|n| ExpressionValue::Number(*n)).collect())
+    }
+
+    fn doubles(values: &[f64]) -> ExpressionValue {
+        ExpressionValue::Array(values.iter().map(|d| ExpressionValue::Double(*d)).collect())
+    }
+
+    #[test]
+    fn test_array_scaled_by_scalar() {
+        assert_eq!(
+            evaluate_operation(
+                numbers(&[1, 2, 4]),
+                Operator::Multiplication,
+                ExpressionValue::Double(1.5)
+            ),
+            doubles(&[1.5, 3.0, 6.0])
+        );
+        // The scalar can be on either side, and the order of the operands is kept
+        assert_eq!(
+            evaluate_operation(
+                ExpressionValue::Number(10),
+                Operator::Subtraction,
+                numbers(&[1, 2])
+            ),
+            numbers(&[9, 8])
+        );
+    }
+
+    #[test]
+    fn test_arrays_added_element_wise() {
+        assert_eq!(
+            evaluate_operation(numbers(&[1, 2]), Operator::Addition, doubles(&[0.5, 0.25])),
+            doubles(&[1.5, 2.25])
+        );
+        assert_eq!(
+            evaluate_operation(numbers(&[]), Operator::Addition, numbers(&[])),
+            numbers(&[])
+        );
+    }
+
+    #[test]
+    fn test_element_wise_length_mismatch() {
+        let error = std::panic::catch_unwind(|| {
+            evaluate_operation(numbers(&[1, 2, 3]), Operator::Addition, numbers(&[1, 2]))
+        })
+        .expect_err("the arrays differ in length");
+        assert_eq!(
+            error.downcast_ref::<String>().unwrap(),
+            "Interpretation error: cannot apply '+' element-wise to arrays of length 3 and 2"
+        );
+    }
+
+    #[test]
+    fn test_derived_column_from_column_arithmetic() {
+        let program = "
+            var table(int id, double score) t = table(int id, double score);
+            table_add_row(t, row(int id = 1, double score = 10.0));
+            table_add_row(t, row(int id = 2, double score = 20.0));
+            var table(int id, double score, double scaled) scaled = table_with_column(t, \"scaled\", t.score * 1.1 + t.id);
+            return scaled;";
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        let tree = crate::frontend::main::check(program).unwrap();
+        let StatementValue::Return(ExpressionValue::Table(scaled)) =
+            evaluate_statement(tree, &mut env)
+        else {
+            unreachable!()
+        };
+        let scaled = scaled.borrow();
+        assert_eq!(scaled.get_structure()["scaled"], TableCellType::Double);
+        assert_eq!(scaled.get_column("scaled"), doubles(&[12.0, 24.0]));
+        assert_eq!(scaled.get_column("id"), numbers(&[1, 2]));
+        // The table the column was derived from is left as it was
+        assert!(!table_has_column(&env, "t", "scaled"));
+    }
+
+    fn table_has_column(env: &[Vec<EnvironmentCell>], name: &str, column: &str) -> bool {
+        match env_get_ref(env, name) {
+            EnvironmentCell::Variable(_, ExpressionValue::Table(t)) => {
+                t.borrow().get_structure().contains_key(column)
+            }
+            _ => self::panic!("Expected a table"),
+        }
+    }
+
+    #[test]
+    fn test_derived_column_of_empty_table_has_given_type() {
+        let program = "
+            var table(int id) t = table(int id);
+            return table_with_column(t, \"half\", t.id / 2.0, \"double\");";
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        let tree = crate::frontend::main::check(program).unwrap();
+        let StatementValue::Return(ExpressionValue::Table(half)) =
+            evaluate_statement(tree, &mut env)
+        else {
+            unreachable!()
+        };
+        assert_eq!(half.borrow().get_structure()["half"], TableCellType::Double);
+    }
 }
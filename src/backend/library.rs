@@ -1,182 +1,3317 @@
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Write},
+    panic::{self, UnwindSafe},
+    path::Path,
+    process,
+    rc::Rc,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use super::{
+    civil_date::civil_from_days,
     evaluate::ExpressionValue,
-    table::{Row, TableCell, TableCellType},
+    pager,
+    pipes::{last_pipe_stats, panic_message},
+    table::{
+        ColumnFill, Row, Table, TableCell, TableCellType, coerce_cell, describe_parameters,
+        histogram_parameters, render_histogram, structure_columns,
+    },
 };
-use csv::Reader;
+use crate::frontend::ast::{Parameter, TypeConstruct};
+use csv::{QuoteStyle, Reader, StringRecord, WriterBuilder};
 
 /*
  * This file contains the wrench library functions, and helper functions for those
  */
 
+// A function implemented in Rust that can be called from wrench, with the signature it is type checked against
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: String,
+    pub signature: TypeConstruct,
+    // Names of the parameters in the signature, used in error messages
+    pub parameter_names: Vec<String>,
+    // Parameters that may be left out, after those in the signature
+    pub optional_parameters: Vec<(String, TypeConstruct)>,
+    pub function: fn(Vec<ExpressionValue>) -> ExpressionValue,
+}
+
+impl Builtin {
+    // A parameter of type table() or row() accepts any table or row
+    pub fn new(
+        name: &str,
+        return_type: TypeConstruct,
+        parameters: Vec<(&str, TypeConstruct)>,
+        function: fn(Vec<ExpressionValue>) -> ExpressionValue,
+    ) -> Self {
+        let (parameter_names, parameter_types) = parameters
+            .into_iter()
+            .map(|(name, parameter_type)| (name.to_string(), parameter_type))
+            .unzip();
+        Builtin {
+            name: name.to_string(),
+            signature: TypeConstruct::Function(Box::new(return_type), parameter_types),
+            parameter_names,
+            optional_parameters: vec![],
+            function,
+        }
+    }
+
+    pub fn with_optional_parameter(mut self, name: &str, parameter_type: TypeConstruct) -> Self {
+        self.optional_parameters
+            .push((name.to_string(), parameter_type));
+        self
+    }
+
+    // The parameters in the signature followed by the optional ones
+    fn parameters(&self) -> impl Iterator<Item = (&str, &TypeConstruct)> {
+        let TypeConstruct::Function(_, parameter_types) = &self.signature else {
+            panic!("Builtin '{}' has no function signature", self.name);
+        };
+        self.parameter_names
+            .iter()
+            .map(String::as_str)
+            .zip(parameter_types)
+            .chain(
+                self.optional_parameters
+                    .iter()
+                    .map(|(name, parameter_type)| (name.as_str(), parameter_type)),
+            )
+    }
+
+    // Checks the number and kinds of arguments, so a wrong call fails with a message naming the parameters
+    // instead of somewhere inside the builtin, e.g. import expects 2 arguments (file name, table), got 1
+    pub fn check_arguments(&self, args: &[ExpressionValue]) {
+        let required = self.parameter_names.len();
+        let total = required + self.optional_parameters.len();
+        if args.len() < required || args.len() > total {
+            let count = if required == total {
+                required.to_string()
+            } else {
+                format!("{} to {}", required, total)
+            };
+            let names: Vec<&str> = self.parameters().map(|(name, _)| name).collect();
+            panic!(
+                "Interpretation error: {} expects {} argument{} ({}), got {}",
+                self.name,
+                count,
+                if total == 1 { "" } else { "s" },
+                names.join(", "),
+                args.len()
+            );
+        }
+
+        for ((name, parameter_type), arg) in self.parameters().zip(args) {
+            if !value_has_type(arg, parameter_type) {
+                panic!(
+                    "Interpretation error: {} expects {} to be {}, found {}",
+                    self.name,
+                    name,
+                    type_kind_name(parameter_type),
+                    arg.short_description()
+                );
+            }
+        }
+    }
+
+    // Checks the arguments and calls the builtin
+    pub fn call(&self, args: Vec<ExpressionValue>) -> ExpressionValue {
+        self.check_arguments(&args);
+        (self.function)(args)
+    }
+}
+
+// Whether a value can be given for a parameter of the type. Only the kind is compared, not the columns of tables and rows
+fn value_has_type(value: &ExpressionValue, parameter_type: &TypeConstruct) -> bool {
+    matches!(
+        (value, parameter_type),
+        (_, TypeConstruct::Any)
+            | (ExpressionValue::Number(_), TypeConstruct::Int)
+            | (ExpressionValue::Double(_), TypeConstruct::Double)
+            | (ExpressionValue::String(_), TypeConstruct::String)
+            | (ExpressionValue::Char(_), TypeConstruct::Char)
+            | (ExpressionValue::Bool(_), TypeConstruct::Bool)
+            | (ExpressionValue::Table(_), TypeConstruct::Table(_))
+            | (ExpressionValue::Row(_), TypeConstruct::Row(_))
+            | (ExpressionValue::Array(_), TypeConstruct::Array(_))
+            | (ExpressionValue::Null, TypeConstruct::Null)
+    )
+}
+
+// The name of a parameter type in error messages, with an article, e.g. a table
+fn type_kind_name(parameter_type: &TypeConstruct) -> &'static str {
+    match parameter_type {
+        TypeConstruct::Bool => "a bool",
+        TypeConstruct::Int => "an int",
+        TypeConstruct::Double => "a double",
+        TypeConstruct::String => "a string",
+        TypeConstruct::Char => "a char",
+        TypeConstruct::Null => "null",
+        TypeConstruct::Array(_) => "an array",
+        TypeConstruct::Function(_, _) => "a function",
+        TypeConstruct::Table(_) => "a table",
+        TypeConstruct::Row(_) => "a row",
+        TypeConstruct::Nullable(inner) => type_kind_name(inner),
+        TypeConstruct::Enum(_, _) => "a string",
+        TypeConstruct::Any => "any value",
+    }
+}
+
+// The builtins shipped with wrench. Some of them are further checked by name in the type checker, e.g. import
+fn default_builtins() -> Vec<Builtin> {
+    vec![
+        // print: (any) -> table
+        Builtin::new(
+            "print",
+            TypeConstruct::Table(vec![]),
+            vec![("value", TypeConstruct::Any)],
+            wrench_print,
+        ),
+        // import: (string, table, row?) -> table
+        Builtin::new(
+            "import",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("file name", TypeConstruct::String),
+                ("table", TypeConstruct::Table(vec![])),
+            ],
+            wrench_import,
+        )
+        .with_optional_parameter("options", TypeConstruct::Row(vec![])),
+        // async_import: (string, table, row?) -> table
+        Builtin::new(
+            "async_import",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("file name", TypeConstruct::String),
+                ("table", TypeConstruct::Table(vec![])),
+            ],
+            wrench_async_import,
+        )
+        .with_optional_parameter("options", TypeConstruct::Row(vec![])),
+        // import_glob: (string, table, row?) -> table
+        Builtin::new(
+            "import_glob",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("pattern", TypeConstruct::String),
+                ("table", TypeConstruct::Table(vec![])),
+            ],
+            wrench_import_glob,
+        )
+        .with_optional_parameter("options", TypeConstruct::Row(vec![])),
+        // async_import_glob: (string, table, row?) -> table
+        Builtin::new(
+            "async_import_glob",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("pattern", TypeConstruct::String),
+                ("table", TypeConstruct::Table(vec![])),
+            ],
+            wrench_async_import_glob,
+        )
+        .with_optional_parameter("options", TypeConstruct::Row(vec![])),
+        // rows_from_range: (int, string) -> table
+        Builtin::new(
+            "rows_from_range",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("count", TypeConstruct::Int),
+                ("maker", TypeConstruct::String),
+            ],
+            wrench_rows_from_range,
+        ),
+        // print_full: (any) -> table
+        Builtin::new(
+            "print_full",
+            TypeConstruct::Table(vec![]),
+            vec![("value", TypeConstruct::Any)],
+            wrench_print_full,
+        ),
+        // view: (table) -> null
+        Builtin::new(
+            "view",
+            TypeConstruct::Null,
+            vec![("table", TypeConstruct::Table(vec![]))],
+            wrench_view,
+        ),
+        // log_debug, log_info, log_warn, log_error: (string) -> null
+        Builtin::new(
+            "log_debug",
+            TypeConstruct::Null,
+            vec![("message", TypeConstruct::String)],
+            wrench_log_debug,
+        ),
+        Builtin::new(
+            "log_info",
+            TypeConstruct::Null,
+            vec![("message", TypeConstruct::String)],
+            wrench_log_info,
+        ),
+        Builtin::new(
+            "log_warn",
+            TypeConstruct::Null,
+            vec![("message", TypeConstruct::String)],
+            wrench_log_warn,
+        ),
+        Builtin::new(
+            "log_error",
+            TypeConstruct::Null,
+            vec![("message", TypeConstruct::String)],
+            wrench_log_error,
+        ),
+        // describe: (table) -> table(string column, string type, int count, int nulls, double min, double max, double mean)
+        Builtin::new(
+            "describe",
+            TypeConstruct::Table(describe_parameters()),
+            vec![("table", TypeConstruct::Table(vec![]))],
+            wrench_describe,
+        ),
+        // histogram: (table, string, int) -> table(double bucket_low, double bucket_high, int count)
+        Builtin::new(
+            "histogram",
+            TypeConstruct::Table(histogram_parameters()),
+            vec![
+                ("table", TypeConstruct::Table(vec![])),
+                ("column", TypeConstruct::String),
+                ("buckets", TypeConstruct::Int),
+            ],
+            wrench_histogram,
+        ),
+        // table_add_row: (table, row) -> null
+        Builtin::new(
+            "table_add_row",
+            TypeConstruct::Null,
+            vec![
+                ("table", TypeConstruct::Table(vec![])),
+                ("row", TypeConstruct::Row(vec![])),
+            ],
+            wrench_table_add_row,
+        ),
+        // table_update_row: (table, int, row) -> null
+        Builtin::new(
+            "table_update_row",
+            TypeConstruct::Null,
+            vec![
+                ("table", TypeConstruct::Table(vec![])),
+                ("index", TypeConstruct::Int),
+                ("row", TypeConstruct::Row(vec![])),
+            ],
+            wrench_table_update_row,
+        ),
+        // table_clone: (table) -> table
+        Builtin::new(
+            "table_clone",
+            TypeConstruct::Table(vec![]),
+            vec![("table", TypeConstruct::Table(vec![]))],
+            wrench_table_clone,
+        ),
+        // export_csv: (string, table, row?) -> null
+        Builtin::new(
+            "export_csv",
+            TypeConstruct::Null,
+            vec![
+                ("file name", TypeConstruct::String),
+                ("table", TypeConstruct::Table(vec![])),
+            ],
+            wrench_export_csv,
+        )
+        .with_optional_parameter("options", TypeConstruct::Row(vec![])),
+        // table_with_column: (table, string, array, string?) -> table
+        Builtin::new(
+            "table_with_column",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("table", TypeConstruct::Table(vec![])),
+                ("column name", TypeConstruct::String),
+                ("values", TypeConstruct::Array(Box::new(TypeConstruct::Any))),
+            ],
+            wrench_table_with_column,
+        )
+        .with_optional_parameter("column type", TypeConstruct::String),
+        // table_without_column: (table, string) -> table
+        Builtin::new(
+            "table_without_column",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("table", TypeConstruct::Table(vec![])),
+                ("column name", TypeConstruct::String),
+            ],
+            wrench_table_without_column,
+        ),
+        // to_table: (row[]) -> table. The type checker gives the call the columns of the rows
+        Builtin::new(
+            "to_table",
+            TypeConstruct::Table(vec![]),
+            vec![("rows", TypeConstruct::Array(Box::new(TypeConstruct::Any)))],
+            wrench_to_table,
+        ),
+        // to_table_column: (array, string) -> table
+        Builtin::new(
+            "to_table_column",
+            TypeConstruct::Table(vec![]),
+            vec![
+                ("values", TypeConstruct::Array(Box::new(TypeConstruct::Any))),
+                ("column name", TypeConstruct::String),
+            ],
+            wrench_to_table_column,
+        ),
+        // get_or: (array, int, any) -> any. The type checker gives the call the element type of the array
+        Builtin::new(
+            "get_or",
+            TypeConstruct::Any,
+            vec![
+                ("array", TypeConstruct::Array(Box::new(TypeConstruct::Any))),
+                ("index", TypeConstruct::Int),
+                ("default", TypeConstruct::Any),
+            ],
+            wrench_get_or,
+        ),
+        // row_get_or: (row, string, any) -> any. The type checker gives the call the type of the column or default
+        Builtin::new(
+            "row_get_or",
+            TypeConstruct::Any,
+            vec![
+                ("row", TypeConstruct::Row(vec![])),
+                ("column name", TypeConstruct::String),
+                ("default", TypeConstruct::Any),
+            ],
+            wrench_row_get_or,
+        ),
+        // assert: (bool, string) -> null
+        Builtin::new(
+            "assert",
+            TypeConstruct::Null,
+            vec![
+                ("condition", TypeConstruct::Bool),
+                ("message", TypeConstruct::String),
+            ],
+            wrench_assert,
+        ),
+        // fail: (string) -> null
+        Builtin::new(
+            "fail",
+            TypeConstruct::Null,
+            vec![("message", TypeConstruct::String)],
+            wrench_fail,
+        ),
+        // str_len: (string) -> int, in characters
+        Builtin::new(
+            "str_len",
+            TypeConstruct::Int,
+            vec![("string", TypeConstruct::String)],
+            wrench_str_len,
+        ),
+        // str_bytes_len: (string) -> int, in bytes
+        Builtin::new(
+            "str_bytes_len",
+            TypeConstruct::Int,
+            vec![("string", TypeConstruct::String)],
+            wrench_str_bytes_len,
+        ),
+        // substring: (string, int, int) -> string
+        Builtin::new(
+            "substring",
+            TypeConstruct::String,
+            vec![
+                ("string", TypeConstruct::String),
+                ("start", TypeConstruct::Int),
+                ("end", TypeConstruct::Int),
+            ],
+            wrench_substring,
+        ),
+        // to_upper: (string) -> string
+        Builtin::new(
+            "to_upper",
+            TypeConstruct::String,
+            vec![("string", TypeConstruct::String)],
+            wrench_to_upper,
+        ),
+        // to_lower: (string) -> string
+        Builtin::new(
+            "to_lower",
+            TypeConstruct::String,
+            vec![("string", TypeConstruct::String)],
+            wrench_to_lower,
+        ),
+        // split: (string, string) -> string[]
+        Builtin::new(
+            "split",
+            TypeConstruct::Array(Box::new(TypeConstruct::String)),
+            vec![
+                ("string", TypeConstruct::String),
+                ("separator", TypeConstruct::String),
+            ],
+            wrench_split,
+        ),
+        // debug_env: () -> null
+        Builtin::new("debug_env", TypeConstruct::Null, vec![], wrench_debug_env),
+        // bind: (string, any...) -> function. The type checker works out the function it makes, see infer_bind_type
+        Builtin::new(
+            "bind",
+            TypeConstruct::Function(Box::new(TypeConstruct::Any), vec![]),
+            vec![("function name", TypeConstruct::String)],
+            wrench_bind,
+        ),
+        // import_stats: () -> row(int rows_read, int rows_skipped, string last_file)
+        Builtin::new(
+            "import_stats",
+            TypeConstruct::Row(import_stats_parameters()),
+            vec![],
+            wrench_import_stats,
+        ),
+        // pipe_stats: () -> table(string stage, int rows_in, int rows_out, double seconds)
+        Builtin::new(
+            "pipe_stats",
+            TypeConstruct::Table(pipe_stats_parameters()),
+            vec![],
+            wrench_pipe_stats,
+        ),
+    ]
+}
+
+// The builtins a program can call, found by name. Each run has its own, see RunOptions::register_builtin
+// Builtins live in the global namespace and their names are reserved, so programs cannot declare names with them
+#[derive(Clone)]
+pub struct Builtins {
+    builtins: Vec<Arc<Builtin>>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Builtins {
+    // Makes a Rust function callable from the programs run with these builtins. Panics if the name is taken
+    pub fn register(&mut self, builtin: Builtin) {
+        if self.by_name.contains_key(&builtin.name) {
+            panic!("Builtin '{}' is already registered", builtin.name);
+        }
+        self.by_name
+            .insert(builtin.name.clone(), self.builtins.len());
+        self.builtins.push(Arc::new(builtin));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Builtin>> {
+        self.by_name.get(name).map(|&i| &self.builtins[i])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.builtins.iter().map(|builtin| &**builtin)
+    }
+}
+
+// The builtins shipped with wrench, made once and cloned for each run
+static DEFAULT_BUILTINS: LazyLock<Builtins> = LazyLock::new(|| {
+    let mut builtins = Builtins {
+        builtins: vec![],
+        by_name: HashMap::new(),
+    };
+    for builtin in default_builtins() {
+        builtins.register(builtin);
+    }
+    builtins
+});
+
+impl Default for Builtins {
+    fn default() -> Self {
+        DEFAULT_BUILTINS.clone()
+    }
+}
+
+thread_local! {
+    // The builtins of the program run on this thread, shared by the type checker, the interpreter and pipe stage threads
+    static BUILTINS: RefCell<Arc<Builtins>> = RefCell::new(Arc::new(Builtins::default()));
+}
+
+// The builtins of programs run on this thread. Pipe stages are given those of the thread evaluating the pipe, see set_builtins
+pub fn current_builtins() -> Arc<Builtins> {
+    BUILTINS.with(|builtins| builtins.borrow().clone())
+}
+
+pub fn set_builtins(builtins: Arc<Builtins>) {
+    BUILTINS.with(|current| *current.borrow_mut() = builtins);
+}
+
+// Helper function to find a builtin of the program run on this thread by name
+pub fn lookup_builtin(name: &str) -> Option<Arc<Builtin>> {
+    BUILTINS.with(|builtins| builtins.borrow().get(name).cloned())
+}
+
 // Wrench function for printing it's actual parameters. Returns null
 pub fn wrench_print(args: Vec<ExpressionValue>) -> ExpressionValue {
+    print_values(&args, &mut output());
+    ExpressionValue::Null
+}
+
+// Helper function to write each value on its own line, as print does
+fn print_values<W: Write>(values: &[ExpressionValue], out: &mut W) {
+    for value in values {
+        check_output(writeln!(out, "{}", value.display_string()));
+    }
+}
+
+// Where the output of a program goes, e.g. what print writes
+#[derive(Clone)]
+pub enum Output {
+    Stdout,
+    // Collected by capture_output
+    Captured(Arc<Mutex<Vec<u8>>>),
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout => io::stdout().write(buf),
+            Output::Captured(buffer) => {
+                buffer.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    // Each write holds the lock throughout, so lines written by pipe stages at the same time are not mixed
+    fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().write_fmt(args),
+            Output::Captured(buffer) => buffer.lock().unwrap().write_fmt(args),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().flush(),
+            Output::Captured(_) => Ok(()),
+        }
+    }
+}
+
+thread_local! {
+    // Where the output of programs run on this thread goes, see capture_output
+    static OUTPUT: RefCell<Output> = const { RefCell::new(Output::Stdout) };
+}
+
+// The output of programs run on this thread. Pipe stages are given the output of the thread evaluating the pipe, see set_output
+pub fn output() -> Output {
+    OUTPUT.with(|output| output.borrow().clone())
+}
+
+pub fn set_output(output: Output) {
+    OUTPUT.with(|current| *current.borrow_mut() = output);
+}
+
+// Runs f with the output of programs on this thread, and of the pipe stages they start, collected rather than written to stdout
+// Returns what f returned, or the message it panicked with, along with the output
+pub fn capture_output<R>(f: impl FnOnce() -> R + UnwindSafe) -> (Result<R, String>, String) {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let previous = OUTPUT.with(|output| output.replace(Output::Captured(buffer.clone())));
+    let result = panic::catch_unwind(f).map_err(panic_message);
+    set_output(previous);
+    let captured = String::from_utf8_lossy(&buffer.lock().unwrap()).to_string();
+    (result, captured)
+}
+
+// How important a line logged by a program is. Lines below the level of the log are left out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    // Reads a level as given to --log-level, e.g. warn
+    pub fn parse(name: &str) -> Result<LogLevel, String> {
+        match name {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(format!(
+                "unknown log level '{}', expected debug, info, warn or error",
+                name
+            )),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+// The log of programs run on a thread: the lowest level written, and whether an error has been logged
+#[derive(Clone, Default)]
+pub struct Log {
+    level: LogLevel,
+    // Shared with the pipe stages of the program, which log from their own threads
+    error_logged: Arc<AtomicBool>,
+}
+
+thread_local! {
+    // The log of programs run on this thread, see start_log
+    static LOG: RefCell<Log> = RefCell::new(Log::default());
+}
+
+// The log of programs run on this thread. Pipe stages are given the log of the thread evaluating the pipe, see set_log
+pub fn current_log() -> Log {
+    LOG.with(|log| log.borrow().clone())
+}
+
+pub fn set_log(log: Log) {
+    LOG.with(|current| *current.borrow_mut() = log);
+}
+
+// Starts a new log for programs run on this thread, writing the lines at the level and above, with no error logged yet
+pub fn start_log(level: LogLevel) {
+    set_log(Log {
+        level,
+        error_logged: Arc::default(),
+    });
+}
+
+// Whether a program run on this thread has logged an error since the log was started
+pub fn error_logged() -> bool {
+    LOG.with(|log| log.borrow().error_logged.load(Ordering::Relaxed))
+}
+
+// Writes a line of the log when the level is not below the level of the log, e.g.
+// 2024-05-01T12:00:00.123Z INFO loaded 3 files
+fn write_log<W: Write>(log: &Log, level: LogLevel, message: &str, time: SystemTime, out: &mut W) {
+    if level == LogLevel::Error {
+        log.error_logged.store(true, Ordering::Relaxed);
+    }
+    if level >= log.level {
+        write_status_line(
+            out,
+            format_args!("{} {} {}", utc_timestamp(time), level.label(), message),
+        );
+    }
+}
+
+// Writes a line about the running program, like the log or the progress of an import, usually to stderr
+// These lines are best effort, so a failing stderr does not stop the program
+fn write_status_line<W: Write>(out: &mut W, line: fmt::Arguments) {
+    let _ = writeln!(out, "{}", line);
+}
+
+// The time in UTC as an RFC 3339 timestamp with milliseconds, e.g. 2024-05-01T12:00:00.123Z
+fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let seconds_of_day = seconds % 86_400;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// Handles the result of writing the output of a program. When the reader has gone away, like head does after its lines,
+// the program ends quietly with exit code 0, as is the convention. Any other failure to write is a runtime error
+pub fn check_output(result: io::Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        Err(e) => panic!("Interpretation error: Cannot write the output: {}", e),
+    }
+}
+
+// Wrench function for printing it's actual parameters like print, but without leaving out rows of large tables. Returns null
+pub fn wrench_print_full(args: Vec<ExpressionValue>) -> ExpressionValue {
     for arg in args {
         match arg {
-            ExpressionValue::Number(num) => println!("{}", num),
-            ExpressionValue::Double(num) => println!("{}", num),
-            ExpressionValue::String(s) => println!("{}", s),
-            ExpressionValue::Bool(b) => println!("{}", b),
-            ExpressionValue::Null => println!("Null"),
-            ExpressionValue::Row(row) => {
-                row.print();
-            }
-            ExpressionValue::Table(table) => {
-                let table = table.borrow();
-                table.print();
-            }
-            ExpressionValue::Array(arr) => {
-                for item in arr {
-                    wrench_print(vec![item]);
-                }
-            }
+            ExpressionValue::Table(table) => table.borrow().print_full(),
+            other => check_output(writeln!(output(), "{}", other.full_display_string())),
         }
     }
     ExpressionValue::Null
 }
 
+// Wrench library function for looking through a table in a pager, or printing it like print when the output is not a terminal. Returns null
+pub fn wrench_view(args: Vec<ExpressionValue>) -> ExpressionValue {
+    match &args[0] {
+        ExpressionValue::Table(table) => pager::view(&table.borrow()),
+        _ => panic!("Interpretation error: Expected a table to view"),
+    }
+    ExpressionValue::Null
+}
+
+// Wrench library functions for logging a message at each level, see log_message. Return null
+pub fn wrench_log_debug(args: Vec<ExpressionValue>) -> ExpressionValue {
+    log_message(LogLevel::Debug, &args)
+}
+
+pub fn wrench_log_info(args: Vec<ExpressionValue>) -> ExpressionValue {
+    log_message(LogLevel::Info, &args)
+}
+
+pub fn wrench_log_warn(args: Vec<ExpressionValue>) -> ExpressionValue {
+    log_message(LogLevel::Warn, &args)
+}
+
+pub fn wrench_log_error(args: Vec<ExpressionValue>) -> ExpressionValue {
+    log_message(LogLevel::Error, &args)
+}
+
+// Helper function to write the message of a log function to stderr, so the log is kept apart from the data a program prints
+fn log_message(level: LogLevel, args: &[ExpressionValue]) -> ExpressionValue {
+    let ExpressionValue::String(message) = &args[0] else {
+        panic!("Interpretation error: Expected a message to log");
+    };
+    write_log(
+        &current_log(),
+        level,
+        message,
+        SystemTime::now(),
+        &mut io::stderr(),
+    );
+    ExpressionValue::Null
+}
+
+// Wrench library function for computing statistics for each column of a table. Returns a new table with a row per column
+pub fn wrench_describe(args: Vec<ExpressionValue>) -> ExpressionValue {
+    match &args[0] {
+        ExpressionValue::Table(table) => {
+            ExpressionValue::Table(Rc::new(RefCell::new(table.borrow().describe())))
+        }
+        _ => panic!("Interpretation error: Expected a table to describe"),
+    }
+}
+
+// Wrench library function for counting the values of a numeric column in buckets. Prints a bar per bucket, and returns
+// the buckets as a table
+pub fn wrench_histogram(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let (
+        ExpressionValue::Table(table),
+        ExpressionValue::String(column),
+        ExpressionValue::Number(bucket_count),
+    ) = (&args[0], &args[1], &args[2])
+    else {
+        panic!("Interpretation error: Expected a table, a column name and a bucket count");
+    };
+    let buckets = match table.borrow().histogram(column, *bucket_count) {
+        Ok(buckets) => buckets,
+        Err(e) => panic!("Interpretation error: {}", e),
+    };
+    check_output(writeln!(output(), "{}", render_histogram(&buckets)));
+    ExpressionValue::Table(Rc::new(RefCell::new(buckets)))
+}
+
 // Wrench library function for importing a table from a CSV file. Called with a file name and a table which types and columns matches a csv file
+// An options row can be given as a third argument, see ImportOptions
 pub fn wrench_import(args: Vec<ExpressionValue>) -> ExpressionValue {
     let file_name = match &args[0] {
-        ExpressionValue::String(s) => s.clone(),
+        ExpressionValue::String(s) => s.to_string(),
         _ => panic!("First argument must be a string"),
     };
 
+    if is_dry_run() {
+        dry_run_import(&[file_name], &args);
+        return args[1].clone();
+    }
+
+    let options = ImportOptions::from_args(&args);
+
     let mut table = match &args[1] {
         ExpressionValue::Table(table) => table.borrow_mut(),
         _ => panic!("Second argument must be a table"),
     };
 
-    import_csv(file_name, table.get_structure().clone(), |row| {
+    let stats = import_csv(file_name, table.get_structure().clone(), &options, |row| {
         table.add_row(row);
+        true
     });
+    record_import_stats(stats);
 
     args[1].clone()
 }
 
-// Helper function to Itterate over a CSV file and call the callback function for each row
-pub fn import_csv<F>(name: String, structure: HashMap<String, TableCellType>, mut row_callback: F)
-where
-    F: FnMut(Row),
-{
-    let mut reader = Reader::from_path(name).expect("Failed to open file");
+// Wrench library function for exporting a table to a CSV file. Called with a file name and a table
+// Columns are written in the order tables print them in. An options row can be given as a third argument, see ExportOptions
+pub fn wrench_export_csv(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let file_name = match &args[0] {
+        ExpressionValue::String(s) => s.to_string(),
+        _ => panic!("First argument must be a string"),
+    };
 
-    let headers = reader.headers().expect("Error reading headers").clone();
-    let header_map: HashMap<&str, usize> = headers
-        .iter()
-        .enumerate()
-        .map(|(i, name)| (name, i))
-        .collect();
+    let table = match &args[1] {
+        ExpressionValue::Table(table) => table.borrow(),
+        _ => panic!("Second argument must be a table"),
+    };
 
-    for result in reader.records() {
-        match result {
-            Ok(record) => {
-                //Parse csv record into a row
-                let mut row_data: Vec<(String, TableCell)> = Vec::new();
-                for (name, cell_type) in &structure {
-                    if let Some(index) = header_map.get(name.as_str()) {
-                        let value = record.get(*index).unwrap_or("");
-                        let cell = match cell_type {
-                            TableCellType::Int => TableCell::Int(value.parse::<i32>().unwrap()),
-                            TableCellType::String => TableCell::String(value.to_string()),
-                            TableCellType::Bool => TableCell::Bool(value.parse::<bool>().unwrap()),
-                            TableCellType::Double => {
-                                TableCell::Double(value.parse::<f64>().unwrap())
-                            }
-                        };
-                        row_data.push((name.clone(), cell));
-                    } else {
-                        panic!("CSV file is missing column '{}'", name);
-                    }
-                }
-                row_callback(Row::new(row_data));
+    let options = ExportOptions::from_args(&args);
+
+    if is_dry_run() {
+        dry_run_export(&file_name);
+        return ExpressionValue::Null;
+    }
+
+    let mut writer = WriterBuilder::new()
+        .quote_style(options.quote.style())
+        .from_path(&file_name)
+        .unwrap_or_else(|e| panic!("Failed to create file '{}': {}", file_name, e));
+    let columns = structure_columns(table.get_structure());
+    writer
+        .write_record(&columns)
+        .unwrap_or_else(|e| panic!("Error writing to '{}': {}", file_name, e));
+    for row in table.iter() {
+        let record = columns.iter().map(|column| {
+            // A null is written as a blank field, which is how it is imported
+            match row.get_cell(column) {
+                Some(TableCell::Null(_)) | None => String::new(),
+                Some(cell) => cell.to_string(),
             }
-            Err(e) => panic!("Error reading record: {}", e),
-        }
+        });
+        writer
+            .write_record(record)
+            .unwrap_or_else(|e| panic!("Error writing to '{}': {}", file_name, e));
     }
+    writer
+        .flush()
+        .unwrap_or_else(|e| panic!("Error writing to '{}': {}", file_name, e));
+
+    ExpressionValue::Null
 }
 
-// Wrench library function for adding a row to a table. Called with a table and a row
-pub fn wrench_table_add_row(args: Vec<ExpressionValue>) -> ExpressionValue {
-    let table = match &args[0] {
-        ExpressionValue::Table(table) => table,
-        _ => panic!("Interpretation error: Expected a table"),
+// Wrench library function for importing every CSV file matching a pattern into one table, see glob_files
+// Called like import, with a pattern instead of a file name. The files are imported in the order of their names
+pub fn wrench_import_glob(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let pattern = match &args[0] {
+        ExpressionValue::String(s) => s.clone(),
+        _ => panic!("First argument must be a string"),
     };
 
-    let row = match &args[1] {
-        ExpressionValue::Row(row) => row,
-        _ => panic!("Interpretation error: Expected a row"),
+    let options = ImportOptions::from_args(&args);
+    let files = glob_files(&pattern).unwrap_or_else(|e| panic!("Interpretation error: {}", e));
+
+    if is_dry_run() {
+        dry_run_import(&files, &args);
+        return args[1].clone();
+    }
+
+    let mut table = match &args[1] {
+        ExpressionValue::Table(table) => table.borrow_mut(),
+        _ => panic!("Second argument must be a table"),
     };
 
-    table.borrow_mut().add_row(row.clone());
-    ExpressionValue::Null
+    let stats = import_csv_files(&files, table.get_structure().clone(), &options, |row| {
+        table.add_row(row);
+        true
+    });
+    record_import_stats(stats);
+
+    args[1].clone()
 }
-#[cfg(test)]
-mod tests {
-    use std::{cell::RefCell, rc::Rc};
 
-    use crate::backend::table::Table;
+// async_import_glob is only evaluated as the source of a pipe, see pipes.rs
+fn wrench_async_import_glob(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    panic!("Interpretation error: async_import_glob can only be used as the source of a pipe");
+}
 
-    use super::*;
+// async_import is only evaluated as the source of a pipe, see pipes.rs
+fn wrench_async_import(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    panic!("Interpretation error: async_import can only be used as the source of a pipe");
+}
 
-    #[test]
-    fn test_wrench_print_basic_types() {
-        let args = vec![
-            ExpressionValue::Number(42),
-            ExpressionValue::Double(3.14),
-            ExpressionValue::String("hello".to_string()),
-            ExpressionValue::Bool(true),
-            ExpressionValue::Null,
-        ];
-        // Should not panic
-        let result = wrench_print(args);
-        assert_eq!(result, ExpressionValue::Null);
-    }
+// rows_from_range is only evaluated as the source of a pipe, see pipes.rs
+fn wrench_rows_from_range(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    panic!("Interpretation error: rows_from_range can only be used as the source of a pipe");
+}
 
-    #[test]
-    fn test_wrench_print_array() {
-        let arr = vec![
-            ExpressionValue::Number(1),
-            ExpressionValue::Number(2),
-            ExpressionValue::Number(3),
-        ];
-        let args = vec![ExpressionValue::Array(arr)];
-        let result = wrench_print(args);
-        assert_eq!(result, ExpressionValue::Null);
+// debug_env needs the environment of the call, so it is evaluated in evaluate_function_call
+fn wrench_debug_env(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    panic!("Interpretation error: debug_env can only be called directly");
+}
+
+// bind makes a function, so it is evaluated where the function is declared or piped to, see evaluate_declaration and pipes.rs
+fn wrench_bind(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    panic!("Interpretation error: bind can only be used to declare a constant or as a pipe stage");
+}
+
+// Options for import and async_import, given as an optional row after the table e.g. row(bool strict = true)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportOptions {
+    // Fail if the CSV file has columns that are not declared in the table
+    pub strict: bool,
+    // Report progress to stderr every this many rows. Imports are silent when not set
+    pub progress_every: Option<usize>,
+    // Fill the string column _file of the table with the name of the file each row is from
+    pub file_column: bool,
+    // What to do with a record that cannot be imported, given as on_error = "fail" or "skip"
+    pub on_error: ImportErrorPolicy,
+    // What a blank field is read as in the columns of the table with a default or null, see ColumnFill
+    pub fills: HashMap<String, ColumnFill>,
+}
+
+// What an import does with a record that cannot be imported, e.g. with text in an int column
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ImportErrorPolicy {
+    // Stop the program with an error naming the line
+    #[default]
+    Fail,
+    // Leave the record out and carry on. The records left out are counted in import_stats
+    Skip,
+}
+
+impl ImportOptions {
+    pub fn from_row(row: &Row) -> Self {
+        let mut options = ImportOptions::default();
+        for (name, value) in row.iter() {
+            match (name.as_str(), value) {
+                ("strict", TableCell::Bool(b)) => options.strict = *b,
+                ("file_column", TableCell::Bool(b)) => options.file_column = *b,
+                ("on_error", TableCell::String(s)) if &**s == "fail" => {
+                    options.on_error = ImportErrorPolicy::Fail
+                }
+                ("on_error", TableCell::String(s)) if &**s == "skip" => {
+                    options.on_error = ImportErrorPolicy::Skip
+                }
+                ("on_error", _) => panic!(
+                    "Import option 'on_error' must be \"fail\" or \"skip\", found {}",
+                    value
+                ),
+                ("progress", TableCell::Int(n)) if *n > 0 => {
+                    options.progress_every = Some(*n as usize)
+                }
+                ("progress", TableCell::Int(n)) => {
+                    panic!("Import option 'progress' must be positive, found {}", n)
+                }
+                _ => panic!("Unknown import option '{}' with value {}", name, value),
+            }
+        }
+        options
     }
 
-    #[test]
-    #[should_panic(expected = "First argument must be a string")]
-    fn test_wrench_import_invalid_first_arg() {
-        let args = vec![ExpressionValue::Number(1), ExpressionValue::Null];
-        wrench_import(args);
+    // Reads the options from the third argument of an import call, if there is one, and the fills from the table
+    pub fn from_args(args: &[ExpressionValue]) -> Self {
+        let mut options = match args.get(2) {
+            Some(ExpressionValue::Row(row)) => ImportOptions::from_row(row),
+            Some(_) => panic!("Third argument must be a row of import options"),
+            None => ImportOptions::default(),
+        };
+        if let Some(ExpressionValue::Table(table)) = args.get(1) {
+            options.fills = table.borrow().get_fills().clone();
+        }
+        options
     }
+}
 
-    #[test]
-    #[should_panic(expected = "Second argument must be a table")]
-    fn test_wrench_import_invalid_second_arg() {
-        let args = vec![
-            ExpressionValue::String("file.csv".to_string()),
-            ExpressionValue::Null,
-        ];
-        wrench_import(args);
+// When export_csv puts a field in quotes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ExportQuoting {
+    // Only fields with commas, quotes or line breaks
+    #[default]
+    Minimal,
+    // Every field, for programs that read CSV files without understanding quotes
+    Always,
+}
+
+impl ExportQuoting {
+    fn style(&self) -> QuoteStyle {
+        match self {
+            ExportQuoting::Minimal => QuoteStyle::Necessary,
+            ExportQuoting::Always => QuoteStyle::Always,
+        }
     }
+}
 
-    #[test]
-    #[should_panic(expected = "Interpretation error: Expected a table")]
-    fn test_wrench_table_add_row_invalid_table() {
-        let args = vec![ExpressionValue::Null, ExpressionValue::Null];
-        wrench_table_add_row(args);
+// Options for export_csv, given as an optional row after the table e.g. row(string quote = "always")
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportOptions {
+    pub quote: ExportQuoting,
+}
+
+impl ExportOptions {
+    pub fn from_row(row: &Row) -> Self {
+        let mut options = ExportOptions::default();
+        for (name, value) in row.iter() {
+            match (name.as_str(), value) {
+                ("quote", TableCell::String(s)) if &**s == "minimal" => {
+                    options.quote = ExportQuoting::Minimal
+                }
+                ("quote", TableCell::String(s)) if &**s == "always" => {
+                    options.quote = ExportQuoting::Always
+                }
+                ("quote", _) => panic!(
+                    "Export option 'quote' must be \"minimal\" or \"always\", found {}",
+                    value
+                ),
+                _ => panic!("Unknown export option '{}' with value {}", name, value),
+            }
+        }
+        options
+    }
+
+    // Reads the options from the third argument of an export call, if there is one
+    pub fn from_args(args: &[ExpressionValue]) -> Self {
+        match args.get(2) {
+            Some(ExpressionValue::Row(row)) => ExportOptions::from_row(row),
+            Some(_) => panic!("Third argument must be a row of export options"),
+            None => ExportOptions::default(),
+        }
+    }
+}
+
+// Helper function to check the header of a CSV file against the structure of the table imported into
+// All missing columns are reported at once, before any record is read
+// Returns the index in the CSV file of every column in the structure
+fn match_csv_header(
+    headers: &StringRecord,
+    structure: &HashMap<String, TableCellType>,
+    options: &ImportOptions,
+    source: &str,
+) -> Vec<(String, TableCellType, usize)> {
+    if structure.is_empty() {
+        panic!("import requires a table with at least one declared column");
+    }
+
+    let header_map: HashMap<&str, usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name, i))
+        .collect();
+
+    let mut missing: Vec<&str> = structure
+        .keys()
+        .filter(|name| !header_map.contains_key(name.as_str()))
+        .map(|name| name.as_str())
+        .collect();
+    missing.sort();
+    match missing.len() {
+        0 => {}
+        1 => panic!("{} is missing column '{}'", source, missing[0]),
+        _ => panic!("{} is missing columns '{}'", source, missing.join("', '")),
+    }
+
+    if options.strict {
+        let extra: Vec<&str> = headers
+            .iter()
+            .filter(|name| !structure.contains_key(*name))
+            .collect();
+        if !extra.is_empty() {
+            panic!(
+                "{} has columns not declared in the table: '{}'",
+                source,
+                extra.join("', '")
+            );
+        }
+    }
+
+    let mut columns: Vec<(String, TableCellType, usize)> = structure
+        .iter()
+        .map(|(name, cell_type)| (name.clone(), cell_type.clone(), header_map[name.as_str()]))
+        .collect();
+    // Cells are built in column name order, so rows print the same whatever order the CSV file has its columns in
+    columns.sort_by(|a, b| a.0.cmp(&b.0));
+    columns
+}
+
+// Keeps count of the rows imported from a CSV file, and reports how far the import has come
+struct ImportProgress {
+    file_name: String,
+    every: usize,
+    rows: usize,
+    started: Instant,
+}
+
+impl ImportProgress {
+    fn new(name: &str, every: usize) -> Self {
+        let file_name = Path::new(name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or(name.to_string());
+        ImportProgress {
+            file_name,
+            every,
+            rows: 0,
+            started: Instant::now(),
+        }
+    }
+
+    // Called after each row, bytes is how far into the file the import has read
+    fn row_imported<W: Write>(&mut self, bytes: u64, out: &mut W) {
+        self.rows += 1;
+        if self.rows.is_multiple_of(self.every) {
+            self.report(bytes, out);
+        }
+    }
+
+    fn finish<W: Write>(&self, bytes: u64, out: &mut W) {
+        self.report(bytes, out);
+    }
+
+    fn report<W: Write>(&self, bytes: u64, out: &mut W) {
+        write_status_line(
+            out,
+            format_args!(
+                "imported {} rows ({}) from {} in {:.1}s",
+                format_thousands(self.rows),
+                format_bytes(bytes),
+                self.file_name,
+                self.started.elapsed().as_secs_f64()
+            ),
+        );
+    }
+}
+
+// Helper function to format a number with thousands separators e.g. 1,200,000
+fn format_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+// Helper function to format a size in bytes e.g. 132 MB
+fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1_000_000_000 {
+        format!("{} GB", bytes / 1_000_000_000)
+    } else if bytes >= 1_000_000 {
+        format!("{} MB", bytes / 1_000_000)
+    } else if bytes >= 1_000 {
+        format!("{} KB", bytes / 1_000)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+// Helper function to parse a cell of a CSV file into the type of its column
+// Fields may be quoted and span several lines, so line is where the record starts
+// The source is how the file is named in errors, e.g. CSV file 'data/2024-01-01.csv'
+fn parse_cell(
+    value: &str,
+    cell_type: &TableCellType,
+    column: &str,
+    line: u64,
+    source: &str,
+) -> Result<TableCell, String> {
+    let invalid = || {
+        if value.is_empty() {
+            // A blank field is only read in a column with a default or null, see ColumnFill
+            return format!(
+                "{} has a blank field in {} column '{}' on line {}, which has no default and is not nullable",
+                source, cell_type, column, line
+            );
+        }
+        format!(
+            "{} has '{}' in {} column '{}' on line {}",
+            source, value, cell_type, column, line
+        )
+    };
+    match cell_type {
+        TableCellType::Int => value
+            .parse::<i64>()
+            .map(TableCell::Int)
+            .map_err(|_| invalid()),
+        TableCellType::String => Ok(TableCell::String(value.into())),
+        TableCellType::Enum(_, values) if values.iter().any(|v| v == value) => {
+            Ok(TableCell::String(value.into()))
+        }
+        TableCellType::Enum(_, values) if !value.is_empty() => Err(format!(
+            "{}, which is not one of {}",
+            invalid(),
+            values.join(", ")
+        )),
+        TableCellType::Enum(_, _) => Err(invalid()),
+        TableCellType::Bool => value
+            .parse::<bool>()
+            .map(TableCell::Bool)
+            .map_err(|_| invalid()),
+        // An int in a double column is widened like in every other table
+        TableCellType::Double if value.parse::<i64>().is_ok() => {
+            coerce_cell(TableCell::Int(value.parse().unwrap()), cell_type).map_err(|_| invalid())
+        }
+        TableCellType::Double => {
+            let d = value.parse::<f64>().map_err(|_| invalid())?;
+            // Infinity and NaN are not values in wrench, so they are rejected
+            if !d.is_finite() {
+                return Err(format!(
+                    "{} has the non-finite double '{}' in column '{}' on line {}",
+                    source, value, column, line
+                ));
+            }
+            Ok(TableCell::Double(d))
+        }
+    }
+}
+
+//Columns of the row returned by import_stats
+fn import_stats_parameters() -> Vec<Parameter> {
+    vec![
+        Parameter::Parameter(TypeConstruct::Int, "rows_read".to_string()),
+        Parameter::Parameter(TypeConstruct::Int, "rows_skipped".to_string()),
+        Parameter::Parameter(TypeConstruct::String, "last_file".to_string()),
+    ]
+}
+
+//Columns of the table returned by pipe_stats
+fn pipe_stats_parameters() -> Vec<Parameter> {
+    vec![
+        Parameter::Parameter(TypeConstruct::String, "stage".to_string()),
+        Parameter::Parameter(TypeConstruct::Int, "rows_in".to_string()),
+        Parameter::Parameter(TypeConstruct::Int, "rows_out".to_string()),
+        Parameter::Parameter(TypeConstruct::Double, "seconds".to_string()),
+    ]
+}
+
+// Wrench library function giving what the most recent import, async_import or glob import on the thread read
+// Before anything is imported every count is 0 and last_file is empty
+fn wrench_import_stats(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    let stats = LAST_IMPORT_STATS.with(|last| last.borrow().clone());
+    ExpressionValue::Row(Row::new(vec![
+        (
+            "rows_read".to_string(),
+            TableCell::Int(stats.rows_read as i64),
+        ),
+        (
+            "rows_skipped".to_string(),
+            TableCell::Int(stats.rows_skipped as i64),
+        ),
+        (
+            "last_file".to_string(),
+            TableCell::String(stats.last_file.into()),
+        ),
+    ]))
+}
+
+// Wrench library function giving a row for each stage of the most recent pipe on the thread,
+// in the order rows flow through them. Empty before any pipe is evaluated
+fn wrench_pipe_stats(_args: Vec<ExpressionValue>) -> ExpressionValue {
+    let mut table = Table::new(Table::parameters_to_structure(pipe_stats_parameters()));
+    for stats in last_pipe_stats() {
+        table.add_row(Row::new(vec![
+            ("stage".to_string(), TableCell::String(stats.stage.into())),
+            ("rows_in".to_string(), TableCell::Int(stats.rows_in as i64)),
+            (
+                "rows_out".to_string(),
+                TableCell::Int(stats.rows_out as i64),
+            ),
+            ("seconds".to_string(), TableCell::Double(stats.seconds)),
+        ]));
+    }
+    ExpressionValue::Table(Rc::new(RefCell::new(table)))
+}
+
+// What an import read, given to wrench by import_stats for the most recent import on the thread
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportStats {
+    // Records read from the files, including those skipped
+    pub rows_read: usize,
+    // Records left out as they could not be imported, see ImportErrorPolicy::Skip
+    pub rows_skipped: usize,
+    // The file read last, empty before anything is imported
+    pub last_file: String,
+}
+
+thread_local! {
+    // The stats of the most recent import on this thread, see record_import_stats
+    static LAST_IMPORT_STATS: RefCell<ImportStats> = RefCell::new(ImportStats::default());
+}
+
+// Keeps the stats of an import for import_stats. An import in a pipe runs on a thread of its own,
+// so its stats are recorded by the thread evaluating the pipe once the pipe has finished
+pub fn record_import_stats(stats: ImportStats) {
+    LAST_IMPORT_STATS.with(|last| *last.borrow_mut() = stats);
+}
+
+// Helper function to Itterate over a CSV file and call the callback function for each row
+// The callback returns whether to continue, so the import can stop early
+pub fn import_csv<F>(
+    name: String,
+    structure: HashMap<String, TableCellType>,
+    options: &ImportOptions,
+    row_callback: F,
+) -> ImportStats
+where
+    F: FnMut(Row) -> bool,
+{
+    import_csv_reporting(name, structure, options, row_callback, &mut io::stderr())
+}
+
+// Same as import_csv, with progress reported to the given writer instead of stderr
+fn import_csv_reporting<F, W>(
+    name: String,
+    structure: HashMap<String, TableCellType>,
+    options: &ImportOptions,
+    row_callback: F,
+    progress_out: &mut W,
+) -> ImportStats
+where
+    F: FnMut(Row) -> bool,
+    W: Write,
+{
+    let mut stats = ImportStats::default();
+    import_csv_file(
+        &name,
+        "CSV file",
+        structure,
+        options,
+        row_callback,
+        progress_out,
+        &mut stats,
+    );
+    stats
+}
+
+// Imports the files one after the other, in the order given, as if they were one file
+// Errors name the file they are in, as the same line can be wrong in several of them
+pub fn import_csv_files<F>(
+    names: &[String],
+    structure: HashMap<String, TableCellType>,
+    options: &ImportOptions,
+    mut row_callback: F,
+) -> ImportStats
+where
+    F: FnMut(Row) -> bool,
+{
+    let mut stats = ImportStats::default();
+    for name in names {
+        let source = format!("CSV file '{}'", name);
+        let finished = import_csv_file(
+            name,
+            &source,
+            structure.clone(),
+            options,
+            &mut row_callback,
+            &mut io::stderr(),
+            &mut stats,
+        );
+        if !finished {
+            break;
+        }
+    }
+    stats
+}
+
+// Imports a single file, with errors naming it as the source. Returns false if the callback stopped the import
+// The records read and skipped are added to stats
+fn import_csv_file<F, W>(
+    name: &str,
+    source: &str,
+    mut structure: HashMap<String, TableCellType>,
+    options: &ImportOptions,
+    mut row_callback: F,
+    progress_out: &mut W,
+    stats: &mut ImportStats,
+) -> bool
+where
+    F: FnMut(Row) -> bool,
+    W: Write,
+{
+    remove_file_column(&mut structure, options);
+    let mut progress = options
+        .progress_every
+        .map(|every| ImportProgress::new(name, every));
+    // Both the blocking and the streaming import go through here, so the counting is shared
+    let mut row_callback = |row: Row, bytes: u64| {
+        if let Some(progress) = progress.as_mut() {
+            progress.row_imported(bytes, progress_out);
+        }
+        row_callback(row)
+    };
+
+    let (mut reader, columns) = open_csv_file(name, source, &structure, options);
+    stats.last_file = name.to_string();
+
+    let mut finished = true;
+    // The number of records skipped in this file, and the error of the first of them
+    let mut skipped: Option<(usize, String)> = None;
+    for result in reader.records() {
+        stats.rows_read += 1;
+        //Parse csv record into a row
+        let row_data = result
+            .map_err(|e| format!("Error reading record in {}: {}", source, e))
+            .and_then(|record| {
+                let line = record.position().map_or(0, |p| p.line());
+                let mut row_data: Vec<(String, TableCell)> = Vec::with_capacity(columns.len() + 1);
+                for (column, cell_type, index) in &columns {
+                    let value = record.get(*index).unwrap_or("");
+                    let cell = match options.fills.get(column) {
+                        Some(fill) if value.is_empty() => fill.cell(cell_type),
+                        _ => parse_cell(value, cell_type, column, line, source)?,
+                    };
+                    row_data.push((column.clone(), cell));
+                }
+                Ok((row_data, record.position().map_or(0, |p| p.byte())))
+            });
+        let (mut row_data, bytes) = match (row_data, &options.on_error) {
+            (Ok(row), _) => row,
+            (Err(e), ImportErrorPolicy::Fail) => panic!("{}", e),
+            (Err(e), ImportErrorPolicy::Skip) => {
+                stats.rows_skipped += 1;
+                skipped.get_or_insert((0, e)).0 += 1;
+                continue;
+            }
+        };
+        if options.file_column {
+            row_data.push((FILE_COLUMN.to_string(), TableCell::String(name.into())));
+            row_data.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        if !row_callback(Row::new(row_data), bytes) {
+            finished = false;
+            break;
+        }
+    }
+
+    if let Some(progress) = &progress {
+        progress.finish(reader.position().byte(), progress_out);
+    }
+    if let Some((count, first_error)) = skipped {
+        write_status_line(
+            progress_out,
+            format_args!(
+                "skipped {} record{} that could not be imported, the first: {}",
+                count,
+                if count == 1 { "" } else { "s" },
+                first_error
+            ),
+        );
+    }
+    finished
+}
+
+// The column import option file_column fills with the name of the file each row is from
+pub const FILE_COLUMN: &str = "_file";
+
+// The _file column is filled with the name of the file, instead of being read from it, so it is left out of the columns read
+fn remove_file_column(structure: &mut HashMap<String, TableCellType>, options: &ImportOptions) {
+    if options.file_column && structure.remove(FILE_COLUMN) != Some(TableCellType::String) {
+        panic!(
+            "Import option 'file_column' needs a string column '{}' in the table",
+            FILE_COLUMN
+        );
+    }
+}
+
+// Opens a CSV file and checks its header against the structure imported into, see match_csv_header
+// Gives the reader, at the first record, and the index in the file of every column in the structure
+fn open_csv_file(
+    name: &str,
+    source: &str,
+    structure: &HashMap<String, TableCellType>,
+    options: &ImportOptions,
+) -> (Reader<CountedFile>, Vec<(String, TableCellType, usize)>) {
+    let file = File::open(name).unwrap_or_else(|e| panic!("Failed to open {}: {}", source, e));
+    let mut reader = Reader::from_reader(CountedFile { file });
+    let headers = reader.headers().expect("Error reading headers").clone();
+    let columns = match_csv_header(&headers, structure, options, source);
+    (reader, columns)
+}
+
+#[cfg(test)]
+thread_local! {
+    // Bytes read from CSV files on this thread, so tests can see how much of a file an import touched
+    static CSV_BYTES_READ: Cell<usize> = const { Cell::new(0) };
+}
+
+// A CSV file being imported. Tests count the bytes read from it
+struct CountedFile {
+    file: File,
+}
+
+impl Read for CountedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        #[cfg(test)]
+        CSV_BYTES_READ.with(|bytes| bytes.set(bytes.get() + n));
+        Ok(n)
+    }
+}
+
+thread_local! {
+    // Whether imports and exports on this thread only check their files, see set_dry_run
+    static DRY_RUN: Cell<bool> = const { Cell::new(false) };
+    // What the dry run has checked, one entry for each import and export of a file
+    static DRY_RUN_CHECKS: RefCell<Vec<DryRunCheck>> = const { RefCell::new(Vec::new()) };
+}
+
+// A file checked by a dry run, and what was found
+struct DryRunCheck {
+    export: bool,
+    description: String,
+}
+
+// Sets whether programs run on this thread are dry runs, in which imports only check that their files exist and have
+// the columns of the table, giving no rows, and export_csv only checks that its file can be written. Clears what an
+// earlier dry run checked. Imports in a pipe are checked on this thread, and the stages run over no rows
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.with(|on| on.set(dry_run));
+    DRY_RUN_CHECKS.with(|checks| checks.borrow_mut().clear());
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.with(Cell::get)
+}
+
+// What the dry run on this thread has checked, with a line for each file, e.g.
+// dry run: 2 imports and 1 export checked, no records were read or written
+//   import 'people.csv': header has the columns id, name
+pub fn dry_run_summary() -> String {
+    DRY_RUN_CHECKS.with(|checks| {
+        let checks = checks.borrow();
+        let exports = checks.iter().filter(|check| check.export).count();
+        let imports = checks.len() - exports;
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let mut summary = format!(
+            "dry run: {} import{} and {} export{} checked, no records were read or written",
+            imports,
+            plural(imports),
+            exports,
+            plural(exports)
+        );
+        for check in checks.iter() {
+            summary.push_str(&format!("\n  {}", check.description));
+        }
+        summary
+    })
+}
+
+fn add_dry_run_check(export: bool, description: String) {
+    DRY_RUN_CHECKS.with(|checks| {
+        checks.borrow_mut().push(DryRunCheck {
+            export,
+            description,
+        })
+    });
+}
+
+// Checks the files of an import in a dry run, reading only their headers. Called with the files and the arguments of the import
+// Gives the empty table of the structure imported into
+pub fn dry_run_import(files: &[String], args: &[ExpressionValue]) -> Table {
+    let table_structure = match &args[1] {
+        ExpressionValue::Table(table) => table.borrow().get_structure().clone(),
+        _ => panic!("Second argument must be a table"),
+    };
+    let options = ImportOptions::from_args(args);
+    let mut structure = table_structure.clone();
+    remove_file_column(&mut structure, &options);
+    for name in files {
+        let source = format!("CSV file '{}'", name);
+        open_csv_file(name, &source, &structure, &options);
+        add_dry_run_check(
+            false,
+            format!(
+                "import '{}': header has the columns {}",
+                name,
+                structure_columns(&structure).join(", ")
+            ),
+        );
+    }
+    Table::new(table_structure)
+}
+
+// Checks in a dry run that export_csv could write the file, without creating or changing it
+fn dry_run_export(file_name: &str) {
+    let path = Path::new(file_name);
+    let writable = if path.exists() {
+        // Opened without truncating, so the file is left as it is
+        fs::OpenOptions::new().write(true).open(path).map(|_| ())
+    } else {
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::metadata(directory).and_then(|metadata| {
+            if metadata.is_dir() && !metadata.permissions().readonly() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "'{}' is not a writable directory",
+                    directory.display()
+                )))
+            }
+        })
+    };
+    if let Err(e) = writable {
+        panic!("Failed to create file '{}': {}", file_name, e);
+    }
+    add_dry_run_check(true, format!("export '{}': can be written", file_name));
+}
+
+// The files matching a pattern, sorted by name. Only the last part of the pattern can have wildcards,
+// * for any number of characters and ? for one, e.g. data/2024-01-*.csv
+pub fn glob_files(pattern: &str) -> Result<Vec<String>, String> {
+    let (directory, file_pattern) = match pattern.rfind('/') {
+        Some(i) => (&pattern[..i + 1], &pattern[i + 1..]),
+        None => ("", pattern),
+    };
+    if directory.contains(['*', '?']) {
+        return Err(format!(
+            "Only the file name can have wildcards, found '{}'",
+            pattern
+        ));
+    }
+    let entries = std::fs::read_dir(if directory.is_empty() { "." } else { directory })
+        .map_err(|e| format!("Cannot read the directory of '{}': {}", pattern, e))?;
+    let mut files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| glob_match(file_pattern, name))
+        .map(|name| format!("{}{}", directory, name))
+        .collect();
+    if files.is_empty() {
+        return Err(format!("No files match '{}'", pattern));
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Whether a name matches a pattern with * and ? wildcards
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Where the last * was in the pattern, and the position in the name it was tried from
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last * take one more character and try again
+            star = Some((star_p, star_n + 1));
+            p = star_p + 1;
+            n = star_n + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Wrench library function for adding a row to a table. Called with a table and a row
+pub fn wrench_table_add_row(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let table = match &args[0] {
+        ExpressionValue::Table(table) => table,
+        _ => panic!("Interpretation error: Expected a table"),
+    };
+
+    let row = match &args[1] {
+        ExpressionValue::Row(row) => row,
+        _ => panic!("Interpretation error: Expected a row"),
+    };
+
+    table.borrow_mut().add_row(row.clone());
+    ExpressionValue::Null
+}
+
+// Wrench library function for replacing a row of a table. Called with a table, the index of the row and the new row.
+// Rows taken from a table are copies, so this is how a changed row is written back
+pub fn wrench_table_update_row(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let table = match &args[0] {
+        ExpressionValue::Table(table) => table,
+        _ => panic!("Interpretation error: Expected a table"),
+    };
+    let index = match &args[1] {
+        ExpressionValue::Number(index) if *index >= 0 => *index as usize,
+        ExpressionValue::Number(index) => {
+            panic!("Interpretation error: Row index {} is negative", index)
+        }
+        _ => panic!("Interpretation error: Expected an int"),
+    };
+    let row = match &args[2] {
+        ExpressionValue::Row(row) => row,
+        _ => panic!("Interpretation error: Expected a row"),
+    };
+
+    if let Err(e) = table.borrow_mut().set_row(index, row.clone()) {
+        panic!("Interpretation error: table_update_row: {}", e);
+    }
+    ExpressionValue::Null
+}
+
+// Wrench library function for copying a table. Called with a table, returns a new table with the same structure and rows
+pub fn wrench_table_clone(args: Vec<ExpressionValue>) -> ExpressionValue {
+    match &args[0] {
+        ExpressionValue::Table(table) => {
+            ExpressionValue::Table(Rc::new(RefCell::new(table.borrow().clone())))
+        }
+        _ => panic!("Interpretation error: Expected a table"),
+    }
+}
+
+// Wrench library function for adding a column to a copy of a table. Called with a table, the name of the new column
+// and an array with a value per row. The column has the type given as a fourth argument, or else that of the values,
+// double if any of them is a double
+pub fn wrench_table_with_column(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let table = match &args[0] {
+        ExpressionValue::Table(table) => table.borrow(),
+        _ => panic!("Interpretation error: Expected a table"),
+    };
+    let column_name = match &args[1] {
+        ExpressionValue::String(s) => s,
+        _ => panic!("Interpretation error: Expected a column name"),
+    };
+    let values = match &args[2] {
+        ExpressionValue::Array(values) => values,
+        _ => panic!("Interpretation error: Expected an array"),
+    };
+    let cells = column_cells(values);
+    let column_type = match args.get(3) {
+        Some(ExpressionValue::String(t)) => match &**t {
+            "int" => TableCellType::Int,
+            "double" => TableCellType::Double,
+            "string" => TableCellType::String,
+            "bool" => TableCellType::Bool,
+            other => panic!("Interpretation error: Unknown column type '{}'", other),
+        },
+        _ => column_type_of(&cells).unwrap_or_else(|| {
+            panic!(
+                "Interpretation error: The type of column '{}' cannot be told from an empty array. Give it as a fourth argument, e.g. \"int\"",
+                column_name
+            )
+        }),
+    };
+    match table.with_column(column_name, column_type, cells) {
+        Ok(new_table) => ExpressionValue::Table(Rc::new(RefCell::new(new_table))),
+        Err(e) => panic!("Interpretation error: {}", e),
+    }
+}
+
+// The cells of a column holding the values. Panics if a value cannot be in a table
+fn column_cells(values: &[ExpressionValue]) -> Vec<TableCell> {
+    values
+        .iter()
+        .map(|value| match value {
+            ExpressionValue::Number(n) => TableCell::Int(*n),
+            ExpressionValue::Double(d) => TableCell::Double(*d),
+            ExpressionValue::String(s) => TableCell::String(s.clone()),
+            ExpressionValue::Bool(b) => TableCell::Bool(*b),
+            other => panic!(
+                "Interpretation error: A table column cannot hold {}",
+                other.short_description()
+            ),
+        })
+        .collect()
+}
+
+// The type of a column of the cells, double if any of them is a double. None if there are no cells to tell it from
+fn column_type_of(cells: &[TableCell]) -> Option<TableCellType> {
+    if cells
+        .iter()
+        .any(|cell| cell.get_type() == TableCellType::Double)
+    {
+        return Some(TableCellType::Double);
+    }
+    cells.first().map(TableCell::get_type)
+}
+
+// Wrench library function for making a table of an array of rows. The columns are those of the first row,
+// and every other row must have the same columns, with cells that convert to their types
+pub fn wrench_to_table(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let values = match &args[0] {
+        ExpressionValue::Array(values) => values,
+        _ => panic!("Interpretation error: Expected an array"),
+    };
+    let rows: Vec<&Row> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match value {
+            ExpressionValue::Row(row) => row,
+            other => panic!(
+                "Interpretation error: to_table expects an array of rows, found {} at index {}",
+                other.short_description(),
+                i
+            ),
+        })
+        .collect();
+    let Some(first) = rows.first() else {
+        panic!(
+            "Interpretation error: to_table cannot tell the columns of a table from an empty array"
+        );
+    };
+    let structure: HashMap<String, TableCellType> = first
+        .iter()
+        .map(|(name, cell)| (name.clone(), cell.get_type()))
+        .collect();
+    let mut table = Table::new(structure);
+    for (i, row) in rows.into_iter().enumerate() {
+        let row = row
+            .clone()
+            .coerce(table.get_structure())
+            .unwrap_or_else(|e| panic!("Interpretation error: to_table row at index {}: {}", i, e));
+        if !row.matches_structure(table.get_structure()) {
+            panic!(
+                "Interpretation error: to_table row at index {} has the columns {}, but the first row has {}",
+                i,
+                row.iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                structure_columns(table.get_structure()).join(", ")
+            );
+        }
+        table.add_row(row);
+    }
+    ExpressionValue::Table(Rc::new(RefCell::new(table)))
+}
+
+// Wrench library function for making a table with a single column of an array of ints, doubles, strings or bools
+pub fn wrench_to_table_column(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let values = match &args[0] {
+        ExpressionValue::Array(values) => values,
+        _ => panic!("Interpretation error: Expected an array"),
+    };
+    let column_name = match &args[1] {
+        ExpressionValue::String(s) => s.to_string(),
+        _ => panic!("Interpretation error: Expected a column name"),
+    };
+    let cells = column_cells(values);
+    let Some(column_type) = column_type_of(&cells) else {
+        panic!(
+            "Interpretation error: to_table_column cannot tell the type of column '{}' from an empty array",
+            column_name
+        );
+    };
+    let mut table = Table::new(HashMap::from([(column_name.clone(), column_type)]));
+    for cell in cells {
+        table.add_row(Row::new(vec![(column_name.clone(), cell)]));
+    }
+    ExpressionValue::Table(Rc::new(RefCell::new(table)))
+}
+
+// Wrench library function for removing a column from a copy of a table. Called with a table and the name of the column
+pub fn wrench_table_without_column(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let table = match &args[0] {
+        ExpressionValue::Table(table) => table.borrow(),
+        _ => panic!("Interpretation error: Expected a table"),
+    };
+    let column_name = match &args[1] {
+        ExpressionValue::String(s) => s,
+        _ => panic!("Interpretation error: Expected a column name"),
+    };
+    match table.without_column(column_name) {
+        Ok(new_table) => ExpressionValue::Table(Rc::new(RefCell::new(new_table))),
+        Err(e) => panic!("Interpretation error: {}", e),
+    }
+}
+
+// The value found by get_or or row_get_or, checked to be of the kind of the default. Ints and doubles can be mixed,
+// as an int default is accepted for a double value, and an int value is widened for a double default
+fn value_or_default(
+    name: &str,
+    found: Option<ExpressionValue>,
+    default: ExpressionValue,
+) -> ExpressionValue {
+    match (found, &default) {
+        (None, _) => default,
+        (Some(ExpressionValue::Number(n)), ExpressionValue::Double(_)) => {
+            ExpressionValue::Double(n as f64)
+        }
+        (Some(value @ ExpressionValue::Double(_)), ExpressionValue::Number(_)) => value,
+        (Some(value), _) if value.kind_name() == default.kind_name() => value,
+        (Some(value), _) => panic!(
+            "Interpretation error: {} found {}, but the default is {}",
+            name,
+            value.short_description(),
+            default.short_description()
+        ),
+    }
+}
+
+// Wrench library function for indexing an array without failing. Called with an array, an index and a default,
+// returns the element at the index, or the default if the index is out of bounds, negative indexes included
+pub fn wrench_get_or(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let mut args = args.into_iter();
+    let (Some(ExpressionValue::Array(array)), Some(ExpressionValue::Number(index)), Some(default)) =
+        (args.next(), args.next(), args.next())
+    else {
+        panic!("Interpretation error: get_or expects an array, an index and a default");
+    };
+    let found = usize::try_from(index)
+        .ok()
+        .and_then(|index| array.into_iter().nth(index));
+    value_or_default("get_or", found, default)
+}
+
+// Wrench library function for reading a column that a row may not have. Called with a row, a column name and a default
+pub fn wrench_row_get_or(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let mut args = args.into_iter();
+    let (
+        Some(ExpressionValue::Row(row)),
+        Some(ExpressionValue::String(column_name)),
+        Some(default),
+    ) = (args.next(), args.next(), args.next())
+    else {
+        panic!("Interpretation error: row_get_or expects a row, a column name and a default");
+    };
+    value_or_default("row_get_or", row.get_optional(&column_name), default)
+}
+
+thread_local! {
+    // Failures of assert and fail while a test script runs, see record_assertion_failures.
+    // None when no test is running, and a failure then stops the program
+    static ASSERTION_FAILURES: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+// Helper function to record a failed assertion, or stop the program with it when no test is running
+fn assertion_failed(message: String) {
+    let recorded = ASSERTION_FAILURES.with(|failures| match failures.borrow_mut().as_mut() {
+        Some(failures) => {
+            failures.push(message.clone());
+            true
+        }
+        None => false,
+    });
+    if !recorded {
+        panic!("Interpretation error: {}", message);
+    }
+}
+
+// Runs f with the failures of assert and fail on this thread recorded rather than stopping the program
+// Returns what f returned, or the message it panicked with, along with the failures recorded
+pub fn record_assertion_failures<R>(
+    f: impl FnOnce() -> R + UnwindSafe,
+) -> (Result<R, String>, Vec<String>) {
+    ASSERTION_FAILURES.with(|failures| *failures.borrow_mut() = Some(Vec::new()));
+    let result = panic::catch_unwind(f).map_err(panic_message);
+    let failures = ASSERTION_FAILURES.with(|failures| failures.borrow_mut().take());
+    (result, failures.unwrap_or_default())
+}
+
+// Wrench library function for checking a condition. Called with the condition and a message for when it is false
+pub fn wrench_assert(args: Vec<ExpressionValue>) -> ExpressionValue {
+    match (&args[0], &args[1]) {
+        (ExpressionValue::Bool(true), _) => {}
+        (ExpressionValue::Bool(false), ExpressionValue::String(message)) => {
+            assertion_failed(format!("Assertion failed: {}", message))
+        }
+        _ => panic!("Interpretation error: assert expects a condition and a message"),
+    }
+    ExpressionValue::Null
+}
+
+// Wrench library function for failing a test unconditionally, with a message
+pub fn wrench_fail(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let message = string_argument("fail", &args[0]);
+    assertion_failed(format!("Failed: {}", message));
+    ExpressionValue::Null
+}
+
+// The string builtins count in Unicode scalar values, the chars of a Rust string, not in bytes.
+// Grapheme clusters are not merged, so an e followed by a combining accent is two characters, while a precomposed é is one.
+// Slicing by chars is always on a boundary, so no string builtin panics on text that is not ASCII
+
+// Helper function to take a string argument of a string builtin
+fn string_argument(name: &str, value: &ExpressionValue) -> Arc<str> {
+    match value {
+        ExpressionValue::String(s) => s.clone(),
+        other => panic!(
+            "Interpretation error: {} expects a string, found {}",
+            name,
+            other.short_description()
+        ),
+    }
+}
+
+// Helper function to convert a length or count to a wrench int
+fn int_of(name: &str, n: usize) -> ExpressionValue {
+    match i64::try_from(n) {
+        Ok(n) => ExpressionValue::Number(n),
+        Err(_) => panic!(
+            "Interpretation error: {} of {} is too large for an int",
+            name, n
+        ),
+    }
+}
+
+// The character at a char index of a string, as a string of its own. None if the index is out of range
+pub fn char_at(s: &str, index: i64) -> Option<String> {
+    let index = usize::try_from(index).ok()?;
+    s.chars().nth(index).map(String::from)
+}
+
+// Wrench library function for the number of characters in a string
+pub fn wrench_str_len(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let s = string_argument("str_len", &args[0]);
+    int_of("str_len", s.chars().count())
+}
+
+// Wrench library function for the number of bytes a string takes in UTF-8
+pub fn wrench_str_bytes_len(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let s = string_argument("str_bytes_len", &args[0]);
+    int_of("str_bytes_len", s.len())
+}
+
+// Wrench library function for the characters from start up to, but not including, end
+// The indices are clamped to the string, so a range past its end gives the characters there are
+pub fn wrench_substring(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let s = string_argument("substring", &args[0]);
+    let (ExpressionValue::Number(start), ExpressionValue::Number(end)) = (&args[1], &args[2])
+    else {
+        panic!("Interpretation error: substring expects a string, a start and an end index");
+    };
+    let start = usize::try_from(*start).unwrap_or(0);
+    let end = usize::try_from(*end).unwrap_or(0);
+    ExpressionValue::String(
+        s.chars()
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect::<String>()
+            .into(),
+    )
+}
+
+// Wrench library function for a string in upper case. A character may become several, e.g. ß becomes SS
+pub fn wrench_to_upper(args: Vec<ExpressionValue>) -> ExpressionValue {
+    ExpressionValue::String(string_argument("to_upper", &args[0]).to_uppercase().into())
+}
+
+// Wrench library function for a string in lower case
+pub fn wrench_to_lower(args: Vec<ExpressionValue>) -> ExpressionValue {
+    ExpressionValue::String(string_argument("to_lower", &args[0]).to_lowercase().into())
+}
+
+// Wrench library function for splitting a string at every occurrence of a separator
+// An empty separator splits the string into its characters
+pub fn wrench_split(args: Vec<ExpressionValue>) -> ExpressionValue {
+    let s = string_argument("split", &args[0]);
+    let separator = string_argument("split", &args[1]);
+    let parts: Vec<ExpressionValue> = if separator.is_empty() {
+        s.chars()
+            .map(|c| ExpressionValue::String(c.to_string().into()))
+            .collect()
+    } else {
+        s.split(&*separator)
+            .map(|part| ExpressionValue::String(part.into()))
+            .collect()
+    };
+    ExpressionValue::Array(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::table::{Table, format_double};
+
+    use super::*;
+
+    #[test]
+    fn test_wrench_print_basic_types() {
+        let args = vec![
+            ExpressionValue::Number(42),
+            ExpressionValue::Double(2.5),
+            ExpressionValue::String("hello".into()),
+            ExpressionValue::Bool(true),
+            ExpressionValue::Null,
+        ];
+        // Should not panic
+        let result = wrench_print(args);
+        assert_eq!(result, ExpressionValue::Null);
+    }
+
+    #[test]
+    fn test_wrench_print_array() {
+        let arr = vec![
+            ExpressionValue::Number(1),
+            ExpressionValue::Number(2),
+            ExpressionValue::Number(3),
+        ];
+        let args = vec![ExpressionValue::Array(arr)];
+        let result = wrench_print(args);
+        assert_eq!(result, ExpressionValue::Null);
+    }
+
+    #[test]
+    #[should_panic(expected = "First argument must be a string")]
+    fn test_wrench_import_invalid_first_arg() {
+        let args = vec![ExpressionValue::Number(1), ExpressionValue::Null];
+        wrench_import(args);
+    }
+
+    #[test]
+    #[should_panic(expected = "Second argument must be a table")]
+    fn test_wrench_import_invalid_second_arg() {
+        let args = vec![
+            ExpressionValue::String("file.csv".into()),
+            ExpressionValue::Null,
+        ];
+        wrench_import(args);
+    }
+
+    #[test]
+    #[should_panic(expected = "Interpretation error: Expected a table")]
+    fn test_wrench_table_add_row_invalid_table() {
+        let args = vec![ExpressionValue::Null, ExpressionValue::Null];
+        wrench_table_add_row(args);
+    }
+
+    #[test]
+    #[should_panic(expected = "Interpretation error: Expected a row")]
+    fn test_wrench_table_add_row_invalid_row() {
+        let mut structure = HashMap::new();
+        structure.insert("id".to_string(), TableCellType::Int);
+        let table = Rc::new(RefCell::new(Table::new(structure)));
+        let args = vec![ExpressionValue::Table(table), ExpressionValue::Null];
+        wrench_table_add_row(args);
+    }
+
+    fn score_table() -> ExpressionValue {
+        ExpressionValue::Table(Rc::new(RefCell::new(Table::new(HashMap::from([
+            ("score".to_string(), TableCellType::Double),
+            ("name".to_string(), TableCellType::String),
+        ])))))
+    }
+
+    #[test]
+    fn test_table_add_row_widens_int_in_double_column() {
+        let table = score_table();
+        wrench_table_add_row(vec![
+            table.clone(),
+            ExpressionValue::Row(Row::new(vec![
+                ("score".to_string(), TableCell::Int(3)),
+                ("name".to_string(), TableCell::String("a".into())),
+            ])),
+        ]);
+        let ExpressionValue::Table(table) = table else {
+            unreachable!()
+        };
+        assert_eq!(
+            table.borrow().get_row(0).get_cell("score"),
+            Some(&TableCell::Double(3.0))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Interpretation error: column 'name' expected string, found int 1")]
+    fn test_table_add_row_rejects_int_in_string_column() {
+        wrench_table_add_row(vec![
+            score_table(),
+            ExpressionValue::Row(Row::new(vec![
+                ("score".to_string(), TableCell::Double(1.0)),
+                ("name".to_string(), TableCell::Int(1)),
+            ])),
+        ]);
     }
 
     #[test]
-    #[should_panic(expected = "Interpretation error: Expected a row")]
-    fn test_wrench_table_add_row_invalid_row() {
+    fn test_import_stores_ints_in_double_column_as_doubles() {
+        let rows = import_into(
+            "score\n3\n2.5\n",
+            &[("score", TableCellType::Double)],
+            ImportOptions::default(),
+        );
+        assert_eq!(rows[0].get_cell("score"), Some(&TableCell::Double(3.0)));
+        assert_eq!(rows[1].get_cell("score"), Some(&TableCell::Double(2.5)));
+    }
+
+    #[test]
+    fn test_wrench_table_clone_is_independent() {
         let mut structure = HashMap::new();
         structure.insert("id".to_string(), TableCellType::Int);
         let table = Rc::new(RefCell::new(Table::new(structure)));
-        let args = vec![ExpressionValue::Table(table), ExpressionValue::Null];
-        wrench_table_add_row(args);
+        let clone = wrench_table_clone(vec![ExpressionValue::Table(table.clone())]);
+
+        let row = Row::new(vec![("id".to_string(), TableCell::Int(1))]);
+        wrench_table_add_row(vec![clone.clone(), ExpressionValue::Row(row)]);
+
+        assert_eq!(table.borrow().iter().count(), 0);
+        if let ExpressionValue::Table(clone) = clone {
+            assert_eq!(clone.borrow().iter().count(), 1);
+            assert_eq!(
+                clone.borrow().get_structure(),
+                table.borrow().get_structure()
+            );
+        } else {
+            panic!("Expected a table");
+        }
+    }
+
+    // Helper function to write a CSV file that lives as long as the returned handle
+    fn write_csv(content: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, content.as_bytes()).unwrap();
+        file
+    }
+
+    fn import_into(
+        content: &str,
+        columns: &[(&str, TableCellType)],
+        options: ImportOptions,
+    ) -> Vec<Row> {
+        let file = write_csv(content);
+        let structure: HashMap<String, TableCellType> = columns
+            .iter()
+            .map(|(name, cell_type)| (name.to_string(), cell_type.clone()))
+            .collect();
+        let mut rows = Vec::new();
+        import_csv(
+            file.path().to_str().unwrap().to_string(),
+            structure,
+            &options,
+            |row| {
+                rows.push(row);
+                true
+            },
+        );
+        rows
+    }
+
+    #[test]
+    fn test_import_ids_above_32_bits() {
+        let rows = import_into(
+            "id\n2147483648\n9223372036854775807\n",
+            &[("id", TableCellType::Int)],
+            ImportOptions::default(),
+        );
+        assert_eq!(rows[0].get_cell("id"), Some(&TableCell::Int(2_147_483_648)));
+        assert_eq!(rows[1].get_cell("id"), Some(&TableCell::Int(i64::MAX)));
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file has '9223372036854775808' in int column 'id' on line 2")]
+    fn test_import_int_above_64_bits() {
+        import_into(
+            "id\n9223372036854775808\n",
+            &[("id", TableCellType::Int)],
+            ImportOptions::default(),
+        );
+    }
+
+    #[test]
+    fn test_import_reads_declared_columns() {
+        let rows = import_into(
+            "id,name,extra\n1,a,x\n2,b,y\n",
+            &[("id", TableCellType::Int), ("name", TableCellType::String)],
+            ImportOptions::default(),
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].get_cell("id"), Some(&TableCell::Int(2)));
+        assert_eq!(rows[1].get_cell("extra"), None);
+    }
+
+    #[test]
+    fn test_import_rows_independent_of_csv_column_order() {
+        let columns = [("name", TableCellType::String), ("id", TableCellType::Int)];
+        let first = import_into("id,name\n1,a\n", &columns, ImportOptions::default());
+        let second = import_into("name,id\na,1\n", &columns, ImportOptions::default());
+        assert_eq!(first[0].print_line(), "id: 1, name: a, ");
+        assert_eq!(first, second);
+    }
+
+    // Quoted commas, escaped quotes and a line break inside a quoted field
+    const QUOTED_CSV: &str = "id,name,address\n\
+        1,\"Smith, John\",\"12 Main St, Springfield\"\n\
+        2,\"The \"\"Best\"\" Shop\",\"Line one\nLine two\"\n\
+        3,plain,\"ends in a quote \"\"\"\n";
+
+    fn quoted_columns() -> [(&'static str, TableCellType); 3] {
+        [
+            ("id", TableCellType::Int),
+            ("name", TableCellType::String),
+            ("address", TableCellType::String),
+        ]
+    }
+
+    fn cell_text(row: &Row, column: &str) -> String {
+        match row.get_cell(column) {
+            Some(TableCell::String(s)) => s.to_string(),
+            other => panic!("Expected a string in '{}', found {:?}", column, other),
+        }
+    }
+
+    #[test]
+    fn test_import_quoted_fields() {
+        let rows = import_into(QUOTED_CSV, &quoted_columns(), ImportOptions::default());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(cell_text(&rows[0], "name"), "Smith, John");
+        assert_eq!(cell_text(&rows[0], "address"), "12 Main St, Springfield");
+        assert_eq!(cell_text(&rows[1], "name"), "The \"Best\" Shop");
+        assert_eq!(cell_text(&rows[1], "address"), "Line one\nLine two");
+        assert_eq!(cell_text(&rows[2], "name"), "plain");
+        assert_eq!(cell_text(&rows[2], "address"), "ends in a quote \"");
+        assert_eq!(rows[2].get_cell("id"), Some(&TableCell::Int(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file has 'x' in int column 'id' on line 6")]
+    fn test_import_error_line_counts_line_breaks_in_quoted_fields() {
+        import_into(
+            &format!("{}x,a,b\n", QUOTED_CSV),
+            &quoted_columns(),
+            ImportOptions::default(),
+        );
+    }
+
+    // Exports a table with the quoted fixture to a new file and returns what was written
+    fn export_quoted(options: Option<ExpressionValue>) -> (tempfile::NamedTempFile, String) {
+        let rows = import_into(QUOTED_CSV, &quoted_columns(), ImportOptions::default());
+        let mut table = Table::new(
+            quoted_columns()
+                .into_iter()
+                .map(|(name, cell_type)| (name.to_string(), cell_type))
+                .collect(),
+        );
+        for row in rows {
+            table.add_row(row);
+        }
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut args = vec![
+            ExpressionValue::String(file.path().to_str().unwrap().into()),
+            ExpressionValue::Table(Rc::new(RefCell::new(table))),
+        ];
+        args.extend(options);
+        wrench_export_csv(args);
+        let written = std::fs::read_to_string(file.path()).unwrap();
+        (file, written)
+    }
+
+    fn quote_option(policy: &str) -> ExpressionValue {
+        ExpressionValue::Row(Row::new(vec![(
+            "quote".to_string(),
+            TableCell::String(policy.into()),
+        )]))
+    }
+
+    #[test]
+    fn test_export_quotes_only_where_needed_by_default() {
+        let (_file, written) = export_quoted(None);
+        assert_eq!(
+            written,
+            "address,id,name\n\
+            \"12 Main St, Springfield\",1,\"Smith, John\"\n\
+            \"Line one\nLine two\",2,\"The \"\"Best\"\" Shop\"\n\
+            \"ends in a quote \"\"\",3,plain\n"
+        );
+    }
+
+    #[test]
+    fn test_export_always_quote() {
+        let (_file, written) = export_quoted(Some(quote_option("always")));
+        assert!(
+            written.starts_with("\"address\",\"id\",\"name\"\n\"12 Main St, Springfield\",\"1\",")
+        );
+        assert!(written.ends_with("\"ends in a quote \"\"\",\"3\",\"plain\"\n"));
+    }
+
+    #[test]
+    fn test_export_round_trip_with_both_quoting_policies() {
+        let original = import_into(QUOTED_CSV, &quoted_columns(), ImportOptions::default());
+        for policy in ["minimal", "always"] {
+            let (_file, written) = export_quoted(Some(quote_option(policy)));
+            let reimported = import_into(&written, &quoted_columns(), ImportOptions::default());
+            assert_eq!(reimported, original, "round trip with quote = {}", policy);
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Export option 'quote' must be \"minimal\" or \"always\", found sometimes"
+    )]
+    fn test_export_unknown_quoting_policy() {
+        ExportOptions::from_row(&Row::new(vec![(
+            "quote".to_string(),
+            TableCell::String("sometimes".into()),
+        )]));
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file is missing columns 'name', 'price'")]
+    fn test_import_reports_all_missing_columns() {
+        import_into(
+            "id\n1\n",
+            &[
+                ("id", TableCellType::Int),
+                ("price", TableCellType::Double),
+                ("name", TableCellType::String),
+            ],
+            ImportOptions::default(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file is missing column 'price'")]
+    fn test_import_missing_column_fails_before_reading_records() {
+        // The second record is malformed, the header check must fail first
+        import_into(
+            "id\n1\n2,3\n",
+            &[("id", TableCellType::Int), ("price", TableCellType::Double)],
+            ImportOptions::default(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "import requires a table with at least one declared column")]
+    fn test_import_into_schemaless_table() {
+        import_into("id\n1\n", &[], ImportOptions::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file has columns not declared in the table: 'extra'")]
+    fn test_import_strict_rejects_extra_columns() {
+        import_into(
+            "id,extra\n1,x\n",
+            &[("id", TableCellType::Int)],
+            ImportOptions {
+                strict: true,
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown import option 'strcit'")]
+    fn test_import_options_unknown_option() {
+        ImportOptions::from_row(&Row::new(vec![(
+            "strcit".to_string(),
+            TableCell::Bool(true),
+        )]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Import option 'on_error' must be \"fail\" or \"skip\", found ignore"
+    )]
+    fn test_import_options_unknown_error_policy() {
+        ImportOptions::from_row(&Row::new(vec![(
+            "on_error".to_string(),
+            TableCell::String("ignore".into()),
+        )]));
+    }
+
+    #[test]
+    fn test_import_skip_leaves_out_malformed_records() {
+        let rows = import_into(
+            "id,name\n1,a\n2\n3,c\n",
+            &[("id", TableCellType::Int), ("name", TableCellType::String)],
+            ImportOptions {
+                on_error: ImportErrorPolicy::Skip,
+                ..Default::default()
+            },
+        );
+        let ids: Vec<_> = rows.iter().map(|row| row.get_cell("id").cloned()).collect();
+        assert_eq!(ids, [Some(TableCell::Int(1)), Some(TableCell::Int(3))]);
+    }
+
+    #[test]
+    fn test_import_fills_blank_fields_from_column_policies() {
+        let rows = import_into(
+            "id,score,note\n1,2.5,ok\n2,,\n",
+            &[
+                ("id", TableCellType::Int),
+                ("score", TableCellType::Double),
+                ("note", TableCellType::String),
+            ],
+            ImportOptions {
+                fills: HashMap::from([
+                    (
+                        "score".to_string(),
+                        ColumnFill::Default(TableCell::Double(0.0)),
+                    ),
+                    ("note".to_string(), ColumnFill::Null),
+                ]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            rows[0].get_cell("note"),
+            Some(&TableCell::String("ok".into()))
+        );
+        assert_eq!(rows[1].get_cell("score"), Some(&TableCell::Double(0.0)));
+        assert_eq!(
+            rows[1].get_cell("note"),
+            Some(&TableCell::Null(TableCellType::String))
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "CSV file has a blank field in double column 'score' on line 3, which has no default and is not nullable"
+    )]
+    fn test_import_blank_field_in_strict_column() {
+        import_into(
+            "id,score\n1,2.5\n2,\n",
+            &[("id", TableCellType::Int), ("score", TableCellType::Double)],
+            ImportOptions::default(),
+        );
+    }
+
+    #[test]
+    fn test_log_leaves_out_lines_below_its_level() {
+        let log = Log {
+            level: LogLevel::Warn,
+            error_logged: Arc::default(),
+        };
+        let time = UNIX_EPOCH + std::time::Duration::from_millis(1_714_564_800_123);
+        let mut out = Vec::new();
+        for level in [
+            LogLevel::Debug,
+            LogLevel::Info,
+            LogLevel::Warn,
+            LogLevel::Error,
+        ] {
+            write_log(&log, level, "disk is full", time, &mut out);
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2024-05-01T12:00:00.123Z WARN disk is full\n2024-05-01T12:00:00.123Z ERROR disk is full\n"
+        );
+        assert!(log.error_logged.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        // The leap day of a year divisible by 400
+        let leap_day = UNIX_EPOCH + std::time::Duration::from_secs(951_782_400 + 86_399);
+        assert_eq!(utc_timestamp(leap_day), "2000-02-29T23:59:59.000Z");
+    }
+
+    #[test]
+    fn test_error_logged_by_a_pipe_stage() {
+        start_log(LogLevel::Error);
+        assert!(!error_logged());
+        let (result, printed) = capture_output(|| {
+            let program = crate::frontend::main::check(
+                "fn bool keep(row(int id) r) { log_error(\"bad row\"); return true; };
+                var table(int id) t = table(int id);
+                table_add_row(t, row(int id = 1));
+                t pipe keep();",
+            )
+            .unwrap();
+            crate::backend::evaluate::interpret(program, vec![], false)
+        });
+        result.unwrap();
+        assert_eq!(printed, "");
+        assert!(error_logged());
+        start_log(LogLevel::Info);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "CSV file has 'actve' in Status column 'status' on line 3, which is not one of active, inactive, pending"
+    )]
+    fn test_import_value_outside_enum() {
+        let status = TableCellType::Enum(
+            "Status".to_string(),
+            vec![
+                "active".to_string(),
+                "inactive".to_string(),
+                "pending".to_string(),
+            ],
+        );
+        import_into(
+            "id,status\n1,pending\n2,actve\n3,active\n",
+            &[("id", TableCellType::Int), ("status", status)],
+            ImportOptions::default(),
+        );
+    }
+
+    #[test]
+    fn test_table_add_row_fills_missing_columns() {
+        let table = Table::new(HashMap::from([
+            ("score".to_string(), TableCellType::Double),
+            ("name".to_string(), TableCellType::String),
+            ("note".to_string(), TableCellType::String),
+        ]))
+        .with_fills(HashMap::from([
+            (
+                "score".to_string(),
+                ColumnFill::Default(TableCell::Double(1.5)),
+            ),
+            ("note".to_string(), ColumnFill::Null),
+        ]));
+        let table = Rc::new(RefCell::new(table));
+        wrench_table_add_row(vec![
+            ExpressionValue::Table(table.clone()),
+            ExpressionValue::Row(Row::new(vec![(
+                "name".to_string(),
+                TableCell::String("a".into()),
+            )])),
+        ]);
+        let table = table.borrow();
+        let row = table.get_row(0);
+        assert_eq!(row.get_cell("score"), Some(&TableCell::Double(1.5)));
+        assert_eq!(
+            row.get_cell("note"),
+            Some(&TableCell::Null(TableCellType::String))
+        );
+    }
+
+    #[test]
+    fn test_dry_run_import_reads_only_the_header() {
+        let mut content = String::from("id,name\n");
+        for i in 0..200_000 {
+            content.push_str(&format!("{},name {}\n", i, i));
+        }
+        let file = write_csv(&content);
+        let table = || {
+            let mut structure = HashMap::new();
+            structure.insert("id".to_string(), TableCellType::Int);
+            structure.insert("name".to_string(), TableCellType::String);
+            ExpressionValue::Table(Rc::new(RefCell::new(Table::new(structure))))
+        };
+        let import = |table: ExpressionValue| {
+            CSV_BYTES_READ.with(|bytes| bytes.set(0));
+            let imported = wrench_import(vec![
+                ExpressionValue::String(file.path().to_str().unwrap().into()),
+                table,
+            ]);
+            let ExpressionValue::Table(imported) = imported else {
+                panic!("Expected a table");
+            };
+            let rows = imported.borrow().row_count();
+            (rows, CSV_BYTES_READ.with(Cell::get))
+        };
+
+        set_dry_run(true);
+        let (dry_rows, dry_bytes) = import(table());
+        set_dry_run(false);
+        assert_eq!(dry_rows, 0);
+        assert!(dry_bytes < 64 * 1024, "read {} bytes", dry_bytes);
+
+        let (rows, bytes) = import(table());
+        assert_eq!(rows, 200_000);
+        assert_eq!(bytes, content.len());
+    }
+
+    #[test]
+    fn test_dry_run_export_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.csv");
+        set_dry_run(true);
+        wrench_export_csv(vec![
+            ExpressionValue::String(path.to_str().unwrap().into()),
+            score_table(),
+        ]);
+        let summary = dry_run_summary();
+        let unwritable = panic::catch_unwind(|| {
+            wrench_export_csv(vec![
+                ExpressionValue::String("no/such/directory/out.csv".into()),
+                score_table(),
+            ])
+        });
+        set_dry_run(false);
+        assert!(!path.exists());
+        assert!(summary.starts_with("dry run: 0 imports and 1 export checked"));
+        assert!(
+            panic_message(unwritable.unwrap_err())
+                .starts_with("Failed to create file 'no/such/directory/out.csv'")
+        );
+    }
+
+    #[test]
+    fn test_capture_output_gets_what_print_writes() {
+        let (result, printed) = capture_output(|| {
+            wrench_print(vec![
+                ExpressionValue::Number(1),
+                ExpressionValue::Bool(true),
+            ]);
+            panic!("Interpretation error: stop");
+        });
+        assert_eq!(printed, "1\ntrue\n");
+        assert_eq!(result.unwrap_err(), "Interpretation error: stop");
+        assert!(matches!(output(), Output::Stdout));
+    }
+
+    #[test]
+    fn test_view_prints_like_print_when_the_output_is_not_a_terminal() {
+        let numbered_table = || {
+            let mut structure = HashMap::new();
+            structure.insert("id".to_string(), TableCellType::Int);
+            let mut table = Table::new(structure);
+            for i in 0..100 {
+                table.add_row(Row::new(vec![("id".to_string(), TableCell::Int(i))]));
+            }
+            ExpressionValue::Table(Rc::new(RefCell::new(table)))
+        };
+        let (_, viewed) = capture_output(|| wrench_view(vec![numbered_table()]));
+        let (_, printed) = capture_output(|| wrench_print(vec![numbered_table()]));
+        assert_eq!(viewed, printed);
+        assert!(viewed.contains("…"));
+        assert!(viewed.ends_with("100 rows x 1 column\n"));
+    }
+
+    fn wrench_test_double(args: Vec<ExpressionValue>) -> ExpressionValue {
+        match args[0] {
+            ExpressionValue::Number(n) => ExpressionValue::Number(n * 2),
+            _ => panic!("Expected a number"),
+        }
+    }
+
+    // Makes test_double callable from the programs run on this thread, next to the builtins shipped with wrench
+    fn register_test_double(name: &str) {
+        let mut builtins = Builtins::default();
+        builtins.register(Builtin::new(
+            name,
+            TypeConstruct::Int,
+            vec![("n", TypeConstruct::Int)],
+            wrench_test_double,
+        ));
+        set_builtins(Arc::new(builtins));
+    }
+
+    #[test]
+    fn test_registered_builtin_is_callable_from_a_script() {
+        register_test_double("test_double");
+        let tree = crate::frontend::main::check("return test_double(21);").unwrap();
+        let mut env = crate::backend::environment::env_new();
+        crate::backend::environment::env_expand_scope(&mut env);
+        match crate::backend::evaluate::evaluate_statement(tree, &mut env) {
+            crate::backend::evaluate::StatementValue::Return(value) => {
+                assert_eq!(value, ExpressionValue::Number(42))
+            }
+            _ => panic!("Expected the script to return"),
+        }
+    }
+
+    #[test]
+    fn test_registered_builtin_signature_is_type_checked() {
+        register_test_double("test_double_checked");
+        let result = crate::frontend::main::check("var int x = test_double_checked(\"a\");");
+        assert!(result.is_err(), "A string is not an int");
+        let result = crate::frontend::main::check("var string x = test_double_checked(2);");
+        assert!(result.is_err(), "The builtin returns an int");
+    }
+
+    #[test]
+    #[should_panic(expected = "Builtin 'print' is already registered")]
+    fn test_register_builtin_twice() {
+        Builtins::default().register(Builtin::new(
+            "print",
+            TypeConstruct::Null,
+            vec![],
+            wrench_test_double,
+        ));
+    }
+
+    #[test]
+    fn test_import_progress_reported_every_n_rows() {
+        let file = write_csv("id\n1\n2\n3\n4\n5\n6\n7\n");
+        let structure = HashMap::from([("id".to_string(), TableCellType::Int)]);
+        let options = ImportOptions {
+            progress_every: Some(2),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        let mut count = 0;
+        import_csv_reporting(
+            file.path().to_str().unwrap().to_string(),
+            structure,
+            &options,
+            |_| {
+                count += 1;
+                true
+            },
+            &mut out,
+        );
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(count, 7);
+        // A line for every second row, and the final summary
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("imported 2 rows ("));
+        assert!(lines[2].starts_with("imported 6 rows ("));
+        assert!(lines[3].starts_with("imported 7 rows (17 B) from "));
+    }
+
+    #[test]
+    fn test_import_without_progress_is_silent() {
+        let file = write_csv("id\n1\n2\n");
+        let structure = HashMap::from([("id".to_string(), TableCellType::Int)]);
+        let mut out = Vec::new();
+        import_csv_reporting(
+            file.path().to_str().unwrap().to_string(),
+            structure,
+            &ImportOptions::default(),
+            |_| true,
+            &mut out,
+        );
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_format_thousands() {
+        assert_eq!(format_thousands(7), "7");
+        assert_eq!(format_thousands(1000), "1,000");
+        assert_eq!(format_thousands(1200000), "1,200,000");
+    }
+
+    #[test]
+    fn test_import_reads_formatted_and_scientific_doubles() {
+        let values = [0.1, 1e-7, 1234567890.123, -0.0, f64::MAX];
+        let mut content = "value\n".to_string();
+        for value in values {
+            content += &format!("{}\n", format_double(value));
+        }
+        content += "1e-07\n";
+        let rows = import_into(
+            &content,
+            &[("value", TableCellType::Double)],
+            ImportOptions::default(),
+        );
+
+        assert_eq!(rows.len(), values.len() + 1);
+        for (row, value) in rows.iter().zip(values.iter().chain([1e-7].iter())) {
+            match row.get_cell("value") {
+                Some(TableCell::Double(d)) => assert_eq!(d.to_bits(), value.to_bits()),
+                other => panic!("Expected a double, found {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file has the non-finite double 'NaN' in column 'value'")]
+    fn test_import_rejects_nan() {
+        import_into(
+            "value\n1.5\nNaN\n",
+            &[("value", TableCellType::Double)],
+            ImportOptions::default(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "CSV file has the non-finite double 'inf' in column 'value'")]
+    fn test_import_rejects_infinity() {
+        import_into(
+            "value\ninf\n",
+            &[("value", TableCellType::Double)],
+            ImportOptions::default(),
+        );
+    }
+
+    fn printed(values: Vec<ExpressionValue>) -> String {
+        let mut out = Vec::new();
+        print_values(&values, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_print_flat_array() {
+        let array = ExpressionValue::Array(vec![
+            ExpressionValue::Number(1),
+            ExpressionValue::Number(2),
+            ExpressionValue::Number(3),
+        ]);
+        assert_eq!(printed(vec![array]), "[1, 2, 3]\n");
+    }
+
+    #[test]
+    fn test_print_nested_array() {
+        let array = ExpressionValue::Array(vec![
+            ExpressionValue::Array(vec![ExpressionValue::Number(1), ExpressionValue::Number(2)]),
+            ExpressionValue::Array(vec![ExpressionValue::Number(3)]),
+        ]);
+        assert_eq!(printed(vec![array]), "[[1, 2], [3]]\n");
+    }
+
+    #[test]
+    fn test_print_mixed_array_quotes_strings() {
+        let array = ExpressionValue::Array(vec![
+            ExpressionValue::String("1".into()),
+            ExpressionValue::Number(1),
+            ExpressionValue::Double(1.0),
+            ExpressionValue::Bool(true),
+        ]);
+        let string = ExpressionValue::String("top level".into());
+        assert_eq!(
+            printed(vec![array, string]),
+            "[\"1\", 1, 1.0, true]\ntop level\n"
+        );
+    }
+
+    #[test]
+    fn test_print_array_of_rows() {
+        let row = |id, name: &str| {
+            ExpressionValue::Row(Row::new(vec![
+                ("id".to_string(), TableCell::Int(id)),
+                ("name".to_string(), TableCell::String(name.into())),
+            ]))
+        };
+        let array = ExpressionValue::Array(vec![row(1, "A"), row(2, "B")]);
+        assert_eq!(
+            printed(vec![array]),
+            "[{id: 1, name: \"A\"}, {id: 2, name: \"B\"}]\n"
+        );
+    }
+
+    fn get_or(index: i64, default: ExpressionValue) -> ExpressionValue {
+        let array = ExpressionValue::Array(vec![
+            ExpressionValue::Number(10),
+            ExpressionValue::Number(20),
+        ]);
+        wrench_get_or(vec![array, ExpressionValue::Number(index), default])
+    }
+
+    #[test]
+    fn test_get_or_in_range() {
+        assert_eq!(
+            get_or(1, ExpressionValue::Number(0)),
+            ExpressionValue::Number(20)
+        );
+    }
+
+    #[test]
+    fn test_get_or_out_of_range_and_negative() {
+        assert_eq!(
+            get_or(2, ExpressionValue::Number(0)),
+            ExpressionValue::Number(0)
+        );
+        assert_eq!(
+            get_or(-1, ExpressionValue::Number(-5)),
+            ExpressionValue::Number(-5)
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: get_or found int 10, but the default is string \"none\""
+    )]
+    fn test_get_or_default_of_other_type() {
+        get_or(0, ExpressionValue::String("none".into()));
+    }
+
+    #[test]
+    fn test_row_get_or_present_and_missing_column() {
+        let row = ExpressionValue::Row(Row::new(vec![(
+            "name".to_string(),
+            TableCell::String("Ann".into()),
+        )]));
+        let row_get_or = |column: &str| {
+            wrench_row_get_or(vec![
+                row.clone(),
+                ExpressionValue::String(column.into()),
+                ExpressionValue::String("default".into()),
+            ])
+        };
+        assert_eq!(row_get_or("name"), ExpressionValue::String("Ann".into()));
+        assert_eq!(
+            row_get_or("maybe_col"),
+            ExpressionValue::String("default".into())
+        );
+    }
+
+    fn person(id: i64, name: &str) -> ExpressionValue {
+        ExpressionValue::Row(Row::new(vec![
+            ("id".to_string(), TableCell::Int(id)),
+            ("name".to_string(), TableCell::String(name.into())),
+        ]))
+    }
+
+    #[test]
+    fn test_to_table_of_rows() {
+        let rows = ExpressionValue::Array(vec![person(1, "Ann"), person(2, "Bo")]);
+        let ExpressionValue::Table(table) = wrench_to_table(vec![rows]) else {
+            panic!("to_table should give a table");
+        };
+        let table = table.borrow();
+        assert_eq!(
+            structure_columns(table.get_structure()),
+            vec!["id".to_string(), "name".to_string()]
+        );
+        assert_eq!(
+            table.get_column("name"),
+            ExpressionValue::Array(vec![
+                ExpressionValue::String("Ann".into()),
+                ExpressionValue::String("Bo".into()),
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: to_table row at index 1 has the columns id, but the first row has id, name"
+    )]
+    fn test_to_table_mismatched_row() {
+        let other = ExpressionValue::Row(Row::new(vec![("id".to_string(), TableCell::Int(2))]));
+        wrench_to_table(vec![ExpressionValue::Array(vec![person(1, "Ann"), other])]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Interpretation error: to_table cannot tell the columns of a table from an empty array"
+    )]
+    fn test_to_table_empty_array() {
+        wrench_to_table(vec![ExpressionValue::Array(vec![])]);
+    }
+
+    #[test]
+    fn test_to_table_column_round_trips_through_get_column() {
+        let values = ExpressionValue::Array(vec![
+            ExpressionValue::Double(1.5),
+            ExpressionValue::Double(2.5),
+        ]);
+        let ExpressionValue::Table(table) = wrench_to_table_column(vec![
+            values.clone(),
+            ExpressionValue::String("score".into()),
+        ]) else {
+            panic!("to_table_column should give a table");
+        };
+        assert_eq!(table.borrow().get_column("score"), values);
+    }
+
+    // A writer whose every write fails, like a full disk
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Interpretation error: Cannot write the output: disk full")]
+    fn test_failing_output_is_a_runtime_error() {
+        print_values(&[ExpressionValue::Number(1)], &mut FailingWriter);
+    }
+
+    #[test]
+    fn test_assert_records_failures_in_a_test() {
+        let (result, failures) = record_assertion_failures(|| {
+            wrench_assert(vec![ExpressionValue::Bool(true), string("holds")]);
+            wrench_assert(vec![ExpressionValue::Bool(false), string("one")]);
+            wrench_fail(vec![string("two")]);
+            3
+        });
+        assert_eq!(result, Ok(3));
+        assert_eq!(
+            failures,
+            vec![
+                "Assertion failed: one".to_string(),
+                "Failed: two".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Interpretation error: Assertion failed: outside a test")]
+    fn test_assert_stops_the_program_outside_a_test() {
+        wrench_assert(vec![ExpressionValue::Bool(false), string("outside a test")]);
+    }
+
+    fn string(s: &str) -> ExpressionValue {
+        ExpressionValue::String(s.into())
+    }
+
+    // ASCII, Danish letters, an e with a combining accent (two scalar values) and an emoji
+    const SAMPLES: [(&str, i64, i64); 4] = [
+        ("hello", 5, 5),
+        ("blåbærgrød", 10, 13),
+        ("cafe\u{301}", 5, 6),
+        ("hi 🎉", 4, 7),
+    ];
+
+    #[test]
+    fn test_str_len_counts_scalar_values() {
+        for (s, chars, bytes) in SAMPLES {
+            assert_eq!(
+                wrench_str_len(vec![string(s)]),
+                ExpressionValue::Number(chars),
+                "{}",
+                s
+            );
+            assert_eq!(
+                wrench_str_bytes_len(vec![string(s)]),
+                ExpressionValue::Number(bytes),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_substring_and_indexing_agree_on_characters() {
+        for (s, chars, _) in SAMPLES {
+            // Every character taken by substring is the one at that index, and together they are the string
+            let mut joined = String::new();
+            for i in 0..chars {
+                let ExpressionValue::String(c) = wrench_substring(vec![
+                    string(s),
+                    ExpressionValue::Number(i),
+                    ExpressionValue::Number(i + 1),
+                ]) else {
+                    panic!("substring should give a string");
+                };
+                assert_eq!(char_at(s, i), Some(c.to_string()), "{} at {}", s, i);
+                joined.push_str(&c);
+            }
+            assert_eq!(joined, s);
+            assert_eq!(char_at(s, chars), None);
+            assert_eq!(char_at(s, -1), None);
+        }
+        assert_eq!(
+            wrench_substring(vec![
+                string("blåbærgrød"),
+                ExpressionValue::Number(3),
+                ExpressionValue::Number(20),
+            ]),
+            string("bærgrød")
+        );
+        assert_eq!(
+            wrench_substring(vec![
+                string("cafe\u{301}"),
+                ExpressionValue::Number(0),
+                ExpressionValue::Number(4),
+            ]),
+            string("cafe")
+        );
+        assert_eq!(
+            wrench_substring(vec![
+                string("hi 🎉"),
+                ExpressionValue::Number(3),
+                ExpressionValue::Number(1),
+            ]),
+            string("")
+        );
+    }
+
+    #[test]
+    fn test_to_upper_and_split_of_non_ascii() {
+        assert_eq!(
+            wrench_to_upper(vec![string("blåbærgrød")]),
+            string("BLÅBÆRGRØD")
+        );
+        assert_eq!(
+            wrench_to_upper(vec![string("cafe\u{301}")]),
+            string("CAFE\u{301}")
+        );
+        assert_eq!(wrench_to_lower(vec![string("ÆØÅ")]), string("æøå"));
+        assert_eq!(
+            wrench_split(vec![string("rød,grød 🎉,e\u{301}"), string(",")]),
+            ExpressionValue::Array(vec![string("rød"), string("grød 🎉"), string("e\u{301}")])
+        );
+        // Splitting into characters gives as many parts as str_len counts
+        for (s, chars, _) in SAMPLES {
+            let ExpressionValue::Array(parts) = wrench_split(vec![string(s), string("")]) else {
+                panic!("split should give an array");
+            };
+            assert_eq!(parts.len(), chars as usize, "{}", s);
+        }
+    }
+
+    // A directory with the daily exports 2024-01-01.csv to 2024-01-03.csv, written out of order, and a file not matching
+    fn daily_exports(second_day: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            ("2024-01-03.csv", "id,amount\n5,50\n"),
+            ("2024-01-01.csv", "id,amount\n1,10\n2,20\n"),
+            ("2024-01-02.csv", second_day),
+            ("2024-02-01.csv", "id,amount\n9,90\n"),
+        ];
+        for (name, content) in files {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    fn import_daily_exports(dir: &tempfile::TempDir, options: Option<Row>) -> Vec<Row> {
+        let mut structure = HashMap::from([
+            ("id".to_string(), TableCellType::Int),
+            ("amount".to_string(), TableCellType::Int),
+        ]);
+        if options.is_some() {
+            structure.insert(FILE_COLUMN.to_string(), TableCellType::String);
+        }
+        let table = ExpressionValue::Table(Rc::new(RefCell::new(Table::new(structure))));
+        let pattern = format!("{}/2024-01-*.csv", dir.path().display());
+        let mut args = vec![ExpressionValue::String(pattern.into()), table];
+        args.extend(options.map(ExpressionValue::Row));
+        let ExpressionValue::Table(table) = wrench_import_glob(args) else {
+            unreachable!()
+        };
+        table.borrow().iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_import_glob_concatenates_in_name_order() {
+        let dir = daily_exports("id,amount\n3,30\n4,40\n");
+        let ids: Vec<ExpressionValue> = import_daily_exports(&dir, None)
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+        assert_eq!(
+            ids,
+            (1..=5).map(ExpressionValue::Number).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_import_glob_file_column() {
+        let dir = daily_exports("id,amount\n3,30\n4,40\n");
+        let options = Row::new(vec![("file_column".to_string(), TableCell::Bool(true))]);
+        let rows = import_daily_exports(&dir, Some(options));
+        let file = |row: &Row| match row.get(FILE_COLUMN) {
+            ExpressionValue::String(path) => path.rsplit('/').next().unwrap().to_string(),
+            other => panic!("Expected a file name, found {:?}", other),
+        };
+        assert_eq!(
+            rows.iter().map(file).collect::<Vec<_>>(),
+            [
+                "2024-01-01.csv",
+                "2024-01-01.csv",
+                "2024-01-02.csv",
+                "2024-01-02.csv",
+                "2024-01-03.csv"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_glob_error_names_file() {
+        let dir = daily_exports("id,amount\n3,30\nfour,40\n");
+        let error = std::panic::catch_unwind(|| import_daily_exports(&dir, None))
+            .expect_err("the second file has a string in an int column");
+        assert_eq!(
+            error.downcast_ref::<String>().unwrap(),
+            &format!(
+                "CSV file '{}/2024-01-02.csv' has 'four' in int column 'id' on line 3",
+                dir.path().display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_glob_without_matches() {
+        let dir = daily_exports("id,amount\n");
+        let pattern = format!("{}/2023-*.csv", dir.path().display());
+        assert_eq!(
+            glob_files(&pattern),
+            Err(format!("No files match '{}'", pattern))
+        );
+        assert_eq!(
+            glob_files("data/*/2024.csv"),
+            Err("Only the file name can have wildcards, found 'data/*/2024.csv'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("2024-01-*.csv", "2024-01-31.csv"));
+        assert!(glob_match("2024-01-??.csv", "2024-01-31.csv"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("2024-01-??.csv", "2024-01-3.csv"));
+        assert!(!glob_match("*.csv", "data.csv.bak"));
     }
 }
@@ -1,5 +1,8 @@
+pub mod civil_date;
+pub mod coverage;
 pub mod environment;
 pub mod evaluate;
 pub mod library;
+pub mod pager;
 pub mod pipes;
 pub mod table;
@@ -0,0 +1,315 @@
+use std::ops::Range;
+
+use super::table::{Table, TableRenderer};
+
+#[cfg(feature = "pager")]
+use super::library::{Output, output};
+#[cfg(feature = "pager")]
+use std::io::{self, IsTerminal};
+
+// How many characters the arrow keys scroll sideways
+const COLUMN_STEP: usize = 8;
+
+// Lines of the screen that are not rows of the table: the header, its separator and the status line
+const RESERVED_LINES: usize = 3;
+
+// What a key pressed in the pager asks for
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PagerKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Quit,
+}
+
+// The part of a table the pager shows, which the keys move around
+#[derive(Debug, PartialEq)]
+pub struct PagerWindow {
+    first_row: usize,
+    first_column: usize,
+    row_count: usize,
+    // Number of rows shown at once
+    height: usize,
+    // Number of characters shown of each line
+    width: usize,
+    // Characters in the widest line shown, which is as far as the window scrolls sideways
+    line_width: usize,
+}
+
+impl PagerWindow {
+    // A window at the top left of the table for a screen of the given size
+    pub fn new(row_count: usize, screen_height: usize, screen_width: usize) -> Self {
+        let mut window = PagerWindow {
+            first_row: 0,
+            first_column: 0,
+            row_count,
+            height: 0,
+            width: 0,
+            line_width: 0,
+        };
+        window.resize(screen_height, screen_width);
+        window
+    }
+
+    pub fn resize(&mut self, screen_height: usize, screen_width: usize) {
+        self.height = screen_height.saturating_sub(RESERVED_LINES).max(1);
+        self.width = screen_width.max(1);
+        self.clamp();
+    }
+
+    // Moves the window for the key. Returns false when the key closes the pager
+    pub fn handle(&mut self, key: PagerKey) -> bool {
+        match key {
+            PagerKey::Up => self.first_row = self.first_row.saturating_sub(1),
+            PagerKey::Down => self.first_row += 1,
+            PagerKey::Left => self.first_column = self.first_column.saturating_sub(COLUMN_STEP),
+            PagerKey::Right => self.first_column += COLUMN_STEP,
+            PagerKey::PageUp => self.first_row = self.first_row.saturating_sub(self.height),
+            PagerKey::PageDown => self.first_row += self.height,
+            PagerKey::Home => self.first_row = 0,
+            PagerKey::End => self.first_row = self.row_count,
+            PagerKey::Quit => return false,
+        }
+        self.clamp();
+        true
+    }
+
+    // The rows shown, as indices into the table
+    pub fn visible_rows(&self) -> Range<usize> {
+        self.first_row..(self.first_row + self.height).min(self.row_count)
+    }
+
+    // Tells the window how wide the lines it shows are, so it does not scroll past the widest of them
+    pub fn fit_lines(&mut self, lines: &[String]) {
+        self.line_width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        self.clamp();
+    }
+
+    // The part of a rendered line inside the window
+    pub fn clip(&self, line: &str) -> String {
+        line.chars()
+            .skip(self.first_column)
+            .take(self.width)
+            .collect()
+    }
+
+    // The line under the table, e.g. rows 1-20 of 100
+    pub fn status(&self) -> String {
+        let rows = self.visible_rows();
+        if rows.is_empty() {
+            format!("no rows of {} (q quits)", self.row_count)
+        } else {
+            format!(
+                "rows {}-{} of {} (arrows scroll, q quits)",
+                rows.start + 1,
+                rows.end,
+                self.row_count
+            )
+        }
+    }
+
+    // Helper function to keep the window inside the table, with the last page full when there is one
+    fn clamp(&mut self) {
+        self.first_row = self
+            .first_row
+            .min(self.row_count.saturating_sub(self.height));
+        self.first_column = self
+            .first_column
+            .min(self.line_width.saturating_sub(self.width));
+    }
+}
+
+// The lines of the screen for the window, rendering only the rows it shows
+pub fn render_window(table: &Table, window: &mut PagerWindow) -> Vec<String> {
+    let renderer = TableRenderer::new(table.get_structure());
+    let mut lines: Vec<String> = renderer.header().lines().map(str::to_string).collect();
+    lines.extend(
+        table
+            .row_slice(window.visible_rows())
+            .iter()
+            .map(|row| renderer.row(row)),
+    );
+    window.fit_lines(&lines);
+    let mut screen: Vec<String> = lines.iter().map(|line| window.clip(line)).collect();
+    screen.push(window.clip(&window.status()));
+    screen
+}
+
+// Shows a table in the pager when the output is a terminal, and prints it like print otherwise
+pub fn view(table: &Table) {
+    #[cfg(feature = "pager")]
+    if matches!(output(), Output::Stdout) && io::stdout().is_terminal() {
+        if let Err(e) = terminal::run(table) {
+            panic!("Interpretation error: Cannot show the table viewer: {}", e);
+        }
+        return;
+    }
+    table.print();
+}
+
+// The pager on a terminal in raw mode, on the alternate screen so the output before it is left as it was
+#[cfg(feature = "pager")]
+mod terminal {
+    use std::io::{self, Write};
+
+    use crossterm::{
+        cursor,
+        event::{self, Event, KeyCode, KeyEventKind},
+        execute, queue,
+        terminal::{self, ClearType},
+    };
+
+    use super::{PagerKey, PagerWindow, render_window};
+    use crate::backend::table::Table;
+
+    pub fn run(table: &Table) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        let result = show(table, &mut stdout);
+        // The terminal is restored even when showing the table failed
+        execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn show(table: &Table, stdout: &mut io::Stdout) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let mut window = PagerWindow::new(table.row_count(), rows as usize, columns as usize);
+        loop {
+            queue!(stdout, terminal::Clear(ClearType::All))?;
+            for (i, line) in render_window(table, &mut window).iter().enumerate() {
+                queue!(stdout, cursor::MoveTo(0, i as u16))?;
+                write!(stdout, "{}", line)?;
+            }
+            stdout.flush()?;
+            match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    if let Some(key) = pager_key(key.code)
+                        && !window.handle(key)
+                    {
+                        return Ok(());
+                    }
+                }
+                Event::Resize(columns, rows) => window.resize(rows as usize, columns as usize),
+                _ => {}
+            }
+        }
+    }
+
+    fn pager_key(code: KeyCode) -> Option<PagerKey> {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => Some(PagerKey::Up),
+            KeyCode::Down | KeyCode::Char('j') => Some(PagerKey::Down),
+            KeyCode::Left | KeyCode::Char('h') => Some(PagerKey::Left),
+            KeyCode::Right | KeyCode::Char('l') => Some(PagerKey::Right),
+            KeyCode::PageUp => Some(PagerKey::PageUp),
+            KeyCode::PageDown | KeyCode::Char(' ') => Some(PagerKey::PageDown),
+            KeyCode::Home | KeyCode::Char('g') => Some(PagerKey::Home),
+            KeyCode::End | KeyCode::Char('G') => Some(PagerKey::End),
+            KeyCode::Char('q') | KeyCode::Esc => Some(PagerKey::Quit),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::table::{TableBuilder, TableCellType};
+
+    fn numbered_table(rows: i64) -> Table {
+        let mut builder = TableBuilder::new()
+            .column("id", TableCellType::Int)
+            .column("name", TableCellType::String);
+        for i in 0..rows {
+            builder = builder.row(|row| row.int(i).str(&format!("name {}", i)));
+        }
+        builder.build().unwrap()
+    }
+
+    // Feeds the keys to a window, as the terminal would
+    fn press(window: &mut PagerWindow, keys: &[PagerKey]) {
+        for key in keys {
+            assert!(window.handle(*key));
+        }
+    }
+
+    #[test]
+    fn test_window_scrolls_rows_within_the_table() {
+        // A 13 line screen leaves room for 10 rows
+        let mut window = PagerWindow::new(25, 13, 80);
+        assert_eq!(window.visible_rows(), 0..10);
+
+        press(&mut window, &[PagerKey::Down, PagerKey::Down]);
+        assert_eq!(window.visible_rows(), 2..12);
+        press(&mut window, &[PagerKey::PageDown, PagerKey::PageDown]);
+        assert_eq!(window.visible_rows(), 15..25);
+        press(&mut window, &[PagerKey::Up]);
+        assert_eq!(window.visible_rows(), 14..24);
+        press(
+            &mut window,
+            &[PagerKey::Home, PagerKey::Up, PagerKey::PageUp],
+        );
+        assert_eq!(window.visible_rows(), 0..10);
+        press(&mut window, &[PagerKey::End]);
+        assert_eq!(window.visible_rows(), 15..25);
+        assert_eq!(window.status(), "rows 16-25 of 25 (arrows scroll, q quits)");
+        assert!(!window.handle(PagerKey::Quit));
+    }
+
+    #[test]
+    fn test_window_on_a_table_shorter_than_the_screen() {
+        let mut window = PagerWindow::new(3, 40, 80);
+        press(&mut window, &[PagerKey::PageDown, PagerKey::End]);
+        assert_eq!(window.visible_rows(), 0..3);
+
+        let mut empty = PagerWindow::new(0, 40, 80);
+        press(&mut empty, &[PagerKey::Down]);
+        assert_eq!(empty.visible_rows(), 0..0);
+        assert_eq!(empty.status(), "no rows of 0 (q quits)");
+    }
+
+    #[test]
+    fn test_window_scrolls_sideways_up_to_the_widest_line() {
+        let mut window = PagerWindow::new(1, 10, 10);
+        window.fit_lines(&["0123456789abcdefghij".to_string()]);
+        press(&mut window, &[PagerKey::Right]);
+        assert_eq!(window.clip("0123456789abcdefghij"), "89abcdefgh");
+        press(&mut window, &[PagerKey::Right, PagerKey::Right]);
+        assert_eq!(window.clip("0123456789abcdefghij"), "abcdefghij");
+        press(&mut window, &[PagerKey::Left, PagerKey::Left]);
+        assert_eq!(window.clip("0123456789abcdefghij"), "0123456789");
+    }
+
+    #[test]
+    fn test_window_is_kept_inside_the_table_when_resized() {
+        let mut window = PagerWindow::new(25, 13, 80);
+        press(&mut window, &[PagerKey::End]);
+        window.resize(23, 80);
+        assert_eq!(window.visible_rows(), 5..25);
+    }
+
+    #[test]
+    fn test_render_window_renders_only_the_rows_shown() {
+        let table = numbered_table(1000);
+        let mut window = PagerWindow::new(table.row_count(), 8, 80);
+        press(&mut window, &[PagerKey::PageDown]);
+        let screen = render_window(&table, &mut window);
+        // The header, its separator, 5 rows and the status line
+        assert_eq!(screen.len(), 8);
+        assert!(screen[0].starts_with("id"));
+        assert!(screen[2].starts_with("5 "));
+        assert!(screen[6].ends_with("name 9"));
+        assert_eq!(screen[7], "rows 6-10 of 1000 (arrows scroll, q quits)");
+    }
+}
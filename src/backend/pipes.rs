@@ -1,18 +1,29 @@
 use std::{
-    cell::RefCell,
+    any::Any,
+    cell::{Cell, RefCell},
     collections::HashMap,
+    io::Write,
+    panic::{self, AssertUnwindSafe},
     rc::Rc,
-    sync::mpsc,
+    sync::{Arc, Mutex, mpsc},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use crate::frontend::ast::{Expr, Parameter, TypeConstruct};
+use crate::frontend::{
+    ast::{Expr, LAMBDA_STAGE, TypeConstruct},
+    diagnostics::{pipe_left_is_function_message, pipe_right_is_table_message},
+};
 
 use super::{
     environment::{EnvironmentCell, WrenchFunction, env_get},
     evaluate::{ExpressionValue, evaluate_custom_function_call, evaluate_expression},
-    library::{import_csv, wrench_print},
-    table::{Row, Table, TableCellType},
+    library::{
+        ImportOptions, ImportStats, check_output, current_builtins, current_log, dry_run_import,
+        glob_files, import_csv, import_csv_files, is_dry_run, lookup_builtin, output,
+        record_import_stats, set_builtins, set_log, set_output,
+    },
+    table::{Row, Table, TableCellType, TableRenderer, describe_structure, structure_columns},
 };
 
 /*
@@ -27,26 +38,13 @@ struct SimplePipe {
 }
 
 impl SimplePipe {
-    //Gets the table structure of how the pipe's function is called
-    fn get_call_structure(&self) -> HashMap<String, TableCellType> {
-        if let PipeFunction::Custom(f) = &self.function {
-            let Parameter::Parameter(t, _) = f.parameters[0].clone();
-            if let TypeConstruct::Table(table_type) = t {
-                Table::parameters_to_structure(table_type)
-            } else {
-                panic!("Expected a table for the first parameter of the function");
-            }
-        } else {
-            panic!("Expected a custom function for the pipe");
-        }
-    }
     //Get the table structure of how the pipe's function returns
     fn get_return_structure(&self) -> HashMap<String, TableCellType> {
         if let PipeFunction::Custom(f) = &self.function {
             if let TypeConstruct::Table(table_type) = f.return_type.clone() {
                 Table::parameters_to_structure(table_type)
             } else if let TypeConstruct::Row(row_type) = f.return_type.clone() {
-                return Table::parameters_to_structure(row_type);
+                Table::parameters_to_structure(row_type)
             } else {
                 panic!("Expected a table for the first parameter of the function");
             }
@@ -54,6 +52,27 @@ impl SimplePipe {
             panic!("Expected a custom function for the pipe");
         }
     }
+    //Get the table structure of the rows the pipe sends downstream, given the structure it receives
+    fn get_output_structure(
+        &self,
+        input_structure: &HashMap<String, TableCellType>,
+    ) -> HashMap<String, TableCellType> {
+        match &self.function {
+            PipeFunction::Print => input_structure.clone(),
+            PipeFunction::Custom(_) => match self.get_pipe_type() {
+                PipeType::Filter => input_structure.clone(),
+                PipeType::Map | PipeType::Reduce => self.get_return_structure(),
+            },
+        }
+    }
+    //Get the name of the pipe's function, used in error messages
+    fn get_name(&self) -> String {
+        match &self.function {
+            PipeFunction::Print => "print".to_string(),
+            PipeFunction::Custom(f) if f.name.is_empty() => LAMBDA_STAGE.to_string(),
+            PipeFunction::Custom(f) => f.name.clone(),
+        }
+    }
     //Determine wheter the pipe is a map, filter or reduce
     fn get_pipe_type(&self) -> PipeType {
         if let PipeFunction::Custom(f) = &self.function {
@@ -75,16 +94,20 @@ enum PipeType {
     Reduce,
 }
 
-//The value that can be passed between threads. Like expression value, tables are passed by value instead of reference
+//The value that can be passed between threads. Unlike expression value, tables are passed by value instead of reference,
+//as Rc<RefCell<Table>> cannot be shared between threads. A table given as an argument to a pipe stage is therefore
+//a copy, and mutations made to it inside the stage are not visible outside the pipe
 #[derive(Clone, Debug)]
 pub enum PipeValue {
-    Number(i32),
+    Number(i64),
     Double(f64),
-    String(String),
+    String(Arc<str>),
+    Char(char),
     Bool(bool),
     Table(Table),
     Row(Row),
     Array(Vec<PipeValue>),
+    Function(WrenchFunction),
     Null,
 }
 
@@ -100,12 +123,14 @@ fn expression_value_to_pipe_value(expr: ExpressionValue) -> PipeValue {
         ExpressionValue::Number(n) => PipeValue::Number(n),
         ExpressionValue::Double(d) => PipeValue::Double(d),
         ExpressionValue::String(s) => PipeValue::String(s),
+        ExpressionValue::Char(c) => PipeValue::Char(c),
         ExpressionValue::Bool(b) => PipeValue::Bool(b),
         ExpressionValue::Table(t) => PipeValue::Table(t.borrow().clone()),
         ExpressionValue::Row(r) => PipeValue::Row(r),
         ExpressionValue::Array(a) => {
             PipeValue::Array(a.into_iter().map(expression_value_to_pipe_value).collect())
         }
+        ExpressionValue::Function(f) => PipeValue::Function(f),
         ExpressionValue::Null => PipeValue::Null,
     }
 }
@@ -115,275 +140,860 @@ fn pipe_value_to_expression_value(expr: PipeValue) -> ExpressionValue {
         PipeValue::Number(n) => ExpressionValue::Number(n),
         PipeValue::Double(d) => ExpressionValue::Double(d),
         PipeValue::String(s) => ExpressionValue::String(s),
+        PipeValue::Char(c) => ExpressionValue::Char(c),
         PipeValue::Bool(b) => ExpressionValue::Bool(b),
         PipeValue::Table(t) => ExpressionValue::Table(Rc::new(RefCell::new(t))),
         PipeValue::Row(r) => ExpressionValue::Row(r),
         PipeValue::Array(a) => {
             ExpressionValue::Array(a.into_iter().map(pipe_value_to_expression_value).collect())
         }
+        PipeValue::Function(f) => ExpressionValue::Function(f),
         PipeValue::Null => ExpressionValue::Null,
     }
 }
 
+// Default for how many rows a table in memory can have and still be piped without threads
+const DEFAULT_SEQUENTIAL_PIPE_ROWS: usize = 1000;
+
+// Default for how many bytes of rows a reduce stage can buffer, see set_pipe_memory_limit
+pub const DEFAULT_PIPE_MEMORY_LIMIT: usize = 2 * 1024 * 1024 * 1024;
+
+thread_local! {
+    // Tables with fewer rows than this are piped on the thread evaluating the pipe, see set_sequential_pipe_rows
+    static SEQUENTIAL_PIPE_ROWS: Cell<usize> = const { Cell::new(DEFAULT_SEQUENTIAL_PIPE_ROWS) };
+    // How many bytes of rows a reduce stage of a pipe evaluated on this thread can buffer, see set_pipe_memory_limit
+    static PIPE_MEMORY_LIMIT: Cell<usize> = const { Cell::new(DEFAULT_PIPE_MEMORY_LIMIT) };
+}
+
+thread_local! {
+    // The stats of the stages of the most recent pipe evaluated on this thread, see last_pipe_stats
+    static LAST_PIPE_STATS: RefCell<Vec<StageStats>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(test)]
+thread_local! {
+    // Number of stage threads spawned by pipes evaluated on this thread, so tests can see which path was taken
+    static STAGE_THREADS_SPAWNED: Cell<usize> = const { Cell::new(0) };
+    // Number of stage threads joined by pipes evaluated on this thread, so tests can see that none are left running
+    static STAGE_THREADS_JOINED: Cell<usize> = const { Cell::new(0) };
+}
+
+// A stage is moved into its own thread, so it must own everything it holds. Nothing can then be shared with the
+// stages of another evaluation of the same pipe, e.g. the next iteration of a loop the pipe is in
+const _: fn() = || {
+    fn owns_its_data<T: Send + 'static>() {}
+    owns_its_data::<SimplePipe>();
+};
+
+//Sets how many rows a table in memory can have and still be piped without threads, for pipes evaluated on this thread
+//Small pipes then skip the cost of spawning threads, and their stages fail on the thread running the program.
+//0 always uses threads. Sources that import or generate rows always use threads
+pub fn set_sequential_pipe_rows(rows: usize) {
+    SEQUENTIAL_PIPE_ROWS.with(|limit| limit.set(rows));
+}
+
+//Sets about how many bytes of rows a reduce stage can buffer before it is called, for pipes evaluated on this thread
+//A reduce is given all its rows at once, so on a large import the stage fails with an error rather than running out of memory
+pub fn set_pipe_memory_limit(bytes: usize) {
+    PIPE_MEMORY_LIMIT.with(|limit| limit.set(bytes));
+}
+
+//The rows a reduce stage has received so far, with about how much memory they take
+//The limit is read when the buffer is made, on the thread evaluating the pipe, as the stage may run on a thread of its own
+struct ReduceBuffer {
+    stage: String,
+    table: Table,
+    bytes: usize,
+    limit: usize,
+}
+
+impl ReduceBuffer {
+    fn new(stage: &str, structure: HashMap<String, TableCellType>) -> Self {
+        ReduceBuffer {
+            stage: stage.to_string(),
+            table: Table::new(structure),
+            bytes: 0,
+            limit: PIPE_MEMORY_LIMIT.with(Cell::get),
+        }
+    }
+
+    fn push(&mut self, row: Row) {
+        check_row_structure(&self.stage, &row, self.table.get_structure());
+        self.bytes += row.approximate_size();
+        if self.bytes > self.limit {
+            panic!(
+                "reduce stage '{}' exceeded memory limit ({:.1} MB buffered, {} rows); consider pre-filtering the rows or raising --pipe-memory-limit",
+                self.stage,
+                self.bytes as f64 / (1024.0 * 1024.0),
+                self.table.row_count() + 1
+            );
+        }
+        self.table.add_row(row);
+    }
+}
+
+//What a stage of a pipe did, given to wrench by pipe_stats
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageStats {
+    pub stage: String,
+    pub rows_in: usize,
+    pub rows_out: usize,
+    // Time from the stage starting to it sending its last row
+    pub seconds: f64,
+}
+
+//What the source and the stages of one evaluation of a pipe counted
+//Stages on threads of their own add their stats as they finish, so they are kept with the index of the stage
+#[derive(Default)]
+struct PipeMetrics {
+    import: Option<ImportStats>,
+    stages: Vec<(usize, StageStats)>,
+}
+
+type SharedPipeMetrics = Arc<Mutex<PipeMetrics>>;
+
+fn add_stage_stats(metrics: &SharedPipeMetrics, index: usize, stats: StageStats) {
+    metrics.lock().unwrap().stages.push((index, stats));
+}
+
+//Keeps what a pipe counted for pipe_stats and import_stats, on the thread that evaluated it
+fn record_pipe_metrics(metrics: SharedPipeMetrics) {
+    let mut metrics = metrics.lock().unwrap();
+    metrics.stages.sort_by_key(|(index, _)| *index);
+    let stages = metrics.stages.drain(..).map(|(_, stats)| stats).collect();
+    LAST_PIPE_STATS.with(|last| *last.borrow_mut() = stages);
+    if let Some(import) = metrics.import.take() {
+        record_import_stats(import);
+    }
+}
+
+//The stats of the stages of the most recent pipe evaluated on this thread, in the order rows flow through them
+pub fn last_pipe_stats() -> Vec<StageStats> {
+    LAST_PIPE_STATS.with(|last| last.borrow().clone())
+}
+
+//Spawns the thread of a pipe stage, writing its output and its log where the thread evaluating the pipe does,
+//and calling the same builtins
+fn spawn_stage<F: FnOnce() + Send + 'static>(f: F) -> JoinHandle<()> {
+    #[cfg(test)]
+    STAGE_THREADS_SPAWNED.with(|n| n.set(n.get() + 1));
+    let output = output();
+    let log = current_log();
+    let builtins = current_builtins();
+    thread::spawn(move || {
+        set_output(output);
+        set_log(log);
+        set_builtins(builtins);
+        f()
+    })
+}
+
 //Function that evaluates a pipe expression
+//Like everything else, a pipe is evaluated left to right: the source first, then the arguments of each stage in order
+//Every evaluation makes its own stages, channels and threads, and joins the threads before returning, so a pipe
+//can be evaluated again, e.g. in a loop or a function called from a stage, without reusing anything of an earlier run
 pub fn evaluate_pipes(
     expr: Box<Expr>,
     function_name: String,
     args: Vec<Expr>,
     env: &mut Vec<Vec<EnvironmentCell>>,
 ) -> ExpressionValue {
-    let (pipes, initial_expression) = pipe_rollout(expr.clone(), function_name, args, env);
+    let (stage_calls, initial_expression) = pipe_rollout(expr, function_name, args);
 
-    let (t1, mut rx) = init_pipe(initial_expression, env);
-    let mut middle_threads = Vec::new();
+    let source = evaluate_pipe_source(initial_expression, &stage_calls[0].0, env);
+
+    let pipes: Vec<SimplePipe> = stage_calls
+        .into_iter()
+        .map(|(name, args)| make_simple_pipe(name, args, env))
+        .collect();
+
+    let metrics = SharedPipeMetrics::default();
+    let table = match source {
+        PipeSource::Table(table)
+            if table.row_count() < SEQUENTIAL_PIPE_ROWS.with(|limit| limit.get()) =>
+        {
+            run_pipe_sequentially(table, &pipes, &metrics)
+        }
+        source => run_pipe_threaded(source, &pipes, &metrics),
+    };
+    record_pipe_metrics(metrics);
 
-    for pipe in pipes.iter() {
+    ExpressionValue::Table(Rc::new(RefCell::new(table)))
+}
+
+//Runs every stage in its own thread, with rows sent between them as they are ready
+fn run_pipe_threaded(
+    source: PipeSource,
+    pipes: &[SimplePipe],
+    metrics: &SharedPipeMetrics,
+) -> Table {
+    let (t1, mut rx, mut structure) = spawn_pipe_source(source, metrics.clone());
+    let mut stages = vec![("source".to_string(), t1)];
+
+    // Each stage receives the structure produced by the stage before it
+    for (index, pipe) in pipes.iter().enumerate() {
         let (sn, rn) = mpsc::channel();
-        //let function_env = env_to_closure(&env);
-        let t = pipe_middle_map(pipe.clone(), rx, sn);
+        let output_structure = pipe.get_output_structure(&structure);
+        let t = pipe_middle_map(pipe.clone(), structure, rx, sn, index, metrics.clone());
+        structure = output_structure;
         rx = rn;
-        middle_threads.push(t);
+        stages.push((pipe.get_name(), t));
     }
 
-    let last_pipe = pipes.last().unwrap();
+    // A print stage sends no rows, so this only waits for it to finish
+    let table = collect_pipe_result(pipes.last().unwrap(), structure, rx.iter());
 
-    let mut table;
+    join_pipe_stages(stages);
 
-    match &last_pipe.function {
-        PipeFunction::Custom(_) => {
-            // Collect the response from the last pipe into table
-            table = Table::new(last_pipe.get_return_structure());
-            for row in rx.iter() {
-                table.add_row(row.clone());
-            }
+    table
+}
+
+//The table a pipe gives, of the rows sent by its last stage, with the structure flowing out of that stage
+//That is the structure the type checker gives the pipe, so the table fits the variable it is assigned to
+//Every row is converted to the structure like at any other table boundary, and must have exactly its columns
+fn collect_pipe_result(
+    last_pipe: &SimplePipe,
+    structure: HashMap<String, TableCellType>,
+    rows: impl IntoIterator<Item = Row>,
+) -> Table {
+    let mut table = Table::new(structure);
+    for row in rows {
+        let row = row
+            .coerce(table.get_structure())
+            .unwrap_or_else(|e| panic!("Interpretation error: {}", e));
+        if !row.matches_structure(table.get_structure()) {
+            let columns: Vec<&str> = row.iter().map(|(name, _)| name.as_str()).collect();
+            panic!(
+                "Interpretation error: Pipe stage '{}' sent a row with the columns {}, but the pipe gives a table with the columns {}",
+                last_pipe.get_name(),
+                columns.join(", "),
+                structure_columns(table.get_structure()).join(", ")
+            );
         }
+        table.add_row(row);
+    }
+    table
+}
+
+//Runs the stages one after another on this thread, each over all the rows sent by the stage before it
+//Gives the same table as run_pipe_threaded, and a failing stage is reported the same way
+fn run_pipe_sequentially(table: Table, pipes: &[SimplePipe], metrics: &SharedPipeMetrics) -> Table {
+    let mut structure = table.get_structure().clone();
+    let mut rows: Vec<Row> = table.iter().cloned().collect();
+
+    for (index, pipe) in pipes.iter().enumerate() {
+        let output_structure = pipe.get_output_structure(&structure);
+        let started = Instant::now();
+        let rows_in = rows.len();
+        rows = panic::catch_unwind(AssertUnwindSafe(|| {
+            run_stage_sequentially(pipe, &structure, rows)
+        }))
+        .unwrap_or_else(|e| {
+            panic!(
+                "Pipe stage '{}' terminated unexpectedly: {}",
+                pipe.get_name(),
+                panic_message(e)
+            )
+        });
+        add_stage_stats(
+            metrics,
+            index,
+            StageStats {
+                stage: pipe.get_name(),
+                rows_in,
+                // A print stage sends no rows on
+                rows_out: rows.len(),
+                seconds: started.elapsed().as_secs_f64(),
+            },
+        );
+        structure = output_structure;
+    }
+
+    collect_pipe_result(pipes.last().unwrap(), structure, rows)
+}
+
+//Gives the rows a single stage sends on, given all the rows it receives
+fn run_stage_sequentially(
+    pipe: &SimplePipe,
+    input_structure: &HashMap<String, TableCellType>,
+    rows: Vec<Row>,
+) -> Vec<Row> {
+    let stage = pipe.get_name();
+    match &pipe.function {
+        PipeFunction::Custom(f) => match pipe.get_pipe_type() {
+            PipeType::Map | PipeType::Filter => rows
+                .into_iter()
+                .filter_map(|row| {
+                    check_row_structure(&stage, &row, input_structure);
+                    apply_row_stage(&stage, &pipe.get_pipe_type(), f, &pipe.args, row)
+                })
+                .collect(),
+            PipeType::Reduce => {
+                let mut buffer = ReduceBuffer::new(&stage, input_structure.clone());
+                for row in rows {
+                    buffer.push(row);
+                }
+                apply_table_stage(&stage, f, &pipe.args, buffer.table)
+                    .iter()
+                    .cloned()
+                    .collect()
+            }
+        },
         PipeFunction::Print => {
-            table = Table::new(HashMap::new());
+            pipe_print(rows, input_structure, &mut output());
+            vec![]
+        }
+    }
+}
+
+// How long the remaining stages get to finish after a stage has terminated unexpectedly
+const PIPE_FAILURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+//Makes sure all stage threads are finished, and reports the stage that terminated unexpectedly if any
+//Once a stage has failed, the remaining stages are only waited on for a limited time, so a stage
+//stuck on a closed channel cannot hang the whole program
+fn join_pipe_stages(stages: Vec<(String, JoinHandle<()>)>) {
+    let mut running: Vec<(usize, String, JoinHandle<()>)> = stages
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, t))| (i, name, t))
+        .collect();
+    // The index, name and cause of the most downstream stage that failed
+    let mut failure: Option<(usize, String, String)> = None;
+    let mut failed_at: Option<Instant> = None;
+
+    while !running.is_empty() {
+        let (finished, still_running): (Vec<_>, Vec<_>) =
+            running.into_iter().partition(|(_, _, t)| t.is_finished());
+        running = still_running;
+
+        for (i, name, t) in finished {
+            let result = t.join();
+            #[cfg(test)]
+            STAGE_THREADS_JOINED.with(|n| n.set(n.get() + 1));
+            if let Err(e) = result
+                && failure.as_ref().is_none_or(|(j, _, _)| i > *j)
+            {
+                failure = Some((i, name, panic_message(e)));
+                failed_at.get_or_insert_with(Instant::now);
+            }
+        }
+
+        if failed_at.is_some_and(|t| t.elapsed() > PIPE_FAILURE_TIMEOUT) {
+            break;
+        }
+        if !running.is_empty() {
+            thread::sleep(Duration::from_millis(1));
         }
     }
 
-    // Make sure threads are finished
-    t1.join().unwrap();
-    for t in middle_threads {
-        t.join().unwrap();
+    if let Some((_, name, cause)) = failure {
+        panic!("Pipe stage '{}' terminated unexpectedly: {}", name, cause);
     }
+}
 
-    ExpressionValue::Table(Rc::new(RefCell::new(table)))
+//Gets the message of a panic caught from a thread
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown error".to_string()
+    }
 }
 
-//Takes a pipe that can contain multiple pipes and converts them to a vector and evaluates arguments
-//async_import(...) pipe x(...) pipe y(...) is converted to a vector of simple pipes and returned along with the initial expression "async_import(...)"
+//Takes a pipe that can contain multiple pipes and converts it to the calls of its stages, in the order rows flow through them
+//async_import(...) pipe x(...) pipe y(...) is converted to the calls x(...) and y(...), returned along with the initial expression "async_import(...)"
 //Initial expression can be async_import(...) or an expression that evaluates to a table
+//Nothing is evaluated here, so the arguments of the stages can be evaluated after the initial expression
 fn pipe_rollout(
     expr: Box<Expr>,
     function_name: String,
     args: Vec<Expr>,
+) -> (Vec<(String, Vec<Expr>)>, Box<Expr>) {
+    let mut stage_calls = vec![(function_name, args)];
+    let mut initial_expression = expr;
+    while let Expr::Pipe(e, f, a) = *initial_expression {
+        stage_calls.push((f, a.into_iter().map(|boxed| *boxed).collect()));
+        initial_expression = e;
+    }
+    stage_calls.reverse();
+    (stage_calls, initial_expression)
+}
+
+//Evaluates the arguments of a stage, left to right, and finds the function it calls
+fn make_simple_pipe(
+    function_name: String,
+    args: Vec<Expr>,
     env: &mut Vec<Vec<EnvironmentCell>>,
-) -> (Vec<SimplePipe>, Box<Expr>) {
+) -> SimplePipe {
     let evaluated_args = args
-        .iter()
-        .map(|arg| expression_value_to_pipe_value(evaluate_expression(arg.clone(), env)))
+        .into_iter()
+        .map(|arg| expression_value_to_pipe_value(evaluate_expression(arg, env)))
         .collect::<Vec<PipeValue>>();
 
+    // A stage into a lambda calls the lambda, which is its only argument
+    if function_name == LAMBDA_STAGE
+        && let [PipeValue::Function(lambda)] = evaluated_args.as_slice()
+    {
+        return SimplePipe {
+            function: PipeFunction::Custom(lambda.clone()),
+            args: Vec::new(),
+        };
+    }
+
+    // A bound stage calls the function it names, with the bound arguments after the rows
+    let (function_name, evaluated_args) = match function_name.as_str() {
+        "bind" => match evaluated_args.split_first() {
+            Some((PipeValue::String(name), bound)) => (name.to_string(), bound.to_vec()),
+            _ => panic!("Interpretation error: bind expects the name of a function"),
+        },
+        _ => (function_name, evaluated_args),
+    };
+
     let function = match function_name.as_str() {
         "print" => PipeFunction::Print,
-        _ => {
-            if let EnvironmentCell::Function(f) = env_get(env, &function_name) {
-                PipeFunction::Custom(f)
-            } else {
-                panic!("Expected a function for the pipe");
+        _ => match env_get(env, &function_name) {
+            EnvironmentCell::Function(f) => PipeFunction::Custom(f),
+            // A lambda held in a variable is named by the variable in the stats of the pipe
+            EnvironmentCell::Variable(_, ExpressionValue::Function(f)) => {
+                PipeFunction::Custom(WrenchFunction {
+                    name: function_name.clone(),
+                    ..f
+                })
             }
-        }
+            EnvironmentCell::Variable(_, ExpressionValue::Table(_)) => panic!(
+                "Interpretation error: {}",
+                pipe_right_is_table_message(&function_name)
+            ),
+            EnvironmentCell::Variable(_, _) => panic!(
+                "Interpretation error: The right side of 'pipe' must be a function, but '{}' is a variable",
+                function_name
+            ),
+            EnvironmentCell::Deferred(_) => unreachable!("deferred statements have no name"),
+        },
     };
 
-    let pipe = SimplePipe {
-        function: function.clone(),
+    SimplePipe {
+        function,
         args: evaluated_args,
-    };
-
-    // Collect through recursion
-    if let Expr::Pipe(e, f, a) = *expr {
-        let a_unboxed: Vec<Expr> = a.into_iter().map(|boxed| *boxed).collect();
-        let (mut rest_pipes, initial_expression) = pipe_rollout(e, f, a_unboxed, env);
-        rest_pipes.push(pipe);
-        (rest_pipes, initial_expression)
-    } else {
-        //Base case
-        let pipes = vec![pipe];
-
-        (pipes, expr.clone())
     }
 }
 
+//The first expression of a pipe, evaluated before the arguments of the stages
+enum PipeSource {
+    // async_import(...), with its evaluated arguments
+    Import(Vec<PipeValue>),
+    // async_import_glob(...), with the files matching its pattern and its evaluated arguments
+    ImportGlob(Vec<String>, Vec<PipeValue>),
+    // rows_from_range(count, "maker")
+    Generate(i64, WrenchFunction),
+    // Any other expression, which must evaluate to a table
+    Table(Table),
+}
+
 //Is responsible for evaluating the first expression of the pipe
 //In async_import(...) pipe x(...), async_import(...) is evaluated in a separate thread, and values are passed to the next pipe
-fn init_pipe(
+//rows_from_range(n, "maker") likewise generates its rows in a separate thread, without materializing a table first
+fn evaluate_pipe_source(
     initial_expression: Box<Expr>,
+    first_stage_name: &str,
     env: &mut Vec<Vec<EnvironmentCell>>,
-) -> (JoinHandle<()>, mpsc::Receiver<Row>) {
-    if let Expr::FunctionCall(name, args) = *initial_expression.clone() {
-        if name == "async_import" {
-            let left_args = args
-                .iter()
-                .map(|arg| expression_value_to_pipe_value(evaluate_expression(*arg.clone(), env)))
-                .collect::<Vec<PipeValue>>();
-            let (s, r): (mpsc::Sender<Row>, mpsc::Receiver<Row>) = mpsc::channel();
-            let t = thread::spawn({
-                move || {
-                    pipe_import(left_args.clone(), s);
-                }
-            });
-            (t, r)
-        } else {
-            let expr = evaluate_expression(*initial_expression, env);
-            let (s, r): (mpsc::Sender<Row>, mpsc::Receiver<Row>) = mpsc::channel();
+) -> PipeSource {
+    // A function on the left is almost always a pipe written the wrong way around
+    if let Expr::Identifier(name) = &*initial_expression
+        && let EnvironmentCell::Function(_) = env_get(env, name)
+    {
+        panic!(
+            "Interpretation error: {}",
+            pipe_left_is_function_message(name, first_stage_name)
+        );
+    }
 
-            if let ExpressionValue::Table(t) = expr {
-                let table = t.borrow().clone();
+    if let Expr::FunctionCall(name, args) = *initial_expression.clone()
+        && name == "async_import"
+    {
+        let values: Vec<ExpressionValue> = args
+            .iter()
+            .map(|arg| evaluate_expression(*arg.clone(), env))
+            .collect();
+        check_source_arguments(&name, &values);
+        // A dry run only checks the file, so the stages run over no rows
+        if is_dry_run() {
+            let file = match &values[0] {
+                ExpressionValue::String(s) => s.to_string(),
+                other => panic!(
+                    "Expected a file name for the first argument of async_import, found {:?}",
+                    other
+                ),
+            };
+            return PipeSource::Table(dry_run_import(&[file], &values));
+        }
+        return PipeSource::Import(
+            values
+                .into_iter()
+                .map(expression_value_to_pipe_value)
+                .collect(),
+        );
+    }
 
-                let t = thread::spawn({
-                    move || {
-                        pipe_init_table(table, s);
-                    }
-                });
-                (t, r)
-            } else {
-                panic!("Table expected for the pipe");
+    // The pattern is expanded before any stage runs, so a pattern matching no files fails here
+    if let Expr::FunctionCall(name, args) = *initial_expression.clone()
+        && name == "async_import_glob"
+    {
+        let values: Vec<ExpressionValue> = args
+            .iter()
+            .map(|arg| evaluate_expression(*arg.clone(), env))
+            .collect();
+        check_source_arguments(&name, &values);
+        let files = match &values[0] {
+            ExpressionValue::String(pattern) => {
+                glob_files(pattern).unwrap_or_else(|e| panic!("Interpretation error: {}", e))
             }
+            other => panic!(
+                "Expected a pattern for the first argument of async_import_glob, found {:?}",
+                other
+            ),
+        };
+        if is_dry_run() {
+            return PipeSource::Table(dry_run_import(&files, &values));
         }
-    } else {
-        let expr = evaluate_expression(*initial_expression, env);
-        let (s, r): (mpsc::Sender<Row>, mpsc::Receiver<Row>) = mpsc::channel();
+        return PipeSource::ImportGlob(
+            files,
+            values
+                .into_iter()
+                .map(expression_value_to_pipe_value)
+                .collect(),
+        );
+    }
+
+    if let Expr::FunctionCall(name, args) = *initial_expression.clone()
+        && name == "rows_from_range"
+    {
+        let values: Vec<ExpressionValue> = args
+            .iter()
+            .map(|arg| evaluate_expression(*arg.clone(), env))
+            .collect();
+        check_source_arguments(&name, &values);
+        let count = match &values[0] {
+            ExpressionValue::Number(n) => *n,
+            other => panic!(
+                "Expected an int for the first argument of rows_from_range, found {:?}",
+                other
+            ),
+        };
+        let maker = match &values[1] {
+            ExpressionValue::String(s) => match env_get(env, s) {
+                EnvironmentCell::Function(f) => f,
+                _ => panic!("Expected '{}' given to rows_from_range to be a function", s),
+            },
+            other => panic!(
+                "Expected a function name for the second argument of rows_from_range, found {:?}",
+                other
+            ),
+        };
+        return PipeSource::Generate(count, maker);
+    }
 
-        if let ExpressionValue::Table(t) = expr {
-            let table = t.borrow().clone();
+    if let ExpressionValue::Table(t) = evaluate_expression(*initial_expression, env) {
+        PipeSource::Table(t.borrow().clone())
+    } else {
+        panic!("Table expected for the pipe");
+    }
+}
 
-            let t = thread::spawn({
-                move || {
-                    pipe_init_table(table, s);
-                }
+//Spawns the thread sending the rows of the source to the first stage
+//Also returns the structure of the rows the source sends
+//An import records its stats in the metrics of the pipe
+fn spawn_pipe_source(
+    source: PipeSource,
+    metrics: SharedPipeMetrics,
+) -> (
+    JoinHandle<()>,
+    mpsc::Receiver<Row>,
+    HashMap<String, TableCellType>,
+) {
+    let (s, r): (mpsc::Sender<Row>, mpsc::Receiver<Row>) = mpsc::channel();
+    match source {
+        PipeSource::Import(left_args) => {
+            let structure = if let Some(PipeValue::Table(t)) = left_args.get(1) {
+                t.get_structure().clone()
+            } else {
+                panic!("Expected a table for the second argument of pipe_import");
+            };
+            let t = spawn_stage(move || {
+                let stats = pipe_import(left_args, s);
+                metrics.lock().unwrap().import = Some(stats);
             });
-            (t, r)
-        } else {
-            panic!("Table expected for the pipe");
+            (t, r, structure)
+        }
+        PipeSource::ImportGlob(files, left_args) => {
+            let structure = if let Some(PipeValue::Table(t)) = left_args.get(1) {
+                t.get_structure().clone()
+            } else {
+                panic!("Expected a table for the second argument of pipe_import");
+            };
+            let t = spawn_stage(move || {
+                let stats = pipe_import_files(files, left_args, s);
+                metrics.lock().unwrap().import = Some(stats);
+            });
+            (t, r, structure)
+        }
+        PipeSource::Generate(count, maker) => {
+            let structure = if let TypeConstruct::Row(params) = maker.return_type.clone() {
+                Table::parameters_to_structure(params)
+            } else {
+                panic!("Expected generator '{}' to return a row", maker.name);
+            };
+            let t = spawn_stage(move || pipe_generate(count, maker, s));
+            (t, r, structure)
+        }
+        PipeSource::Table(table) => {
+            let structure = table.get_structure().clone();
+            let t = spawn_stage(move || pipe_init_table(table, s));
+            (t, r, structure)
+        }
+    }
+}
+
+//Checks the arguments of a builtin used as the source of a pipe, as they would be checked when called outside a pipe
+fn check_source_arguments(name: &str, args: &[ExpressionValue]) {
+    if let Some(builtin) = lookup_builtin(name) {
+        builtin.check_arguments(args);
+    }
+}
+
+//Checks that a row arriving at a pipe stage has the structure the stage expects
+//Only done in debug builds, so release builds do not pay for the check on every row
+fn check_row_structure(stage: &str, row: &Row, structure: &HashMap<String, TableCellType>) {
+    if cfg!(debug_assertions) && !row.matches_structure(structure) {
+        panic!(
+            "Pipe stage '{}' received a row that does not match its input structure. Expected {}, found {}",
+            stage,
+            describe_structure(structure),
+            describe_structure(&row.structure())
+        );
+    }
+}
+
+//What a map or filter stage sends on for a row it receives: the row a map returns, or the row itself if a filter keeps it
+fn apply_row_stage(
+    stage: &str,
+    pipe_type: &PipeType,
+    function: &WrenchFunction,
+    args: &[PipeValue],
+    row: Row,
+) -> Option<Row> {
+    let result = evaluate_fn_row_call(row.clone(), function.clone(), args.to_vec());
+    match (pipe_type, result) {
+        (PipeType::Map, PipeValue::Row(r)) => Some(coerce_result_row(function, r)),
+        (PipeType::Map, other) => panic!(
+            "Expected map '{}' to return a row, but it returned {:?}",
+            stage, other
+        ),
+        (PipeType::Filter, PipeValue::Bool(b)) => b.then_some(row),
+        (PipeType::Filter, other) => panic!(
+            "Expected filter '{}' to return a boolean, but it returned {:?}",
+            stage, other
+        ),
+        (PipeType::Reduce, _) => unreachable!("a reduce is given the table of all its rows"),
+    }
+}
+
+//The columns of the row or table a stage function is declared to return
+fn return_structure(function: &WrenchFunction) -> HashMap<String, TableCellType> {
+    match &function.return_type {
+        TypeConstruct::Row(params) | TypeConstruct::Table(params) => {
+            Table::parameters_to_structure(params.clone())
         }
+        other => panic!(
+            "Expected '{}' to return a row or a table, found {:?}",
+            function.name, other
+        ),
     }
 }
+
+//A row returned by a map, with its cells converted to the types of the columns the map declares
+fn coerce_result_row(function: &WrenchFunction, row: Row) -> Row {
+    row.coerce(&return_structure(function))
+        .unwrap_or_else(|e| panic!("Interpretation error: {}", e))
+}
+
+//The table a reduce stage returns, given the table of all the rows it receives
+fn apply_table_stage(
+    stage: &str,
+    function: &WrenchFunction,
+    args: &[PipeValue],
+    table: Table,
+) -> Table {
+    match evaluate_fn_table_call(table, function.clone(), args.to_vec()) {
+        PipeValue::Table(t) => {
+            let mut result = Table::new(return_structure(function));
+            for row in t.iter() {
+                result.add_row(row.clone());
+            }
+            result
+        }
+        other => panic!(
+            "Expected reduce '{}' to return a table, but it returned {:?}",
+            stage, other
+        ),
+    }
+}
+
+//Spawns the thread for a single pipe stage. input_structure is the structure of the rows sent by the previous stage
+//The stage adds its stats to the metrics of the pipe when it finishes, as the stage at index of the pipe
 fn pipe_middle_map(
     pipe: SimplePipe,
+    input_structure: HashMap<String, TableCellType>,
     receiver: mpsc::Receiver<Row>,
     sender: mpsc::Sender<Row>,
+    index: usize,
+    metrics: SharedPipeMetrics,
 ) -> JoinHandle<()> {
+    let stage = pipe.get_name();
+    let mut stats = StageStats {
+        stage: stage.clone(),
+        rows_in: 0,
+        rows_out: 0,
+        seconds: 0.0,
+    };
     match pipe.clone().function {
-        PipeFunction::Custom(f) => {
-            match pipe.clone().get_pipe_type() {
-                PipeType::Map => {
-                    // Evaluate each row at a time
-                    thread::spawn({
-                        move || {
-                            for row in receiver {
-                                let result =
-                                    evaluate_fn_row_call(row.clone(), f.clone(), pipe.args.clone());
-                                match result {
-                                    PipeValue::Row(r) => {
-                                        sender.send(r).unwrap();
-                                    }
-                                    _ => {
-                                        panic!("Expected a row or table for the map");
-                                    }
-                                }
+        PipeFunction::Custom(f) => match pipe.get_pipe_type() {
+            pipe_type @ (PipeType::Map | PipeType::Filter) => {
+                // Evaluate each row at a time
+                spawn_stage(move || {
+                    let started = Instant::now();
+                    for row in receiver {
+                        stats.rows_in += 1;
+                        check_row_structure(&stage, &row, &input_structure);
+                        if let Some(row) = apply_row_stage(&stage, &pipe_type, &f, &pipe.args, row)
+                        {
+                            // The next stage has stopped, so there is no one to send to
+                            if sender.send(row).is_err() {
+                                break;
                             }
+                            stats.rows_out += 1;
                         }
-                    })
-                }
-                PipeType::Filter => {
-                    // Evaluate each row at a time
-                    thread::spawn({
-                        move || {
-                            for row in receiver {
-                                let result =
-                                    evaluate_fn_row_call(row.clone(), f.clone(), pipe.args.clone());
-                                match result {
-                                    PipeValue::Bool(b) => {
-                                        if b {
-                                            sender.send(row).unwrap();
-                                        }
-                                    }
-                                    _ => {
-                                        panic!("Expected a boolean for the filter");
-                                    }
-                                }
-                            }
-                        }
-                    })
-                }
-                PipeType::Reduce => {
-                    // Evaluate each row at a time
-                    thread::spawn({
-                        move || {
-                            let mut table = Table::new(pipe.get_call_structure());
-                            for row in receiver {
-                                table.add_row(row.clone());
-                            }
-                            let result =
-                                evaluate_fn_table_call(table, f.clone(), pipe.args.clone());
-                            match result {
-                                PipeValue::Table(t) => {
-                                    for row in t.iter() {
-                                        sender.send(row.clone()).unwrap();
-                                    }
-                                }
-                                _ => {
-                                    panic!("Expected a table for the reduce");
-                                }
-                            }
+                    }
+                    stats.seconds = started.elapsed().as_secs_f64();
+                    add_stage_stats(&metrics, index, stats);
+                })
+            }
+            PipeType::Reduce => {
+                let mut buffer = ReduceBuffer::new(&stage, input_structure);
+                spawn_stage(move || {
+                    let started = Instant::now();
+                    for row in receiver {
+                        stats.rows_in += 1;
+                        buffer.push(row);
+                    }
+                    let result = apply_table_stage(&stage, &f, &pipe.args, buffer.table);
+                    for row in result.iter() {
+                        if sender.send(row.clone()).is_err() {
+                            break;
                         }
-                    })
-                }
+                        stats.rows_out += 1;
+                    }
+                    stats.seconds = started.elapsed().as_secs_f64();
+                    add_stage_stats(&metrics, index, stats);
+                })
             }
-        }
+        },
         PipeFunction::Print => {
             // Evaluate each row at a time
-            thread::spawn({
-                move || {
-                    pipe_print(receiver);
-                }
+            spawn_stage(move || {
+                let started = Instant::now();
+                let rows = receiver.into_iter().inspect(|_| stats.rows_in += 1);
+                pipe_print(rows, &input_structure, &mut output());
+                stats.seconds = started.elapsed().as_secs_f64();
+                add_stage_stats(&metrics, index, stats);
             })
         }
     }
 }
 
 //Imports a CSV file one row at a time and sends it to the next pipe
-fn pipe_import(args: Vec<PipeValue>, sender: mpsc::Sender<Row>) {
-    let name = if let PipeValue::String(s) = args[0].clone() {
-        s
+fn pipe_import(args: Vec<PipeValue>, sender: mpsc::Sender<Row>) -> ImportStats {
+    let name = if let PipeValue::String(s) = &args[0] {
+        s.to_string()
     } else {
         panic!("Expected a string literal for the first argument of pipe_import");
     };
-    let structure = if let PipeValue::Table(t) = args[1].clone() {
-        t.get_structure().clone()
-    } else {
+    let (structure, options) = pipe_import_arguments(&args);
+    // Stop importing when the next stage has stopped receiving
+    let row_callback = move |row: Row| sender.send(row).is_ok();
+    import_csv(name, structure, &options, row_callback)
+}
+
+//Imports the CSV files one after the other, a row at a time, and sends the rows to the next pipe
+fn pipe_import_files(
+    files: Vec<String>,
+    args: Vec<PipeValue>,
+    sender: mpsc::Sender<Row>,
+) -> ImportStats {
+    let (structure, options) = pipe_import_arguments(&args);
+    let row_callback = move |row: Row| sender.send(row).is_ok();
+    import_csv_files(&files, structure, &options, row_callback)
+}
+
+//The structure to import into and the import options, from the arguments of async_import or async_import_glob
+fn pipe_import_arguments(args: &[PipeValue]) -> (HashMap<String, TableCellType>, ImportOptions) {
+    let PipeValue::Table(table) = &args[1] else {
         panic!("Expected a table for the second argument of pipe_import");
     };
-    let row_callback = move |row: Row| {
-        sender.send(row).unwrap();
+    let mut options = match args.get(2) {
+        Some(PipeValue::Row(row)) => ImportOptions::from_row(row),
+        Some(_) => panic!("Expected a row of import options for the third argument of pipe_import"),
+        None => ImportOptions::default(),
     };
-    import_csv(name, structure, row_callback);
+    options.fills = table.get_fills().clone();
+    (table.get_structure().clone(), options)
+}
+
+//Calls the maker function for each index from 0 to count and sends the rows it returns to the next pipe
+fn pipe_generate(count: i64, maker: WrenchFunction, sender: mpsc::Sender<Row>) {
+    for i in 0..count {
+        match evaluate_custom_function_call(&maker, vec![ExpressionValue::Number(i)]) {
+            ExpressionValue::Row(row) => {
+                if sender.send(row).is_err() {
+                    break;
+                }
+            }
+            other => panic!(
+                "Expected generator '{}' to return a row, but it returned {:?}",
+                maker.name, other
+            ),
+        }
+    }
 }
 
 //Helper function which evaluates an entire pipe expression with posible multiple pipes to a table
 fn pipe_init_table(table: Table, sender: mpsc::Sender<Row>) {
     for row in table.iter() {
-        sender.send(row.clone()).unwrap();
+        if sender.send(row.clone()).is_err() {
+            break;
+        }
     }
 }
 
 //Wrench library function for printing in a pipe
-fn pipe_print(receiver: mpsc::Receiver<Row>) {
+//Prints the header once, then each row as it arrives, and finally the number of rows
+fn pipe_print<W: Write>(
+    rows: impl IntoIterator<Item = Row>,
+    structure: &HashMap<String, TableCellType>,
+    out: &mut W,
+) {
+    let renderer = TableRenderer::new(structure);
+    check_output(writeln!(out, "{}", renderer.header()));
+    let mut row_count = 0;
     // Evaluate each row at a time
-    for row in receiver {
-        wrench_print(vec![ExpressionValue::Row(row.clone())]);
+    for row in rows {
+        check_output(writeln!(out, "{}", renderer.row(&row)));
+        row_count += 1;
     }
+    check_output(writeln!(out, "{}", renderer.footer(row_count)));
 }
 
 //Evaluates a function call where row is inserted as the first argument followed by the rest of the arguments given
@@ -415,10 +1025,38 @@ fn evaluate_fn_table_call(
 }
 #[cfg(test)]
 mod tests {
-    use crate::frontend::ast::Statement;
+    use std::io::Write;
+
+    use crate::{
+        backend::{
+            environment::{env_add, env_expand_scope, env_new},
+            evaluate::{StatementValue, evaluate_statement},
+            table::TableCell,
+        },
+        frontend::{
+            ast::{Parameter, Statement},
+            main::create_syntax_tree,
+        },
+    };
 
     use super::*;
 
+    // Runs a program and returns the value of its top level return statement
+    fn run_program(input: &str) -> ExpressionValue {
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        match evaluate_statement(create_syntax_tree(input).unwrap(), &mut env) {
+            StatementValue::Return(value) => value,
+            _ => ExpressionValue::Null,
+        }
+    }
+
+    fn write_csv(content: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file
+    }
+
     fn make_env_with_function(_: &str, func: WrenchFunction) -> Vec<Vec<EnvironmentCell>> {
         vec![vec![EnvironmentCell::Function(func)]]
     }
@@ -443,8 +1081,8 @@ mod tests {
     fn test_expression_value_to_pipe_value_and_back() {
         let exprs = vec![
             ExpressionValue::Number(42),
-            ExpressionValue::Double(3.14),
-            ExpressionValue::String("hello".to_string()),
+            ExpressionValue::Double(2.5),
+            ExpressionValue::String("hello".into()),
             ExpressionValue::Bool(true),
             ExpressionValue::Null,
             ExpressionValue::Array(vec![ExpressionValue::Number(1), ExpressionValue::Number(2)]),
@@ -465,8 +1103,11 @@ mod tests {
         )]));
         let env = &mut make_env_with_function("dummy", func.clone());
         let expr = Box::new(Expr::Number(1));
-        let (pipes, initial) = pipe_rollout(expr.clone(), "dummy".to_string(), vec![], env);
-        assert_eq!(pipes.len(), 1);
+        let (stage_calls, initial) = pipe_rollout(expr.clone(), "dummy".to_string(), vec![]);
+        assert_eq!(stage_calls.len(), 1);
+        let (name, args) = stage_calls.into_iter().next().unwrap();
+        let pipe = make_simple_pipe(name, args, env);
+        assert_eq!(pipe.get_name(), "dummy");
         assert_eq!(format!("{:?}", *initial), format!("{:?}", *expr));
     }
 
@@ -502,4 +1143,1173 @@ mod tests {
         };
         assert!(matches!(pipe.get_pipe_type(), PipeType::Reduce));
     }
+
+    #[test]
+    fn test_output_structure_follows_stages() {
+        let input: HashMap<String, TableCellType> =
+            HashMap::from([("id".to_string(), TableCellType::Int)]);
+        let renamed = Table::parameters_to_structure(vec![Parameter::Parameter(
+            TypeConstruct::Int,
+            "new_id".to_string(),
+        )]);
+
+        let map = SimplePipe {
+            function: PipeFunction::Custom(dummy_wrench_function(TypeConstruct::Row(vec![
+                Parameter::Parameter(TypeConstruct::Int, "new_id".to_string()),
+            ]))),
+            args: vec![],
+        };
+        let filter = SimplePipe {
+            function: PipeFunction::Custom(dummy_wrench_function(TypeConstruct::Bool)),
+            args: vec![],
+        };
+        let print = SimplePipe {
+            function: PipeFunction::Print,
+            args: vec![],
+        };
+
+        assert_eq!(map.get_output_structure(&input), renamed);
+        assert_eq!(filter.get_output_structure(&renamed), renamed);
+        assert_eq!(print.get_output_structure(&input), input);
+    }
+
+    // Type checks and runs a program, and returns the value of its top level return statement
+    fn run_checked_program(input: &str) -> ExpressionValue {
+        let tree = crate::frontend::main::check(input).unwrap();
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        match evaluate_statement(tree, &mut env) {
+            StatementValue::Return(value) => value,
+            _ => ExpressionValue::Null,
+        }
+    }
+
+    // The rows of a table returned by a program
+    fn table_rows(value: ExpressionValue) -> Vec<Row> {
+        match value {
+            ExpressionValue::Table(t) => t.borrow().iter().cloned().collect(),
+            other => panic!("Expected a table, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_row_literal_as_call_argument() {
+        let rows = table_rows(run_checked_program(
+            "
+            var table(int id, string name) t = table(int id, string name);
+            table_add_row(t, row(int id = 7, string name = \"Zoe\"));
+            return t;
+            ",
+        ));
+        assert_eq!(
+            rows,
+            vec![Row::new(vec![
+                ("id".to_string(), TableCell::Int(7)),
+                ("name".to_string(), TableCell::String("Zoe".into())),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_table_literal_in_import_piped_with_arguments() {
+        let file = write_csv("a,b\n1,0.5\n2,1.5\n");
+        let program = format!(
+            "
+            fn row(int a, double b) scale(row(int a, double b) r, double k) {{
+                return row(int a = r.a, double b = r.b * k);
+            }};
+            return import(\"{}\", table(int a, double b)) pipe scale(2.0);
+            ",
+            file.path().display()
+        );
+        let rows = table_rows(run_checked_program(&program));
+        let b: Vec<Option<&TableCell>> = rows.iter().map(|r| r.get_cell("b")).collect();
+        assert_eq!(
+            b,
+            vec![Some(&TableCell::Double(1.0)), Some(&TableCell::Double(3.0))]
+        );
+    }
+
+    #[test]
+    fn test_table_argument_copied_for_each_stage_call() {
+        // Pipe stages run on their own threads, so a table given as an argument to a stage is copied when the
+        // pipeline starts, and every call of the stage function gets its own copy of it.
+        // Rows added to it in the stage are seen neither by the next call nor by the program after the pipe.
+        // A table given to an ordinary function call is shared, so rows added to it there are seen by the caller
+        let program = "
+            fn int count(table(int id) t) {
+                var int n = 0;
+                for (row(int id) r in t) {
+                    n = n + 1;
+                }
+                return n;
+            };
+            fn bool first_seen(row(int id) r, table(int id) seen) {
+                table_add_row(seen, r);
+                return count(seen) == 1;
+            };
+            var table(int id) seen = table(int id);
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 1));
+            table_add_row(t, row(int id = 2));
+            var table(int id) kept = t pipe first_seen(seen);
+            var int after_pipe = count(seen);
+            var table(int id) kept_literal = t pipe first_seen(table(int id));
+            first_seen(row(int id = 3), seen);
+            return row(int kept = count(kept), int kept_literal = count(kept_literal), int after_pipe = after_pipe, int after_call = count(seen));
+            ";
+        match run_checked_program(program) {
+            ExpressionValue::Row(row) => {
+                assert_eq!(row.get_cell("kept"), Some(&TableCell::Int(2)));
+                assert_eq!(row.get_cell("kept_literal"), Some(&TableCell::Int(2)));
+                assert_eq!(row.get_cell("after_pipe"), Some(&TableCell::Int(0)));
+                assert_eq!(row.get_cell("after_call"), Some(&TableCell::Int(1)));
+            }
+            other => panic!("Expected a row, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pipe_stage_argument_is_type_checked() {
+        let result = crate::frontend::main::check(
+            "
+            fn bool has_id(row(int id) r, table(int id) ids) {
+                return true;
+            };
+            var table(int id) t = table(int id);
+            return t pipe has_id(5);
+            ",
+        );
+        assert_eq!(
+            result.err(),
+            Some(
+                "Type mismatch in argument to pipe function 'has_id': expected Table([Parameter(Int, \"id\")]), found Int"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_filter_comparisons_mixing_int_and_double_columns() {
+        // Each condition is used as a filter, with the ages of the rows it should keep
+        let cases = [
+            ("r.score >= 0.5", vec![18, 30]),
+            ("0.5 <= r.score", vec![18, 30]),
+            ("r.age > 17.5", vec![18, 30]),
+            ("17.5 < r.age", vec![18, 30]),
+            ("r.age >= 18.0", vec![18, 30]),
+            ("18.0 <= r.age", vec![18, 30]),
+            ("r.age <= 17.5", vec![17]),
+            ("r.age < 18.0", vec![17]),
+            ("r.score > 0", vec![17, 18, 30]),
+            ("1 >= r.score", vec![17, 18, 30]),
+            ("r.age == 18.0", vec![18]),
+            ("0.5 == r.score", vec![18]),
+            ("r.age <= r.score * 40", vec![18, 30]),
+            ("r.score * 40 < r.age", vec![17]),
+        ];
+        for (condition, expected) in cases {
+            let program = format!(
+                "
+                fn bool keep(row(int age, double score) r) {{
+                    return {};
+                }};
+                var table(int age, double score) t = table(int age, double score);
+                table_add_row(t, row(int age = 17, double score = 0.4));
+                table_add_row(t, row(int age = 18, double score = 0.5));
+                table_add_row(t, row(int age = 30, double score = 0.9));
+                return t pipe keep();
+                ",
+                condition
+            );
+            let ages: Vec<Option<TableCell>> = table_rows(run_checked_program(&program))
+                .iter()
+                .map(|row| row.get_cell("age").cloned())
+                .collect();
+            let expected: Vec<Option<TableCell>> = expected
+                .into_iter()
+                .map(|age| Some(TableCell::Int(age)))
+                .collect();
+            assert_eq!(ages, expected, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn test_constant_file_name_imported_through_pipe() {
+        let file = write_csv("id,name\n1,a\n2,b\n");
+        let program = format!(
+            "
+            const string FILE = \"{}\";
+            const string SAME_FILE = FILE;
+            fn bool keep(row(int id, string name) r) {{
+                return r.id > 1;
+            }};
+            var table(int id, string name) t = import(SAME_FILE, table(int id, string name)) pipe keep();
+            return t;
+            ",
+            file.path().display()
+        );
+        let rows = table_rows(run_checked_program(&program));
+        assert_eq!(
+            rows,
+            vec![Row::new(vec![
+                ("id".to_string(), TableCell::Int(2)),
+                ("name".to_string(), TableCell::String("b".into())),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_constant_maker_name_for_rows_from_range() {
+        let program = "
+            const string MAKER = \"make\";
+            const int COUNT = 3;
+            fn row(int id) make(int i) {
+                return row(int id = i);
+            };
+            fn bool all(row(int id) r) {
+                return true;
+            };
+            return rows_from_range(COUNT, MAKER) pipe all();
+            ";
+        assert_eq!(table_rows(run_checked_program(program)).len(), 3);
+    }
+
+    #[test]
+    fn test_maker_name_from_variable_is_rejected() {
+        let result = crate::frontend::main::check(
+            "
+            var string maker = \"make\";
+            fn row(int id) make(int i) {
+                return row(int id = i);
+            };
+            fn bool all(row(int id) r) {
+                return true;
+            };
+            return rows_from_range(3, maker) pipe all();
+            ",
+        );
+        assert_eq!(
+            result.err(),
+            Some("Second argument to 'rows_from_range' must be the name of a function as a string literal or constant".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bound_filter_in_pipe() {
+        let program = "
+            fn row(int age) make(int i) {
+                return row(int age = i);
+            };
+            fn bool at_least(row(int age) r, int age) {
+                return age <= r.age;
+            };
+            const fn bool(row(int age)) adult = bind(\"at_least\", 18);
+            var table(int age) declared = rows_from_range(25, \"make\") pipe adult();
+            var table(int age) inline = rows_from_range(25, \"make\") pipe bind(\"at_least\", 21);
+            return [declared, inline];
+            ";
+        let ExpressionValue::Array(tables) = run_checked_program(program) else {
+            panic!("Expected an array of tables");
+        };
+        let ages = |table: &ExpressionValue| -> Vec<TableCell> {
+            let mut ages: Vec<TableCell> = table_rows(table.clone())
+                .iter()
+                .map(|row| row.get_cell("age").unwrap().clone())
+                .collect();
+            ages.sort_by_key(|age| age.to_string().parse::<i64>().unwrap());
+            ages
+        };
+        assert_eq!(
+            ages(&tables[0]),
+            (18..25).map(TableCell::Int).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ages(&tables[1]),
+            (21..25).map(TableCell::Int).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_streaming_import_of_quoted_fields_matches_blocking_import() {
+        let file =
+            write_csv("id,note\n1,\"a, b\"\n2,\"say \"\"hi\"\"\"\n3,\"two\nlines\"\n4,plain\n");
+        let program = format!(
+            "
+            fn bool all(row(int id, string note) r) {{
+                return true;
+            }};
+            var table(int id, string note) blocking = import(\"{0}\", table(int id, string note));
+            var table(int id, string note) streamed = async_import(\"{0}\", table(int id, string note)) pipe all();
+            return [blocking, streamed];
+            ",
+            file.path().to_str().unwrap()
+        );
+        let ExpressionValue::Array(tables) = run_checked_program(&program) else {
+            panic!("Expected an array of tables");
+        };
+        let blocking = table_rows(tables[0].clone());
+        assert_eq!(blocking.len(), 4);
+        assert_eq!(
+            blocking[2].get_cell("note"),
+            Some(&TableCell::String("two\nlines".into()))
+        );
+        assert_eq!(table_rows(tables[1].clone()), blocking);
+    }
+
+    #[test]
+    fn test_streaming_glob_import_matches_blocking_import() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("b.csv", "id\n3\n4\n"),
+            ("a.csv", "id\n1\n2\n"),
+            ("c.csv", "id\n5\n"),
+        ] {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        let program = format!(
+            "
+            fn bool odd(row(int id) r) {{
+                return r.id % 2 == 1;
+            }};
+            var table(int id) blocking = import_glob(\"{0}/*.csv\", table(int id));
+            var table(int id) streamed = async_import_glob(\"{0}/*.csv\", table(int id)) pipe odd();
+            return [blocking, streamed];
+            ",
+            dir.path().display()
+        );
+        let ExpressionValue::Array(tables) = run_checked_program(&program) else {
+            panic!("Expected an array of tables");
+        };
+        let ids = |table: ExpressionValue| -> Vec<Option<TableCell>> {
+            table_rows(table)
+                .iter()
+                .map(|row| row.get_cell("id").cloned())
+                .collect()
+        };
+        assert_eq!(
+            ids(tables[0].clone()),
+            (1..=5)
+                .map(|id| Some(TableCell::Int(id)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ids(tables[1].clone()),
+            vec![
+                Some(TableCell::Int(1)),
+                Some(TableCell::Int(3)),
+                Some(TableCell::Int(5))
+            ]
+        );
+    }
+
+    // The map returns its columns in another order than they are sorted in, and widens the int id to a double
+    const DECLARED_RESULT: &str = "
+        fn row(string name, double score, int id) grade(row(int id) r) {
+            return row(string name = \"n\", double score = r.id, int id = r.id);
+        };
+        fn bool positive(row(string name, double score, int id) r) {
+            return 0 < r.id;
+        };
+        var table(int id) data = table(int id);
+        table_add_row(data, row(int id = 1));
+        table_add_row(data, row(int id = 2));
+        var table(string name, double score, int id) out = data pipe grade() pipe positive();
+        var table(string name, double score, int id) printed = out pipe print();
+        return [out, printed];";
+
+    #[test]
+    fn test_pipe_result_has_declared_structure() {
+        // The declared columns, in the order every table has its columns in
+        let declared = Some(TypeConstruct::Table(vec![
+            Parameter::Parameter(TypeConstruct::Int, "id".to_string()),
+            Parameter::Parameter(TypeConstruct::String, "name".to_string()),
+            Parameter::Parameter(TypeConstruct::Double, "score".to_string()),
+        ]));
+        for rows in [0, DEFAULT_SEQUENTIAL_PIPE_ROWS] {
+            for _ in 0..5 {
+                let (result, _) = run_with_sequential_rows(DECLARED_RESULT, rows);
+                let ExpressionValue::Array(tables) = result else {
+                    panic!("Expected an array of tables");
+                };
+                assert_eq!(tables[0].value_type(), declared);
+                assert_eq!(tables[1].value_type(), declared);
+                assert_eq!(
+                    table_rows(tables[0].clone())[1].get_cell("score"),
+                    Some(&TableCell::Double(2.0))
+                );
+                assert_eq!(table_rows(tables[1].clone()).len(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipe_result_row_with_missing_column() {
+        // Not type checked, so the map can return a row without the columns it declares
+        let program = "
+            fn row(int id, string name) partial(row(int id) r) {
+                return row(int id = r.id);
+            };
+            var table(int id) data = table(int id);
+            table_add_row(data, row(int id = 1));
+            return data pipe partial();";
+        let error = panic::catch_unwind(|| run_program(program)).expect_err("the row has no name");
+        assert_eq!(
+            panic_message(error),
+            "Interpretation error: Pipe stage 'partial' sent a row with the columns id, but the pipe gives a table with the columns id, name"
+        );
+    }
+
+    #[test]
+    fn test_swapped_pipe_is_explained_at_runtime() {
+        let program = "
+            fn bool adults(row(int age) r) { return r.age > 17; };
+            var table(int age) data = table(int age);
+            table_add_row(data, row(int age = 30));
+            return adults pipe data();";
+        let error = panic::catch_unwind(|| run_program(program)).expect_err("the pipe is swapped");
+        assert_eq!(
+            panic_message(error),
+            "Interpretation error: The left side of 'pipe' must be a table or import, but 'adults' is a function; did you mean 'data pipe adults(...)'?"
+        );
+        let program = "
+            var table(int age) data = table(int age);
+            var table(int age) other = table(int age);
+            return other pipe data();";
+        let error =
+            panic::catch_unwind(|| run_program(program)).expect_err("data is not a function");
+        assert_eq!(
+            panic_message(error),
+            "Interpretation error: The right side of 'pipe' must be a function, but 'data' is a table; a pipe starts with the table, e.g. 'data pipe f(...)'"
+        );
+    }
+
+    // A map, a filter with an argument and a reduce over a table of 50 rows
+    const STAGES_FIXTURE: &str = "
+        fn row(int id, double half) halve(row(int id) r) {
+            return row(int id = r.id, double half = r.id / 2.0);
+        };
+        fn bool above(row(int id, double half) r, double limit) {
+            return limit < r.half;
+        };
+        fn table(int id, double half) keep(table(int id, double half) t) {
+            table_add_row(t, row(int id = 0, double half = 0.0));
+            return t;
+        };
+        fn row(int id) make(int i) {
+            return row(int id = i);
+        };
+        var table(int id) t = table(int id);
+        var int i = 0;
+        while (i < 50) {
+            table_add_row(t, row(int id = i));
+            i = i + 1;
+        }
+        return t pipe halve() pipe above(10.5) pipe keep();
+        ";
+
+    // Runs a program with pipes of tables below the given number of rows run without threads
+    // Returns what the program returns, and how many stage threads were spawned
+    fn run_with_sequential_rows(program: &str, rows: usize) -> (ExpressionValue, usize) {
+        set_sequential_pipe_rows(rows);
+        STAGE_THREADS_SPAWNED.with(|n| n.set(0));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run_checked_program(program)));
+        set_sequential_pipe_rows(DEFAULT_SEQUENTIAL_PIPE_ROWS);
+        let spawned = STAGE_THREADS_SPAWNED.with(|n| n.get());
+        match result {
+            Ok(value) => (value, spawned),
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+
+    #[test]
+    fn test_sequential_and_threaded_pipes_give_the_same_rows() {
+        let (sequential, sequential_threads) = run_with_sequential_rows(STAGES_FIXTURE, usize::MAX);
+        let (threaded, threaded_threads) = run_with_sequential_rows(STAGES_FIXTURE, 0);
+        let rows = table_rows(sequential);
+        assert_eq!(rows.len(), 29);
+        assert_eq!(rows, table_rows(threaded));
+        assert_eq!(sequential_threads, 0);
+        // The source and the three stages
+        assert_eq!(threaded_threads, 4);
+    }
+
+    #[test]
+    fn test_not_equals_in_filter() {
+        let program = "
+            fn bool not_five(row(int id) r) { return r.id != 5; };
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 4));
+            table_add_row(t, row(int id = 5));
+            table_add_row(t, row(int id = 6));
+            return t pipe not_five();";
+        let ids: Vec<ExpressionValue> = table_rows(run_checked_program(program))
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![ExpressionValue::Number(4), ExpressionValue::Number(6)]
+        );
+    }
+
+    #[test]
+    fn test_pipe_in_a_loop_runs_independently_each_time() {
+        let program = "
+            fn row(int k, int n) times_ten(row(int k, int n) r) {
+                return row(int k = r.k, int n = r.n * 10);
+            };
+            fn bool above_ten(row(int k, int n) r) { return r.n > 10; };
+            var table(int k, int n) results = table(int k, int n);
+            for (int k in [1, 2, 3]) {
+                var table(int k, int n) t = table(int k, int n);
+                var int i = 0;
+                while (i < k + 1) {
+                    table_add_row(t, row(int k = k, int n = i + k));
+                    i = i + 1;
+                }
+                var table(int k, int n) out = t pipe times_ten() pipe above_ten();
+                for (row(int k, int n) r in out) {
+                    table_add_row(results, r);
+                }
+            }
+            return results;";
+        STAGE_THREADS_JOINED.with(|n| n.set(0));
+        let (result, spawned) = run_with_sequential_rows(program, 0);
+        // The source and the two stages, in each of the three iterations
+        assert_eq!(spawned, 9);
+        assert_eq!(STAGE_THREADS_JOINED.with(|n| n.get()), spawned);
+        let rows: Vec<(ExpressionValue, ExpressionValue)> = table_rows(result)
+            .iter()
+            .map(|row| (row.get("k"), row.get("n")))
+            .collect();
+        let expected = [
+            (1, 20),
+            (2, 20),
+            (2, 30),
+            (2, 40),
+            (3, 30),
+            (3, 40),
+            (3, 50),
+            (3, 60),
+        ];
+        assert_eq!(
+            rows,
+            expected
+                .into_iter()
+                .map(|(k, n)| (ExpressionValue::Number(k), ExpressionValue::Number(n)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_small_tables_piped_without_threads_by_default() {
+        let (_, spawned) = run_with_sequential_rows(STAGES_FIXTURE, DEFAULT_SEQUENTIAL_PIPE_ROWS);
+        assert_eq!(spawned, 0);
+        let (_, spawned) = run_with_sequential_rows(STAGES_FIXTURE, 50);
+        assert_eq!(spawned, 4, "a table of 50 rows is not below a limit of 50");
+        // Generated rows always go through threads
+        let generated =
+            STAGES_FIXTURE.replace("return t pipe", "return rows_from_range(5, \"make\") pipe");
+        let (_, spawned) = run_with_sequential_rows(&generated, usize::MAX);
+        assert_eq!(spawned, 4);
+    }
+
+    #[test]
+    fn test_failing_stage_reported_the_same_without_threads() {
+        let program = STAGES_FIXTURE.replace("limit < r.half", "limit < [r.half][r.id]");
+        let message = |rows| {
+            panic_message(
+                panic::catch_unwind(AssertUnwindSafe(|| {
+                    run_with_sequential_rows(&program, rows)
+                }))
+                .map(|_| ())
+                .unwrap_err(),
+            )
+        };
+        let sequential = message(usize::MAX);
+        assert!(
+            sequential.starts_with(
+                "Pipe stage 'above' terminated unexpectedly: Interpretation error: Index 1 is out of bounds for an array of length 1"
+            ),
+            "{}",
+            sequential
+        );
+        assert_eq!(sequential, message(0));
+    }
+
+    #[test]
+    fn test_map_result_cells_converted_to_declared_columns() {
+        // Not type checked, so the map can return ints for its double column
+        let program = "
+            fn row(double half) halve(row(int id) r) {
+                return row(int half = r.id);
+            };
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 4));
+            return t pipe halve();
+            ";
+        assert_eq!(
+            table_rows(run_program(program))[0].get_cell("half"),
+            Some(&TableCell::Double(4.0))
+        );
+        let result = panic::catch_unwind(|| {
+            run_program(&program.replace("row(double half)", "row(string half)"))
+        });
+        assert_eq!(
+            panic_message(result.unwrap_err()),
+            "Pipe stage 'halve' terminated unexpectedly: Interpretation error: column 'half' expected string, found int 4"
+        );
+    }
+
+    #[test]
+    fn test_import_map_rename_reduce() {
+        let file = write_csv("id,name\n1,a\n2,b\n3,c\n");
+        let program = format!(
+            "
+            fn row(int new_id, string name) rename(row(int id, string name) r) {{
+                return row(int new_id = r.id, string name = r.name);
+            }};
+            fn table(int total) sum(table(int new_id) t) {{
+                var int s = 0;
+                for (row(int new_id, string name) r in t) {{
+                    s = s + r.new_id;
+                }}
+                var table(int total) out = table(int total);
+                table_add_row(out, row(int total = s));
+                return out;
+            }};
+            return async_import(\"{}\", table(int id, string name)) pipe rename() pipe sum();
+            ",
+            file.path().display()
+        );
+
+        let result = run_program(&program);
+        if let ExpressionValue::Table(t) = result {
+            let rows: Vec<Row> = t.borrow().iter().cloned().collect();
+            assert_eq!(
+                rows,
+                vec![Row::new(vec![("total".to_string(), TableCell::Int(6))])]
+            );
+        } else {
+            panic!("Expected a table");
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Pipe stage 'sum' received a row that does not match its input structure"
+    )]
+    fn test_mismatch_caught_at_stage_boundary() {
+        // rename claims to return new_id, but actually returns a row with another column
+        let program = "
+            fn row(int new_id) rename(row(int id) r) {
+                return row(int other = r.id);
+            };
+            fn table(int new_id) sum(table(int new_id) t) {
+                return t;
+            };
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 1));
+            return t pipe rename() pipe sum();
+            ";
+        run_program(program);
+    }
+
+    #[test]
+    fn test_import_pipe_output_is_deterministic() {
+        let file = write_csv("name,price,id\nWidget,2.5,1\nGadget,10.0,2\nDoodad,0.75,3\n");
+        let program = format!(
+            "
+            fn row(string name, double total, int id) add_tax(row(int id, string name, double price) r) {{
+                return row(string name = r.name, double total = r.price * 1.25, int id = r.id);
+            }};
+            fn table(string name, double total, int id) keep(table(string name, double total, int id) t) {{
+                return t;
+            }};
+            return async_import(\"{}\", table(double price, string name, int id)) pipe add_tax() pipe keep();
+            ",
+            file.path().display()
+        );
+
+        // The same output as print would write, rendered table and each row on its own
+        let capture = || match run_program(&program) {
+            ExpressionValue::Table(t) => {
+                let table = t.borrow();
+                let mut out = table.render(None);
+                for row in table.iter() {
+                    out.push('\n');
+                    out.push_str(&row.print_line());
+                }
+                out
+            }
+            _ => panic!("Expected a table"),
+        };
+
+        let first = capture();
+        assert_eq!(first, capture());
+        assert!(first.starts_with("id       | name     | total"));
+        assert!(first.ends_with("name: Doodad, total: 0.9375, id: 3, "));
+    }
+
+    #[test]
+    fn test_pipe_print_header_once() {
+        let structure = HashMap::from([
+            ("id".to_string(), TableCellType::Int),
+            ("name".to_string(), TableCellType::String),
+        ]);
+        let (sender, receiver) = mpsc::channel();
+        for (id, name) in [(1, "Widget"), (2, "Gadget"), (3, "Doodad")] {
+            sender
+                .send(Row::new(vec![
+                    ("name".to_string(), TableCell::String(name.into())),
+                    ("id".to_string(), TableCell::Int(id)),
+                ]))
+                .unwrap();
+        }
+        drop(sender);
+
+        let mut out = Vec::new();
+        pipe_print(receiver, &structure, &mut out);
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id       | name\n\
+             ---------+---------\n\
+             1        | Widget\n\
+             2        | Gadget\n\
+             3        | Doodad\n\
+             3 rows\n"
+        );
+    }
+
+    #[test]
+    fn test_table_argument_to_pipe_stage_is_a_copy() {
+        let result = run_program(
+            "
+            fn bool log_row(row(int id) r, table(int id) log) {
+                table_add_row(log, row(int id = r.id));
+                return true;
+            };
+            var table(int id) t = table(int id);
+            table_add_row(t, row(int id = 1));
+            var table(int id) log = table(int id);
+            t pipe log_row(log);
+            return log;
+            ",
+        );
+        if let ExpressionValue::Table(log) = result {
+            assert_eq!(log.borrow().iter().count(), 0);
+        } else {
+            panic!("Expected a table");
+        }
+    }
+
+    #[test]
+    fn test_stage_returning_null_terminates_with_error() {
+        // The program runs in its own thread, so the test fails instead of hanging if the pipe never finishes
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = std::panic::catch_unwind(|| {
+                run_program(
+                    "
+                    fn table(int id) broken(table(int id) t) {
+                        var int x = 1;
+                    };
+                    var table(int id) t = table(int id);
+                    table_add_row(t, row(int id = 1));
+                    return t pipe broken() pipe print();
+                    ",
+                )
+            });
+            sender
+                .send(result.map(|_| ()).map_err(panic_message))
+                .unwrap();
+        });
+
+        let result = receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("Pipe did not terminate");
+        assert_eq!(
+            result.unwrap_err(),
+            "Pipe stage 'broken' terminated unexpectedly: Expected reduce 'broken' to return a table, but it returned Null"
+        );
+    }
+
+    #[test]
+    fn test_rows_from_range_into_filter_and_reduce() {
+        let result = run_program(
+            "
+            fn row(int id) make(int i) {
+                return row(int id = i);
+            };
+            fn bool even(row(int id) r) {
+                return r.id % 2 == 0;
+            };
+            fn table(int total) sum(table(int id) t) {
+                var table(int total) b = table(int total);
+                var int s = 0;
+                for (row(int id) r in t) {
+                    s = s + r.id;
+                }
+                table_add_row(b, row(int total = s));
+                return b;
+            };
+            var table(int total) t = rows_from_range(10000, \"make\") pipe even() pipe sum();
+            return t;
+            ",
+        );
+        if let ExpressionValue::Table(t) = result {
+            let t = t.borrow();
+            assert_eq!(t.iter().count(), 1);
+            assert_eq!(
+                t.get_row(0).get_cell("total"),
+                Some(&TableCell::Int(24995000))
+            );
+        } else {
+            panic!("Expected a table");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected generator 'make' to return a row")]
+    fn test_rows_from_range_maker_must_return_row() {
+        run_program(
+            "
+            fn int make(int i) {
+                return i;
+            };
+            var table(int id) t = rows_from_range(10, \"make\") pipe print();
+            ",
+        );
+    }
+
+    // Sums the ids of a generated stream, or of a table of 200 rows in memory, in a reduce stage
+    const REDUCE_FIXTURE: &str = "
+        fn row(int id) make(int i) {
+            return row(int id = i);
+        };
+        fn bool all(row(int id) r) {
+            return true;
+        };
+        fn table(int total) sum(table(int id) t) {
+            var table(int total) b = table(int total);
+            var int s = 0;
+            for (row(int id) r in t) {
+                s = s + r.id;
+            }
+            table_add_row(b, row(int total = s));
+            return b;
+        };
+    ";
+
+    // Runs the program with the memory limit of reduce stages set to the bytes, returning what it panicked with if it did
+    fn run_with_pipe_memory_limit(program: &str, bytes: usize) -> Result<ExpressionValue, String> {
+        set_pipe_memory_limit(bytes);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run_checked_program(program)));
+        set_pipe_memory_limit(DEFAULT_PIPE_MEMORY_LIMIT);
+        result.map_err(panic_message)
+    }
+
+    #[test]
+    fn test_reduce_stage_over_memory_limit_fails() {
+        let generated = format!(
+            "{}return rows_from_range(10000, \"make\") pipe sum();",
+            REDUCE_FIXTURE
+        );
+        let message = run_with_pipe_memory_limit(&generated, 4096).unwrap_err();
+        assert!(
+            message.starts_with(
+                "Pipe stage 'sum' terminated unexpectedly: reduce stage 'sum' exceeded memory limit (0.0 MB buffered, "
+            ),
+            "{}",
+            message
+        );
+        assert!(
+            message.ends_with(
+                " rows); consider pre-filtering the rows or raising --pipe-memory-limit"
+            ),
+            "{}",
+            message
+        );
+        // A table in memory is piped on this thread, and fails the same way
+        let in_memory = format!(
+            "{}var table(int id) t = rows_from_range(200, \"make\") pipe all();\nreturn t pipe sum();",
+            REDUCE_FIXTURE
+        );
+        let message = run_with_pipe_memory_limit(&in_memory, 4096).unwrap_err();
+        assert!(
+            message.contains("reduce stage 'sum' exceeded memory limit"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_reduce_stage_within_memory_limit_is_unaffected() {
+        let generated = format!(
+            "{}return rows_from_range(10000, \"make\") pipe sum();",
+            REDUCE_FIXTURE
+        );
+        let rows = table_rows(run_with_pipe_memory_limit(&generated, 10 * 1024 * 1024).unwrap());
+        assert_eq!(
+            rows,
+            vec![Row::new(vec![(
+                "total".to_string(),
+                TableCell::Int(49995000)
+            )])]
+        );
+    }
+
+    // Filters a table of long strings given by the host, returning the table and the rows the filter kept
+    fn filter_wide_table(rows: usize) -> (Table, Vec<Row>) {
+        let mut builder = crate::backend::table::TableBuilder::new();
+        for column in ["a", "b", "c", "d", "e"] {
+            builder = builder.column(column, TableCellType::String);
+        }
+        for i in 0..rows {
+            let text = format!("{:>1000}", i);
+            builder = builder.row(|r| r.str(&text).str(&text).str(&text).str(&text).str(&text));
+        }
+        let table = builder.build().unwrap();
+        let mut env = env_new();
+        env_expand_scope(&mut env);
+        env_add(
+            &mut env,
+            EnvironmentCell::Variable(
+                "wide".to_string(),
+                ExpressionValue::Table(Rc::new(RefCell::new(table.clone()))),
+            ),
+        );
+        let program = "
+            fn bool keep(row(string a, string b, string c, string d, string e) r) {
+                return str_len(r.a) > 0;
+            };
+            return wide pipe keep();
+        ";
+        match evaluate_statement(create_syntax_tree(program).unwrap(), &mut env) {
+            StatementValue::Return(value) => (table, table_rows(value)),
+            _ => panic!("the program returns the filtered table"),
+        }
+    }
+
+    // Strings are shared, not copied, as rows go through a pipe, which is what keeps text heavy pipes fast
+    #[test]
+    fn test_filter_shares_strings_of_wide_rows() {
+        for rows in [10, 2000] {
+            let (table, filtered) = filter_wide_table(rows);
+            assert_eq!(filtered.len(), rows);
+            for (original, kept) in table.iter().zip(&filtered) {
+                for ((_, original), (_, kept)) in original.iter().zip(kept.iter()) {
+                    let (TableCell::String(original), TableCell::String(kept)) = (original, kept)
+                    else {
+                        panic!("every column holds strings");
+                    };
+                    assert!(Arc::ptr_eq(original, kept));
+                }
+            }
+        }
+    }
+
+    // Five records, of which the second and the fourth have text where an int is declared
+    const BAD_ROWS_CSV: &str = "id,name\n1,a\nx,b\n3,c\n4.5,d\n5,e\n";
+
+    #[test]
+    fn test_import_stats_count_skipped_rows() {
+        let file = write_csv(BAD_ROWS_CSV);
+        let path = file.path().to_str().unwrap();
+        let program = |import: &str| {
+            format!(
+                "var table(int id, string name) t = {}(\"{}\", table(int id, string name), row(string on_error = \"skip\"));
+                var row(int rows_read, int rows_skipped, string last_file) stats = import_stats();
+                return stats;",
+                import, path
+            )
+        };
+        let expected = Row::new(vec![
+            ("rows_read".to_string(), TableCell::Int(5)),
+            ("rows_skipped".to_string(), TableCell::Int(2)),
+            ("last_file".to_string(), TableCell::String(path.into())),
+        ]);
+        assert_eq!(
+            run_checked_program(&program("import")),
+            ExpressionValue::Row(expected.clone())
+        );
+
+        // An import in a pipe runs on a thread of its own, and is recorded once the pipe is done
+        let piped = format!(
+            "var table(int id, string name) t = async_import(\"{}\", table(int id, string name), row(string on_error = \"skip\")) pipe keep();
+            return import_stats();",
+            path
+        );
+        let piped = format!(
+            "fn bool keep(row(int id, string name) r) {{ return true; }};\n{}",
+            piped
+        );
+        assert_eq!(run_checked_program(&piped), ExpressionValue::Row(expected));
+    }
+
+    #[test]
+    #[should_panic(expected = "has 'x' in int column 'id' on line 3")]
+    fn test_import_fails_on_bad_row_by_default() {
+        let file = write_csv(BAD_ROWS_CSV);
+        run_checked_program(&format!(
+            "var table(int id, string name) t = import(\"{}\", table(int id, string name));",
+            file.path().to_str().unwrap()
+        ));
+    }
+
+    // Keeps the rows with an even id of ten rows, then maps them to their id doubled
+    const STATS_FIXTURE: &str = "
+        fn row(int id) make(int i) { return row(int id = i); };
+        fn bool even(row(int id) r) { return r.id % 2 == 0; };
+        fn row(int id) double_id(row(int id) r) { return row(int id = r.id * 2); };
+        var table(int id) t = table(int id);
+        var int i = 0;
+        while (i < 10) { table_add_row(t, make(i)); i++; }
+        var table(int id) result = t pipe even() pipe double_id();
+        return pipe_stats();";
+
+    #[test]
+    fn test_pipe_stats_match_filter_selectivity() {
+        for sequential_rows in [usize::MAX, 0] {
+            let (stats, _) = run_with_sequential_rows(STATS_FIXTURE, sequential_rows);
+            let counts: Vec<(ExpressionValue, ExpressionValue, ExpressionValue)> =
+                table_rows(stats)
+                    .iter()
+                    .map(|row| {
+                        assert!(
+                            matches!(row.get("seconds"), ExpressionValue::Double(s) if s >= 0.0)
+                        );
+                        (row.get("stage"), row.get("rows_in"), row.get("rows_out"))
+                    })
+                    .collect();
+            assert_eq!(
+                counts,
+                [
+                    (
+                        ExpressionValue::String("even".into()),
+                        ExpressionValue::Number(10),
+                        ExpressionValue::Number(5)
+                    ),
+                    (
+                        ExpressionValue::String("double_id".into()),
+                        ExpressionValue::Number(5),
+                        ExpressionValue::Number(5)
+                    ),
+                ],
+                "with sequential_rows = {}",
+                sequential_rows
+            );
+        }
+    }
+
+    #[test]
+    fn test_map_changes_one_column_of_the_row() {
+        let rows = table_rows(run_checked_program(
+            "
+            fn row(int id, string name) renumber(row(int id, string name) r) {
+                r.id = r.id * 10;
+                return r;
+            };
+            var table(int id, string name) t = table(int id, string name);
+            table_add_row(t, row(int id = 1, string name = \"a\"));
+            table_add_row(t, row(int id = 2, string name = \"b\"));
+            return t pipe renumber();
+            ",
+        ));
+        let cells: Vec<(Option<&TableCell>, Option<&TableCell>)> = rows
+            .iter()
+            .map(|row| (row.get_cell("id"), row.get_cell("name")))
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                (
+                    Some(&TableCell::Int(10)),
+                    Some(&TableCell::String("a".into()))
+                ),
+                (
+                    Some(&TableCell::Int(20)),
+                    Some(&TableCell::String("b".into()))
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipe_into_lambdas() {
+        let ids = |program: &str| -> Vec<Option<TableCell>> {
+            table_rows(run_checked_program(program))
+                .iter()
+                .map(|row| row.get_cell("id").cloned())
+                .collect()
+        };
+        let table = "var table(int id) t = table(int id);
+            for (int i in 0..20) { table_add_row(t, row(int id = i)); }";
+        assert_eq!(
+            ids(&format!(
+                "{} return t pipe (fn (row(int id) r) bool {{ return r.id < 10; }})
+                    pipe (fn (row(int id) r) row(int id) {{ return row(int id = r.id * 100); }})
+                    pipe (fn (row(int id) r) bool {{ return r.id % 300 == 0; }});",
+                table
+            )),
+            vec![
+                Some(TableCell::Int(0)),
+                Some(TableCell::Int(300)),
+                Some(TableCell::Int(600)),
+                Some(TableCell::Int(900))
+            ]
+        );
+        // A lambda held in a variable is piped into by the name of the variable
+        assert_eq!(
+            ids(&format!(
+                "{} var fn bool(row(int id)) last = fn (row(int id) r) bool {{ return r.id == 19; }};
+                return t pipe last();",
+                table
+            )),
+            vec![Some(TableCell::Int(19))]
+        );
+        // A lambda captures the variables around it by value when it is made
+        assert_eq!(
+            ids(&format!(
+                "{} var int limit = 3;
+                var fn bool(row(int id)) small = fn (row(int id) r) bool {{ return r.id < limit; }};
+                limit = 100;
+                return t pipe (fn (row(int id) r) bool {{ return r.id < limit; }}) pipe small();",
+                table
+            )),
+            vec![
+                Some(TableCell::Int(0)),
+                Some(TableCell::Int(1)),
+                Some(TableCell::Int(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enum_values_pass_through_pipes_unchanged() {
+        let file = write_csv("id,status\n1,active\n2,pending\n");
+        let program = format!(
+            "
+            enum Status = [\"active\", \"inactive\", \"pending\"];
+            fn row(int id, Status status) bump(row(int id, Status status) r) {{
+                return row(int id = r.id + 10, Status status = r.status);
+            }};
+            return import(\"{}\", table(int id, Status status)) pipe bump();
+            ",
+            file.path().display()
+        );
+        let statuses: Vec<Option<TableCell>> = table_rows(run_checked_program(&program))
+            .iter()
+            .map(|r| r.get_cell("status").cloned())
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                Some(TableCell::String("active".into())),
+                Some(TableCell::String("pending".into())),
+            ]
+        );
+    }
 }
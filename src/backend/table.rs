@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, io::Write, mem, ops::Range, sync::Arc};
 
-use crate::frontend::ast::{Parameter, TypeConstruct};
+use crate::frontend::{
+    ast::{Parameter, TypeConstruct},
+    diagnostics::unknown_column_message,
+};
 
-use super::evaluate::ExpressionValue;
+use super::{
+    evaluate::{ExpressionValue, check_value_count},
+    library::{check_output, output},
+};
 
 /*
  * This file deals with creating and managing tables and rows
@@ -10,10 +16,108 @@ use super::evaluate::ExpressionValue;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TableCell {
-    Int(i32),
+    Int(i64),
     Double(f64),
-    String(String),
+    String(Arc<str>),
     Bool(bool),
+    // A missing value in a nullable column, which keeps the type of the column
+    Null(TableCellType),
+}
+
+impl TableCell {
+    pub fn get_type(&self) -> TableCellType {
+        match self {
+            TableCell::Int(_) => TableCellType::Int,
+            TableCell::Double(_) => TableCellType::Double,
+            TableCell::String(_) => TableCellType::String,
+            TableCell::Bool(_) => TableCellType::Bool,
+            TableCell::Null(cell_type) => cell_type.clone(),
+        }
+    }
+
+    //About how many bytes the cell takes in memory, counting the text of a string
+    pub fn approximate_size(&self) -> usize {
+        let text = match self {
+            TableCell::String(s) => s.len(),
+            _ => 0,
+        };
+        mem::size_of::<TableCell>() + text
+    }
+}
+
+//Converts a cell to the type of the column it is stored in. The one rule for every table boundary:
+//an int is widened to a double, and any other cell must already have the type of its column
+pub fn coerce_cell(value: TableCell, cell_type: &TableCellType) -> Result<TableCell, String> {
+    match (value, cell_type) {
+        (TableCell::Int(i), TableCellType::Double) => Ok(TableCell::Double(i as f64)),
+        (TableCell::Null(_), cell_type) => Ok(TableCell::Null(cell_type.clone())),
+        (value, cell_type) if cell_type.holds(&value) => Ok(value),
+        (value, cell_type) => Err(format!(
+            "expected {}, found {} {}",
+            cell_type,
+            value.get_type(),
+            value
+        )),
+    }
+}
+
+impl fmt::Display for TableCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableCell::Int(i) => write!(f, "{}", i),
+            TableCell::Double(d) => write!(f, "{}", format_double(*d)),
+            TableCell::String(s) => write!(f, "{}", s),
+            TableCell::Bool(b) => write!(f, "{}", b),
+            TableCell::Null(_) => write!(f, "null"),
+        }
+    }
+}
+
+// What a column holds where a value is missing: a blank field of an imported file, or a column left out of a row given to
+// table_add_row. Declared in the table expression, e.g. table(double score = 0.0, string note ?). Other columns have none
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnFill {
+    Default(TableCell),
+    Null,
+}
+
+impl ColumnFill {
+    // The cell filled in for a column of the type
+    pub fn cell(&self, cell_type: &TableCellType) -> TableCell {
+        match self {
+            ColumnFill::Default(cell) => cell.clone(),
+            ColumnFill::Null => TableCell::Null(cell_type.clone()),
+        }
+    }
+}
+
+// Doubles this small or large are written in scientific notation, everything in between is written out in full
+const PLAIN_DOUBLE_MIN: f64 = 1e-7;
+const PLAIN_DOUBLE_MAX: f64 = 1e16;
+
+//The one way a double is written as text, used everywhere doubles are printed or exported
+//The shortest digits that parse back to the same double, always with a decimal point, e.g. 0.1, 3.0 and 0.0000001
+//Outside PLAIN_DOUBLE_MIN..PLAIN_DOUBLE_MAX scientific notation is used, e.g. 1.0e-8 and 1.7976931348623157e308
+pub fn format_double(d: f64) -> String {
+    if !d.is_finite() {
+        return d.to_string();
+    }
+    let magnitude = d.abs();
+    if magnitude != 0.0 && !(PLAIN_DOUBLE_MIN..PLAIN_DOUBLE_MAX).contains(&magnitude) {
+        let formatted = format!("{:e}", d);
+        return match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                format!("{}.0e{}", mantissa, exponent)
+            }
+            _ => formatted,
+        };
+    }
+    let formatted = d.to_string();
+    if formatted.contains('.') {
+        formatted
+    } else {
+        formatted + ".0"
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -22,6 +126,43 @@ pub enum TableCellType {
     Double,
     String,
     Bool,
+    // A string that is one of the values of the named enum
+    Enum(String, Vec<String>),
+}
+
+impl TableCellType {
+    //Whether a cell can be stored in a column of this type as it is. A string is only held by an enum column if it is one of its values
+    pub fn holds(&self, cell: &TableCell) -> bool {
+        match (self, cell) {
+            (TableCellType::Enum(_, values), TableCell::String(s)) => {
+                values.iter().any(|value| **value == **s)
+            }
+            (cell_type, cell) => cell.get_type() == *cell_type,
+        }
+    }
+
+    //The type of the column as it is declared in wrench
+    pub fn type_construct(&self) -> TypeConstruct {
+        match self {
+            TableCellType::Int => TypeConstruct::Int,
+            TableCellType::Double => TypeConstruct::Double,
+            TableCellType::String => TypeConstruct::String,
+            TableCellType::Bool => TypeConstruct::Bool,
+            TableCellType::Enum(name, values) => TypeConstruct::Enum(name.clone(), values.clone()),
+        }
+    }
+}
+
+impl fmt::Display for TableCellType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableCellType::Int => write!(f, "int"),
+            TableCellType::Double => write!(f, "double"),
+            TableCellType::String => write!(f, "string"),
+            TableCellType::Bool => write!(f, "bool"),
+            TableCellType::Enum(name, _) => write!(f, "{}", name),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -29,10 +170,26 @@ pub struct Row {
     data: Vec<(String, TableCell)>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct Table {
     data: Vec<Row>,
     structure: HashMap<String, TableCellType>,
+    // The columns with a default or null for missing values, see ColumnFill
+    fills: HashMap<String, ColumnFill>,
+}
+
+//The structure is written in the order of its columns, not the order of the HashMap, so debug output is the same every run
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let structure: Vec<(&String, &TableCellType)> = structure_columns(&self.structure)
+            .into_iter()
+            .map(|name| self.structure.get_key_value(&name).unwrap())
+            .collect();
+        f.debug_struct("Table")
+            .field("data", &self.data)
+            .field("structure", &structure)
+            .finish()
+    }
 }
 
 impl Row {
@@ -40,30 +197,128 @@ impl Row {
         Row { data: d }
     }
 
+    //The value of a column. A column the row does not have is reported like the type checker reports it
     pub fn get(&self, column_name: &str) -> ExpressionValue {
-        for (key, value) in &self.data {
-            if key == column_name {
-                return match value {
-                    TableCell::Int(i) => ExpressionValue::Number(*i),
-                    TableCell::Double(d) => ExpressionValue::Double(*d),
-                    TableCell::String(s) => ExpressionValue::String(s.clone()),
-                    TableCell::Bool(b) => ExpressionValue::Bool(*b),
-                };
+        match self.get_optional(column_name) {
+            Some(value) => value,
+            None => {
+                let columns: Vec<(String, String)> = self
+                    .data
+                    .iter()
+                    .map(|(name, cell)| (name.clone(), cell.get_type().to_string()))
+                    .collect();
+                panic!(
+                    "Interpretation error: {}",
+                    unknown_column_message(column_name, &columns)
+                )
             }
         }
-        panic!("Column name not found in row for {}", column_name);
     }
 
-    pub fn print(&self) {
-        for (key, value) in &self.data {
-            match value {
-                TableCell::Int(i) => print!("{}: {}, ", key, i),
-                TableCell::Double(d) => print!("{}: {}, ", key, d),
-                TableCell::String(s) => print!("{}: {}, ", key, s),
-                TableCell::Bool(b) => print!("{}: {}, ", key, b),
-            }
+    //The value of a column, or None if the row has no column of that name
+    pub fn get_optional(&self, column_name: &str) -> Option<ExpressionValue> {
+        self.get_cell(column_name).map(|value| match value {
+            TableCell::Int(i) => ExpressionValue::Number(*i),
+            TableCell::Double(d) => ExpressionValue::Double(*d),
+            TableCell::String(s) => ExpressionValue::String(s.clone()),
+            TableCell::Bool(b) => ExpressionValue::Bool(*b),
+            TableCell::Null(_) => ExpressionValue::Null,
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, TableCell)> {
+        self.data.iter()
+    }
+
+    //About how many bytes the row takes in memory, counting its column names and cells
+    pub fn approximate_size(&self) -> usize {
+        mem::size_of::<Row>()
+            + self
+                .data
+                .iter()
+                .map(|(name, cell)| {
+                    mem::size_of::<String>() + name.capacity() + cell.approximate_size()
+                })
+                .sum::<usize>()
+    }
+
+    pub fn get_cell(&self, column_name: &str) -> Option<&TableCell> {
+        self.data
+            .iter()
+            .find(|(key, _)| key == column_name)
+            .map(|(_, value)| value)
+    }
+
+    //The columns of the row and the types of their cells
+    pub fn structure(&self) -> HashMap<String, TableCellType> {
+        self.data
+            .iter()
+            .map(|(key, value)| (key.clone(), value.get_type()))
+            .collect()
+    }
+
+    //Checks that the row has exactly the columns and cell types described by the structure
+    pub fn matches_structure(&self, structure: &HashMap<String, TableCellType>) -> bool {
+        self.data.len() == structure.len()
+            && self
+                .data
+                .iter()
+                .all(|(key, value)| structure.get(key).is_some_and(|t| t.holds(value)))
+    }
+
+    //Converts every cell of a column in the structure with coerce_cell. Columns not in the structure are left as they are
+    pub fn coerce(self, structure: &HashMap<String, TableCellType>) -> Result<Row, String> {
+        let mut data = Vec::with_capacity(self.data.len());
+        for (key, value) in self.data {
+            let value = match structure.get(&key) {
+                Some(cell_type) => {
+                    coerce_cell(value, cell_type).map_err(|e| format!("column '{}' {}", key, e))?
+                }
+                None => value,
+            };
+            data.push((key, value));
         }
-        println!();
+        Ok(Row { data })
+    }
+
+    //Sets the cell of a column, converted to the type of the cell it replaces. The row does not get new columns
+    pub fn set(&mut self, column_name: &str, value: TableCell) -> Result<(), String> {
+        let columns: Vec<(String, String)> = self
+            .data
+            .iter()
+            .map(|(name, cell)| (name.clone(), cell.get_type().to_string()))
+            .collect();
+        let Some((_, cell)) = self.data.iter_mut().find(|(key, _)| key == column_name) else {
+            return Err(unknown_column_message(column_name, &columns));
+        };
+        *cell = coerce_cell(value, &cell.get_type())
+            .map_err(|e| format!("column '{}' {}", column_name, e))?;
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        check_output(writeln!(output(), "{}", self.print_line()));
+    }
+
+    //The line print writes for the row, e.g. id: 1, name: A,
+    pub fn print_line(&self) -> String {
+        self.data
+            .iter()
+            .map(|(key, value)| format!("{}: {}, ", key, value))
+            .collect()
+    }
+
+    //The row as it is written inside an array, with strings quoted e.g. {id: 1, name: "A"}
+    pub fn nested_string(&self) -> String {
+        let cells: Vec<String> = self
+            .data
+            .iter()
+            .map(|(key, value)| match value {
+                TableCell::String(s) => format!("{}: {:?}", key, s),
+                other => format!("{}: {}", key, other),
+            })
+            .collect();
+        format!("{{{}}}", cells.join(", "))
     }
 }
 
@@ -72,14 +327,37 @@ impl Table {
         Table {
             data: Vec::new(),
             structure: s,
+            fills: HashMap::new(),
         }
     }
+
+    //The table with what its columns hold where a value is missing, see ColumnFill
+    pub fn with_fills(mut self, fills: HashMap<String, ColumnFill>) -> Self {
+        self.fills = fills;
+        self
+    }
+
+    pub fn get_fills(&self) -> &HashMap<String, ColumnFill> {
+        &self.fills
+    }
     pub fn iter(&self) -> impl Iterator<Item = &Row> {
         self.data.iter()
     }
 
-    pub fn add_row(&mut self, row: Row) {
-        self.data.push(row);
+    //Adds a row, with its cells converted to the types of their columns. Panics if a cell cannot be
+    //Columns the row leaves out are filled in for the columns that have a default or are nullable
+    pub fn add_row(&mut self, mut row: Row) {
+        check_value_count((self.data.len() + 1) * self.structure.len(), "A table");
+        for (column, fill) in &self.fills {
+            if row.get_cell(column).is_none() {
+                row.data
+                    .push((column.clone(), fill.cell(&self.structure[column])));
+            }
+        }
+        match row.coerce(&self.structure) {
+            Ok(row) => self.data.push(row),
+            Err(e) => panic!("Interpretation error: {}", e),
+        }
     }
 
     pub fn get_row(&self, index: usize) -> Row {
@@ -90,11 +368,54 @@ impl Table {
         }
     }
 
+    //Replaces the row at the index, with its cells converted to the types of their columns.
+    //The row must have the columns of the table
+    pub fn set_row(&mut self, index: usize, row: Row) -> Result<(), String> {
+        if index >= self.data.len() {
+            return Err(format!(
+                "Row index {} is out of bounds for a table of {} rows",
+                index,
+                self.data.len()
+            ));
+        }
+        let row = row.coerce(&self.structure)?;
+        if !row.matches_structure(&self.structure) {
+            return Err(format!(
+                "The row has the columns {}, but the table has {}",
+                describe_structure(&row.structure()),
+                describe_structure(&self.structure)
+            ));
+        }
+        self.data[index] = row;
+        Ok(())
+    }
+
+    //The rows in the range, for rendering a table a part at a time
+    pub fn row_slice(&self, rows: Range<usize>) -> &[Row] {
+        &self.data[rows]
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.data.len()
+    }
+
     pub fn get_structure(&self) -> &HashMap<String, TableCellType> {
         &self.structure
     }
 
     pub fn get_column(&self, column_name: &str) -> ExpressionValue {
+        // Checked against the structure, so a table without rows does not give an empty column it does not have
+        if !self.structure.contains_key(column_name) {
+            let columns: Vec<(String, String)> = self
+                .structure
+                .iter()
+                .map(|(name, cell_type)| (name.clone(), cell_type.to_string()))
+                .collect();
+            panic!(
+                "Interpretation error: {}",
+                unknown_column_message(column_name, &columns)
+            );
+        }
         let mut column_data = Vec::new();
         for row in &self.data {
             column_data.push(row.get(column_name));
@@ -102,10 +423,77 @@ impl Table {
         ExpressionValue::Array(column_data)
     }
 
+    //A copy of the table with a column more, holding a cell per row in order. Ints are widened in a double column
+    pub fn with_column(
+        &self,
+        name: &str,
+        cell_type: TableCellType,
+        cells: Vec<TableCell>,
+    ) -> Result<Table, String> {
+        if self.structure.contains_key(name) {
+            return Err(format!("Table already has a column named '{}'", name));
+        }
+        if cells.len() != self.data.len() {
+            return Err(format!(
+                "Column '{}' has {} values for a table with {} rows",
+                name,
+                cells.len(),
+                self.data.len()
+            ));
+        }
+        let mut structure = self.structure.clone();
+        structure.insert(name.to_string(), cell_type);
+        let mut data = Vec::with_capacity(self.data.len());
+        for (row, cell) in self.data.iter().zip(cells) {
+            let mut row = row.clone();
+            row.data.push((name.to_string(), cell));
+            data.push(row.coerce(&structure)?);
+        }
+        Ok(Table {
+            data,
+            structure,
+            fills: self.fills.clone(),
+        })
+    }
+
+    //A copy of the table without one of its columns
+    pub fn without_column(&self, name: &str) -> Result<Table, String> {
+        if !self.structure.contains_key(name) {
+            return Err(format!("Table has no column named '{}'", name));
+        }
+        let mut structure = self.structure.clone();
+        structure.remove(name);
+        let data = self
+            .data
+            .iter()
+            .map(|row| Row {
+                data: row
+                    .data
+                    .iter()
+                    .filter(|(key, _)| key != name)
+                    .cloned()
+                    .collect(),
+            })
+            .collect();
+        let mut fills = self.fills.clone();
+        fills.remove(name);
+        Ok(Table {
+            data,
+            structure,
+            fills,
+        })
+    }
+
     pub fn parameters_to_structure(parameters: Vec<Parameter>) -> HashMap<String, TableCellType> {
         let mut structure = HashMap::new();
         for param in parameters {
             match param {
+                // A nullable column holds cells of its type, or null cells of its type
+                Parameter::Parameter(TypeConstruct::Nullable(t), name) => {
+                    structure.extend(Table::parameters_to_structure(vec![Parameter::Parameter(
+                        *t, name,
+                    )]));
+                }
                 Parameter::Parameter(t, name) => match t {
                     TypeConstruct::Bool => {
                         structure.insert(name.clone(), TableCellType::Bool);
@@ -119,6 +507,9 @@ impl Table {
                     TypeConstruct::Double => {
                         structure.insert(name.clone(), TableCellType::Double);
                     }
+                    TypeConstruct::Enum(enum_name, values) => {
+                        structure.insert(name.clone(), TableCellType::Enum(enum_name, values));
+                    }
                     _ => {
                         panic!("Unsupported type in table declaration for {}", name);
                     }
@@ -128,12 +519,538 @@ impl Table {
         structure
     }
 
-    pub fn print(&self) {
+    //The columns of a structure as parameters, in the order they are printed in
+    pub fn structure_to_parameters(structure: &HashMap<String, TableCellType>) -> Vec<Parameter> {
+        structure_columns(structure)
+            .into_iter()
+            .map(|name| Parameter::Parameter(structure[&name].type_construct(), name))
+            .collect()
+    }
+
+    //Computes statistics for each column in a single pass over the rows, returned as a table with a row per column
+    //min, max and mean are only computed for int and double columns, and are NaN for other columns and empty tables
+    //This is the only place NaN is produced, as a placeholder until cells can be null
+    pub fn describe(&self) -> Table {
+        let columns = structure_columns(&self.structure);
+        let mut counts = vec![0; columns.len()];
+        let mut nulls = vec![0; columns.len()];
+        let mut mins = vec![f64::INFINITY; columns.len()];
+        let mut maxs = vec![f64::NEG_INFINITY; columns.len()];
+        let mut sums = vec![0.0; columns.len()];
+
         for row in &self.data {
-            row.print();
+            for (i, column) in columns.iter().enumerate() {
+                let value = match row.get_cell(column) {
+                    Some(TableCell::Int(n)) => Some(*n as f64),
+                    Some(TableCell::Double(d)) => Some(*d),
+                    Some(TableCell::Null(_)) => {
+                        nulls[i] += 1;
+                        continue;
+                    }
+                    None => continue,
+                    Some(_) => None,
+                };
+                counts[i] += 1;
+                if let Some(value) = value {
+                    mins[i] = mins[i].min(value);
+                    maxs[i] = maxs[i].max(value);
+                    sums[i] += value;
+                }
+            }
         }
+
+        let mut statistics = Table::new(Table::parameters_to_structure(describe_parameters()));
+        for (i, column) in columns.iter().enumerate() {
+            let cell_type = &self.structure[column];
+            let numeric = matches!(cell_type, TableCellType::Int | TableCellType::Double);
+            let (min, max, mean) = if numeric && counts[i] > 0 {
+                (mins[i], maxs[i], sums[i] / counts[i] as f64)
+            } else {
+                (f64::NAN, f64::NAN, f64::NAN)
+            };
+            statistics.add_row(Row::new(vec![
+                (
+                    "column".to_string(),
+                    TableCell::String(column.as_str().into()),
+                ),
+                (
+                    "type".to_string(),
+                    TableCell::String(cell_type.to_string().into()),
+                ),
+                ("count".to_string(), TableCell::Int(counts[i])),
+                ("nulls".to_string(), TableCell::Int(nulls[i])),
+                ("min".to_string(), TableCell::Double(min)),
+                ("max".to_string(), TableCell::Double(max)),
+                ("mean".to_string(), TableCell::Double(mean)),
+            ]));
+        }
+        statistics
+    }
+
+    //Counts the values of a numeric column in equally wide buckets between its smallest and largest value
+    //Returns a table(double bucket_low, double bucket_high, int count) with a row per bucket. Null cells are not counted
+    //A column with a single distinct value gets a single bucket, and a column with no values gets none
+    pub fn histogram(&self, column_name: &str, bucket_count: i64) -> Result<Table, String> {
+        let Some(cell_type) = self.structure.get(column_name) else {
+            let columns: Vec<(String, String)> = self
+                .structure
+                .iter()
+                .map(|(name, cell_type)| (name.clone(), cell_type.to_string()))
+                .collect();
+            return Err(unknown_column_message(column_name, &columns));
+        };
+        if !matches!(cell_type, TableCellType::Int | TableCellType::Double) {
+            return Err(format!(
+                "histogram needs an int or double column, but '{}' is a {} column",
+                column_name, cell_type
+            ));
+        }
+        if bucket_count < 1 {
+            return Err(format!(
+                "histogram needs at least 1 bucket, got {}",
+                bucket_count
+            ));
+        }
+        let value = |row: &Row| match row.get_cell(column_name) {
+            Some(TableCell::Int(n)) => Some(*n as f64),
+            Some(TableCell::Double(d)) => Some(*d),
+            _ => None,
+        };
+
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        for value in self.data.iter().filter_map(value) {
+            min = min.min(value);
+            max = max.max(value);
+        }
+
+        let mut buckets = Table::new(Table::parameters_to_structure(histogram_parameters()));
+        if min > max {
+            return Ok(buckets);
+        }
+        let bucket_count = if min == max { 1 } else { bucket_count as usize };
+        let width = (max - min) / bucket_count as f64;
+        let mut counts = vec![0; bucket_count];
+        for value in self.data.iter().filter_map(value) {
+            // The largest value is in the last bucket, which includes its upper bound
+            let bucket = if width > 0.0 {
+                (((value - min) / width) as usize).min(bucket_count - 1)
+            } else {
+                0
+            };
+            counts[bucket] += 1;
+        }
+        for (i, count) in counts.into_iter().enumerate() {
+            let high = if i + 1 == bucket_count {
+                max
+            } else {
+                min + width * (i + 1) as f64
+            };
+            buckets.add_row(Row::new(vec![
+                (
+                    "bucket_low".to_string(),
+                    TableCell::Double(min + width * i as f64),
+                ),
+                ("bucket_high".to_string(), TableCell::Double(high)),
+                ("count".to_string(), TableCell::Int(count)),
+            ]));
+        }
+        Ok(buckets)
+    }
+
+    //Prints the table with at most PRINT_ROW_LIMIT rows, so large tables do not flood the terminal
+    pub fn print(&self) {
+        check_output(writeln!(output(), "{}", self.render(Some(PRINT_ROW_LIMIT))));
+    }
+
+    //Prints every row of the table
+    pub fn print_full(&self) {
+        check_output(writeln!(output(), "{}", self.render(None)));
+    }
+
+    //Renders the table as aligned text. With a row limit, only the first and last half of the limit are rendered
+    pub fn render(&self, row_limit: Option<usize>) -> String {
+        let renderer = TableRenderer::new(&self.structure);
+        let mut lines = vec![renderer.header()];
+        match row_limit {
+            Some(limit) if self.data.len() > limit => {
+                let half = limit / 2;
+                lines.extend(self.data[..half].iter().map(|row| renderer.row(row)));
+                lines.push("…".to_string());
+                lines.extend(
+                    self.data[self.data.len() - half..]
+                        .iter()
+                        .map(|row| renderer.row(row)),
+                );
+            }
+            _ => lines.extend(self.data.iter().map(|row| renderer.row(row))),
+        }
+        lines.push(renderer.summary(self.data.len()));
+        lines.join("\n")
+    }
+}
+
+//Rows as plain pairs of column names and cells, for hosts embedding wrench
+impl From<Vec<(String, TableCell)>> for Row {
+    fn from(data: Vec<(String, TableCell)>) -> Self {
+        Row::new(data)
+    }
+}
+
+impl From<Row> for Vec<(String, TableCell)> {
+    fn from(row: Row) -> Self {
+        row.data
     }
 }
+
+//Builds a table from Rust data, for hosts giving wrench a table without going through CSV, e.g.
+//TableBuilder::new().column("id", TableCellType::Int).column("name", TableCellType::String).row(|r| r.int(1).str("A")).build()
+//The cells of a row are given in the order of the columns
+#[derive(Default)]
+pub struct TableBuilder {
+    columns: Vec<(String, TableCellType)>,
+    rows: Vec<Vec<TableCell>>,
+}
+
+//The cells of a row given to TableBuilder::row
+#[derive(Default)]
+pub struct RowBuilder {
+    cells: Vec<TableCell>,
+}
+
+impl RowBuilder {
+    pub fn int(mut self, value: i64) -> Self {
+        self.cells.push(TableCell::Int(value));
+        self
+    }
+
+    pub fn double(mut self, value: f64) -> Self {
+        self.cells.push(TableCell::Double(value));
+        self
+    }
+
+    pub fn str(mut self, value: &str) -> Self {
+        self.cells.push(TableCell::String(value.into()));
+        self
+    }
+
+    pub fn bool(mut self, value: bool) -> Self {
+        self.cells.push(TableCell::Bool(value));
+        self
+    }
+}
+
+impl TableBuilder {
+    pub fn new() -> Self {
+        TableBuilder::default()
+    }
+
+    pub fn column(mut self, name: &str, cell_type: TableCellType) -> Self {
+        self.columns.push((name.to_string(), cell_type));
+        self
+    }
+
+    pub fn row(mut self, cells: impl FnOnce(RowBuilder) -> RowBuilder) -> Self {
+        self.rows.push(cells(RowBuilder::default()).cells);
+        self
+    }
+
+    //The table, with every row checked to have a cell for each column, converted to the type of its column
+    pub fn build(self) -> Result<Table, String> {
+        let mut structure = HashMap::new();
+        for (name, cell_type) in &self.columns {
+            if structure.insert(name.clone(), cell_type.clone()).is_some() {
+                return Err(format!("Table already has a column named '{}'", name));
+            }
+        }
+        let mut table = Table::new(structure);
+        for (index, cells) in self.rows.into_iter().enumerate() {
+            if cells.len() != self.columns.len() {
+                return Err(format!(
+                    "Row at index {} has {} cells, but the table has {} columns",
+                    index,
+                    cells.len(),
+                    self.columns.len()
+                ));
+            }
+            let data = self
+                .columns
+                .iter()
+                .map(|(name, _)| name.clone())
+                .zip(cells)
+                .collect();
+            let row = Row::new(data)
+                .coerce(table.get_structure())
+                .map_err(|e| format!("Row at index {}: {}", index, e))?;
+            table.data.push(row);
+        }
+        Ok(table)
+    }
+}
+
+//A table from rows given as maps of column names to values, e.g. built by a host from its own records.
+//The columns are those of the first row, and a column is a double if any row has a double in it
+impl TryFrom<Vec<HashMap<String, ExpressionValue>>> for Table {
+    type Error = String;
+
+    fn try_from(rows: Vec<HashMap<String, ExpressionValue>>) -> Result<Self, Self::Error> {
+        let Some(first) = rows.first() else {
+            return Err("Cannot tell the columns of a table from no rows".to_string());
+        };
+        let mut structure: HashMap<String, TableCellType> = HashMap::new();
+        for (index, values) in rows.iter().enumerate() {
+            if values.len() != first.len() || values.keys().any(|key| !first.contains_key(key)) {
+                let mut columns: Vec<&String> = values.keys().collect();
+                columns.sort();
+                let mut first_columns: Vec<&String> = first.keys().collect();
+                first_columns.sort();
+                return Err(format!(
+                    "Row at index {} has the columns {:?}, but the first row has {:?}",
+                    index, columns, first_columns
+                ));
+            }
+            for (name, value) in values {
+                let cell_type = match value {
+                    ExpressionValue::Number(_) => TableCellType::Int,
+                    ExpressionValue::Double(_) => TableCellType::Double,
+                    ExpressionValue::String(_) => TableCellType::String,
+                    ExpressionValue::Bool(_) => TableCellType::Bool,
+                    other => {
+                        return Err(format!(
+                            "Row at index {} column '{}' cannot hold {}",
+                            index,
+                            name,
+                            other.short_description()
+                        ));
+                    }
+                };
+                let column_type = structure.entry(name.clone()).or_insert(cell_type.clone());
+                match (&*column_type, &cell_type) {
+                    (TableCellType::Int, TableCellType::Double) => {
+                        *column_type = TableCellType::Double
+                    }
+                    (TableCellType::Double, TableCellType::Int) => {}
+                    (column_type, cell_type) if column_type != cell_type => {
+                        return Err(format!(
+                            "Row at index {} column '{}' is {}, but other rows have {}",
+                            index, name, cell_type, column_type
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut table = Table::new(structure);
+        for values in rows {
+            let mut data: Vec<(String, TableCell)> = values
+                .into_iter()
+                .map(|(name, value)| {
+                    let cell = match value {
+                        ExpressionValue::Number(n) => TableCell::Int(n),
+                        ExpressionValue::Double(d) => TableCell::Double(d),
+                        ExpressionValue::String(s) => TableCell::String(s),
+                        ExpressionValue::Bool(b) => TableCell::Bool(b),
+                        _ => unreachable!("checked above"),
+                    };
+                    (name, cell)
+                })
+                .collect();
+            // The map has no order, so the cells are kept in the order of their column names
+            data.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let row = Row::new(data).coerce(table.get_structure())?;
+            table.data.push(row);
+        }
+        Ok(table)
+    }
+}
+
+//Columns of the table returned by describe, in the order they are declared in wrench
+//table(string column, string type, int count, int nulls, double min, double max, double mean)
+pub fn describe_parameters() -> Vec<Parameter> {
+    vec![
+        Parameter::Parameter(TypeConstruct::String, "column".to_string()),
+        Parameter::Parameter(TypeConstruct::String, "type".to_string()),
+        Parameter::Parameter(TypeConstruct::Int, "count".to_string()),
+        Parameter::Parameter(TypeConstruct::Int, "nulls".to_string()),
+        Parameter::Parameter(TypeConstruct::Double, "min".to_string()),
+        Parameter::Parameter(TypeConstruct::Double, "max".to_string()),
+        Parameter::Parameter(TypeConstruct::Double, "mean".to_string()),
+    ]
+}
+
+//The columns of the table returned by histogram
+pub fn histogram_parameters() -> Vec<Parameter> {
+    vec![
+        Parameter::Parameter(TypeConstruct::Double, "bucket_low".to_string()),
+        Parameter::Parameter(TypeConstruct::Double, "bucket_high".to_string()),
+        Parameter::Parameter(TypeConstruct::Int, "count".to_string()),
+    ]
+}
+
+// Number of # in the bar of the largest bucket of a histogram
+const HISTOGRAM_BAR_WIDTH: i64 = 40;
+
+//Renders a table made by histogram as a line per bucket, with its bounds, count and a bar of # as long as the count
+//relative to the largest one, e.g. 0.0 - 2.5 | 3 | ####
+pub fn render_histogram(buckets: &Table) -> String {
+    let double = |row: &Row, column: &str| match row.get_cell(column) {
+        Some(TableCell::Double(d)) => format_double(*d),
+        _ => String::new(),
+    };
+    let lines: Vec<(String, i64)> = buckets
+        .iter()
+        .map(|row| {
+            let count = match row.get_cell("count") {
+                Some(TableCell::Int(n)) => *n,
+                _ => 0,
+            };
+            let bounds = format!(
+                "{} - {}",
+                double(row, "bucket_low"),
+                double(row, "bucket_high")
+            );
+            (bounds, count)
+        })
+        .collect();
+    if lines.is_empty() {
+        return "(no values)".to_string();
+    }
+    let bounds_width = lines
+        .iter()
+        .map(|(b, _)| b.chars().count())
+        .max()
+        .unwrap_or(0);
+    let largest = lines.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let count_width = largest.to_string().len();
+    lines
+        .iter()
+        .map(|(bounds, count)| {
+            // Every bucket with values gets at least one #, so small counts are not hidden
+            let bar = match *count {
+                0 => 0,
+                count => (count * HISTOGRAM_BAR_WIDTH / largest).max(1),
+            };
+            format!(
+                "{:<bounds_width$} | {:>count_width$} | {}",
+                bounds,
+                count,
+                "#".repeat(bar as usize)
+            )
+            .trim_end()
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+//Column names of a structure in the order they are printed in
+//The structure is a HashMap, so columns are sorted alphabetically to keep the output stable
+pub fn structure_columns(structure: &HashMap<String, TableCellType>) -> Vec<String> {
+    let mut columns: Vec<String> = structure.keys().cloned().collect();
+    columns.sort();
+    columns
+}
+
+//The columns of a structure with their types, in the order they are printed in, e.g. id: int, name: string
+pub fn describe_structure(structure: &HashMap<String, TableCellType>) -> String {
+    structure_columns(structure)
+        .iter()
+        .map(|name| format!("{}: {}", name, structure[name]))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Smallest width of a column when rendering, so short names still leave room for their values
+const MIN_COLUMN_WIDTH: usize = 8;
+
+// Cells longer than this are cut off when rendering, so a long string does not break the alignment
+const MAX_CELL_WIDTH: usize = 40;
+
+// Number of rows printed by print before the middle of a table is left out
+pub const PRINT_ROW_LIMIT: usize = 20;
+
+//Helper function to cut a cell off at MAX_CELL_WIDTH characters, marking that it was cut with …
+fn truncate_cell(cell: String) -> String {
+    if cell.chars().count() > MAX_CELL_WIDTH {
+        let mut truncated: String = cell.chars().take(MAX_CELL_WIDTH - 1).collect();
+        truncated.push('…');
+        truncated
+    } else {
+        cell
+    }
+}
+
+//Renders rows as aligned text lines under a header with the column names
+//Widths are fixed up front from the column names, so rows can be rendered one at a time as they arrive
+pub struct TableRenderer {
+    columns: Vec<String>,
+    widths: Vec<usize>,
+}
+
+impl TableRenderer {
+    pub fn new(structure: &HashMap<String, TableCellType>) -> Self {
+        let columns = structure_columns(structure);
+        let widths = columns
+            .iter()
+            .map(|c| c.chars().count().max(MIN_COLUMN_WIDTH))
+            .collect();
+        TableRenderer { columns, widths }
+    }
+
+    fn render_line(&self, cells: Vec<String>) -> String {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(self.widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect();
+        line.join(" | ").trim_end().to_string()
+    }
+
+    //The column names followed by a separator line
+    pub fn header(&self) -> String {
+        let separator: Vec<String> = self.widths.iter().map(|w| "-".repeat(*w)).collect();
+        format!(
+            "{}\n{}",
+            self.render_line(self.columns.clone()),
+            separator.join("-+-")
+        )
+    }
+
+    pub fn row(&self, row: &Row) -> String {
+        let cells = self
+            .columns
+            .iter()
+            .map(|c| {
+                row.get_cell(c)
+                    .map(|v| truncate_cell(v.to_string()))
+                    .unwrap_or_default()
+            })
+            .collect();
+        self.render_line(cells)
+    }
+
+    pub fn footer(&self, row_count: usize) -> String {
+        if row_count == 1 {
+            "1 row".to_string()
+        } else {
+            format!("{} rows", row_count)
+        }
+    }
+
+    //The footer followed by the number of columns, used when printing a whole table
+    pub fn summary(&self, row_count: usize) -> String {
+        if self.columns.len() == 1 {
+            format!("{} x 1 column", self.footer(row_count))
+        } else {
+            format!(
+                "{} x {} columns",
+                self.footer(row_count),
+                self.columns.len()
+            )
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,7 +1067,7 @@ mod tests {
     fn make_row() -> Row {
         Row::new(vec![
             ("id".to_string(), TableCell::Int(1)),
-            ("name".to_string(), TableCell::String("Alice".to_string())),
+            ("name".to_string(), TableCell::String("Alice".into())),
             ("score".to_string(), TableCell::Double(95.5)),
             ("active".to_string(), TableCell::Bool(true)),
         ])
@@ -160,28 +1077,196 @@ mod tests {
     fn test_row_get() {
         let row = make_row();
         assert_eq!(row.get("id"), ExpressionValue::Number(1));
-        assert_eq!(
-            row.get("name"),
-            ExpressionValue::String("Alice".to_string())
-        );
+        assert_eq!(row.get("name"), ExpressionValue::String("Alice".into()));
         assert_eq!(row.get("score"), ExpressionValue::Double(95.5));
         assert_eq!(row.get("active"), ExpressionValue::Bool(true));
     }
 
     #[test]
-    #[should_panic(expected = "Column name not found in row for missing")]
+    #[should_panic(
+        expected = "Interpretation error: Column 'missing' not found, the columns are active: bool, id: int, name: string, score: double."
+    )]
     fn test_row_get_missing_column() {
         let row = make_row();
         row.get("missing");
     }
 
+    #[test]
+    fn test_row_matches_structure() {
+        let row = make_row();
+        assert!(row.matches_structure(&make_structure()));
+
+        let mut missing_column = make_structure();
+        missing_column.remove("active");
+        assert!(!row.matches_structure(&missing_column));
+
+        let mut wrong_type = make_structure();
+        wrong_type.insert("id".to_string(), TableCellType::String);
+        assert!(!row.matches_structure(&wrong_type));
+    }
+
+    #[test]
+    fn test_row_set_converts_to_the_cell_type() {
+        let mut row = make_row();
+        assert_eq!(row.set("score", TableCell::Int(80)), Ok(()));
+        assert_eq!(row.get("score"), ExpressionValue::Double(80.0));
+        assert!(row.set("id", TableCell::String("2".into())).is_err());
+        assert!(row.set("missing", TableCell::Int(1)).is_err());
+    }
+
+    #[test]
+    fn test_table_set_row() {
+        let mut table = Table::new(make_structure());
+        table.add_row(make_row());
+        let mut row = make_row();
+        row.set("name", TableCell::String("Bob".into())).unwrap();
+        assert_eq!(table.set_row(0, row.clone()), Ok(()));
+        assert_eq!(table.get_row(0), row);
+        assert_eq!(
+            table.set_row(1, row),
+            Err("Row index 1 is out of bounds for a table of 1 rows".to_string())
+        );
+        let narrow = Row::new(vec![("id".to_string(), TableCell::Int(1))]);
+        assert_eq!(
+            table.set_row(0, narrow),
+            Err("The row has the columns id: int, but the table has active: bool, id: int, name: string, score: double".to_string())
+        );
+    }
+
+    #[test]
+    fn test_table_builder() {
+        let table = TableBuilder::new()
+            .column("id", TableCellType::Int)
+            .column("name", TableCellType::String)
+            .column("score", TableCellType::Double)
+            .row(|r| r.int(1).str("A").int(5))
+            .row(|r| r.int(2).str("B").double(2.5))
+            .build()
+            .unwrap();
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(table.get_row(0).get("score"), ExpressionValue::Double(5.0));
+        assert_eq!(
+            table.get_row(1).get("name"),
+            ExpressionValue::String("B".into())
+        );
+        assert_eq!(
+            TableBuilder::new()
+                .column("id", TableCellType::Int)
+                .row(|r| r.str("1"))
+                .build(),
+            Err("Row at index 0: column 'id' expected int, found string 1".to_string())
+        );
+        assert_eq!(
+            TableBuilder::new()
+                .column("id", TableCellType::Int)
+                .row(|r| r.int(1).int(2))
+                .build(),
+            Err("Row at index 0 has 2 cells, but the table has 1 columns".to_string())
+        );
+    }
+
+    #[test]
+    fn test_table_from_maps() {
+        let record = |id: ExpressionValue, name: &str| {
+            HashMap::from([
+                ("id".to_string(), id),
+                ("name".to_string(), ExpressionValue::String(name.into())),
+            ])
+        };
+        let table = Table::try_from(vec![
+            record(ExpressionValue::Number(1), "A"),
+            record(ExpressionValue::Double(2.5), "B"),
+        ])
+        .unwrap();
+        assert_eq!(
+            table.get_structure().get("id"),
+            Some(&TableCellType::Double)
+        );
+        assert_eq!(table.get_row(0).get("id"), ExpressionValue::Double(1.0));
+        assert_eq!(
+            Table::try_from(vec![
+                record(ExpressionValue::Number(1), "A"),
+                record(ExpressionValue::Bool(true), "B"),
+            ]),
+            Err("Row at index 1 column 'id' is bool, but other rows have int".to_string())
+        );
+        assert!(Table::try_from(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_row_from_pairs() {
+        let pairs: Vec<(String, TableCell)> = make_row().into();
+        assert_eq!(pairs[0], ("id".to_string(), TableCell::Int(1)));
+        assert_eq!(Row::from(pairs), make_row());
+    }
+
+    #[test]
+    fn test_coerce_cell_widens_only_int_to_double() {
+        assert_eq!(
+            coerce_cell(TableCell::Int(2), &TableCellType::Double),
+            Ok(TableCell::Double(2.0))
+        );
+        assert_eq!(
+            coerce_cell(TableCell::Double(2.5), &TableCellType::Double),
+            Ok(TableCell::Double(2.5))
+        );
+        assert_eq!(
+            coerce_cell(TableCell::Double(2.5), &TableCellType::Int),
+            Err("expected int, found double 2.5".to_string())
+        );
+        assert_eq!(
+            coerce_cell(TableCell::Int(1), &TableCellType::String),
+            Err("expected string, found int 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_add_row_widens_ints_in_double_columns() {
+        let mut table = Table::new(make_structure());
+        table.add_row(Row::new(vec![
+            ("id".to_string(), TableCell::Int(1)),
+            ("name".to_string(), TableCell::String("Alice".into())),
+            ("score".to_string(), TableCell::Int(95)),
+            ("active".to_string(), TableCell::Bool(true)),
+        ]));
+        assert_eq!(
+            table.get_row(0).get_cell("score"),
+            Some(&TableCell::Double(95.0))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Interpretation error: column 'name' expected string, found int 1")]
+    fn test_add_row_rejects_int_in_string_column() {
+        let mut table = Table::new(make_structure());
+        table.add_row(Row::new(vec![
+            ("id".to_string(), TableCell::Int(1)),
+            ("name".to_string(), TableCell::Int(1)),
+        ]));
+    }
+
+    #[test]
+    fn test_renderer_aligns_rows_under_header() {
+        let renderer = TableRenderer::new(&make_structure());
+        assert_eq!(
+            renderer.header(),
+            "active   | id       | name     | score\n---------+----------+----------+---------"
+        );
+        assert_eq!(
+            renderer.row(&make_row()),
+            "true     | 1        | Alice    | 95.5"
+        );
+        assert_eq!(renderer.footer(1), "1 row");
+        assert_eq!(renderer.footer(3), "3 rows");
+    }
+
     #[test]
     fn test_table_add_and_iter() {
         let mut table = Table::new(make_structure());
         let row1 = make_row();
         let row2 = Row::new(vec![
             ("id".to_string(), TableCell::Int(2)),
-            ("name".to_string(), TableCell::String("Bob".to_string())),
+            ("name".to_string(), TableCell::String("Bob".into())),
             ("score".to_string(), TableCell::Double(88.0)),
             ("active".to_string(), TableCell::Bool(false)),
         ]);
@@ -198,7 +1283,7 @@ mod tests {
         table.add_row(make_row());
         table.add_row(Row::new(vec![
             ("id".to_string(), TableCell::Int(2)),
-            ("name".to_string(), TableCell::String("Bob".to_string())),
+            ("name".to_string(), TableCell::String("Bob".into())),
             ("score".to_string(), TableCell::Double(88.0)),
             ("active".to_string(), TableCell::Bool(false)),
         ]));
@@ -238,4 +1323,354 @@ mod tests {
         ];
         Table::parameters_to_structure(params);
     }
+
+    fn make_numbered_table(rows: i64) -> Table {
+        let mut structure = HashMap::new();
+        structure.insert("id".to_string(), TableCellType::Int);
+        structure.insert("name".to_string(), TableCellType::String);
+        let mut table = Table::new(structure);
+        for i in 0..rows {
+            table.add_row(Row::new(vec![
+                ("id".to_string(), TableCell::Int(i)),
+                (
+                    "name".to_string(),
+                    TableCell::String(format!("name{}", i).into()),
+                ),
+            ]));
+        }
+        table
+    }
+
+    #[test]
+    fn test_render_limits_rows() {
+        let rendered = make_numbered_table(100).render(Some(PRINT_ROW_LIMIT));
+        let lines: Vec<&str> = rendered.lines().collect();
+        // Header and separator, 10 rows, ellipsis, 10 rows and the summary
+        assert_eq!(lines.len(), 24);
+        assert_eq!(lines[2], "0        | name0");
+        assert_eq!(lines[11], "9        | name9");
+        assert_eq!(lines[12], "…");
+        assert_eq!(lines[13], "90       | name90");
+        assert_eq!(lines[22], "99       | name99");
+        assert_eq!(lines[23], "100 rows x 2 columns");
+    }
+
+    #[test]
+    fn test_render_full_table() {
+        let rendered = make_numbered_table(100).render(None);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 103);
+        assert!(!lines.contains(&"…"));
+        assert_eq!(lines[102], "100 rows x 2 columns");
+    }
+
+    #[test]
+    fn test_renderer_truncates_long_cells() {
+        let mut structure = HashMap::new();
+        structure.insert("text".to_string(), TableCellType::String);
+        let renderer = TableRenderer::new(&structure);
+        let row = Row::new(vec![(
+            "text".to_string(),
+            TableCell::String("x".repeat(50).into()),
+        )]);
+        assert_eq!(renderer.row(&row), format!("{}…", "x".repeat(39)));
+    }
+
+    #[test]
+    fn test_describe_mixed_columns() {
+        let mut table = Table::new(make_structure());
+        table.add_row(make_row());
+        table.add_row(Row::new(vec![
+            ("id".to_string(), TableCell::Int(3)),
+            ("name".to_string(), TableCell::String("Bob".into())),
+            ("score".to_string(), TableCell::Double(80.5)),
+            ("active".to_string(), TableCell::Bool(false)),
+        ]));
+        let statistics = table.describe();
+        let rows: Vec<&Row> = statistics.iter().collect();
+        assert_eq!(rows.len(), 4);
+
+        // Columns are described in alphabetical order: active, id, name, score
+        assert_eq!(
+            rows[0].get_cell("type"),
+            Some(&TableCell::String("bool".into()))
+        );
+        assert_eq!(rows[0].get_cell("count"), Some(&TableCell::Int(2)));
+        assert!(matches!(rows[0].get_cell("mean"), Some(TableCell::Double(d)) if d.is_nan()));
+
+        assert_eq!(
+            rows[1].get_cell("column"),
+            Some(&TableCell::String("id".into()))
+        );
+        assert_eq!(rows[1].get_cell("min"), Some(&TableCell::Double(1.0)));
+        assert_eq!(rows[1].get_cell("max"), Some(&TableCell::Double(3.0)));
+        assert_eq!(rows[1].get_cell("mean"), Some(&TableCell::Double(2.0)));
+
+        assert_eq!(rows[2].get_cell("count"), Some(&TableCell::Int(2)));
+        assert_eq!(rows[3].get_cell("mean"), Some(&TableCell::Double(88.0)));
+        assert_eq!(rows[3].get_cell("nulls"), Some(&TableCell::Int(0)));
+    }
+
+    #[test]
+    fn test_describe_empty_table() {
+        let statistics = Table::new(make_structure()).describe();
+        assert_eq!(statistics.iter().count(), 4);
+        for row in statistics.iter() {
+            assert_eq!(row.get_cell("count"), Some(&TableCell::Int(0)));
+            assert!(matches!(row.get_cell("min"), Some(TableCell::Double(d)) if d.is_nan()));
+        }
+    }
+
+    #[test]
+    fn test_describe_counts_null_cells() {
+        let statistics = score_table(&[Some(1.0), None, Some(3.0), None]).describe();
+        let rows: Vec<&Row> = statistics.iter().collect();
+        // Columns are described in alphabetical order: name, score
+        assert_eq!(rows[0].get_cell("nulls"), Some(&TableCell::Int(0)));
+        assert_eq!(rows[1].get_cell("count"), Some(&TableCell::Int(2)));
+        assert_eq!(rows[1].get_cell("nulls"), Some(&TableCell::Int(2)));
+        assert_eq!(rows[1].get_cell("mean"), Some(&TableCell::Double(2.0)));
+    }
+
+    fn score_table(scores: &[Option<f64>]) -> Table {
+        let mut structure = HashMap::new();
+        structure.insert("score".to_string(), TableCellType::Double);
+        structure.insert("name".to_string(), TableCellType::String);
+        let mut table = Table::new(structure);
+        for score in scores {
+            let cell = match score {
+                Some(score) => TableCell::Double(*score),
+                None => TableCell::Null(TableCellType::Double),
+            };
+            table.add_row(Row::new(vec![
+                ("score".to_string(), cell),
+                ("name".to_string(), TableCell::String("a".into())),
+            ]));
+        }
+        table
+    }
+
+    // The bucket_low, bucket_high and count of each bucket
+    fn buckets(histogram: &Table) -> Vec<(f64, f64, i64)> {
+        histogram
+            .iter()
+            .map(|row| {
+                match (
+                    row.get("bucket_low"),
+                    row.get("bucket_high"),
+                    row.get("count"),
+                ) {
+                    (
+                        ExpressionValue::Double(low),
+                        ExpressionValue::Double(high),
+                        ExpressionValue::Number(count),
+                    ) => (low, high, count),
+                    other => panic!("Unexpected bucket {:?}", other),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_histogram_counts_values_in_buckets() {
+        let table = score_table(&[
+            Some(0.0),
+            Some(1.0),
+            Some(2.4),
+            Some(2.5),
+            None,
+            Some(7.0),
+            Some(10.0),
+        ]);
+        let histogram = table.histogram("score", 4).unwrap();
+        assert_eq!(
+            buckets(&histogram),
+            [(0.0, 2.5, 3), (2.5, 5.0, 1), (5.0, 7.5, 1), (7.5, 10.0, 1)]
+        );
+        assert_eq!(
+            render_histogram(&histogram),
+            "0.0 - 2.5  | 3 | ########################################\n\
+             2.5 - 5.0  | 1 | #############\n\
+             5.0 - 7.5  | 1 | #############\n\
+             7.5 - 10.0 | 1 | #############"
+        );
+    }
+
+    #[test]
+    fn test_histogram_of_constant_column_has_one_bucket() {
+        let histogram = score_table(&[Some(3.0), Some(3.0), Some(3.0)])
+            .histogram("score", 10)
+            .unwrap();
+        assert_eq!(buckets(&histogram), [(3.0, 3.0, 3)]);
+
+        let empty = score_table(&[None]).histogram("score", 10).unwrap();
+        assert_eq!(empty.row_count(), 0);
+        assert_eq!(render_histogram(&empty), "(no values)");
+    }
+
+    #[test]
+    fn test_histogram_table_structure() {
+        let histogram = make_numbered_table(10).histogram("id", 2).unwrap();
+        assert_eq!(
+            histogram.get_structure(),
+            &Table::parameters_to_structure(histogram_parameters())
+        );
+        assert_eq!(buckets(&histogram), [(0.0, 4.5, 5), (4.5, 9.0, 5)]);
+    }
+
+    #[test]
+    fn test_histogram_errors() {
+        let table = make_numbered_table(3);
+        assert_eq!(
+            table.histogram("name", 3).unwrap_err(),
+            "histogram needs an int or double column, but 'name' is a string column"
+        );
+        assert_eq!(
+            table.histogram("id", 0).unwrap_err(),
+            "histogram needs at least 1 bucket, got 0"
+        );
+        assert!(table.histogram("nope", 3).is_err());
+    }
+
+    #[test]
+    fn test_format_double() {
+        assert_eq!(format_double(0.1), "0.1");
+        assert_eq!(format_double(3.0), "3.0");
+        assert_eq!(format_double(-0.0), "-0.0");
+        assert_eq!(format_double(1e-7), "0.0000001");
+        assert_eq!(format_double(1e-8), "1.0e-8");
+        assert_eq!(format_double(1234567890.123), "1234567890.123");
+        assert_eq!(format_double(f64::MAX), "1.7976931348623157e308");
+    }
+
+    #[test]
+    fn test_format_double_round_trips() {
+        for d in [
+            0.1,
+            1e-7,
+            1e-8,
+            1234567890.123,
+            -0.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+        ] {
+            let parsed: f64 = format_double(d).parse().unwrap();
+            assert_eq!(parsed.to_bits(), d.to_bits(), "{} did not round trip", d);
+        }
+    }
+
+    fn make_table() -> Table {
+        let mut table = Table::new(make_structure());
+        table.add_row(make_row());
+        table.add_row(Row::new(vec![
+            ("id".to_string(), TableCell::Int(2)),
+            ("name".to_string(), TableCell::String("Bob".into())),
+            ("score".to_string(), TableCell::Double(80.0)),
+            ("active".to_string(), TableCell::Bool(false)),
+        ]));
+        table
+    }
+
+    #[test]
+    fn test_debug_writes_structure_in_column_order() {
+        let debug = format!("{:?}", Table::new(make_structure()));
+        assert_eq!(
+            debug,
+            "Table { data: [], structure: [(\"active\", Bool), (\"id\", Int), (\"name\", String), (\"score\", Double)] }"
+        );
+        assert_eq!(
+            describe_structure(&make_structure()),
+            "active: bool, id: int, name: string, score: double"
+        );
+    }
+
+    #[test]
+    fn test_with_int_and_string_columns() {
+        let table = make_table();
+        let with_rank = table
+            .with_column(
+                "rank",
+                TableCellType::Int,
+                vec![TableCell::Int(2), TableCell::Int(1)],
+            )
+            .unwrap();
+        let with_team = with_rank
+            .with_column(
+                "team",
+                TableCellType::String,
+                vec![
+                    TableCell::String("red".into()),
+                    TableCell::String("blue".into()),
+                ],
+            )
+            .unwrap();
+        assert_eq!(with_team.get_structure()["rank"], TableCellType::Int);
+        assert_eq!(with_team.get_structure()["team"], TableCellType::String);
+        assert_eq!(
+            with_team.get_column("rank"),
+            ExpressionValue::Array(vec![ExpressionValue::Number(2), ExpressionValue::Number(1)])
+        );
+        assert_eq!(
+            with_team.get_row(1).get("team"),
+            ExpressionValue::String("blue".into())
+        );
+        // The old columns are kept, and the table it was made from is left as it was
+        assert_eq!(
+            with_team.get_row(1).get("name"),
+            ExpressionValue::String("Bob".into())
+        );
+        assert!(!table.get_structure().contains_key("rank"));
+    }
+
+    #[test]
+    fn test_with_column_widens_ints_in_double_column() {
+        let table = make_table()
+            .with_column(
+                "bonus",
+                TableCellType::Double,
+                vec![TableCell::Int(1), TableCell::Double(0.5)],
+            )
+            .unwrap();
+        assert_eq!(
+            table.get_row(0).get_cell("bonus"),
+            Some(&TableCell::Double(1.0))
+        );
+    }
+
+    #[test]
+    fn test_with_column_length_mismatch() {
+        assert_eq!(
+            make_table().with_column("bonus", TableCellType::Int, vec![TableCell::Int(1)]),
+            Err("Column 'bonus' has 1 values for a table with 2 rows".to_string())
+        );
+    }
+
+    #[test]
+    fn test_with_column_duplicate_name() {
+        assert_eq!(
+            make_table().with_column(
+                "id",
+                TableCellType::Int,
+                vec![TableCell::Int(1), TableCell::Int(2)]
+            ),
+            Err("Table already has a column named 'id'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_without_column_is_inverse_of_with_column() {
+        let table = make_table();
+        let with_rank = table
+            .with_column(
+                "rank",
+                TableCellType::Int,
+                vec![TableCell::Int(2), TableCell::Int(1)],
+            )
+            .unwrap();
+        assert_eq!(with_rank.without_column("rank"), Ok(table.clone()));
+        assert_eq!(
+            table.without_column("rank"),
+            Err("Table has no column named 'rank'".to_string())
+        );
+    }
 }
@@ -1,6 +1,14 @@
 #![allow(clippy::vec_box)]
 
-use std::fmt::Debug;
+use std::{
+    fmt::{self, Debug},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use serde::Serialize;
 
 #[derive(PartialEq, Debug)]
 pub struct TypedExpr {
@@ -8,62 +16,164 @@ pub struct TypedExpr {
     pub expr_type: TypeConstruct, // Represents the type of the expression
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Statement {
     Expr(Box<Expr>),                               // Represents an expression statement
     VariableAssignment(String, Box<Expr>), // Represents a variable assignment with its name and value
-    Declaration(Declaration),              // Represents a declaration
+    FieldAssignment(String, String, Box<Expr>), // Represents an assignment to a column of a row variable, e.g. r.score = 5;
+    IndexAssignment(String, Box<Expr>, Box<Expr>), // Represents an assignment to an element of an array variable, e.g. xs[0] = 5;
+    Declaration(Declaration),                      // Represents a declaration
     Return(Box<Expr>), // Represents a return statement with an optional expression
+    Break,             // Represents a break out of the loop it is in
+    Continue,          // Represents a continue with the next iteration of the loop it is in
     If(Box<Expr>, Box<Statement>, Box<Statement>), // Represents an if statement with its condition, body, and optional else body
-    For(Parameter, Box<Expr>, Box<Statement>), // Represents a for loop with its initialization, condition, and body
+    For(Option<Parameter>, Parameter, Box<Expr>, Box<Statement>), // Represents a for loop with its optional index variable, loop variable, iterable and body
     While(Box<Expr>, Box<Statement>), // Represents a while loop with its condition and body
+    Defer(Box<Statement>), // Represents a block run when the scope it is in exits, e.g. defer { print("done"); }
+    EnumDeclaration(String, Vec<String>), // Represents an enum with its name and values, e.g. enum Status = ["active", "inactive"];
     Compound(Box<Statement>, Box<Statement>), // Represents a compound statement with two statements
     Skip,
+    Counted(Counter, Box<Statement>), // A statement with a counter of how many times it runs, only added for --coverage
+    Located(Span, Box<Statement>), // A statement with where it is in the source, added by the parser around every statement
+}
+
+// Statements are compared without the spans the parser gives them, so a parsed program equals the same program built by hand
+impl PartialEq for Statement {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Statement::Located(_, statement), other)
+            | (other, Statement::Located(_, statement)) => **statement == *other,
+            (Statement::Expr(a), Statement::Expr(b)) => a == b,
+            (Statement::VariableAssignment(a, x), Statement::VariableAssignment(b, y)) => {
+                a == b && x == y
+            }
+            (Statement::FieldAssignment(a, c, x), Statement::FieldAssignment(b, d, y)) => {
+                a == b && c == d && x == y
+            }
+            (Statement::IndexAssignment(a, i, x), Statement::IndexAssignment(b, j, y)) => {
+                a == b && i == j && x == y
+            }
+            (Statement::Declaration(a), Statement::Declaration(b)) => a == b,
+            (Statement::Return(a), Statement::Return(b)) => a == b,
+            (Statement::Break, Statement::Break)
+            | (Statement::Continue, Statement::Continue)
+            | (Statement::Skip, Statement::Skip) => true,
+            (Statement::If(a, x, u), Statement::If(b, y, v)) => a == b && x == y && u == v,
+            (Statement::For(a, p, i, x), Statement::For(b, q, j, y)) => {
+                a == b && p == q && i == j && x == y
+            }
+            (Statement::While(a, x), Statement::While(b, y)) => a == b && x == y,
+            (Statement::Defer(a), Statement::Defer(b)) => a == b,
+            (Statement::EnumDeclaration(a, x), Statement::EnumDeclaration(b, y)) => {
+                a == b && x == y
+            }
+            (Statement::Compound(a, x), Statement::Compound(b, y)) => a == b && x == y,
+            (Statement::Counted(a, x), Statement::Counted(b, y)) => a == b && x == y,
+            _ => false,
+        }
+    }
+}
+
+impl Statement {
+    // The statement without the span the parser put around it
+    pub fn unlocated(&self) -> &Statement {
+        match self {
+            Statement::Located(_, statement) => statement.unlocated(),
+            statement => statement,
+        }
+    }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum Declaration {
     Variable(TypeConstruct, String, Box<Expr>), // Represents a variable declaration with its type, name, and assigned value
     Constant(TypeConstruct, String, Box<Expr>), // Represents a variable declaration with its type, name, and assigned value
     Function(TypeConstruct, String, Vec<Parameter>, Box<Statement>), // Represents a function declaration with its return type, name, parameters, and body
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum Expr {
-    Number(i32),                               // Represents a number
-    Double(f64),                               // Represents a double value
-    Null,                                      // Represents a null value
-    StringLiteral(String),                     // Represents a string literal
+    Number(i64),                                               // Represents a number
+    Double(f64),                                               // Represents a double value
+    Null,                                                      // Represents a null value
+    StringLiteral(Arc<str>),                                   // Represents a string literal
+    Char(char),                                // Represents a character literal, e.g. 'a'
     Identifier(String),                        // Represents an identifier (variable name)
     Bool(bool),                                // Represents a boolean value
     Operation(Box<Expr>, Operator, Box<Expr>), // Represents an operation with left and right operands and an operator
     Not(Box<Expr>), // Represents a unary operation with an operator and an operand
-    Table(Vec<Parameter>),
+    Negation(Box<Expr>), // Represents unary minus, e.g. -x
+    Table(Vec<Parameter>, Vec<ColumnDefault>, Vec<Box<Expr>>), // Represents a table with its columns, the defaults declared for some of them and the rows it starts with
     Row(Vec<ColumnAssignmentEnum>),
     Indexing(Box<Expr>, Box<Expr>), // Represents indexing, e.g. into an array
     Array(Vec<Box<Expr>>),          // Represents an array with its elements
     Pipe(Box<Expr>, String, Vec<Box<Expr>>), // Represents a pipe operation, e.g. for chaining operations
     FunctionCall(String, Vec<Box<Expr>>), // Represents a function call with its name and arguments
     ColumnIndexing(Box<Expr>, String),    // Represents indexing into a column of a table or row
+    Range(Box<Expr>, Box<Expr>, bool), // Represents the ints from the start up to the end, and the end too if the bool is set, e.g. 0..10 in a for loop
+    Lambda(Vec<Parameter>, TypeConstruct, Box<Statement>), // Represents a function with no name, with its parameters, return type and body, e.g. fn (int x) int { return x + 1; }
 }
 
+// The stage name of a pipe into a lambda, e.g. t pipe (fn (row(int id) r) bool { return r.id < 10; }), where the lambda
+// is the only argument of the stage. It is a keyword, so no function can have it as its name
+pub const LAMBDA_STAGE: &str = "fn";
+
 // Enum representing types
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum TypeConstruct {
     Bool,
     Int,
     Double,
     String,
+    Char,
     Null,
     Array(Box<TypeConstruct>), // Represents an array type
     Function(Box<TypeConstruct>, Vec<TypeConstruct>), // Represents a function type with return type and parameter types
     Table(Vec<Parameter>),                            // Represents a table type with its columns
     Row(Vec<Parameter>),                              // Represents a row type with its columns
-    Any,                                              // Represents any type used for print
+    Nullable(Box<TypeConstruct>), // Represents the type of a column that may hold null, e.g. string note ?
+    Enum(String, Vec<String>), // Represents a string that is one of the values of a named enum, e.g. Status
+    Any,                       // Represents any type used for print
+}
+
+// Types are written as in wrench, e.g. fn int(string, table(int id))
+impl fmt::Display for TypeConstruct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeConstruct::Bool => write!(f, "bool"),
+            TypeConstruct::Int => write!(f, "int"),
+            TypeConstruct::Double => write!(f, "double"),
+            TypeConstruct::String => write!(f, "string"),
+            TypeConstruct::Char => write!(f, "char"),
+            TypeConstruct::Null => write!(f, "null"),
+            TypeConstruct::Array(element) => write!(f, "{}[]", element),
+            TypeConstruct::Function(return_type, parameters) => {
+                let parameters: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+                write!(f, "fn {}({})", return_type, parameters.join(", "))
+            }
+            TypeConstruct::Table(columns) => write!(f, "table({})", parameter_list(columns)),
+            TypeConstruct::Row(columns) => write!(f, "row({})", parameter_list(columns)),
+            TypeConstruct::Nullable(inner) => write!(f, "{}?", inner),
+            TypeConstruct::Enum(name, _) => write!(f, "{}", name),
+            TypeConstruct::Any => write!(f, "any"),
+        }
+    }
+}
+
+// Parameters and columns as they are declared, e.g. int id, string name, string note ?
+pub fn parameter_list(parameters: &[Parameter]) -> String {
+    parameters
+        .iter()
+        .map(|Parameter::Parameter(t, name)| match t {
+            TypeConstruct::Nullable(inner) => format!("{} {} ?", inner, name),
+            t => format!("{} {}", t, name),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
 }
 
 // Enum representing the different types of operations
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum Operator {
     Multiplication,  // multiplication (*)
     Exponent,        // exponent (**)
@@ -78,27 +188,101 @@ pub enum Operator {
                      //And
 }
 
+impl Operator {
+    // The symbol of the operator as written in wrench, used in error messages
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Multiplication => "*",
+            Operator::Exponent => "**",
+            Operator::Addition => "+",
+            Operator::Subtraction => "-",
+            Operator::Division => "/",
+            Operator::Modulo => "%",
+            Operator::Equals => "==",
+            Operator::LessThan => "<",
+            Operator::LessThanOrEqual => "<=",
+            Operator::Or => "or",
+        }
+    }
+}
+
 /*
 =======================================
 Building blocks, used in other enums
 =======================================
 */
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum Parameter {
     Parameter(TypeConstruct, String), // Represents a parameter with its type and name
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum ColumnAssignmentEnum {
     ColumnAssignment(TypeConstruct, String, Box<Expr>), // Represents a column assignment with its type, name, and value
 }
 
+// Where a statement is in the source, as the byte offsets of its first character and of the character after it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+// An error raised by an action of the grammar, e.g. a reserved word used as a name, with the span of the tokens it is about
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrammarError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl GrammarError {
+    pub fn new(message: String, start: usize, end: usize) -> Self {
+        GrammarError {
+            message,
+            span: Span { start, end },
+        }
+    }
+}
+
+// Counts how many times a statement runs. Clones of the statement, like the bodies of functions when they are called
+// or the stages of a pipe on their own threads, share the count. Counters are equal only to their own clones
+// In JSON a counter is the count it has reached
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Counter(Arc<AtomicUsize>);
+
+impl Counter {
+    pub fn hit(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for Counter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// The syntax tree as JSON, for tools that work on programs as wrench sees them, e.g. {"Expr":{"Number":1}}
+// Each variant is an object with the name of the variant as its key, and boxes are left out
+pub fn ast_to_json(statement: &Statement) -> String {
+    serde_json::to_string_pretty(statement).expect("A syntax tree can always be written as JSON")
+}
+
 /*
 =======================================
 Helper functions for building ASTs
 =======================================
 */
+// The pipe into a lambda, see LAMBDA_STAGE
+pub fn make_lambda_pipe(left: Box<Expr>, lambda: Box<Expr>) -> Box<Expr> {
+    Box::new(Expr::Pipe(left, LAMBDA_STAGE.to_string(), vec![lambda]))
+}
+
 pub fn make_compound(stmts: Vec<Statement>) -> Box<Statement> {
     stmts
         .into_iter()
@@ -108,6 +292,59 @@ pub fn make_compound(stmts: Vec<Statement>) -> Box<Statement> {
         })
 }
 
+// The default of a column declared in a table expression, e.g. double score = 0.0, by the name of the column
+pub type ColumnDefault = (String, Box<Expr>);
+
+// The table expression table(...) with the columns declared in it, some of them with a default, and the rows given after it
+// in brackets, if any, e.g. table(int id) [row(int id = 1)]
+pub fn make_table(columns: Vec<(Parameter, Option<Box<Expr>>)>, rows: Vec<Box<Expr>>) -> Box<Expr> {
+    let mut defaults = Vec::new();
+    let mut params = Vec::new();
+    for (param, default) in columns {
+        if let (Parameter::Parameter(_, name), Some(default)) = (&param, default) {
+            defaults.push((name.clone(), default));
+        }
+        params.push(param);
+    }
+    Box::new(Expr::Table(params, defaults, rows))
+}
+
+// The statement name++; or name--; which adds or subtracts one with the operator given
+pub fn make_step(name: String, op: Operator) -> Statement {
+    let value = Expr::Operation(
+        Box::new(Expr::Identifier(name.clone())),
+        op,
+        Box::new(Expr::Number(1)),
+    );
+    Statement::VariableAssignment(name, Box::new(value))
+}
+
+// The assignment target = value; Only a variable, an element of an array variable or a column of a row variable can be
+// assigned to
+pub fn make_assignment(target: Expr, value: Box<Expr>) -> Result<Statement, String> {
+    match target {
+        Expr::Identifier(name) => Ok(Statement::VariableAssignment(name, value)),
+        Expr::Indexing(array, index) => match *array {
+            Expr::Identifier(name) => Ok(Statement::IndexAssignment(name, index, value)),
+            _ => Err(
+                "Cannot assign to an element of an expression, only to an element of an array variable, e.g. xs[0] = ...;"
+                    .to_string(),
+            ),
+        },
+        Expr::ColumnIndexing(row, column) => match *row {
+            Expr::Identifier(name) => Ok(Statement::FieldAssignment(name, column, value)),
+            _ => Err(format!(
+                "Cannot assign to the column '{}' of an expression, only to a column of a row variable, e.g. r.{} = ...;",
+                column, column
+            )),
+        },
+        _ => Err(
+            "Only a variable, e.g. x = ...;, an element of an array variable, e.g. xs[0] = ...;, or a column of a row variable, e.g. r.score = ...;, can be assigned to"
+                .to_string(),
+        ),
+    }
+}
+
 pub fn ast_less_than(left: Box<Expr>, right: Box<Expr>) -> Box<Expr> {
     Box::new(Expr::Operation(left, Operator::LessThan, right))
 }
@@ -138,3 +375,7 @@ pub fn ast_greater_than_or_equal(left: Box<Expr>, right: Box<Expr>) -> Box<Expr>
 pub fn ast_greater_than(left: Box<Expr>, right: Box<Expr>) -> Box<Expr> {
     ast_not(ast_less_than_or_equal(left, right)) // !(A <= B) == A > B
 }
+
+pub fn ast_not_equals(left: Box<Expr>, right: Box<Expr>) -> Box<Expr> {
+    ast_not(Box::new(Expr::Operation(left, Operator::Equals, right))) // !(A == B) == A != B
+}
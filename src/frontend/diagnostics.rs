@@ -0,0 +1,653 @@
+use std::fmt;
+
+use super::source::{SourceLocation, SourceMap};
+
+/*
+ * This file gives the errors found by the type checker codes, with a longer explanation of each for wrench --explain,
+ * and writes the diagnostics found running a program for people or, as JSON, for tools
+ */
+
+// The kinds of type errors. The type checker gives an error its code where the error is made, see TypeError
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    UndefinedName,
+    TypeMismatch,
+    IncompatibleOperands,
+    DivisionByZero,
+    DoubleToInt,
+    ArgumentCount,
+    ConstantAssignment,
+    ConditionNotBool,
+    ReturnTypeMismatch,
+    BuiltinRedefined,
+    UnknownColumn,
+    DuplicateColumn,
+    InvalidPipeFunction,
+    PipeSchemaMismatch,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 14] = [
+        ErrorCode::UndefinedName,
+        ErrorCode::TypeMismatch,
+        ErrorCode::IncompatibleOperands,
+        ErrorCode::DivisionByZero,
+        ErrorCode::DoubleToInt,
+        ErrorCode::ArgumentCount,
+        ErrorCode::ConstantAssignment,
+        ErrorCode::ConditionNotBool,
+        ErrorCode::ReturnTypeMismatch,
+        ErrorCode::BuiltinRedefined,
+        ErrorCode::UnknownColumn,
+        ErrorCode::DuplicateColumn,
+        ErrorCode::InvalidPipeFunction,
+        ErrorCode::PipeSchemaMismatch,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::UndefinedName => "E001",
+            ErrorCode::TypeMismatch => "E002",
+            ErrorCode::IncompatibleOperands => "E003",
+            ErrorCode::DivisionByZero => "E004",
+            ErrorCode::DoubleToInt => "E005",
+            ErrorCode::ArgumentCount => "E006",
+            ErrorCode::ConstantAssignment => "E007",
+            ErrorCode::ConditionNotBool => "E008",
+            ErrorCode::ReturnTypeMismatch => "E009",
+            ErrorCode::BuiltinRedefined => "E010",
+            ErrorCode::UnknownColumn => "E011",
+            ErrorCode::DuplicateColumn => "E012",
+            ErrorCode::InvalidPipeFunction => "E013",
+            ErrorCode::PipeSchemaMismatch => "E014",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<ErrorCode> {
+        ErrorCode::ALL
+            .into_iter()
+            .find(|error| error.code().eq_ignore_ascii_case(code))
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::UndefinedName => "undefined variable or function",
+            ErrorCode::TypeMismatch => "value of the wrong type",
+            ErrorCode::IncompatibleOperands => "operator used on values it does not work on",
+            ErrorCode::DivisionByZero => "division by a literal zero",
+            ErrorCode::DoubleToInt => "double used where an int is expected",
+            ErrorCode::ArgumentCount => "wrong number of arguments",
+            ErrorCode::ConstantAssignment => "assignment to a constant",
+            ErrorCode::ConditionNotBool => "condition that is not a bool",
+            ErrorCode::ReturnTypeMismatch => "returned value of the wrong type",
+            ErrorCode::BuiltinRedefined => "name declared that a builtin has",
+            ErrorCode::UnknownColumn => "column that the table or row does not have",
+            ErrorCode::DuplicateColumn => "column declared twice",
+            ErrorCode::InvalidPipeFunction => "pipe with something that cannot be piped",
+            ErrorCode::PipeSchemaMismatch => "pipe function that does not fit the table",
+        }
+    }
+
+    pub fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::UndefinedName => {
+                "A name is used that is not declared in any scope the use can see. Variables declared inside \
+                 a block are gone after it, and functions cannot see the variables around them.\n\
+                 \n\
+                 Example:\n    if (true) { var int x = 1; }\n    print(x);\n\
+                 \n\
+                 Fix: declare the name before it is used, in the same scope or one around it, \
+                 or pass the value to the function as an argument."
+            }
+            ErrorCode::TypeMismatch => {
+                "A value is given where a value of another type is declared, e.g. in a declaration, \
+                 a function argument or a row column.\n\
+                 \n\
+                 Example:\n    var int x = \"five\";\n\
+                 \n\
+                 Fix: give a value of the declared type, or change the declared type."
+            }
+            ErrorCode::IncompatibleOperands => {
+                "An operator is used on values it is not defined for. Arithmetic works on ints and doubles, \
+                 and element-wise on arrays of them. or works on bools. Tables and rows cannot be operands.\n\
+                 \n\
+                 Example:\n    var int x = 1 + true;\n\
+                 \n\
+                 Fix: convert or change the operands so they have types the operator works on."
+            }
+            ErrorCode::DivisionByZero => {
+                "A number is divided by the literal 0 or 0.0, which would fail when the program runs.\n\
+                 \n\
+                 Example:\n    var int x = 10 / 0;\n\
+                 \n\
+                 Fix: divide by another number, or check the divisor with an if before dividing."
+            }
+            ErrorCode::DoubleToInt => {
+                "A double is given where an int is declared. Ints are widened to doubles by themselves, \
+                 but doubles are never narrowed to ints, as the fraction would be lost.\n\
+                 \n\
+                 Example:\n    var int x = 2.5;\n\
+                 \n\
+                 Fix: declare the variable as double, or give an int."
+            }
+            ErrorCode::ArgumentCount => {
+                "A function is called with more or fewer arguments than it has parameters. \
+                 Some builtins take optional arguments after the required ones.\n\
+                 \n\
+                 Example:\n    fn int add(int a, int b) { return a + b; };\n    var int x = add(1);\n\
+                 \n\
+                 Fix: give an argument for each parameter of the function."
+            }
+            ErrorCode::ConstantAssignment => {
+                "A constant is assigned a new value. Constants keep the value they are declared with.\n\
+                 \n\
+                 Example:\n    const int limit = 10;\n    limit = 20;\n\
+                 \n\
+                 Fix: declare it with var if it has to change, or use a new variable."
+            }
+            ErrorCode::ConditionNotBool => {
+                "The condition of an if or while, or the operand of !, is not a bool. \
+                 Numbers are not used as truth values.\n\
+                 \n\
+                 Example:\n    if (1) { print(1); }\n\
+                 \n\
+                 Fix: compare the value to get a bool, e.g. if (x == 1)."
+            }
+            ErrorCode::ReturnTypeMismatch => {
+                "A function returns a value of another type than its declared return type.\n\
+                 \n\
+                 Example:\n    fn int half(int x) { return x / 2.0; };\n\
+                 \n\
+                 Fix: return a value of the declared type, or change the return type of the function."
+            }
+            ErrorCode::BuiltinRedefined => {
+                "A function, variable, constant, parameter or loop variable is declared with the name of a builtin. \
+                 Builtins are called before the names a program declares are looked up, so a call by that name \
+                 would never reach what was declared.\n\
+                 \n\
+                 Example:\n    fn null print(int x) { return null; };\n\
+                 \n\
+                 Fix: give it another name."
+            }
+            ErrorCode::UnknownColumn => {
+                "A column is used that the table or row does not have, by indexing or by name in a builtin.\n\
+                 \n\
+                 Example:\n    var row(int id) r = row(int id = 1);\n    print(r.name);\n\
+                 \n\
+                 Fix: check the spelling of the column, or add it to the declared columns. \
+                 row_get_or reads a column that may be missing."
+            }
+            ErrorCode::DuplicateColumn => {
+                "A table or row gets two columns with the same name, in its declaration or when a column is added.\n\
+                 \n\
+                 Example:\n    var table(int id, string id) t = table(int id, string id);\n\
+                 \n\
+                 Fix: give each column its own name."
+            }
+            ErrorCode::InvalidPipeFunction => {
+                "Something is piped that cannot be, e.g. a pipe that does not start with a table, \
+                 or a stage that is not a function.\n\
+                 \n\
+                 Example:\n    var int x = 5;\n    var table(int id) t = x pipe keep();\n\
+                 \n\
+                 Fix: start the pipe with a table, and use functions declared with fn as its stages."
+            }
+            ErrorCode::PipeSchemaMismatch => {
+                "A function used as a pipe stage does not fit the rows piped into it. \
+                 A stage must be a map (row -> row), a filter (row -> bool) or a reduce (table -> table), \
+                 and the arguments after the rows must have the types of its other parameters.\n\
+                 \n\
+                 Example:\n    fn int score(row(string name) r) { return 1; };\n    \
+                 var table(string name) t = table(string name) pipe score();\n\
+                 \n\
+                 Fix: make the function return a row, a bool or a table, \
+                 or add a map stage before it that makes the values it works on."
+            }
+        }
+    }
+}
+
+// Written as the code, e.g. E014
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+// The text wrench --explain prints for a code, or the error for an unknown code with the valid codes
+pub fn explain(code: &str) -> Result<String, String> {
+    match ErrorCode::from_code(code) {
+        Some(error) => Ok(format!(
+            "{}: {}\n\n{}",
+            error,
+            error.title(),
+            error.explanation()
+        )),
+        None => {
+            let codes: Vec<&str> = ErrorCode::ALL.iter().map(|error| error.code()).collect();
+            Err(format!(
+                "Unknown error code '{}'. Valid codes are: {}",
+                code,
+                codes.join(", ")
+            ))
+        }
+    }
+}
+
+// The line written after a type error with a code, pointing to its explanation
+pub fn explain_hint(error: ErrorCode) -> String {
+    format!("run `wrench --explain {}` for more", error)
+}
+
+// The number of single character edits, or swaps of two neighbouring characters, that turn one name into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i characters of a and the first j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+// The sentence suggesting the known name closest to a name that was not found, or an empty string if none is close enough
+// to be a typo of it. A name may be off by about one edit for every three characters, and ties go to the first in name order
+pub fn did_you_mean<'a>(name: &str, known: impl IntoIterator<Item = &'a str>) -> String {
+    let allowed = name.chars().count().div_ceil(3).max(1);
+    known
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min()
+        .map(|(_, candidate)| format!(" Did you mean '{}'?", candidate))
+        .unwrap_or_default()
+}
+
+// The message for a column a row or table does not have, the same from the type checker and at runtime. The columns it does
+// have are listed as name: type in name order, e.g. Column 'nmae' not found, the columns are id: int, name: string. Did you mean 'name'?
+pub fn unknown_column_message(column_name: &str, columns: &[(String, String)]) -> String {
+    let mut columns: Vec<&(String, String)> = columns.iter().collect();
+    columns.sort();
+    let listed: Vec<String> = columns
+        .iter()
+        .map(|(name, column_type)| format!("{}: {}", name, column_type))
+        .collect();
+    let listed = if listed.is_empty() {
+        "there are no columns".to_string()
+    } else {
+        format!("the columns are {}", listed.join(", "))
+    };
+    format!(
+        "Column '{}' not found, {}.{}",
+        column_name,
+        listed,
+        did_you_mean(column_name, columns.iter().map(|(name, _)| name.as_str()))
+    )
+}
+
+// The message for a pipe written the wrong way around, with a function on the left, e.g. adults pipe data(),
+// the same from the type checker and at runtime
+pub fn pipe_left_is_function_message(function_name: &str, stage_name: &str) -> String {
+    format!(
+        "The left side of 'pipe' must be a table or import, but '{}' is a function; did you mean '{} pipe {}(...)'?",
+        function_name, stage_name, function_name
+    )
+}
+
+// The message for a pipe stage that names a table instead of a function, the same from the type checker and at runtime
+pub fn pipe_right_is_table_message(table_name: &str) -> String {
+    format!(
+        "The right side of 'pipe' must be a function, but '{}' is a table; a pipe starts with the table, e.g. '{} pipe f(...)'",
+        table_name, table_name
+    )
+}
+
+// How serious a diagnostic is. Errors stop the program, while warnings and notes are only reported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+// The step of running a program a diagnostic comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Lex,
+    Parse,
+    Type,
+    Runtime,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Lex => "lex",
+            Stage::Parse => "parse",
+            Stage::Type => "type",
+            Stage::Runtime => "runtime",
+        }
+    }
+}
+
+// An error, warning or note about a program. Both the messages written for people and the JSON written for tools,
+// see human and diagnostics_json, are made from these, so they report the same things
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub stage: Stage,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    // The file the program was read from, None for programs given with -e
+    pub file: Option<String>,
+    // Where in the source the diagnostic starts and ends, if it is known
+    pub start: Option<SourceLocation>,
+    pub end: Option<SourceLocation>,
+    // The source line with a marker under the start, written below the message for people
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, stage: Stage, message: String) -> Self {
+        Diagnostic {
+            severity,
+            stage,
+            code: None,
+            message,
+            file: None,
+            start: None,
+            end: None,
+            snippet: None,
+        }
+    }
+
+    // A type error, with its code if it is of a kind with one
+    pub fn type_error(code: Option<ErrorCode>, message: String) -> Self {
+        Diagnostic {
+            code,
+            ..Diagnostic::new(Severity::Error, Stage::Type, message)
+        }
+    }
+
+    // The diagnostic placed at the byte offsets start to end of the source
+    pub fn at(self, source_map: &SourceMap, start: usize, end: usize) -> Self {
+        Diagnostic {
+            start: Some(source_map.location(start)),
+            end: Some(source_map.location(end)),
+            ..self
+        }
+    }
+
+    pub fn with_snippet(self, snippet: String) -> Self {
+        Diagnostic {
+            snippet: Some(snippet),
+            ..self
+        }
+    }
+
+    // The diagnostic as it is written to stderr, e.g. Type checking failed [E001] at line 2, column 1: Undefined variable 'x'
+    // Other messages say where they are themselves, when it is known
+    pub fn human(&self) -> String {
+        let location = match &self.start {
+            Some(start) => format!(" at line {}, column {}", start.line, start.column),
+            None => String::new(),
+        };
+        let message = match (self.stage, self.severity, self.code) {
+            (Stage::Type, Severity::Error, Some(code)) => {
+                format!(
+                    "Type checking failed [{}]{}: {}",
+                    code, location, self.message
+                )
+            }
+            (Stage::Type, Severity::Error, None) => {
+                format!("Type checking failed{}: {}", location, self.message)
+            }
+            _ => self.message.clone(),
+        };
+        match &self.snippet {
+            Some(snippet) => format!("{}\n{}", message, snippet),
+            None => message,
+        }
+    }
+
+    fn json(&self) -> String {
+        let string_or_null = |value: Option<&str>| value.map_or("null".to_string(), json_string);
+        let number_or_null =
+            |value: Option<usize>| value.map_or("null".to_string(), |n| n.to_string());
+        format!(
+            "{{\"severity\":{},\"stage\":{},\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}}}",
+            json_string(self.severity.name()),
+            json_string(self.stage.name()),
+            string_or_null(self.code.map(ErrorCode::code)),
+            json_string(&self.message),
+            string_or_null(self.file.as_deref()),
+            number_or_null(self.start.as_ref().map(|location| location.line)),
+            number_or_null(self.start.as_ref().map(|location| location.column)),
+            number_or_null(self.end.as_ref().map(|location| location.line)),
+            number_or_null(self.end.as_ref().map(|location| location.column)),
+        )
+    }
+}
+
+// The diagnostics as one JSON array, with an object for each of them, for wrench --diagnostics=json
+// Positions that are not known are null, as are the code and file
+pub fn diagnostics_json(diagnostics: &[Diagnostic]) -> String {
+    let entries: Vec<String> = diagnostics.iter().map(Diagnostic::json).collect();
+    format!("[{}]", entries.join(","))
+}
+
+// A JSON string literal with the text, escaping quotes, backslashes and control characters
+pub(crate) fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::main::{RunOptions, check_with_warnings, create_syntax_tree};
+
+    #[test]
+    fn test_did_you_mean_suggests_close_names() {
+        assert_eq!(
+            did_you_mean("nmae", ["id", "name"]),
+            " Did you mean 'name'?"
+        );
+        assert_eq!(
+            did_you_mean("count", ["conut", "total"]),
+            " Did you mean 'conut'?"
+        );
+        assert_eq!(did_you_mean("x", ["id", "name"]), "");
+        assert_eq!(did_you_mean("price", ["id", "name"]), "");
+    }
+
+    #[test]
+    fn test_unknown_column_message_lists_columns_in_name_order() {
+        let columns = [
+            ("name".to_string(), "string".to_string()),
+            ("id".to_string(), "int".to_string()),
+        ];
+        assert_eq!(
+            unknown_column_message("nmae", &columns),
+            "Column 'nmae' not found, the columns are id: int, name: string. Did you mean 'name'?"
+        );
+        assert_eq!(
+            unknown_column_message("total", &[]),
+            "Column 'total' not found, there are no columns."
+        );
+    }
+
+    #[test]
+    fn test_every_code_has_explanation() {
+        for error in ErrorCode::ALL {
+            // A new kind of error must be added to ALL, or this match does not compile
+            match error {
+                ErrorCode::UndefinedName
+                | ErrorCode::TypeMismatch
+                | ErrorCode::IncompatibleOperands
+                | ErrorCode::DivisionByZero
+                | ErrorCode::DoubleToInt
+                | ErrorCode::ArgumentCount
+                | ErrorCode::ConstantAssignment
+                | ErrorCode::ConditionNotBool
+                | ErrorCode::ReturnTypeMismatch
+                | ErrorCode::BuiltinRedefined
+                | ErrorCode::UnknownColumn
+                | ErrorCode::DuplicateColumn
+                | ErrorCode::InvalidPipeFunction
+                | ErrorCode::PipeSchemaMismatch => {}
+            }
+            assert_eq!(ErrorCode::from_code(error.code()), Some(error));
+            let explanation = error.explanation();
+            assert!(explanation.contains("\nExample:\n"), "{}", error);
+            assert!(explanation.contains("\nFix: "), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_codes_are_unique() {
+        for (i, error) in ErrorCode::ALL.iter().enumerate() {
+            assert!(
+                ErrorCode::ALL[..i]
+                    .iter()
+                    .all(|other| other.code() != error.code())
+            );
+        }
+    }
+
+    #[test]
+    fn test_explain_prints_code_title_and_text() {
+        let text = explain("E014").unwrap();
+        assert!(text.starts_with(
+            "E014: pipe function that does not fit the table\n\nA function used as a pipe stage"
+        ));
+        assert!(text.ends_with("or add a map stage before it that makes the values it works on."));
+        // Codes can be written in lower case
+        assert_eq!(explain("e014"), Ok(text));
+    }
+
+    #[test]
+    fn test_explain_unknown_code_lists_valid_codes() {
+        assert_eq!(
+            explain("E999"),
+            Err("Unknown error code 'E999'. Valid codes are: E001, E002, E003, E004, E005, E006, E007, E008, E009, E010, E011, E012, E013, E014".to_string())
+        );
+    }
+
+    // The examples in the explanations give the errors they explain
+    #[test]
+    fn test_examples_give_their_code() {
+        for error in ErrorCode::ALL {
+            let explanation = error.explanation();
+            let example = &explanation[explanation.find("Example:\n").unwrap() + 9
+                ..explanation.find("\n\nFix: ").unwrap()];
+            let program = example
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let tree = create_syntax_tree(&program).unwrap();
+            let errors = check_with_warnings(&tree, &RunOptions::default()).expect_err(&program);
+            assert_eq!(errors[0].code, Some(error), "{}: {:?}", program, errors[0]);
+        }
+    }
+
+    #[test]
+    fn test_human_message_of_each_stage() {
+        let type_error = Diagnostic::type_error(
+            Some(ErrorCode::UndefinedName),
+            "Undefined variable 'x'".to_string(),
+        );
+        assert_eq!(type_error.code, Some(ErrorCode::UndefinedName));
+        assert_eq!(
+            type_error.human(),
+            "Type checking failed [E001]: Undefined variable 'x'"
+        );
+        assert_eq!(
+            Diagnostic::type_error(
+                None,
+                "host variable 'print' has the name of a builtin".to_string()
+            )
+            .human(),
+            "Type checking failed: host variable 'print' has the name of a builtin"
+        );
+        let source_map = SourceMap::new("var int x = 1;\nprint(y);");
+        let located = Diagnostic::type_error(
+            Some(ErrorCode::UndefinedName),
+            "Undefined variable 'y'".to_string(),
+        )
+        .at(&source_map, 15, 24)
+        .with_snippet(source_map.snippet(15));
+        assert_eq!(
+            located.human(),
+            "Type checking failed [E001] at line 2, column 1: Undefined variable 'y'\nprint(y);\n^"
+        );
+        let source_map = SourceMap::new("var int x = ?;");
+        let lex_error = Diagnostic::new(Severity::Error, Stage::Lex, "Invalid token".to_string())
+            .at(&source_map, 12, 13)
+            .with_snippet(source_map.snippet(12));
+        assert_eq!(
+            lex_error.human(),
+            "Invalid token\nvar int x = ?;\n            ^"
+        );
+    }
+
+    #[test]
+    fn test_json_escapes_and_nulls() {
+        let source_map = SourceMap::new("a\nbc");
+        let diagnostic = Diagnostic {
+            file: Some("dir\\a.wr".to_string()),
+            ..Diagnostic::new(
+                Severity::Warning,
+                Stage::Type,
+                "say \"hi\"\n\tnow\u{1}".to_string(),
+            )
+            .at(&source_map, 2, 4)
+        };
+        assert_eq!(
+            diagnostics_json(&[diagnostic]),
+            "[{\"severity\":\"warning\",\"stage\":\"type\",\"code\":null,\"message\":\"say \\\"hi\\\"\\n\\tnow\\u0001\",\
+             \"file\":\"dir\\\\a.wr\",\"line\":2,\"col\":1,\"end_line\":2,\"end_col\":3}]"
+        );
+        assert_eq!(diagnostics_json(&[]), "[]");
+    }
+}
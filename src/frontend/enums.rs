@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use super::ast::{ColumnAssignmentEnum, Declaration, Expr, Parameter, Statement, TypeConstruct};
+use super::diagnostics::did_you_mean;
+
+/*
+ * The parser gives an enum type written by name, e.g. Status, as TypeConstruct::Enum with no values.
+ * This pass fills in the values from the enum declarations, so the type checker and the interpreter see the whole type
+ */
+
+// Enums can be used after they are declared, anywhere in the program
+pub fn resolve_enums(program: Statement) -> Result<Statement, String> {
+    let mut enums = HashMap::new();
+    resolve_statement(program, &mut enums)
+}
+
+fn resolve_statement(
+    statement: Statement,
+    enums: &mut HashMap<String, Vec<String>>,
+) -> Result<Statement, String> {
+    Ok(match statement {
+        Statement::EnumDeclaration(name, values) => {
+            declare_enum(&name, &values, enums)?;
+            Statement::EnumDeclaration(name, values)
+        }
+        Statement::Expr(expr) => Statement::Expr(resolve_expr(*expr, enums)?),
+        Statement::VariableAssignment(name, expr) => {
+            Statement::VariableAssignment(name, resolve_expr(*expr, enums)?)
+        }
+        Statement::FieldAssignment(name, column, expr) => {
+            Statement::FieldAssignment(name, column, resolve_expr(*expr, enums)?)
+        }
+        Statement::IndexAssignment(name, index, expr) => Statement::IndexAssignment(
+            name,
+            resolve_expr(*index, enums)?,
+            resolve_expr(*expr, enums)?,
+        ),
+        Statement::Declaration(declaration) => Statement::Declaration(match declaration {
+            Declaration::Variable(t, name, expr) => {
+                Declaration::Variable(resolve_type(t, enums)?, name, resolve_expr(*expr, enums)?)
+            }
+            Declaration::Constant(t, name, expr) => {
+                Declaration::Constant(resolve_type(t, enums)?, name, resolve_expr(*expr, enums)?)
+            }
+            Declaration::Function(t, name, parameters, body) => Declaration::Function(
+                resolve_type(t, enums)?,
+                name,
+                resolve_parameters(parameters, enums)?,
+                Box::new(resolve_statement(*body, enums)?),
+            ),
+        }),
+        Statement::Return(expr) => Statement::Return(resolve_expr(*expr, enums)?),
+        Statement::If(condition, body, else_body) => Statement::If(
+            resolve_expr(*condition, enums)?,
+            Box::new(resolve_statement(*body, enums)?),
+            Box::new(resolve_statement(*else_body, enums)?),
+        ),
+        Statement::For(index, parameter, iterable, body) => Statement::For(
+            index
+                .map(|index| resolve_parameter(index, enums))
+                .transpose()?,
+            resolve_parameter(parameter, enums)?,
+            resolve_expr(*iterable, enums)?,
+            Box::new(resolve_statement(*body, enums)?),
+        ),
+        Statement::While(condition, body) => Statement::While(
+            resolve_expr(*condition, enums)?,
+            Box::new(resolve_statement(*body, enums)?),
+        ),
+        Statement::Defer(body) => Statement::Defer(Box::new(resolve_statement(*body, enums)?)),
+        // The chain of statements is walked in a loop, so long programs do not grow the stack
+        Statement::Compound(first, rest) => {
+            let mut statements = vec![resolve_statement(*first, enums)?];
+            let mut rest = *rest;
+            while let Statement::Compound(first, next) = rest {
+                statements.push(resolve_statement(*first, enums)?);
+                rest = *next;
+            }
+            let last = resolve_statement(rest, enums)?;
+            statements.into_iter().rev().fold(last, |rest, statement| {
+                Statement::Compound(Box::new(statement), Box::new(rest))
+            })
+        }
+        Statement::Counted(counter, statement) => {
+            Statement::Counted(counter, Box::new(resolve_statement(*statement, enums)?))
+        }
+        Statement::Located(span, statement) => {
+            Statement::Located(span, Box::new(resolve_statement(*statement, enums)?))
+        }
+        Statement::Break => Statement::Break,
+        Statement::Continue => Statement::Continue,
+        Statement::Skip => Statement::Skip,
+    })
+}
+
+// Helper function to check an enum declaration before adding it
+fn declare_enum(
+    name: &str,
+    values: &[String],
+    enums: &mut HashMap<String, Vec<String>>,
+) -> Result<(), String> {
+    if enums.contains_key(name) {
+        return Err(format!("Enum '{}' is already declared", name));
+    }
+    if values.is_empty() {
+        return Err(format!("Enum '{}' must have at least one value", name));
+    }
+    for (i, value) in values.iter().enumerate() {
+        if values[..i].contains(value) {
+            return Err(format!("Enum '{}' has the value \"{}\" twice", name, value));
+        }
+    }
+    enums.insert(name.to_string(), values.to_vec());
+    Ok(())
+}
+
+fn resolve_expr(expr: Expr, enums: &HashMap<String, Vec<String>>) -> Result<Box<Expr>, String> {
+    let resolve_all = |exprs: Vec<Box<Expr>>| -> Result<Vec<Box<Expr>>, String> {
+        exprs
+            .into_iter()
+            .map(|expr| resolve_expr(*expr, enums))
+            .collect()
+    };
+    Ok(Box::new(match expr {
+        // Left leaning chains like 1 + 1 + 1 + ... are walked in a loop, so long chains do not grow the stack
+        Expr::Operation(left, op, right) => {
+            let mut operations = vec![(op, right)];
+            let mut base = *left;
+            while let Expr::Operation(left, op, right) = base {
+                operations.push((op, right));
+                base = *left;
+            }
+            let mut resolved = resolve_expr(base, enums)?;
+            for (op, right) in operations.into_iter().rev() {
+                resolved = Box::new(Expr::Operation(resolved, op, resolve_expr(*right, enums)?));
+            }
+            return Ok(resolved);
+        }
+        Expr::Not(expr) => Expr::Not(resolve_expr(*expr, enums)?),
+        Expr::Negation(expr) => Expr::Negation(resolve_expr(*expr, enums)?),
+        Expr::Table(columns, defaults, rows) => Expr::Table(
+            resolve_parameters(columns, enums)?,
+            defaults
+                .into_iter()
+                .map(|(name, default)| Ok((name, resolve_expr(*default, enums)?)))
+                .collect::<Result<_, String>>()?,
+            resolve_all(rows)?,
+        ),
+        Expr::Row(columns) => Expr::Row(
+            columns
+                .into_iter()
+                .map(|ColumnAssignmentEnum::ColumnAssignment(t, name, value)| {
+                    Ok(ColumnAssignmentEnum::ColumnAssignment(
+                        resolve_type(t, enums)?,
+                        name,
+                        resolve_expr(*value, enums)?,
+                    ))
+                })
+                .collect::<Result<_, String>>()?,
+        ),
+        Expr::Indexing(expr, index) => {
+            Expr::Indexing(resolve_expr(*expr, enums)?, resolve_expr(*index, enums)?)
+        }
+        Expr::Array(elements) => Expr::Array(resolve_all(elements)?),
+        Expr::Pipe(left, name, args) => {
+            Expr::Pipe(resolve_expr(*left, enums)?, name, resolve_all(args)?)
+        }
+        Expr::FunctionCall(name, args) => Expr::FunctionCall(name, resolve_all(args)?),
+        Expr::Range(start, end, inclusive) => Expr::Range(
+            resolve_expr(*start, enums)?,
+            resolve_expr(*end, enums)?,
+            inclusive,
+        ),
+        Expr::ColumnIndexing(expr, column) => {
+            Expr::ColumnIndexing(resolve_expr(*expr, enums)?, column)
+        }
+        // An enum declared in the body of a lambda can only be used in it
+        Expr::Lambda(parameters, return_type, body) => Expr::Lambda(
+            resolve_parameters(parameters, enums)?,
+            resolve_type(return_type, enums)?,
+            Box::new(resolve_statement(*body, &mut enums.clone())?),
+        ),
+        literal @ (Expr::Number(_)
+        | Expr::Double(_)
+        | Expr::Null
+        | Expr::StringLiteral(_)
+        | Expr::Char(_)
+        | Expr::Identifier(_)
+        | Expr::Bool(_)) => literal,
+    }))
+}
+
+fn resolve_parameters(
+    parameters: Vec<Parameter>,
+    enums: &HashMap<String, Vec<String>>,
+) -> Result<Vec<Parameter>, String> {
+    parameters
+        .into_iter()
+        .map(|parameter| resolve_parameter(parameter, enums))
+        .collect()
+}
+
+fn resolve_parameter(
+    Parameter::Parameter(t, name): Parameter,
+    enums: &HashMap<String, Vec<String>>,
+) -> Result<Parameter, String> {
+    Ok(Parameter::Parameter(resolve_type(t, enums)?, name))
+}
+
+fn resolve_type(
+    t: TypeConstruct,
+    enums: &HashMap<String, Vec<String>>,
+) -> Result<TypeConstruct, String> {
+    Ok(match t {
+        TypeConstruct::Enum(name, _) => match enums.get(&name) {
+            Some(values) => TypeConstruct::Enum(name, values.clone()),
+            None => {
+                return Err(format!(
+                    "Unknown type '{}'.{}",
+                    name,
+                    did_you_mean(&name, enums.keys().map(String::as_str))
+                ));
+            }
+        },
+        TypeConstruct::Array(element) => {
+            TypeConstruct::Array(Box::new(resolve_type(*element, enums)?))
+        }
+        TypeConstruct::Function(return_type, parameters) => TypeConstruct::Function(
+            Box::new(resolve_type(*return_type, enums)?),
+            parameters
+                .into_iter()
+                .map(|t| resolve_type(t, enums))
+                .collect::<Result<_, String>>()?,
+        ),
+        TypeConstruct::Table(columns) => TypeConstruct::Table(resolve_parameters(columns, enums)?),
+        TypeConstruct::Row(columns) => TypeConstruct::Row(resolve_parameters(columns, enums)?),
+        TypeConstruct::Nullable(inner) => {
+            TypeConstruct::Nullable(Box::new(resolve_type(*inner, enums)?))
+        }
+        t @ (TypeConstruct::Bool
+        | TypeConstruct::Int
+        | TypeConstruct::Double
+        | TypeConstruct::String
+        | TypeConstruct::Char
+        | TypeConstruct::Null
+        | TypeConstruct::Any) => t,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::main::parse_program;
+
+    // Enums are resolved when a program is parsed
+    fn resolve(program: &str) -> Result<Statement, String> {
+        match parse_program(program, false) {
+            (Some(statement), _) => Ok(statement),
+            (None, diagnostics) => Err(diagnostics[0].message.clone()),
+        }
+    }
+
+    #[test]
+    fn test_enum_types_get_their_values() {
+        let program = resolve(
+            "enum Status = [\"active\", \"inactive\"];
+            var table(int id, Status status) t = table(int id, Status status);",
+        )
+        .unwrap();
+        let Statement::Compound(_, rest) = program else {
+            panic!("Expected two statements");
+        };
+        let Statement::Compound(declaration, _) = *rest else {
+            panic!("Expected two statements");
+        };
+        let status = TypeConstruct::Enum(
+            "Status".to_string(),
+            vec!["active".to_string(), "inactive".to_string()],
+        );
+        assert!(matches!(
+            declaration.unlocated(),
+            Statement::Declaration(Declaration::Variable(TypeConstruct::Table(columns), _, _))
+                if columns[1] == Parameter::Parameter(status, "status".to_string())
+        ));
+    }
+
+    #[test]
+    fn test_enum_declaration_errors() {
+        assert_eq!(
+            resolve("enum Status = [\"a\"]; var Statuss s = \"a\";").unwrap_err(),
+            "Unknown type 'Statuss'. Did you mean 'Status'?"
+        );
+        assert_eq!(
+            resolve("var Status s = \"a\"; enum Status = [\"a\"];").unwrap_err(),
+            "Unknown type 'Status'."
+        );
+        assert_eq!(
+            resolve("enum Status = [\"a\"]; enum Status = [\"b\"];").unwrap_err(),
+            "Enum 'Status' is already declared"
+        );
+        assert_eq!(
+            resolve("enum Status = [];").unwrap_err(),
+            "Enum 'Status' must have at least one value"
+        );
+        assert_eq!(
+            resolve("enum Status = [\"a\", \"b\", \"a\"];").unwrap_err(),
+            "Enum 'Status' has the value \"a\" twice"
+        );
+    }
+}
@@ -1,7 +1,12 @@
 //Define enum
+use std::ops::Range;
+
 use logos::Logos;
 
+use super::limits::{over_limit_message, source_limits};
+
 #[derive(Logos, Debug, PartialEq, Clone)]
+#[logos(error = LexErrorKind)]
 pub enum Token {
     //ignore whitespace
     #[regex(r"[ \t\r\n\f]+", logos::skip)]
@@ -21,12 +26,21 @@ pub enum Token {
     #[token("==")]
     EqualsOperator,
 
+    #[token("!=")]
+    NotEqualsOperator,
+
     #[token("=")]
     AssignmentOperator,
 
     #[token("+")]
     Plus,
 
+    #[token("++")]
+    Increment,
+
+    #[token("--")]
+    Decrement,
+
     #[token("%")]
     Modulo,
 
@@ -44,8 +58,9 @@ pub enum Token {
 
     //Constants
     #[regex("[0-9]+", priority = 2, callback = parse_integer)] //Priority above identifiers
-    Integer(i32),
+    Integer(i64),
 
+    //Digits are required after the dot, so 3.name lexes as 3 . name like any other column access
     #[regex(r"[0-9]+\.[0-9]+", priority = 2, callback = parse_double)]
     Doubleliteral(f64),
 
@@ -62,6 +77,9 @@ pub enum Token {
     #[token("string")]
     String,
 
+    #[token("char")]
+    CharKeyword,
+
     #[token("table")]
     Table,
 
@@ -101,6 +119,18 @@ pub enum Token {
     #[token("while")]
     While,
 
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
+    #[token("defer")]
+    Defer,
+
+    #[token("enum")]
+    Enum,
+
     #[token("for")]
     For,
 
@@ -151,26 +181,339 @@ pub enum Token {
     #[token(".")]
     Dot,
 
+    //Ranges of ints, e.g. 0..10 and 0..=10
+    #[token("..")]
+    DotDot,
+
+    #[token("..=")]
+    DotDotEquals,
+
+    #[token("?")]
+    QuestionMark,
+
     // Identifiers variables, or function names
-    #[regex("[a-zA-Z_][a-zA-Z_]*", |lex| lex.slice().to_string())]
+    #[regex("[a-zA-Z_][a-zA-Z_]*", callback = parse_identifier)]
     Identifier(String),
 
     //Literals
-    #[regex(r#""([^"\\]|\\.)*""#, callback = parse_string)] //Things like "Hello"
+    //Things like "Hello". The literal after the opening quote is read by parse_string, as a regex over it would grow
+    //the stack with the length of the literal
+    #[token("\"", callback = parse_string)]
     Stringliteral(String),
+
+    #[regex(r"'([^'\\]|\\.)'", callback = parse_char)] //Things like 'a' and '\n'
+    Charliteral(char),
 }
 
-fn parse_integer(lex: &mut logos::Lexer<Token>) -> i32 {
-    lex.slice().parse().unwrap()
+// Every reserved word of the language with the token it lexes to. This is the list the parser and editor tooling use,
+// so a keyword added to Token must be added here as well, and to Keyword in the grammar. Tests check that they agree
+const KEYWORDS: [(&str, Token); 26] = [
+    ("and", Token::LogicalAnd),
+    ("bool", Token::Boolean),
+    ("break", Token::Break),
+    ("char", Token::CharKeyword),
+    ("const", Token::Constant),
+    ("continue", Token::Continue),
+    ("defer", Token::Defer),
+    ("double", Token::DoubleKeyword),
+    ("else", Token::Else),
+    ("enum", Token::Enum),
+    ("false", Token::False),
+    ("fn", Token::Function),
+    ("for", Token::For),
+    ("if", Token::If),
+    ("in", Token::In),
+    ("int", Token::IntegerKeyword),
+    ("null", Token::Null),
+    ("or", Token::LogicalOr),
+    ("pipe", Token::Pipe),
+    ("return", Token::Return),
+    ("row", Token::Row),
+    ("string", Token::String),
+    ("table", Token::Table),
+    ("true", Token::True),
+    ("var", Token::Var),
+    ("while", Token::While),
+];
+
+impl Token {
+    // All reserved words, in alphabetical order
+    pub fn keyword_list() -> Vec<&'static str> {
+        KEYWORDS.iter().map(|(word, _)| *word).collect()
+    }
+
+    // The reserved word the token was lexed from, if it is a keyword
+    pub fn keyword(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, token)| token == self)
+            .map(|(word, _)| *word)
+    }
 }
 
-fn parse_double(lex: &mut logos::Lexer<Token>) -> f64 {
-    lex.slice().parse().unwrap()
+// The error given when a reserved word is used where a name is expected, e.g. var int table = 5;
+pub fn reserved_word_error(token: &Token, name_kind: &str) -> String {
+    format!(
+        "'{}' is a reserved word and cannot be used as a {} name",
+        token.keyword().unwrap_or("?"),
+        name_kind
+    )
 }
 
-fn parse_string(lex: &mut logos::Lexer<Token>) -> String {
+// The regex only matches digits, so the only way parsing can fail is a literal too large for an int
+fn parse_integer(lex: &mut logos::Lexer<Token>) -> Result<i64, LexErrorKind> {
+    lex.slice()
+        .parse()
+        .map_err(|_| LexErrorKind::IntegerOutOfRange)
+}
+
+// A literal with too many digits parses as infinity, which is no double a program can use
+fn parse_double(lex: &mut logos::Lexer<Token>) -> Result<f64, LexErrorKind> {
+    let value: f64 = lex.slice().parse().unwrap();
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(LexErrorKind::DoubleOutOfRange)
+    }
+}
+
+fn parse_identifier(lex: &mut logos::Lexer<Token>) -> Result<String, LexErrorKind> {
+    let name = lex.slice();
+    let limit = source_limits().max_identifier_length;
+    if name.len() > limit {
+        return Err(LexErrorKind::IdentifierTooLong {
+            length: name.len(),
+            limit,
+        });
+    }
+    Ok(name.to_string())
+}
+
+// Reads the literal up to the closing quote, where a backslash escapes the character after it
+// A literal with no closing quote leaves the opening quote as an invalid token
+fn parse_string(lex: &mut logos::Lexer<Token>) -> Result<String, LexErrorKind> {
+    let mut escaped = false;
+    let end = lex.remainder().char_indices().find_map(|(i, c)| {
+        match (escaped, c) {
+            (true, _) => escaped = false,
+            (false, '\\') => escaped = true,
+            (false, '"') => return Some(i),
+            (false, _) => {}
+        }
+        None
+    });
+    let Some(end) = end else {
+        return Err(LexErrorKind::Unrecognized);
+    };
+    let content = &lex.remainder()[..end];
+    lex.bump(end + 1);
+    let limit = source_limits().max_string_length;
+    if content.len() > limit {
+        return Err(LexErrorKind::StringTooLong {
+            length: content.len(),
+            limit,
+        });
+    }
+    Ok(content.to_string())
+}
+
+// The character between the quotes, where \n, \t, \r, \0, \\ and \' are escapes. Any other escape is an invalid token
+fn parse_char(lex: &mut logos::Lexer<Token>) -> Option<char> {
     let content = lex.slice();
-    content[1..content.len() - 1].to_string() // Strip the quotes
+    let mut chars = content[1..content.len() - 1].chars();
+    match (chars.next()?, chars.next()) {
+        ('\\', Some(escaped)) => match escaped {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '\'' => Some('\''),
+            _ => None,
+        },
+        (c, None) => Some(c),
+        _ => None,
+    }
+}
+
+// Why a piece of the input is not a token
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum LexErrorKind {
+    // No token matches the text
+    #[default]
+    Unrecognized,
+    IntegerOutOfRange,
+    DoubleOutOfRange,
+    // Longer than the limits allow, see SourceLimits
+    StringTooLong {
+        length: usize,
+        limit: usize,
+    },
+    IdentifierTooLong {
+        length: usize,
+        limit: usize,
+    },
+}
+
+impl LexErrorKind {
+    // What is wrong with the token, for the ones where "invalid token" alone does not say
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            LexErrorKind::Unrecognized => None,
+            LexErrorKind::IntegerOutOfRange => {
+                Some("integer literal out of range for int".to_string())
+            }
+            LexErrorKind::DoubleOutOfRange => {
+                Some("double literal out of range for double".to_string())
+            }
+            LexErrorKind::StringTooLong { length, limit } => Some(over_limit_message(
+                &format!("string literal is {} bytes long", length),
+                *limit,
+                "--max-string-length",
+            )),
+            LexErrorKind::IdentifierTooLong { length, limit } => Some(over_limit_message(
+                &format!("identifier is {} characters long", length),
+                *limit,
+                "--max-identifier-length",
+            )),
+        }
+    }
+}
+
+// Text the lexer found no token in, with where it is in the input
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub span: Range<usize>,
+    pub slice: String,
+    pub kind: LexErrorKind,
+}
+
+/*
+ * The full token stream, for tooling like the formatter that needs the comments and line breaks the parser does not
+ */
+
+// A token of the full token stream. Spaces and tabs are still left out, as they can be found from the spans
+#[derive(Debug, PartialEq, Clone)]
+pub enum FullToken {
+    Token(Token),
+    // The whole comment, including the //
+    Comment(String),
+    // A line break, \n or \r\n
+    Newline,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: FullToken,
+    pub span: Range<usize>,
+}
+
+// Lexes the input like the lexer used by the parser, but keeps comments and line breaks
+// Invalid tokens are left out, as they are reported when the input is lexed for the parser
+pub fn lex_full(input: &str) -> Vec<SpannedToken> {
+    let mut tokens = Vec::new();
+    let mut end_of_last = 0;
+    for (token, span) in Token::lexer(input).spanned() {
+        // Only whitespace, comments and invalid tokens are skipped, so the gap has the trivia before this token
+        lex_trivia(input, end_of_last..span.start, &mut tokens);
+        end_of_last = span.end;
+        if let Ok(token) = token {
+            tokens.push(SpannedToken {
+                token: FullToken::Token(token),
+                span,
+            });
+        }
+    }
+    lex_trivia(input, end_of_last..input.len(), &mut tokens);
+    tokens
+}
+
+// Finds the comments and line breaks in a part of the input with no other tokens
+fn lex_trivia(input: &str, gap: Range<usize>, tokens: &mut Vec<SpannedToken>) {
+    let text = &input[gap.clone()];
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let start = gap.start + i;
+        if rest.starts_with("//") {
+            let comment = rest[..rest.find('\n').unwrap_or(rest.len())].trim_end_matches('\r');
+            tokens.push(SpannedToken {
+                token: FullToken::Comment(comment.to_string()),
+                span: start..start + comment.len(),
+            });
+            i += comment.len();
+        } else if rest.starts_with("\r\n") || rest.starts_with('\n') {
+            let length = if rest.starts_with('\n') { 1 } else { 2 };
+            tokens.push(SpannedToken {
+                token: FullToken::Newline,
+                span: start..start + length,
+            });
+            i += length;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+}
+
+// The tokens the parser is given, without comments and line breaks, in the form the parser takes them
+pub fn strip_trivia(tokens: &[SpannedToken]) -> Vec<(usize, Token, usize)> {
+    tokens
+        .iter()
+        .filter_map(|spanned| match &spanned.token {
+            FullToken::Token(token) => Some((spanned.span.start, token.clone(), spanned.span.end)),
+            _ => None,
+        })
+        .collect()
+}
+
+// A comment and the token it belongs to, given as an index into the tokens returned by strip_trivia
+// A comment after a token on the same line trails that token, any other comment leads the next token
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttachedComment {
+    pub text: String,
+    pub span: Range<usize>,
+    // None for a comment after the last token, on a line of its own
+    pub owner: Option<usize>,
+    pub trailing: bool,
+}
+
+pub fn attach_comments(tokens: &[SpannedToken]) -> Vec<AttachedComment> {
+    let mut attached = Vec::new();
+    // Comments waiting for the next token to lead
+    let mut leading: Vec<AttachedComment> = Vec::new();
+    let mut significant = 0;
+    let mut on_line_of_last_token = false;
+    for spanned in tokens {
+        match &spanned.token {
+            FullToken::Token(_) => {
+                for mut comment in leading.drain(..) {
+                    comment.owner = Some(significant);
+                    attached.push(comment);
+                }
+                significant += 1;
+                on_line_of_last_token = true;
+            }
+            FullToken::Newline => on_line_of_last_token = false,
+            FullToken::Comment(text) => {
+                let comment = AttachedComment {
+                    text: text.clone(),
+                    span: spanned.span.clone(),
+                    owner: None,
+                    trailing: on_line_of_last_token,
+                };
+                if on_line_of_last_token {
+                    attached.push(AttachedComment {
+                        owner: Some(significant - 1),
+                        ..comment
+                    });
+                } else {
+                    leading.push(comment);
+                }
+            }
+        }
+    }
+    attached.extend(leading);
+    attached
 }
 
 //Unit tests for lexer - HAPPY PATH
@@ -182,16 +525,28 @@ mod tests {
     //Careful! We return Result<Token
     #[test]
     fn test_for_integers_and_doubles() {
-        let mut lexer = Token::lexer("5000 3.1415926535");
+        let mut lexer = Token::lexer("5000 1.2345678901");
 
         assert_eq!(lexer.next(), Some(Ok(Token::Integer(5000))));
-        assert_eq!(lexer.next(), Some(Ok(Token::Doubleliteral(3.1415926535))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Doubleliteral(1.2345678901))));
+    }
+
+    #[test]
+    fn test_for_literals_out_of_range() {
+        let huge_double = format!("{}.0", "9".repeat(400));
+        let input = format!("9223372036854775807 9223372036854775808 {}", huge_double);
+        let mut lexer = Token::lexer(&input);
+
+        assert_eq!(lexer.next(), Some(Ok(Token::Integer(i64::MAX))));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::IntegerOutOfRange)));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::DoubleOutOfRange)));
+        assert_eq!(lexer.next(), None);
     }
 
     #[test]
     fn test_for_operators() {
         //We return Token
-        let mut lexer = Token::lexer("** * / + - == = % and or");
+        let mut lexer = Token::lexer("** * / + - == = % and or != ! ++ --");
 
         assert_eq!(lexer.next(), Some(Ok(Token::Expon)));
         assert_eq!(lexer.next(), Some(Ok(Token::Star)));
@@ -203,6 +558,10 @@ mod tests {
         assert_eq!(lexer.next(), Some(Ok(Token::Modulo)));
         assert_eq!(lexer.next(), Some(Ok(Token::LogicalAnd)));
         assert_eq!(lexer.next(), Some(Ok(Token::LogicalOr)));
+        assert_eq!(lexer.next(), Some(Ok(Token::NotEqualsOperator)));
+        assert_eq!(lexer.next(), Some(Ok(Token::ExclamationMark)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Increment)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Decrement)));
     }
 
     #[test]
@@ -214,7 +573,8 @@ mod tests {
     #[test]
     fn test_for_keywords() {
         let mut lexer = Token::lexer(
-            "bool int double string table row pipe fn return var const null true false if else while for",
+            "bool int double string table row pipe fn return var const null true false if else while for \
+             and or char in break continue defer enum",
         );
 
         assert_eq!(lexer.next(), Some(Ok(Token::Boolean)));
@@ -235,6 +595,15 @@ mod tests {
         assert_eq!(lexer.next(), Some(Ok(Token::Else)));
         assert_eq!(lexer.next(), Some(Ok(Token::While)));
         assert_eq!(lexer.next(), Some(Ok(Token::For)));
+        assert_eq!(lexer.next(), Some(Ok(Token::LogicalAnd)));
+        assert_eq!(lexer.next(), Some(Ok(Token::LogicalOr)));
+        assert_eq!(lexer.next(), Some(Ok(Token::CharKeyword)));
+        assert_eq!(lexer.next(), Some(Ok(Token::In)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Break)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Continue)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Defer)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Enum)));
+        assert_eq!(lexer.next(), None);
     }
 
     #[test]
@@ -258,6 +627,18 @@ mod tests {
         assert_eq!(lexer.next(), None);
     }
 
+    #[test]
+    fn test_for_char_literals() {
+        let mut lexer = Token::lexer(r"char 'a' '\n' '\'' '\\' '\q' 'ab'");
+        assert_eq!(lexer.next(), Some(Ok(Token::CharKeyword)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Charliteral('a'))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Charliteral('\n'))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Charliteral('\''))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Charliteral('\\'))));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::Unrecognized)));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::Unrecognized)));
+    }
+
     #[test]
     fn test_for_string_literals() {
         let mut lexer =
@@ -296,18 +677,17 @@ mod tests {
     #[test]
     fn invalid_input() {
         let mut lexer = Token::lexer("@ £ §");
-        assert_eq!(lexer.next(), Some(Err(())));
-        assert_eq!(lexer.next(), Some(Err(())));
-        assert_eq!(lexer.next(), Some(Err(())));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::Unrecognized)));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::Unrecognized)));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::Unrecognized)));
     }
 
     //Tests for edge cases
 
     #[test]
-    #[should_panic]
-    fn overflow_for_i32() {
+    fn overflow_for_i64() {
         let mut lexer = Token::lexer("8888888888888888888888999999999999999999999999999999999");
-        lexer.next();
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::IntegerOutOfRange)));
     }
 
     #[test]
@@ -330,7 +710,7 @@ mod tests {
     #[test]
     fn invalid_identifier() {
         let mut lexer = Token::lexer("£myvar = 3");
-        assert_eq!(lexer.next(), Some(Err(())));
+        assert_eq!(lexer.next(), Some(Err(LexErrorKind::Unrecognized)));
         assert_eq!(
             lexer.next(),
             Some(Ok(Token::Identifier("myvar".to_string())))
@@ -338,4 +718,170 @@ mod tests {
         assert_eq!(lexer.next(), Some(Ok(Token::AssignmentOperator)));
         assert_eq!(lexer.next(), Some(Ok(Token::Integer(3))));
     }
+
+    #[test]
+    fn keyword_list_matches_lexer() {
+        for word in Token::keyword_list() {
+            let mut lexer = Token::lexer(word);
+            let token = lexer.next().unwrap().unwrap();
+            assert_eq!(token.keyword(), Some(word));
+        }
+        assert_eq!(Token::Identifier("tables".to_string()).keyword(), None);
+    }
+
+    // Lexes the input, failing the test on an invalid token
+    fn lex_all(input: &str) -> Vec<Token> {
+        Token::lexer(input).map(|token| token.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(
+            lex_all("0..10"),
+            vec![Token::Integer(0), Token::DotDot, Token::Integer(10)]
+        );
+        assert_eq!(
+            lex_all("1..=n"),
+            vec![
+                Token::Integer(1),
+                Token::DotDotEquals,
+                Token::Identifier("n".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_followed_by_dot_and_name() {
+        // A dot only belongs to a number when a digit follows it
+        assert_eq!(
+            lex_all("3.name"),
+            vec![
+                Token::Integer(3),
+                Token::Dot,
+                Token::Identifier("name".to_string())
+            ]
+        );
+        assert_eq!(
+            lex_all("1.0.name"),
+            vec![
+                Token::Doubleliteral(1.0),
+                Token::Dot,
+                Token::Identifier("name".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dot_after_closing_brackets() {
+        let name = || Token::Identifier("name".to_string());
+        assert_eq!(
+            lex_all("x[0].name"),
+            vec![
+                Token::Identifier("x".to_string()),
+                Token::Opensquarebracket,
+                Token::Integer(0),
+                Token::Closesquarebracket,
+                Token::Dot,
+                name()
+            ]
+        );
+        assert_eq!(
+            lex_all("f(x).name"),
+            vec![
+                Token::Identifier("f".to_string()),
+                Token::Openparan,
+                Token::Identifier("x".to_string()),
+                Token::Closeparan,
+                Token::Dot,
+                name()
+            ]
+        );
+        assert_eq!(
+            lex_all("rows[10].name"),
+            vec![
+                Token::Identifier("rows".to_string()),
+                Token::Opensquarebracket,
+                Token::Integer(10),
+                Token::Closesquarebracket,
+                Token::Dot,
+                name()
+            ]
+        );
+    }
+
+    // A program with comments on their own lines, after code, and at the end
+    const COMMENTED: &str =
+        "// leading\r\nvar int x = 1; // trailing\n\n// before f\nf(x);\n// last";
+
+    #[test]
+    fn test_full_token_stream_keeps_comments_and_line_breaks() {
+        let tokens: Vec<FullToken> = lex_full(COMMENTED).into_iter().map(|t| t.token).collect();
+        let token = FullToken::Token;
+        let comment = |text: &str| FullToken::Comment(text.to_string());
+        assert_eq!(
+            tokens,
+            vec![
+                comment("// leading"),
+                FullToken::Newline,
+                token(Token::Var),
+                token(Token::IntegerKeyword),
+                token(Token::Identifier("x".to_string())),
+                token(Token::AssignmentOperator),
+                token(Token::Integer(1)),
+                token(Token::Semicolon),
+                comment("// trailing"),
+                FullToken::Newline,
+                FullToken::Newline,
+                comment("// before f"),
+                FullToken::Newline,
+                token(Token::Identifier("f".to_string())),
+                token(Token::Openparan),
+                token(Token::Identifier("x".to_string())),
+                token(Token::Closeparan),
+                token(Token::Semicolon),
+                FullToken::Newline,
+                comment("// last"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_full_token_spans() {
+        let tokens = lex_full(COMMENTED);
+        for spanned in &tokens {
+            let text = &COMMENTED[spanned.span.clone()];
+            match &spanned.token {
+                FullToken::Comment(comment) => assert_eq!(text, comment),
+                FullToken::Newline => assert!(text == "\n" || text == "\r\n", "{:?}", text),
+                FullToken::Token(_) => assert!(!text.trim().is_empty()),
+            }
+        }
+        assert_eq!(&COMMENTED[tokens[1].span.clone()], "\r\n");
+    }
+
+    #[test]
+    fn test_comments_attached_to_their_owners() {
+        let tokens = lex_full(COMMENTED);
+        let stripped = strip_trivia(&tokens);
+        let owners: Vec<(String, Option<Token>, bool)> = attach_comments(&tokens)
+            .into_iter()
+            .map(|c| (c.text, c.owner.map(|i| stripped[i].1.clone()), c.trailing))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![
+                ("// leading".to_string(), Some(Token::Var), false),
+                ("// trailing".to_string(), Some(Token::Semicolon), true),
+                (
+                    "// before f".to_string(),
+                    Some(Token::Identifier("f".to_string())),
+                    false
+                ),
+                ("// last".to_string(), None, false),
+            ]
+        );
+        // The trailing comment belongs to the first semicolon, not the second
+        let owner = attach_comments(&tokens)[1].owner.unwrap();
+        assert_eq!(stripped[owner].0, COMMENTED.find(';').unwrap());
+    }
 }
@@ -0,0 +1,153 @@
+use std::cell::Cell;
+
+/*
+ * Limits on the size of what a program declares, so pathological input, e.g. a string literal of a hundred megabytes,
+ * is stopped early with a message saying what is too large, rather than slowing down or running out of memory later
+ */
+
+// How large the pieces of a program may be. Each limit can be raised from the command line for programs that need it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceLimits {
+    // Bytes between the quotes of a string literal
+    pub max_string_length: usize,
+    // Characters in a name
+    pub max_identifier_length: usize,
+    // Columns of a table or row declared in the program
+    pub max_columns: usize,
+    // Parameters of a function
+    pub max_parameters: usize,
+}
+
+impl Default for SourceLimits {
+    fn default() -> Self {
+        SourceLimits {
+            max_string_length: 1024 * 1024,
+            max_identifier_length: 255,
+            max_columns: 1000,
+            max_parameters: 255,
+        }
+    }
+}
+
+thread_local! {
+    // The limits for programs parsed and type checked on this thread, see set_source_limits
+    static SOURCE_LIMITS: Cell<SourceLimits> = Cell::new(SourceLimits::default());
+}
+
+// Sets the limits for programs parsed and type checked on this thread after this call
+pub fn set_source_limits(limits: SourceLimits) {
+    SOURCE_LIMITS.with(|current| current.set(limits));
+}
+
+pub fn source_limits() -> SourceLimits {
+    SOURCE_LIMITS.with(Cell::get)
+}
+
+// The message for something larger than its limit, where size says how large it is, e.g.
+// string literal is 2000000 bytes long, more than the limit of 1048576. Use --max-string-length to raise it
+pub fn over_limit_message(size: &str, limit: usize, flag: &str) -> String {
+    format!(
+        "{}, more than the limit of {}. Use {} to raise it",
+        size, limit, flag
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::main::{check, parse_program};
+
+    // The message of the first diagnostic for the program, or None if it parses and type checks
+    fn error_for(program: &str) -> Option<String> {
+        match parse_program(program, false) {
+            (None, diagnostics) => Some(diagnostics[0].message.clone()),
+            (Some(_), _) => check(program).err(),
+        }
+    }
+
+    // A name for each number, e.g. c_bc for 28, as names cannot hold digits
+    fn name(prefix: &str, i: usize) -> String {
+        let letters: String = i
+            .to_string()
+            .chars()
+            .map(|digit| (b'a' + digit.to_digit(10).unwrap() as u8) as char)
+            .collect();
+        format!("{}_{}", prefix, letters)
+    }
+
+    // A declaration of a table with the number of int columns
+    fn table_of(columns: usize) -> String {
+        let columns: Vec<String> = (0..columns)
+            .map(|i| format!("int {}", name("c", i)))
+            .collect();
+        format!("table({});", columns.join(", "))
+    }
+
+    #[test]
+    fn test_string_literal_length_limit() {
+        let limit = SourceLimits::default().max_string_length;
+        let literal = |length: usize| format!("var string s = \"{}\";", "a".repeat(length));
+        assert_eq!(error_for(&literal(limit)), None);
+        assert_eq!(
+            error_for(&literal(limit + 1)),
+            Some(format!(
+                "Invalid token '\"{}...' at line 1, column 16: string literal is 1048577 bytes long, more than the limit of 1048576. Use --max-string-length to raise it",
+                "a".repeat(39)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_identifier_length_limit() {
+        let declaration = |length: usize| format!("var int {} = 1;", "x".repeat(length));
+        assert_eq!(error_for(&declaration(255)), None);
+        let error = error_for(&declaration(256)).unwrap();
+        assert!(
+            error.ends_with(": identifier is 256 characters long, more than the limit of 255. Use --max-identifier-length to raise it"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_column_limit() {
+        assert_eq!(error_for(&table_of(1000)), None);
+        assert_eq!(
+            error_for(&table_of(1001)),
+            Some("table declares 1001 columns, more than the limit of 1000. Use --max-columns to raise it".to_string())
+        );
+        let row: Vec<String> = (0..1001)
+            .map(|i| format!("int {} = {}", name("c", i), i))
+            .collect();
+        assert_eq!(
+            error_for(&format!("row({});", row.join(", "))),
+            Some("row declares 1001 columns, more than the limit of 1000. Use --max-columns to raise it".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parameter_limit() {
+        let function = |parameters: usize| {
+            let parameters: Vec<String> = (0..parameters)
+                .map(|i| format!("int {}", name("p", i)))
+                .collect();
+            format!("fn int f({}) {{ return 1; }};", parameters.join(", "))
+        };
+        assert_eq!(error_for(&function(255)), None);
+        assert_eq!(
+            error_for(&function(256)),
+            Some("function 'f' declares 256 parameters, more than the limit of 255. Use --max-parameters to raise it".to_string())
+        );
+    }
+
+    #[test]
+    fn test_limits_can_be_raised() {
+        set_source_limits(SourceLimits {
+            max_columns: 2000,
+            ..SourceLimits::default()
+        });
+        let error = error_for(&table_of(1001));
+        set_source_limits(SourceLimits::default());
+        assert_eq!(error, None);
+    }
+}
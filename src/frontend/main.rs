@@ -1,161 +1,832 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal},
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    sync::Arc,
+};
 
-use crate::backend::evaluate::interpret;
+use crate::backend::{
+    coverage::instrument,
+    evaluate::{
+        DEFAULT_MAX_PRINTED_VALUES, DEFAULT_MAX_VALUES, ExpressionValue, current_span, interpret,
+        set_max_printed_values, set_max_values,
+    },
+    library::{
+        Builtin, Builtins, LogLevel, current_builtins, dry_run_summary, error_logged, set_builtins,
+        set_dry_run, start_log,
+    },
+    pipes::{DEFAULT_PIPE_MEMORY_LIMIT, panic_message, set_pipe_memory_limit},
+    table::Table,
+};
 
 use super::{
-    ast::{Statement, TypeConstruct},
-    typecheck::{VariableInfo, type_check},
+    ast::{Declaration, Expr, GrammarError, Parameter, Statement},
+    typecheck::{
+        TypeError, VariableInfo, check_not_builtin, take_warnings, type_check_all,
+        type_check_echoing,
+    },
 };
 use lalrpop_util::{ParseError, lalrpop_mod};
 use logos::Logos;
 
-use super::lexer::Token;
+use super::{
+    diagnostics::{Diagnostic, Severity, Stage, diagnostics_json, explain_hint},
+    enums::resolve_enums,
+    lexer::{LexError, Token},
+    limits::{SourceLimits, set_source_limits},
+    source::SourceMap,
+};
 
 lalrpop_mod!(#[allow(clippy::all)] pub grammar);
 
-fn lex(input: &str) -> Vec<(usize, Token, usize)> {
-    let lexer = Token::lexer(input);
-    let tokens: Vec<_> = lexer
-        .spanned()
-        .filter_map(|(token, span)| match token {
-            Ok(t) => Some((span.start, t, span.end)),
-            Err(_) => {
-                eprintln!("Invalid token at {:?}", span);
-                None
-            }
-        })
-        .collect();
-    tokens
+//How many characters of an invalid token its diagnostic shows
+const TOKEN_SHOWN_LENGTH: usize = 40;
+
+//Lex tokens from input, or give every invalid token found in it
+//Lenient lexing leaves the invalid tokens out instead, for input that is still being written
+fn lex(input: &str, lenient: bool) -> Result<Vec<(usize, Token, usize)>, Vec<LexError>> {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for (token, span) in Token::lexer(input).spanned() {
+        match token {
+            Ok(t) => tokens.push((span.start, t, span.end)),
+            Err(kind) => errors.push(LexError {
+                slice: input[span.clone()].to_string(),
+                span,
+                kind,
+            }),
+        }
+    }
+    if errors.is_empty() || lenient {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
 }
 
-fn parse(tokens: Vec<(usize, Token, usize)>) -> Statement {
-    let parser = grammar::ProgramParser::new();
-    match parser.parse(tokens) {
-        Ok(program) => program,
-        Err(e) => match e {
-            ParseError::InvalidToken { location } => {
-                panic!("Invalid token at position {}", location);
-            }
-            ParseError::UnrecognizedToken { token, expected } => {
-                let (start, token, end) = token;
-                panic!(
-                    "Unrecognized token {:?} at position {}-{}. Expected one of: {:?}",
-                    token, start, end, expected
-                );
-            }
-            ParseError::ExtraToken { token } => {
-                let (start, token, end) = token;
-                panic!("Extra token {:?} at position {}-{}", token, start, end);
-            }
-            ParseError::User { error } => {
-                panic!("Custom error: {}", error);
-            }
-            ParseError::UnrecognizedEof { location, expected } => {
-                if expected.contains(&"\";\"".to_string()) {
-                    panic!("Parse error : Missing semicolon at the end of the declaration!")
-                } else {
-                    panic!(
-                        "Unrecognized EOF at position {}. Expected one of: {:?}",
-                        location, expected
-                    );
-                }
-            }
-        },
+//The diagnostic for an invalid token, e.g. Invalid token '@' at line 1, column 9
+//Literals out of range also say so, e.g. Invalid token '99999999999999999999' at line 1, column 1: integer literal out of range for int
+//A long token is shown by its start, as it may be too long for any limit, see SourceLimits
+fn lex_error_diagnostic(error: &LexError, source_map: &SourceMap) -> Diagnostic {
+    let mut token: String = error.slice.chars().take(TOKEN_SHOWN_LENGTH).collect();
+    if token.len() < error.slice.len() {
+        token.push_str("...");
     }
+    let mut message = format!(
+        "Invalid token '{}' at {}",
+        token,
+        source_map.describe(error.span.start)
+    );
+    if let Some(reason) = error.kind.reason() {
+        message.push_str(": ");
+        message.push_str(&reason);
+    }
+    Diagnostic::new(Severity::Error, Stage::Lex, message)
+        .at(source_map, error.span.start, error.span.end)
+        .with_snippet(source_map.snippet(error.span.start))
 }
 
-// Define a global environment for functions
-fn create_global_environment() -> HashMap<String, VariableInfo> {
-    let mut global_env = HashMap::new();
+//What kind of syntax error the parser found
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxErrorKind {
+    InvalidToken,
+    UnrecognizedToken,
+    ExtraToken,
+    UnexpectedEnd,
+    //An error raised by an action in the grammar, with its message
+    Other(String),
+}
+
+//A syntax error, with where it is in the input, the token found there and the tokens that could have been there instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    //Byte offsets of the token found, or the same offset twice for the end of the input
+    pub start: usize,
+    pub end: usize,
+    //The token found, e.g. Closeparan. None at the end of the input and for invalid tokens
+    pub found: Option<String>,
+    //The tokens the grammar expected, e.g. "\";\""
+    pub expected: Vec<String>,
+    //How to fix the error, when it is clear, e.g. that a semicolon is missing
+    pub suggestion: Option<String>,
+}
 
-    // print: (any) -> table
-    global_env.insert(
-        "print".to_string(),
-        VariableInfo {
-            var_type: TypeConstruct::Function(
-                Box::new(TypeConstruct::Table(vec![])),
-                vec![TypeConstruct::Any],
+impl From<ParseError<usize, Token, GrammarError>> for SyntaxError {
+    fn from(e: ParseError<usize, Token, GrammarError>) -> Self {
+        let error = |kind, start, end| SyntaxError {
+            kind,
+            start,
+            end,
+            found: None,
+            expected: vec![],
+            suggestion: None,
+        };
+        match e {
+            ParseError::InvalidToken { location } => {
+                error(SyntaxErrorKind::InvalidToken, location, location)
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => SyntaxError {
+                found: Some(format!("{:?}", token)),
+                expected,
+                ..error(SyntaxErrorKind::UnrecognizedToken, start, end)
+            },
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => SyntaxError {
+                found: Some(format!("{:?}", token)),
+                ..error(SyntaxErrorKind::ExtraToken, start, end)
+            },
+            ParseError::User { error: user_error } => error(
+                SyntaxErrorKind::Other(user_error.message),
+                user_error.span.start,
+                user_error.span.end,
             ),
-            is_constant: false,
-        },
-    );
+            //A program ending where a ; is expected is most often a statement missing its semicolon
+            ParseError::UnrecognizedEof { location, expected } => SyntaxError {
+                suggestion: expected
+                    .contains(&"\";\"".to_string())
+                    .then(|| "Missing semicolon at the end of the declaration!".to_string()),
+                expected,
+                ..error(SyntaxErrorKind::UnexpectedEnd, location, location)
+            },
+        }
+    }
+}
 
-    // import: (string, table) -> table
-    global_env.insert(
-        "import".to_string(),
-        VariableInfo {
-            var_type: TypeConstruct::Function(
-                Box::new(TypeConstruct::Table(vec![])),
-                vec![TypeConstruct::String, TypeConstruct::Table(vec![])],
+impl SyntaxError {
+    //The diagnostic for the error, e.g. Unrecognized token Closeparan at line 2, column 13. Expected one of: ...
+    fn diagnostic(&self, source_map: &SourceMap) -> Diagnostic {
+        let found = self.found.as_deref().unwrap_or_default();
+        let at = source_map.describe(self.start);
+        let message = match (&self.kind, &self.suggestion) {
+            (SyntaxErrorKind::Other(message), _) => format!("Parse error: {} at {}", message, at),
+            (_, Some(suggestion)) => format!("Parse error : {}", suggestion),
+            (SyntaxErrorKind::InvalidToken, None) => format!("Invalid token at {}", at),
+            (SyntaxErrorKind::UnrecognizedToken, None) => format!(
+                "Unrecognized token {} at {}. Expected one of: {:?}",
+                found, at, self.expected
             ),
-            is_constant: false,
-        },
-    );
-    // async_import: (string, table) -> table
-    global_env.insert(
-        "async_import".to_string(),
-        VariableInfo {
-            var_type: TypeConstruct::Function(
-                Box::new(TypeConstruct::Table(vec![])),
-                vec![TypeConstruct::String, TypeConstruct::Any],
+            (SyntaxErrorKind::ExtraToken, None) => format!("Extra token {} at {}", found, at),
+            (SyntaxErrorKind::UnexpectedEnd, None) => format!(
+                "Unrecognized EOF at {}. Expected one of: {:?}",
+                at, self.expected
             ),
-            is_constant: false,
-        },
-    );
+        };
+        let diagnostic = Diagnostic::new(Severity::Error, Stage::Parse, message)
+            .at(source_map, self.start, self.end);
+        //The end of the input has no line to show
+        match self.kind {
+            SyntaxErrorKind::UnexpectedEnd => diagnostic,
+            _ => diagnostic.with_snippet(source_map.snippet(self.start)),
+        }
+    }
+}
 
-    // table_add_row: (table, row) -> null
-    global_env.insert(
-        "table_add_row".to_string(),
-        VariableInfo {
-            var_type: TypeConstruct::Function(
-                Box::new(TypeConstruct::Null),
-                vec![TypeConstruct::Any, TypeConstruct::Any],
-            ),
-            is_constant: false,
-        },
+//Parse the tokens into the syntax tree. A statement with a syntax error is skipped up to its ; or past its block, and parsing goes on after it,
+//so every syntax error in the program is given rather than only the first
+fn parse(tokens: Vec<(usize, Token, usize)>) -> Result<Statement, Vec<SyntaxError>> {
+    let mut recovered = Vec::new();
+    let result = grammar::ProgramParser::new().parse(&mut recovered, tokens);
+    let mut errors: Vec<SyntaxError> = recovered
+        .into_iter()
+        .map(|recovery| recovery.error.into())
+        .collect();
+    // An error inside a skipped block is recovered from before the error that skipped it
+    errors.sort_by_key(|error| error.start);
+    match result {
+        Ok(syntax_tree) if errors.is_empty() => Ok(syntax_tree),
+        Ok(_) => Err(errors),
+        Err(e) => {
+            errors.push(e.into());
+            Err(errors)
+        }
+    }
+}
+
+//The diagnostic for a type error, placed at the statement it was found in, e.g. Type checking failed [E001] at line 2, column 1: ...
+fn type_error_diagnostic(error: TypeError, source_map: &SourceMap) -> Diagnostic {
+    let diagnostic = Diagnostic::type_error(error.code, error.message);
+    match error.span {
+        Some(span) => diagnostic
+            .at(source_map, span.start, span.end)
+            .with_snippet(source_map.snippet(span.start)),
+        None => diagnostic,
+    }
+}
+
+//The note saying which statement a runtime error happened in, e.g. the error happened in the statement at line 3, column 5
+fn runtime_error_note(source_map: &SourceMap) -> Option<Diagnostic> {
+    let span = current_span()?;
+    let message = format!(
+        "the error happened in the statement at {}",
+        source_map.describe(span.start)
     );
+    Some(
+        Diagnostic::new(Severity::Note, Stage::Runtime, message)
+            .at(source_map, span.start, span.end)
+            .with_snippet(source_map.snippet(span.start)),
+    )
+}
 
+// Define a global environment for functions, with an entry for every registered builtin
+// User functions are added next to them, but may not take their names
+pub fn create_global_environment() -> HashMap<String, VariableInfo> {
+    let mut global_env = HashMap::new();
+    for builtin in current_builtins().iter() {
+        global_env.insert(
+            builtin.name.clone(),
+            VariableInfo {
+                var_type: builtin.signature.clone(),
+                is_constant: false,
+                value: None,
+                is_table_loop_row: false,
+            },
+        );
+    }
     global_env
 }
 
 //Lex tokens from input and parse them into a syntax tree
-//pub fn create_syntax_tree(input: &str) -> Vec<Statement> {
-pub fn create_syntax_tree(input: &str) -> Statement {
-    ////Statement
+//Every invalid token is given, or else every syntax error
+pub fn create_syntax_tree(input: &str) -> Result<Statement, Vec<Diagnostic>> {
+    match parse_program(input, false) {
+        (Some(syntax_tree), _) => Ok(syntax_tree),
+        (None, diagnostics) => Err(diagnostics),
+    }
+}
+
+//The diagnostics as they are written for a person, one to a line
+pub fn human_messages(diagnostics: &[Diagnostic]) -> String {
+    let messages: Vec<String> = diagnostics.iter().map(Diagnostic::human).collect();
+    messages.join("\n")
+}
+
+//Lex and parse the input, returning the syntax tree if it parses, or the diagnostics for the invalid tokens or the parse error
+//Input with invalid tokens is not parsed, unless lexing is lenient and they are left out
+pub fn parse_program(input: &str, lenient: bool) -> (Option<Statement>, Vec<Diagnostic>) {
+    //Positions in error messages are given as lines and columns in the input
+    let source_map = SourceMap::new(input);
     //Collect tokens
-    let tokens: Vec<(usize, Token, usize)> = lex(input);
-    //Parse tokens and return the syntax tree
-    parse(tokens)
+    let tokens = match lex(input, lenient) {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            let diagnostics = errors
+                .iter()
+                .map(|error| lex_error_diagnostic(error, &source_map))
+                .collect();
+            return (None, diagnostics);
+        }
+    };
+    //Parse tokens into the syntax tree, with the enum types in it given their values
+    match parse(tokens) {
+        Ok(syntax_tree) => match resolve_enums(syntax_tree) {
+            Ok(syntax_tree) => (Some(syntax_tree), vec![]),
+            Err(message) => (
+                None,
+                vec![Diagnostic::new(Severity::Error, Stage::Type, message)],
+            ),
+        },
+        Err(errors) => (
+            None,
+            errors
+                .iter()
+                .map(|error| error.diagnostic(&source_map))
+                .collect(),
+        ),
+    }
+}
+
+//Options for running a program
+#[derive(Default)]
+pub struct RunOptions {
+    //Print the program and its syntax tree before running it
+    pub debug_mode: bool,
+    //Warnings found by the type checker stop the program from running
+    pub deny_warnings: bool,
+    //Variables given by the host, which the program can use like constants it has declared
+    pub vars: Vec<(String, ExpressionValue)>,
+    //Print the value of the last top level expression statement, as for programs given with -e
+    pub echo_last_value: bool,
+    //How many values an array or table can hold, see set_max_values. The default is used if not given
+    pub max_values: Option<usize>,
+    //How many elements of an array print writes, see set_max_printed_values. The default is used if not given
+    pub max_printed_values: Option<usize>,
+    //How many bytes of rows a reduce stage can buffer, see set_pipe_memory_limit. The default is used if not given
+    pub pipe_memory_limit: Option<usize>,
+    //Parse and type check the program without running it
+    pub check_only: bool,
+    //Write the diagnostics as one JSON array on stdout rather than as messages on stderr, see diagnostics_json
+    pub diagnostics_json: bool,
+    //The file the program was read from, named in the diagnostics
+    pub file_name: Option<String>,
+    //Count how many times each statement runs, and write the counts as JSON to this file when the program ends
+    pub coverage_file: Option<String>,
+    //Check the files the program imports and exports without reading or writing any records, see set_dry_run
+    pub dry_run: bool,
+    //How long string literals and names, and how many columns and parameters, the program may declare
+    pub source_limits: SourceLimits,
+    //The lowest level of the lines the log functions write to stderr, e.g. log_info
+    pub log_level: LogLevel,
+    //Exit with code 0 when the program logged an error with log_error, rather than 1
+    pub ignore_error_log: bool,
+    //The builtins the program can call, those shipped with wrench and any registered with register_builtin
+    pub builtins: Arc<Builtins>,
+}
+
+//Where the program to run is read from
+#[derive(Debug, PartialEq)]
+pub enum ProgramSource {
+    File(String),
+    //The program itself, given on the command line with -e
+    Inline(String),
+}
+
+impl RunOptions {
+    pub fn with_var(mut self, name: &str, value: ExpressionValue) -> Self {
+        self.vars.push((name.to_string(), value));
+        self
+    }
+
+    //Makes a Rust function callable from the program, only in runs with these options. Panics if the name is taken
+    pub fn register_builtin(mut self, builtin: Builtin) -> Self {
+        Arc::make_mut(&mut self.builtins).register(builtin);
+        self
+    }
+
+    //Gives the program a table, e.g. one made with TableBuilder. The program sees it as a constant of the table's type
+    pub fn with_table(self, name: &str, table: Table) -> Self {
+        self.with_var(name, ExpressionValue::Table(Rc::new(RefCell::new(table))))
+    }
+}
+
+//Reads a command line argument of the form name=value, used by --var and --var-str
+//Values are ints, doubles or bools when they read as one, and strings otherwise. With force_string they are always strings
+pub fn parse_var_arg(arg: &str, force_string: bool) -> Result<(String, ExpressionValue), String> {
+    let Some((name, text)) = arg.split_once('=') else {
+        return Err(format!("expected name=value, found '{}'", arg));
+    };
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        return Err(format!("'{}' is not a valid variable name", name));
+    }
+    let value = if force_string {
+        ExpressionValue::String(text.into())
+    } else if let Ok(n) = text.parse::<i64>() {
+        ExpressionValue::Number(n)
+    } else if let Ok(b) = text.parse::<bool>() {
+        ExpressionValue::Bool(b)
+    } else if let Ok(d) = text.parse::<f64>()
+        && d.is_finite()
+        && text.contains('.')
+    {
+        ExpressionValue::Double(d)
+    } else {
+        ExpressionValue::String(text.into())
+    };
+    Ok((name.to_string(), value))
+}
+
+//Reads the command line, without the name of the program, into the program to run and the options to run it with
+//A program given with -e echoes the value of its last expression, while one read from a file stays silent
+pub fn parse_args(args: &[String]) -> Result<(ProgramSource, RunOptions), String> {
+    let mut source = None;
+    let mut options = RunOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "debug=true" => options.debug_mode = true,
+            "--deny-warnings" => options.deny_warnings = true,
+            "--check" => options.check_only = true,
+            "--dry-run" => options.dry_run = true,
+            "--no-fail-on-error-log" => options.ignore_error_log = true,
+            "--log-level" => {
+                let Some(level) = args.next() else {
+                    return Err("--log-level expects debug, info, warn or error".to_string());
+                };
+                options.log_level = LogLevel::parse(level)?;
+            }
+            "--diagnostics=json" => options.diagnostics_json = true,
+            "--diagnostics=human" => options.diagnostics_json = false,
+            "--var" | "--var-str" => {
+                let Some(var) = args.next() else {
+                    return Err(format!("{} expects name=value", arg));
+                };
+                options.vars.push(parse_var_arg(var, arg == "--var-str")?);
+            }
+            "--coverage" => {
+                let Some(file) = args.next() else {
+                    return Err("--coverage expects a file to write the counts to".to_string());
+                };
+                options.coverage_file = Some(file.clone());
+            }
+            "--max-values"
+            | "--max-print-values"
+            | "--pipe-memory-limit"
+            | "--max-string-length"
+            | "--max-identifier-length"
+            | "--max-columns"
+            | "--max-parameters" => {
+                let limit = args
+                    .next()
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .ok_or_else(|| format!("{} expects a number", arg))?;
+                let source_limits = &mut options.source_limits;
+                match arg.as_str() {
+                    "--max-values" => options.max_values = Some(limit),
+                    "--max-print-values" => options.max_printed_values = Some(limit),
+                    "--max-string-length" => source_limits.max_string_length = limit,
+                    "--max-identifier-length" => source_limits.max_identifier_length = limit,
+                    "--max-columns" => source_limits.max_columns = limit,
+                    "--max-parameters" => source_limits.max_parameters = limit,
+                    // The limit is given in megabytes
+                    _ => options.pipe_memory_limit = Some(limit.saturating_mul(1024 * 1024)),
+                }
+            }
+            "-e" if source.is_none() => {
+                let Some(program) = args.next() else {
+                    return Err("-e expects a program".to_string());
+                };
+                source = Some(ProgramSource::Inline(program.clone()));
+                options.echo_last_value = true;
+            }
+            _ if source.is_none() => {
+                options.file_name = Some(arg.clone());
+                source = Some(ProgramSource::File(arg.clone()));
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    match source {
+        Some(source) => Ok((source, options)),
+        None => Err("no file to run".to_string()),
+    }
 }
 
-//Create the AST from the input string
-pub fn run(input: &str, debug_mode: bool) {
-    if debug_mode {
+//Create the AST from the input string, and run it. Returns the exit code of the program
+pub fn run(input: &str, options: &RunOptions) -> i32 {
+    set_source_limits(options.source_limits);
+    set_builtins(options.builtins.clone());
+    if options.diagnostics_json {
+        let (exit_code, diagnostics) = run_diagnosed(input, options);
+        println!("{}", diagnostics_json(&diagnostics));
+        return exit_code;
+    }
+    if options.debug_mode {
         println!("Input program:\n{}\n", input);
     }
     // Opret syntakstræ fra input
-    let syntax_tree = create_syntax_tree(input);
+    // Every syntax error is written, and the program is not type checked when there are any
+    let syntax_tree = match parse_program(input, false) {
+        (Some(syntax_tree), _) => syntax_tree,
+        (None, diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.human());
+            }
+            return 1;
+        }
+    };
     // Print syntaxtree
-    if debug_mode {
+    if options.debug_mode {
         println!("Syntaxtree:\n{:?}\n", syntax_tree);
         println!("Evaluating:");
     }
+    let source_map = SourceMap::new(input);
+
+    match check_with_warnings(&syntax_tree, options) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!(
+                    "{}",
+                    Diagnostic::new(Severity::Warning, Stage::Type, warning).human()
+                );
+            }
+            if let Some(notice) = missing_entry_point_notice(&syntax_tree) {
+                eprintln!(
+                    "{}",
+                    Diagnostic::new(Severity::Note, Stage::Type, notice).human()
+                );
+            }
+            if options.check_only {
+                return 0;
+            }
+            // The runtime error has been written when the panic carrying it is caught, so the statement it happened in
+            // is written below it
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| interpret_program(syntax_tree, options)));
+            result.unwrap_or_else(|e| {
+                if let Some(note) = runtime_error_note(&source_map) {
+                    eprintln!("{}", note.human());
+                }
+                panic::resume_unwind(e)
+            })
+        }
+        Err(errors) => {
+            for e in errors {
+                let diagnostic = type_error_diagnostic(e, &source_map);
+                eprintln!("{}", diagnostic.human());
+                // The hint is for people reading the error, not for tools reading the output
+                if let Some(code) = diagnostic.code
+                    && io::stderr().is_terminal()
+                {
+                    eprintln!("{}", explain_hint(code));
+                }
+            }
+            1
+        }
+    }
+}
 
-    // Create a global environment for functions
-    let global_env: HashMap<String, VariableInfo> = create_global_environment();
+//Runs the program like run, but collects the diagnostics rather than writing them, for --diagnostics=json
+//A parse error, type error or runtime error ends the run with exit code 1
+fn run_diagnosed(input: &str, options: &RunOptions) -> (i32, Vec<Diagnostic>) {
+    let source_map = SourceMap::new(input);
+    let (syntax_tree, mut diagnostics) = parse_program(input, false);
+    let exit_code = match syntax_tree {
+        None => 1,
+        Some(syntax_tree) => match check_with_warnings(&syntax_tree, options) {
+            Ok(warnings) => {
+                diagnostics.extend(
+                    warnings
+                        .into_iter()
+                        .map(|warning| Diagnostic::new(Severity::Warning, Stage::Type, warning)),
+                );
+                if let Some(notice) = missing_entry_point_notice(&syntax_tree) {
+                    diagnostics.push(Diagnostic::new(Severity::Note, Stage::Type, notice));
+                }
+                if options.check_only {
+                    0
+                } else {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        interpret_program(syntax_tree, options)
+                    }));
+                    match result {
+                        Ok(exit_code) => exit_code,
+                        Err(e) => {
+                            let diagnostic =
+                                Diagnostic::new(Severity::Error, Stage::Runtime, panic_message(e));
+                            diagnostics.push(match current_span() {
+                                Some(span) => diagnostic.at(&source_map, span.start, span.end),
+                                None => diagnostic,
+                            });
+                            1
+                        }
+                    }
+                }
+            }
+            Err(errors) => {
+                diagnostics.extend(
+                    errors
+                        .into_iter()
+                        .map(|error| type_error_diagnostic(error, &source_map)),
+                );
+                1
+            }
+        },
+    };
+    for diagnostic in &mut diagnostics {
+        diagnostic.file = options.file_name.clone();
+    }
+    (exit_code, diagnostics)
+}
+
+//Interprets a type checked program with the limits of the options. With --coverage its statements are counted as it runs,
+//and the counts are written when it ends, also when it stops with a runtime error
+//A program that ends with exit code 0 after logging an error exits with 1, unless the options ignore the error log
+fn interpret_program(syntax_tree: Statement, options: &RunOptions) -> i32 {
+    set_value_limits(options);
+    set_dry_run(options.dry_run);
+    start_log(options.log_level);
+    let mut exit_code = match &options.coverage_file {
+        Some(coverage_file) => interpret_covered(syntax_tree, coverage_file, options),
+        None => interpret(syntax_tree, options.vars.clone(), options.echo_last_value),
+    };
+    if exit_code == 0 && error_logged() && !options.ignore_error_log {
+        exit_code = 1;
+    }
+    // Like the coverage summary, this is for people and not for tools reading the diagnostics
+    if options.dry_run && !options.diagnostics_json {
+        eprintln!("{}", dry_run_summary());
+    }
+    exit_code
+}
+
+//Interprets the program with a count of how many times each statement runs, written to the coverage file when it ends
+fn interpret_covered(syntax_tree: Statement, coverage_file: &str, options: &RunOptions) -> i32 {
+    let (syntax_tree, coverage) = instrument(syntax_tree);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        interpret(syntax_tree, options.vars.clone(), options.echo_last_value)
+    }));
+    if let Err(e) = fs::write(coverage_file, coverage.to_json()) {
+        panic!("Cannot write the coverage to '{}': {}", coverage_file, e);
+    }
+    // The summary is for people, so it is left out when the diagnostics are written for tools
+    if !options.diagnostics_json {
+        eprintln!("{}", coverage.summary());
+    }
+    result.unwrap_or_else(|e| panic::resume_unwind(e))
+}
+
+//Sets how many values arrays and tables may hold, how many print writes, and how much a reduce stage may buffer,
+//before the program is interpreted
+fn set_value_limits(options: &RunOptions) {
+    set_max_values(options.max_values.unwrap_or(DEFAULT_MAX_VALUES));
+    set_max_printed_values(
+        options
+            .max_printed_values
+            .unwrap_or(DEFAULT_MAX_PRINTED_VALUES),
+    );
+    set_pipe_memory_limit(
+        options
+            .pipe_memory_limit
+            .unwrap_or(DEFAULT_PIPE_MEMORY_LIMIT),
+    );
+}
+
+//A notice for programs that only declare things, so running them does nothing
+//Programs with a main() are run by calling it, and programs without functions only declare values, so neither get a notice
+fn missing_entry_point_notice(syntax_tree: &Statement) -> Option<String> {
+    let mut functions = 0;
+    let mut statements = vec![syntax_tree];
+    while let Some(statement) = statements.pop() {
+        match statement {
+            Statement::Compound(first, rest) => {
+                statements.push(rest);
+                statements.push(first);
+            }
+            Statement::Skip => {}
+            Statement::Located(_, statement) => statements.push(statement),
+            Statement::Declaration(Declaration::Function(_, name, params, _)) => {
+                if name == "main" && params.is_empty() {
+                    return None;
+                }
+                functions += 1;
+            }
+            Statement::Declaration(_) => {}
+            _ => return None,
+        }
+    }
+    if functions == 0 {
+        return None;
+    }
+    Some(format!(
+        "program defines {} function{} but has no top-level statements and no main()",
+        functions,
+        if functions == 1 { "" } else { "s" }
+    ))
+}
+
+//Type check a syntax tree against the global environment without running it
+//Warnings found are kept until taken, see check_with_warnings
+pub fn check_syntax_tree(syntax_tree: &Statement) -> Result<(), String> {
+    check_syntax_tree_with_vars(syntax_tree, &[])
+}
+
+//Type check a syntax tree where the host has declared the given variables as constants
+pub fn check_syntax_tree_with_vars(
+    syntax_tree: &Statement,
+    vars: &[(String, ExpressionValue)],
+) -> Result<(), String> {
+    match type_errors(syntax_tree, vars).into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
 
+//Every error found type checking a syntax tree with the host variables, in program order. Empty if it is well typed
+//Statements after one that fails are still checked, so a single run reports all of them
+pub fn type_errors(syntax_tree: &Statement, vars: &[(String, ExpressionValue)]) -> Vec<String> {
+    program_type_errors(syntax_tree, vars, false)
+        .into_iter()
+        .map(|error| error.message)
+        .collect()
+}
+
+//Like type_errors, where the value of the last expression statement may be echoed, see RunOptions
+fn program_type_errors(
+    syntax_tree: &Statement,
+    vars: &[(String, ExpressionValue)],
+    echo_last_value: bool,
+) -> Vec<TypeError> {
+    // Warnings left from an earlier check do not belong to this tree
+    take_warnings();
     // This stack of scopes keeps track of variable names and their types
+    let mut global_env = create_global_environment();
+    for (name, value) in vars {
+        if let Err(error) = check_not_builtin(name) {
+            return vec![TypeError {
+                message: format!("host variable '{}' has the name of a builtin", name),
+                ..error
+            }];
+        }
+        if declares_name(syntax_tree, name) {
+            return vec![
+                format!(
+                    "'{}' is given by the host, and cannot also be declared in the program",
+                    name
+                )
+                .into(),
+            ];
+        }
+        let Some(var_type) = value.value_type() else {
+            return vec![format!("cannot infer the type of host variable '{}'", name).into()];
+        };
+        // Plain values are substituted like the literal of a declared constant
+        let value = match value {
+            ExpressionValue::Number(n) => Some(Expr::Number(*n)),
+            ExpressionValue::Double(d) => Some(Expr::Double(*d)),
+            ExpressionValue::String(s) => Some(Expr::StringLiteral(s.clone())),
+            ExpressionValue::Bool(b) => Some(Expr::Bool(*b)),
+            _ => None,
+        };
+        global_env.insert(
+            name.clone(),
+            VariableInfo {
+                var_type,
+                is_constant: true,
+                value,
+                is_table_loop_row: false,
+            },
+        );
+    }
     let mut scope_stack: Vec<HashMap<String, VariableInfo>> = vec![global_env];
-    match type_check(&syntax_tree, &mut scope_stack) {
-        Ok(_) => {
-            interpret(syntax_tree);
+    if echo_last_value {
+        type_check_echoing(syntax_tree, &mut scope_stack)
+    } else {
+        type_check_all(syntax_tree, &mut scope_stack)
+    }
+}
+
+//Whether a statement declares the name in the global environment of the program, or in a block of it
+//Functions get their own environment when called, so what they declare is not checked
+fn declares_name(statement: &Statement, name: &str) -> bool {
+    match statement {
+        Statement::Declaration(
+            Declaration::Variable(_, declared, _)
+            | Declaration::Constant(_, declared, _)
+            | Declaration::Function(_, declared, _, _),
+        ) => declared == name,
+        Statement::Compound(first, rest) => {
+            let mut statements = vec![&**first];
+            let mut rest = &**rest;
+            while let Statement::Compound(next, next_rest) = rest {
+                statements.push(next);
+                rest = next_rest;
+            }
+            statements.push(rest);
+            statements.iter().any(|s| declares_name(s, name))
         }
-        Err(e) => {
-            eprintln!("Type checking failed: {}", e);
+        Statement::If(_, body, else_body) => {
+            declares_name(body, name) || declares_name(else_body, name)
+        }
+        Statement::For(index, Parameter::Parameter(_, declared), _, body) => {
+            declared == name
+                || matches!(index, Some(Parameter::Parameter(_, index_name)) if index_name == name)
+                || declares_name(body, name)
         }
+        Statement::While(_, body) | Statement::Located(_, body) | Statement::Defer(body) => {
+            declares_name(body, name)
+        }
+        _ => false,
     }
 }
 
+//Type check a syntax tree, returning the warnings found, or every error found. With deny_warnings, any warning fails the check
+pub fn check_with_warnings(
+    syntax_tree: &Statement,
+    options: &RunOptions,
+) -> Result<Vec<String>, Vec<TypeError>> {
+    let errors = program_type_errors(syntax_tree, &options.vars, options.echo_last_value);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let warnings = take_warnings();
+    if options.deny_warnings && !warnings.is_empty() {
+        return Err(vec![
+            format!("warnings are denied\n{}", warnings.join("\n")).into(),
+        ]);
+    }
+    Ok(warnings)
+}
+
+//Parse and type check the input without running it, returning the syntax tree if it is well typed
+pub fn check(input: &str) -> Result<Statement, String> {
+    let syntax_tree =
+        create_syntax_tree(input).map_err(|diagnostics| human_messages(&diagnostics))?;
+    check_syntax_tree(&syntax_tree)?;
+    Ok(syntax_tree)
+}
+
 /*
 ========================================================
 Unit Tests for parser
@@ -168,12 +839,20 @@ mod tests {
         ColumnAssignmentEnum, Declaration, Expr, Operator, Parameter, Statement, TypeConstruct,
         ast_and,
     };
-    use super::super::lexer::Token; // Import the Token enum from the lexer module
-    use super::{create_syntax_tree, parse}; // Import the module being tested // Import the AST types
+    use super::super::lexer::{LexError, LexErrorKind, Token, lex_full, strip_trivia}; // Import the Token enum from the lexer module
+    use super::{
+        LogLevel, ProgramSource, RunOptions, Stage, SyntaxError, SyntaxErrorKind,
+        check_with_warnings, create_syntax_tree, human_messages, lex, missing_entry_point_notice,
+        parse, parse_args, parse_program, run, run_diagnosed,
+    };
+    use crate::backend::evaluate::ExpressionValue; // Import the module being tested // Import the AST types
+    use crate::backend::library::Builtin;
+    use crate::backend::table::{TableBuilder, TableCellType};
+    use crate::frontend::diagnostics::ErrorCode;
 
     // Helper function for create a tuple of (usize, Token, usize)
     fn f(t: Token) -> (usize, Token, usize) {
-        return (0, t, 0);
+        (0, t, 0)
     }
 
     #[test]
@@ -200,7 +879,7 @@ mod tests {
             )))]);
 
         // Act
-        let syntax_tree = parse(tokens);
+        let syntax_tree = parse(tokens).unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -225,15 +904,19 @@ mod tests {
         ];
 
         let expected_syntax_tree = *make_compound(vec![
-            Statement::Expr(Box::new(Expr::Table(vec![
-                Parameter::Parameter(TypeConstruct::Int, "id".to_string()),
-                Parameter::Parameter(TypeConstruct::String, "name".to_string()),
-            ]))),
+            Statement::Expr(Box::new(Expr::Table(
+                vec![
+                    Parameter::Parameter(TypeConstruct::Int, "id".to_string()),
+                    Parameter::Parameter(TypeConstruct::String, "name".to_string()),
+                ],
+                vec![],
+                vec![],
+            ))),
             Statement::Expr(Box::new(Expr::Not(Box::new(Expr::Bool(true))))),
         ]);
 
         // Act
-        let syntax_tree = parse(tokens);
+        let syntax_tree = parse(tokens).unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -290,6 +973,35 @@ mod tests {
         assert_eq!(and_expr, expected_ast)
     }
 
+    #[test]
+    fn test_every_syntax_error_is_reported() {
+        let (syntax_tree, diagnostics) = parse_program(
+            "var int x = 1;
+            var int y = ;
+            print(x +);
+            if (x == 1) {
+                var string s = \"a\" \"b\";
+                print(x +)
+            }
+            print(x);",
+            false,
+        );
+        assert_eq!(syntax_tree, None);
+        let lines: Vec<usize> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.start.as_ref().unwrap().line)
+            .collect();
+        // The error at the end of the block is skipped up to the brace, so the block closes and nothing after it is reported
+        assert_eq!(lines, [2, 3, 5, 6]);
+        assert!(
+            diagnostics[1]
+                .message
+                .starts_with("Unrecognized token Closeparan at line 3, column 22."),
+            "{}",
+            diagnostics[1].message
+        );
+    }
+
     #[test]
     fn test_parse_if_else() {
         let expected_syntax_tree = Statement::Compound(
@@ -313,11 +1025,59 @@ mod tests {
             Box::new(Statement::Skip),
         );
 
-        let actual_syntax_tree = create_syntax_tree("if (true) { x = 1; } else { x = 0; }");
+        let actual_syntax_tree =
+            create_syntax_tree("if (true) { x = 1; } else { x = 0; }").unwrap();
 
         assert_eq!(actual_syntax_tree, expected_syntax_tree);
     }
 
+    #[test]
+    fn test_parse_if_without_else() {
+        //The missing else is a branch that does nothing
+        assert_eq!(
+            create_syntax_tree("if (true) { x = 1; }").unwrap(),
+            create_syntax_tree("if (true) { x = 1; } else {}").unwrap()
+        );
+        let expected_syntax_tree = *make_compound(vec![Statement::If(
+            Box::new(Expr::Bool(true)),
+            make_compound(vec![Statement::VariableAssignment(
+                "x".to_string(),
+                Box::new(Expr::Number(1)),
+            )]),
+            Box::new(Statement::Skip),
+        )]);
+        assert_eq!(
+            create_syntax_tree("if (true) { x = 1; }").unwrap(),
+            expected_syntax_tree
+        );
+    }
+
+    #[test]
+    fn test_else_belongs_to_the_nearest_if() {
+        let assign = |value: i64| {
+            make_compound(vec![Statement::VariableAssignment(
+                "x".to_string(),
+                Box::new(Expr::Number(value)),
+            )])
+        };
+        let inner_if = Statement::If(
+            Box::new(Expr::Identifier("b".to_string())),
+            assign(1),
+            assign(2),
+        );
+        let expected_syntax_tree = *make_compound(vec![Statement::If(
+            Box::new(Expr::Identifier("a".to_string())),
+            make_compound(vec![inner_if]),
+            Box::new(Statement::Skip),
+        )]);
+        assert_eq!(
+            create_syntax_tree("if (a) { if (b) { x = 1; } else { x = 2; } }").unwrap(),
+            expected_syntax_tree
+        );
+        //An else can only follow the braces of the if it belongs to
+        assert!(create_syntax_tree("if (a) if (b) { x = 1; } else { x = 2; }").is_err());
+    }
+
     #[test]
     fn test_while_loop() {
         let expected_ast = Statement::Compound(
@@ -334,160 +1094,283 @@ mod tests {
             Box::new(Statement::Skip),
         );
 
-        let actual_ast = create_syntax_tree("while (true) { x = 1; }");
+        let actual_ast = create_syntax_tree("while (true) { x = 1; }").unwrap();
 
         assert_eq!(actual_ast, expected_ast);
     }
 
+    #[test]
+    fn test_for_loop_over_a_range() {
+        let expected = |inclusive: bool| {
+            Statement::Compound(
+                Box::new(Statement::For(
+                    None,
+                    Parameter::Parameter(TypeConstruct::Int, "i".to_string()),
+                    Box::new(Expr::Range(
+                        Box::new(Expr::Number(0)),
+                        Box::new(Expr::Identifier("n".to_string())),
+                        inclusive,
+                    )),
+                    Box::new(Statement::Skip),
+                )),
+                Box::new(Statement::Skip),
+            )
+        };
+        assert_eq!(
+            create_syntax_tree("for (int i in 0..n) {}").unwrap(),
+            expected(false)
+        );
+        assert_eq!(
+            create_syntax_tree("for (int i in 0..=n) {}").unwrap(),
+            expected(true)
+        );
+    }
+
+    #[test]
+    fn test_greater_than_is_desugared() {
+        // x > 3 is parsed as !(x <= 3), and x >= 3 as !(x < 3)
+        let comparison = |operator: Operator| {
+            Statement::Compound(
+                Box::new(Statement::Expr(Box::new(Expr::Not(Box::new(
+                    Expr::Operation(
+                        Box::new(Expr::Identifier("x".to_string())),
+                        operator,
+                        Box::new(Expr::Number(3)),
+                    ),
+                ))))),
+                Box::new(Statement::Skip),
+            )
+        };
+        assert_eq!(
+            create_syntax_tree("x > 3;").unwrap(),
+            comparison(Operator::LessThanOrEqual)
+        );
+        assert_eq!(
+            create_syntax_tree("x >= 3;").unwrap(),
+            comparison(Operator::LessThan)
+        );
+    }
+
     //Edge cases
+    // The syntax errors in the input, which lexes but does not parse
+    fn syntax_errors(input: &str) -> Vec<SyntaxError> {
+        parse(lex(input, false).unwrap()).unwrap_err()
+    }
+
     #[test]
-    #[should_panic(expected = "Unrecognized token Closeparan")]
     fn unmatched_paran() {
-        create_syntax_tree("100 + (2 * 3));");
+        let errors = syntax_errors("100 + (2 * 3));");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, SyntaxErrorKind::UnrecognizedToken);
+        assert_eq!(errors[0].found.as_deref(), Some("Closeparan"));
+        assert_eq!((errors[0].start, errors[0].end), (13, 14));
+        assert!(errors[0].expected.contains(&"\";\"".to_string()));
+        assert_eq!(errors[0].suggestion, None);
+    }
+
+    #[test]
+    fn bad_line_in_a_block_is_one_error() {
+        let errors = syntax_errors("while (true) { print(1 +); print(2); }");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        // The } of the if is not taken as the end of the function body
+        let errors =
+            syntax_errors("fn null f() { if (x > ) { print(1); } else { print(2); } print(3); }");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].found.as_deref(), Some("Closeparan"));
+        assert_eq!(errors[0].start, 22);
+        // An error in the skipped block is still given, in order
+        let errors = syntax_errors("fn null f() { while (x > ) { print(1 +); } print(2); }");
+        let starts: Vec<usize> = errors.iter().map(|error| error.start).collect();
+        assert_eq!(starts, vec![25, 38]);
     }
 
     #[test]
-    #[should_panic(expected = "Unrecognized token")]
     fn unmatched_paran2() {
-        create_syntax_tree("100 + (2 * 3;");
+        let errors = syntax_errors("100 + (2 * 3;");
+        assert_eq!(errors[0].kind, SyntaxErrorKind::UnrecognizedToken);
+        assert_eq!(errors[0].found.as_deref(), Some("Semicolon"));
+        assert!(errors[0].expected.contains(&"\")\"".to_string()));
     }
 
     #[test]
-    #[should_panic(expected = "Parse error : Missing semicolon at the end of the declaration!")]
     fn missing_semicolon() {
-        create_syntax_tree("var int x = 2");
+        let errors = syntax_errors("var int x = 2");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, SyntaxErrorKind::UnexpectedEnd);
+        assert_eq!(errors[0].found, None);
+        assert_eq!((errors[0].start, errors[0].end), (13, 13));
+        assert_eq!(
+            errors[0].suggestion.as_deref(),
+            Some("Missing semicolon at the end of the declaration!")
+        );
+        assert_eq!(
+            create_syntax_tree("var int x = 2").unwrap_err()[0].message,
+            "Parse error : Missing semicolon at the end of the declaration!"
+        );
     }
 
     #[test]
-    #[should_panic]
     fn invalid_identifiername() {
-        create_syntax_tree("var ?myname = \"Isabella\""); //Illegal ident
+        assert!(create_syntax_tree("var ?myname = \"Isabella\"").is_err()); //Illegal ident
+    }
+
+    #[test]
+    fn trailing_comma() {
+        //A comma may follow the last argument
+        assert_eq!(
+            create_syntax_tree("print(100, 800, );").unwrap(),
+            create_syntax_tree("print(100, 800);").unwrap()
+        );
     }
 
     #[test]
-    #[should_panic]
     fn invalid_coma() {
-        create_syntax_tree("print(100, 800, )"); //Illegal comma
+        assert!(create_syntax_tree("print(,);").is_err()); //A comma with nothing before it
+        assert!(create_syntax_tree("print(1,,2);").is_err()); //Two commas in a row
+        assert!(create_syntax_tree("[,];").is_err());
+    }
+
+    #[test]
+    fn trailing_commas_in_lists() {
+        let pairs = [
+            (
+                "var table(int id, string name) t = table(\n    int id,\n    string name,\n);",
+                "var table(int id, string name) t = table(int id, string name);",
+            ),
+            (
+                "row(int id = 1, string name = \"a\",);",
+                "row(int id = 1, string name = \"a\");",
+            ),
+            ("[1, 2, 3,];", "[1, 2, 3];"),
+            (
+                "fn int add(int a, int b,) { return a + b; };",
+                "fn int add(int a, int b) { return a + b; };",
+            ),
+            (
+                "var fn int(int) f = fn (int x,) int { return x; };",
+                "var fn int(int) f = fn (int x) int { return x; };",
+            ),
+            ("t pipe filter(small,);", "t pipe filter(small);"),
+        ];
+        for (with_comma, without) in pairs {
+            assert_eq!(
+                create_syntax_tree(with_comma).unwrap(),
+                create_syntax_tree(without).unwrap(),
+                "{}",
+                with_comma
+            );
+        }
     }
     #[test]
-    #[should_panic]
     fn invalid_questionmark() {
-        create_syntax_tree("print(100, 800? )"); //Illegal symbol
+        assert!(create_syntax_tree("print(100, 800? )").is_err()); //Illegal symbol
     }
 
     #[test]
-    #[should_panic]
     fn nobody_function_declr() {
-        create_syntax_tree("fn double dummy(double y);"); //Function has no body
+        assert!(create_syntax_tree("fn double dummy(double y);").is_err()); //Function has no body
     }
 
     #[test]
-    #[should_panic]
     fn invalid_expr() {
-        create_syntax_tree("11 + ??"); //Invalid operation.
+        assert!(create_syntax_tree("11 + ??").is_err()); //Invalid operation.
     }
 
     #[test]
-    #[should_panic]
     fn invalid_array_index() {
-        create_syntax_tree("arr[0;");
+        assert!(create_syntax_tree("arr[0;").is_err());
     }
 
     #[test]
-    #[should_panic]
     fn invalid_pipe_fnname() {
-        create_syntax_tree("data pipe (0, 1); "); //Missing function name for pipe
+        assert!(create_syntax_tree("data pipe (0, 1); ").is_err()); //Missing function name for pipe
     }
 
     #[test]
-    #[should_panic]
     fn invalid_operation() {
-        create_syntax_tree("1 ++ 2;"); //What is ++?
+        assert!(create_syntax_tree("1 ++ 2;").is_err()); //What is ++?
     }
 
     #[test]
-    #[should_panic]
     fn invalid_row_decl() {
-        create_syntax_tree("row(int age, string name);"); //Remember: we declare rows like row(int age = 5)
+        assert!(create_syntax_tree("row(int age, string name);").is_err()); //Remember: we declare rows like row(int age = 5)
     }
 
     #[test]
-    #[should_panic]
     fn invalid_table_decl() {
-        create_syntax_tree("table(age, string name);"); //Missing the age type!
+        assert!(create_syntax_tree("table(age, string name);").is_err()); //Missing the age type!
     }
 
     #[test]
-    #[should_panic]
     fn no_statement() {
-        create_syntax_tree(";"); //Empty statement should not be allowed
+        assert!(create_syntax_tree(";").is_err()); //Empty statement should not be allowed
     }
 
     #[test]
-    #[should_panic]
     fn callingfunction_incorrectly() {
-        create_syntax_tree("myfunction(name age)"); //Dont forget commas between args
+        assert!(create_syntax_tree("myfunction(name age)").is_err()); //Dont forget commas between args
     }
 
     //Check that the correct version of edge cases is working!
     #[test]
     fn unmatched_paran_correct() {
-        create_syntax_tree("100 + (2 * 3);");
+        create_syntax_tree("100 + (2 * 3);").unwrap();
     }
 
     #[test]
     fn unmatched_paran2_correct() {
-        create_syntax_tree("100 + (2 * 3);");
+        create_syntax_tree("100 + (2 * 3);").unwrap();
     }
 
     #[test]
     fn missing_semicolon_correct() {
-        create_syntax_tree("var int x = 2;");
+        create_syntax_tree("var int x = 2;").unwrap();
     }
 
     #[test]
     fn invalid_identifiername_correct() {
-        create_syntax_tree("var string myname = \"Isabella\";");
+        create_syntax_tree("var string myname = \"Isabella\";").unwrap();
     }
 
     #[test]
     fn invalid_coma_and_questionmark_correct() {
-        create_syntax_tree("print(100, 800 );");
+        create_syntax_tree("print(100, 800 );").unwrap();
     }
 
     #[test]
     fn nobody_function_declr_correct() {
-        create_syntax_tree("fn double dummy(double y){};");
+        create_syntax_tree("fn double dummy(double y){}").unwrap();
+        create_syntax_tree("fn double dummy(double y){};").unwrap();
     }
 
     #[test]
     fn invalid_expr_correct() {
-        create_syntax_tree("print(11 + 11);");
+        create_syntax_tree("print(11 + 11);").unwrap();
     }
 
     #[test]
     fn invalid_array_index_correct() {
-        create_syntax_tree("arr[0];");
+        create_syntax_tree("arr[0];").unwrap();
     }
 
     #[test]
     fn invalid_operation_correct() {
-        create_syntax_tree("1 + 2;");
+        create_syntax_tree("1 + 2;").unwrap();
     }
 
     #[test]
     fn invalid_row_decl_correct() {
-        create_syntax_tree("row(int age = 5);");
+        create_syntax_tree("row(int age = 5);").unwrap();
     }
 
     #[test]
     fn invalid_table_decl_correct() {
-        create_syntax_tree("table(int age, string name);");
+        create_syntax_tree("table(int age, string name);").unwrap();
     }
 
     #[test]
     fn callingfunction_incorrectly_correct() {
-        create_syntax_tree("myfunction(name , age);"); //Dont forget commas between args
+        create_syntax_tree("myfunction(name , age);").unwrap(); //Dont forget commas between args
     }
 
     /*
@@ -512,7 +1395,7 @@ mod tests {
             )))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("3 + 5 * 2;");
+        let syntax_tree = create_syntax_tree("3 + 5 * 2;").unwrap();
 
         //Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -534,7 +1417,7 @@ mod tests {
             )))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("3 + 5 * 2;");
+        let syntax_tree = create_syntax_tree("3 + 5 * 2;").unwrap();
 
         //Assert
         assert_ne!(syntax_tree, expected_syntax_tree);
@@ -550,7 +1433,7 @@ mod tests {
         ]);
 
         // Act
-        let syntax_tree = create_syntax_tree("3;      //Comment ag \n2;");
+        let syntax_tree = create_syntax_tree("3;      //Comment ag \n2;").unwrap();
 
         //Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -572,7 +1455,7 @@ mod tests {
             )))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("3 ** 2 ** 1;");
+        let syntax_tree = create_syntax_tree("3 ** 2 ** 1;").unwrap();
 
         //Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -594,7 +1477,7 @@ mod tests {
             )))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("3 + 5 + 2;");
+        let syntax_tree = create_syntax_tree("3 + 5 + 2;").unwrap();
 
         //Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -616,7 +1499,7 @@ mod tests {
             )))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("(3 + 5) * 2;");
+        let syntax_tree = create_syntax_tree("(3 + 5) * 2;").unwrap();
 
         //Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -635,12 +1518,34 @@ mod tests {
             ))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("fn int b(){};");
+        let syntax_tree = create_syntax_tree("fn int b(){}").unwrap();
 
         //Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
     }
 
+    #[test]
+    fn semicolon_after_function_is_optional() {
+        assert_eq!(
+            create_syntax_tree("fn int f(){}").unwrap(),
+            create_syntax_tree("fn int f(){};").unwrap()
+        );
+        assert_eq!(
+            create_syntax_tree(
+                "fn int f() { fn int g() { return 1; } return g(); }
+                print(f());"
+            )
+            .unwrap(),
+            create_syntax_tree(
+                "fn int f() { fn int g() { return 1; }; return g(); };
+                print(f());"
+            )
+            .unwrap()
+        );
+        //Other declarations still end with a semicolon
+        assert!(create_syntax_tree("var int x = 1 print(x);").is_err());
+    }
+
     #[test]
     fn parses_function_with_parameters_and_body() {
         //Test if functions with parameters are parsed correctly
@@ -657,21 +1562,50 @@ mod tests {
             ))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("fn int b(int x){x = 3;};");
+        let syntax_tree = create_syntax_tree("fn int b(int x){x = 3;};").unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
     }
 
+    #[test]
+    fn parses_increment_and_decrement() {
+        let step = |op: Operator| {
+            Statement::VariableAssignment(
+                "i".to_string(),
+                Box::new(Expr::Operation(
+                    Box::new(Expr::Identifier("i".to_string())),
+                    op,
+                    Box::new(Expr::Number(1)),
+                )),
+            )
+        };
+        let expected_syntax_tree =
+            *make_compound(vec![step(Operator::Addition), step(Operator::Subtraction)]);
+        assert_eq!(
+            create_syntax_tree("i++; i--;").unwrap(),
+            expected_syntax_tree
+        );
+    }
+
+    #[test]
+    fn increment_of_an_expression_is_rejected() {
+        assert!(create_syntax_tree("(i + 1)++;").is_err());
+    }
+
     #[test]
     fn parses_tables_and_rows() {
         // Test if tables and rows are parsed correctly
         // Arrange
         let expected_syntax_tree = *make_compound(vec![
-            Statement::Expr(Box::new(Expr::Table(vec![
-                Parameter::Parameter(TypeConstruct::Int, "id".to_string()),
-                Parameter::Parameter(TypeConstruct::String, "name".to_string()),
-            ]))),
+            Statement::Expr(Box::new(Expr::Table(
+                vec![
+                    Parameter::Parameter(TypeConstruct::Int, "id".to_string()),
+                    Parameter::Parameter(TypeConstruct::String, "name".to_string()),
+                ],
+                vec![],
+                vec![],
+            ))),
             Statement::Expr(Box::new(Expr::Row(vec![
                 ColumnAssignmentEnum::ColumnAssignment(
                     TypeConstruct::Int,
@@ -688,12 +1622,117 @@ mod tests {
 
         // Act
         let syntax_tree =
-            create_syntax_tree("table(int id, string name); row(int id = 1, string name = Alice);");
+            create_syntax_tree("table(int id, string name); row(int id = 1, string name = Alice);")
+                .unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
     }
 
+    #[test]
+    fn parses_tables_with_rows() {
+        let id = || vec![Parameter::Parameter(TypeConstruct::Int, "id".to_string())];
+        let row = |n: i64| {
+            Box::new(Expr::Row(vec![ColumnAssignmentEnum::ColumnAssignment(
+                TypeConstruct::Int,
+                "id".to_string(),
+                Box::new(Expr::Number(n)),
+            )]))
+        };
+        let table = Expr::Table(id(), vec![], vec![row(1), row(2)]);
+        let expected_syntax_tree = *make_compound(vec![
+            Statement::Expr(Box::new(table.clone())),
+            Statement::Expr(Box::new(Expr::Pipe(
+                Box::new(table.clone()),
+                "print".to_string(),
+                vec![],
+            ))),
+            //A table expression is indexed in parentheses, as brackets right after it hold its rows
+            Statement::Expr(Box::new(Expr::Indexing(
+                Box::new(table),
+                Box::new(Expr::Number(0)),
+            ))),
+            Statement::Expr(Box::new(Expr::Table(id(), vec![], vec![]))),
+        ]);
+
+        let syntax_tree = create_syntax_tree(
+            "table(int id) [row(int id = 1), row(int id = 2)];
+            table(int id) [row(int id = 1), row(int id = 2),] pipe print();
+            (table(int id) [row(int id = 1), row(int id = 2)])[0];
+            table(int id) [];",
+        )
+        .unwrap();
+
+        assert_eq!(syntax_tree, expected_syntax_tree);
+    }
+
+    #[test]
+    fn parses_schema_types_in_declarations() {
+        let people = || {
+            vec![
+                Parameter::Parameter(TypeConstruct::Int, "id".to_string()),
+                Parameter::Parameter(TypeConstruct::String, "name".to_string()),
+            ]
+        };
+        let expected_syntax_tree = *make_compound(vec![
+            Statement::Declaration(Declaration::Variable(
+                TypeConstruct::Table(people()),
+                "people".to_string(),
+                Box::new(Expr::Table(people(), vec![], vec![])),
+            )),
+            Statement::Declaration(Declaration::Constant(
+                TypeConstruct::Row(people()),
+                "first".to_string(),
+                Box::new(Expr::Identifier("r".to_string())),
+            )),
+            Statement::Declaration(Declaration::Function(
+                TypeConstruct::Row(people()),
+                "f".to_string(),
+                vec![Parameter::Parameter(
+                    TypeConstruct::Table(people()),
+                    "t".to_string(),
+                )],
+                make_compound(vec![]),
+            )),
+        ]);
+
+        let syntax_tree = create_syntax_tree(
+            "var table(int id, string name) people = table(int id, string name);
+            const row(int id, string name) first = r;
+            fn row(int id, string name) f(table(int id, string name) t) {};",
+        )
+        .unwrap();
+
+        assert_eq!(syntax_tree, expected_syntax_tree);
+    }
+
+    #[test]
+    fn parses_column_access_after_each_kind_of_expression() {
+        let column = |e: Expr| {
+            Statement::Expr(Box::new(Expr::ColumnIndexing(
+                Box::new(e),
+                "name".to_string(),
+            )))
+        };
+        let x = || Expr::Identifier("x".to_string());
+        let call = || Expr::FunctionCall("f".to_string(), vec![Box::new(x())]);
+        let index = |e: Expr| Expr::Indexing(Box::new(e), Box::new(Expr::Number(0)));
+        let expected_syntax_tree = *make_compound(vec![
+            column(x()),
+            column(index(x())),
+            column(call()),
+            column(x()),
+            column(index(call())),
+            column(Expr::Number(3)),
+        ]);
+
+        let syntax_tree =
+            create_syntax_tree("x.name; x[0].name; f(x).name; (x).name; f(x)[0].name; 3.name;")
+                .unwrap();
+
+        assert_eq!(syntax_tree, expected_syntax_tree);
+    }
+
     #[test]
     fn parses_boolean_operators() {
         let expected_syntax_tree =
@@ -703,7 +1742,7 @@ mod tests {
                 Box::new(Expr::Bool(true)),
             )))]);
 
-        let syntax_tree = create_syntax_tree("true and false or true;");
+        let syntax_tree = create_syntax_tree("true and false or true;").unwrap();
 
         assert_eq!(syntax_tree, expected_syntax_tree);
     }
@@ -713,10 +1752,10 @@ mod tests {
         // Test if double literals are parsed correctly
         // Arrange
         let expected_syntax_tree =
-            *make_compound(vec![Statement::Expr(Box::new(Expr::Double(3.14)))]);
+            *make_compound(vec![Statement::Expr(Box::new(Expr::Double(2.5)))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("3.14;");
+        let syntax_tree = create_syntax_tree("2.5;").unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -729,7 +1768,7 @@ mod tests {
         let expected_syntax_tree = *make_compound(vec![Statement::Expr(Box::new(Expr::Null))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("null;");
+        let syntax_tree = create_syntax_tree("null;").unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
@@ -744,9 +1783,518 @@ mod tests {
         )))]);
 
         // Act
-        let syntax_tree = create_syntax_tree("!!true;");
+        let syntax_tree = create_syntax_tree("!!true;").unwrap();
 
         // Assert
         assert_eq!(syntax_tree, expected_syntax_tree);
     }
+
+    // Parses the input, and returns the message it fails to parse with
+    fn parse_error(input: &str) -> String {
+        let diagnostics =
+            create_syntax_tree(input).expect_err(&format!("Expected '{}' to fail to parse", input));
+        human_messages(&diagnostics)
+    }
+
+    #[test]
+    fn reserved_word_as_variable_name() {
+        for keyword in ["table", "row", "in", "null"] {
+            assert_eq!(
+                parse_error(&format!("var int {} = 5;", keyword)),
+                format!(
+                    "Parse error: '{}' is a reserved word and cannot be used as a variable name at line 1, column 9\n\
+                     var int {} = 5;\n        ^",
+                    keyword, keyword
+                )
+            );
+        }
+        assert_eq!(
+            parse_error("var int x = 1;\nconst string pipe = \"a\";")
+                .lines()
+                .next(),
+            Some(
+                "Parse error: 'pipe' is a reserved word and cannot be used as a variable name at line 2, column 14"
+            )
+        );
+    }
+
+    #[test]
+    fn every_reserved_word_is_rejected_as_a_variable_name() {
+        for keyword in Token::keyword_list() {
+            let message = parse_error(&format!("var int {} = 5;", keyword));
+            assert!(
+                message.starts_with(&format!(
+                    "Parse error: '{}' is a reserved word and cannot be used as a variable name at line 1, column 9",
+                    keyword
+                )),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn reserved_word_as_function_name() {
+        assert_eq!(
+            parse_error("fn int while(int a) { return a; };")
+                .lines()
+                .next(),
+            Some(
+                "Parse error: 'while' is a reserved word and cannot be used as a function name at line 1, column 8"
+            )
+        );
+    }
+
+    #[test]
+    fn reserved_word_as_parameter_name() {
+        assert_eq!(
+            parse_error("fn int f(int for) { return 1; };")
+                .lines()
+                .next(),
+            Some(
+                "Parse error: 'for' is a reserved word and cannot be used as a parameter name at line 1, column 14"
+            )
+        );
+        assert_eq!(
+            parse_error("var table(int string) t = table(int string);")
+                .lines()
+                .next(),
+            Some(
+                "Parse error: 'string' is a reserved word and cannot be used as a parameter name at line 1, column 15"
+            )
+        );
+    }
+
+    #[test]
+    fn parse_error_reports_line_and_column() {
+        let message = parse_error("var int x = 5;\r\nvar int y = );");
+        assert!(
+            message.starts_with("Unrecognized token Closeparan at line 2, column 13."),
+            "{}",
+            message
+        );
+        assert!(
+            message.ends_with("var int y = );\n            ^"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn check_reports_every_error_in_order() {
+        let syntax_tree =
+            create_syntax_tree("var int x = true; var bool y = 1; var int z = x;").unwrap();
+        let errors = check_with_warnings(&syntax_tree, &RunOptions::default()).unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].message.contains("Bool"), "{:?}", errors[0]);
+        assert!(errors[1].message.contains("Int"), "{:?}", errors[1]);
+    }
+
+    #[test]
+    fn inline_program_echoes_without_discard_warning() {
+        let args = vec!["-e".to_string(), "3 * 14;".to_string()];
+        let (source, options) = parse_args(&args).unwrap();
+        assert_eq!(source, ProgramSource::Inline("3 * 14;".to_string()));
+        assert!(options.echo_last_value);
+        let syntax_tree = create_syntax_tree("3 * 14;").unwrap();
+        assert_eq!(check_with_warnings(&syntax_tree, &options), Ok(vec![]));
+        assert_eq!(
+            check_with_warnings(&syntax_tree, &RunOptions::default()).map(|w| w.len()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn deny_warnings_makes_warnings_fatal() {
+        let syntax_tree = create_syntax_tree("var int x = 1; x + 1;").unwrap();
+        assert_eq!(
+            check_with_warnings(&syntax_tree, &RunOptions::default()).map(|w| w.len()),
+            Ok(1)
+        );
+        let deny = RunOptions {
+            deny_warnings: true,
+            ..Default::default()
+        };
+        let errors = check_with_warnings(&syntax_tree, &deny).unwrap_err();
+        let error = &errors[0].message;
+        assert!(
+            error.starts_with("warnings are denied\nWarning: the Int"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn notice_for_program_with_only_declarations() {
+        let notice = |input: &str| missing_entry_point_notice(&create_syntax_tree(input).unwrap());
+        assert_eq!(
+            notice("fn int a() { return 1; }; fn int b() { return 2; }; var int x = 1;"),
+            Some(
+                "program defines 2 functions but has no top-level statements and no main()"
+                    .to_string()
+            )
+        );
+        assert_eq!(notice("fn int a() { return 1; }; a();"), None);
+        assert_eq!(notice("var int x = 1;"), None);
+        assert_eq!(notice("const int a = 1; const string b = \"b\";"), None);
+        assert_eq!(
+            notice("fn int a() { return 1; }; fn null main() { a(); };"),
+            None
+        );
+    }
+
+    // Writes the numbers from 0 up to count to the CSV file out, both given by the host
+    const COUNT_TO_FILE: &str = "
+        var table(int n) t = table(int n);
+        var int i = 0;
+        while (i < count) {
+            table_add_row(t, row(int n = i));
+            i = i + 1;
+        }
+        export_csv(out, t);";
+
+    fn wrench_triple(args: Vec<ExpressionValue>) -> ExpressionValue {
+        match args[0] {
+            ExpressionValue::Number(n) => ExpressionValue::Number(n * 3),
+            _ => panic!("Expected a number"),
+        }
+    }
+
+    #[test]
+    fn registered_builtin_belongs_to_its_run() {
+        let program = "fn int main() { return triple(2); };";
+        let options = RunOptions::default().register_builtin(Builtin::new(
+            "triple",
+            TypeConstruct::Int,
+            vec![("n", TypeConstruct::Int)],
+            wrench_triple,
+        ));
+        assert_eq!(run(program, &options), 6);
+        // A run with other options does not have it, so its name is free again
+        assert_eq!(run(program, &RunOptions::default()), 1);
+        assert_eq!(
+            run(
+                "fn int triple(int n) { return n; }; fn int main() { return triple(2); };",
+                &RunOptions::default()
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn host_variables_are_used_by_the_program() {
+        let out = tempfile::NamedTempFile::new().unwrap();
+        let options = RunOptions::default()
+            .with_var(
+                "out",
+                ExpressionValue::String(out.path().to_str().unwrap().into()),
+            )
+            .with_var("count", ExpressionValue::Number(3));
+        assert_eq!(run(COUNT_TO_FILE, &options), 0);
+        assert_eq!(std::fs::read_to_string(out.path()).unwrap(), "n\n0\n1\n2\n");
+    }
+
+    #[test]
+    fn host_table_is_used_by_the_program() {
+        let people = TableBuilder::new()
+            .column("id", TableCellType::Int)
+            .column("name", TableCellType::String)
+            .column("age", TableCellType::Double)
+            .row(|r| r.int(1).str("Ann").int(34))
+            .row(|r| r.int(2).str("Bo").double(12.5))
+            .row(|r| r.int(3).str("Cy").double(70.0))
+            .build()
+            .unwrap();
+        let out = tempfile::NamedTempFile::new().unwrap();
+        let program = "
+            fn bool adult(row(double age, int id, string name) r) { return r.age > 17; };
+            export_csv(out, people pipe adult());";
+        let options = RunOptions::default().with_table("people", people).with_var(
+            "out",
+            ExpressionValue::String(out.path().to_str().unwrap().into()),
+        );
+        assert_eq!(run(program, &options), 0);
+        assert_eq!(
+            std::fs::read_to_string(out.path()).unwrap(),
+            "age,id,name\n34.0,1,Ann\n70.0,3,Cy\n"
+        );
+    }
+
+    #[test]
+    fn host_variables_from_the_command_line() {
+        let out = tempfile::NamedTempFile::new().unwrap();
+        let args: Vec<String> = [
+            "program.wrench",
+            "--var",
+            "count=2",
+            "--var-str",
+            &format!("out={}", out.path().to_str().unwrap()),
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let (source, options) = parse_args(&args).unwrap();
+        assert_eq!(source, ProgramSource::File("program.wrench".to_string()));
+        assert_eq!(run(COUNT_TO_FILE, &options), 0);
+        assert_eq!(std::fs::read_to_string(out.path()).unwrap(), "n\n0\n1\n");
+    }
+
+    #[test]
+    fn value_limits_from_the_command_line() {
+        let args: Vec<String> = [
+            "big.wr",
+            "--max-values",
+            "100",
+            "--max-print-values",
+            "5",
+            "--pipe-memory-limit",
+            "3",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let (_, options) = parse_args(&args).unwrap();
+        assert_eq!(options.max_values, Some(100));
+        assert_eq!(options.max_printed_values, Some(5));
+        assert_eq!(options.pipe_memory_limit, Some(3 * 1024 * 1024));
+        let args: Vec<String> = ["big.wr", "--max-values", "many"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(
+            parse_args(&args).err(),
+            Some("--max-values expects a number".to_string())
+        );
+    }
+
+    #[test]
+    fn diagnostics_mode_from_the_command_line() {
+        let args: Vec<String> = ["lint.wr", "--check", "--diagnostics=json"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let (_, options) = parse_args(&args).unwrap();
+        assert!(options.check_only && options.diagnostics_json);
+        assert_eq!(options.file_name, Some("lint.wr".to_string()));
+        // Checking runs nothing, so the division by zero at runtime is not reached
+        let (exit_code, diagnostics) = run_diagnosed("var int z = 0; print(1 / z); x;", &options);
+        assert_eq!(exit_code, 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].stage, Stage::Type);
+        assert_eq!(diagnostics[0].file, Some("lint.wr".to_string()));
+    }
+
+    #[test]
+    fn logged_error_fails_the_run() {
+        let program = "log_error(\"lost a row\"); log_debug(\"not written\");";
+        assert_eq!(run(program, &RunOptions::default()), 1);
+        let args: Vec<String> = ["job.wr", "--log-level", "debug", "--no-fail-on-error-log"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let (_, options) = parse_args(&args).unwrap();
+        assert_eq!(options.log_level, LogLevel::Debug);
+        assert_eq!(run(program, &options), 0);
+        assert_eq!(
+            parse_args(&[
+                "job.wr".to_string(),
+                "--log-level".to_string(),
+                "loud".to_string()
+            ])
+            .err(),
+            Some("unknown log level 'loud', expected debug, info, warn or error".to_string())
+        );
+    }
+
+    #[test]
+    fn source_limits_from_the_command_line() {
+        let args: Vec<String> = [
+            "wide.wr",
+            "--max-columns",
+            "5000",
+            "--max-string-length",
+            "10",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let (_, options) = parse_args(&args).unwrap();
+        assert_eq!(options.source_limits.max_columns, 5000);
+        assert_eq!(options.source_limits.max_string_length, 10);
+        assert_eq!(options.source_limits.max_parameters, 255);
+    }
+
+    #[test]
+    fn coverage_is_written_to_the_file_given() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("coverage.json");
+        let args: Vec<String> = vec![
+            "legacy.wr".to_string(),
+            "--coverage".to_string(),
+            file.display().to_string(),
+        ];
+        let (_, options) = parse_args(&args).unwrap();
+        assert_eq!(run("var int x = 1; x = 2;", &options), 0);
+        let json = std::fs::read_to_string(&file).unwrap();
+        assert!(json.ends_with("\"executed\":2,\"total\":2,\"functions_never_called\":0}"));
+        assert_eq!(
+            parse_args(&["legacy.wr".to_string(), "--coverage".to_string()]).err(),
+            Some("--coverage expects a file to write the counts to".to_string())
+        );
+    }
+
+    #[test]
+    fn exit_code_from_main_out_of_range_is_a_runtime_error() {
+        let (exit_code, diagnostics) =
+            run_diagnosed("fn int main() { return 300; };", &RunOptions::default());
+        assert_eq!(exit_code, 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].stage, Stage::Runtime);
+        assert_eq!(
+            diagnostics[0].message,
+            "Interpretation error: main() returned 300, but an exit code must be from 0 to 255"
+        );
+        let (exit_code, _) =
+            run_diagnosed("fn int main() { return 255; };", &RunOptions::default());
+        assert_eq!(exit_code, 255);
+        let (exit_code, _) = run_diagnosed("fn int main() { return -1; };", &RunOptions::default());
+        assert_eq!(exit_code, 1);
+    }
+
+    #[test]
+    fn runtime_error_is_a_diagnostic() {
+        let (exit_code, diagnostics) =
+            run_diagnosed("var int[] xs = [1]; print(xs[3]);", &RunOptions::default());
+        assert_eq!(exit_code, 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].stage, Stage::Runtime);
+        assert!(diagnostics[0].message.contains("out of bounds"));
+        // The error is placed at the statement it happened in
+        let start = diagnostics[0].start.as_ref().unwrap();
+        assert_eq!((start.line, start.column), (1, 21));
+    }
+
+    #[test]
+    fn every_invalid_token_is_reported_before_parsing() {
+        let input = "var int x = 1 @ 2;\nvar int y = #;\nprint(x $ y);";
+        let (syntax_tree, diagnostics) = parse_program(input, false);
+        assert!(syntax_tree.is_none());
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid token '@' at line 1, column 15",
+                "Invalid token '#' at line 2, column 13",
+                "Invalid token '$' at line 3, column 9",
+            ]
+        );
+        // None of the diagnostics come from parsing, which the invalid tokens would have failed
+        assert!(diagnostics.iter().all(|d| d.stage == Stage::Lex));
+    }
+
+    #[test]
+    fn literal_out_of_range_is_a_diagnostic() {
+        let (syntax_tree, diagnostics) = parse_program("99999999999999999999;", false);
+        assert!(syntax_tree.is_none());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].stage, Stage::Lex);
+        assert_eq!(
+            diagnostics[0].message,
+            "Invalid token '99999999999999999999' at line 1, column 1: integer literal out of range for int"
+        );
+    }
+
+    #[test]
+    fn lenient_lexing_leaves_invalid_tokens_out() {
+        let errors = super::lex("var int x = 1; @", false).unwrap_err();
+        assert_eq!(
+            errors,
+            [LexError {
+                span: 15..16,
+                slice: "@".to_string(),
+                kind: LexErrorKind::Unrecognized,
+            }]
+        );
+        let (syntax_tree, diagnostics) = parse_program("var int x = 1; @", true);
+        assert!(syntax_tree.is_some());
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn command_line_values_are_typed_when_unambiguous() {
+        let value = |arg: &str| super::parse_var_arg(arg, false).map(|(_, v)| v);
+        assert_eq!(value("x=5"), Ok(ExpressionValue::Number(5)));
+        assert_eq!(value("x=2.5"), Ok(ExpressionValue::Double(2.5)));
+        assert_eq!(value("x=true"), Ok(ExpressionValue::Bool(true)));
+        assert_eq!(
+            value("x=data.csv"),
+            Ok(ExpressionValue::String("data.csv".into()))
+        );
+        assert_eq!(value("x=inf"), Ok(ExpressionValue::String("inf".into())));
+        assert_eq!(
+            super::parse_var_arg("x=5", true),
+            Ok(("x".to_string(), ExpressionValue::String("5".into())))
+        );
+        assert_eq!(
+            value("1x=5"),
+            Err("'1x' is not a valid variable name".to_string())
+        );
+    }
+
+    #[test]
+    fn host_variable_declared_by_the_program_is_rejected() {
+        let syntax_tree =
+            create_syntax_tree("var int x = 1; while (x < 3) { const int count = x; x = x + 1; }")
+                .unwrap();
+        let options = RunOptions::default().with_var("count", ExpressionValue::Number(3));
+        assert_eq!(
+            check_with_warnings(&syntax_tree, &options),
+            Err(vec![
+                "'count' is given by the host, and cannot also be declared in the program"
+                    .to_string()
+                    .into()
+            ])
+        );
+        // Functions have their own environment, so they may use the name
+        let syntax_tree =
+            create_syntax_tree("fn int f() { var int count = 1; return count; }; var int y = f();")
+                .unwrap();
+        assert_eq!(check_with_warnings(&syntax_tree, &options), Ok(vec![]));
+    }
+
+    #[test]
+    fn host_variable_declared_in_a_defer_is_rejected() {
+        let syntax_tree =
+            create_syntax_tree("defer { var int count = 1; print(count); } print(2);").unwrap();
+        let options = RunOptions::default().with_var("count", ExpressionValue::Number(3));
+        assert_eq!(
+            check_with_warnings(&syntax_tree, &options),
+            Err(vec![
+                "'count' is given by the host, and cannot also be declared in the program"
+                    .to_string()
+                    .into()
+            ])
+        );
+    }
+
+    #[test]
+    fn host_variable_with_the_name_of_a_builtin_is_rejected() {
+        let syntax_tree = create_syntax_tree("var int x = 1;").unwrap();
+        let options = RunOptions::default().with_var("print", ExpressionValue::Number(3));
+        let errors = check_with_warnings(&syntax_tree, &options).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "host variable 'print' has the name of a builtin"
+        );
+        assert_eq!(errors[0].code, Some(ErrorCode::BuiltinRedefined));
+    }
+
+    #[test]
+    fn stripped_full_token_stream_is_what_the_parser_lexes() {
+        let input = "// sum\nfn int add(int a, int b) { // two ints\n    return a + b; // the sum\n};\r\nprint(add(1, 2.5)); // done";
+        assert_eq!(
+            strip_trivia(&lex_full(input)),
+            super::lex(input, false).unwrap()
+        );
+    }
 }
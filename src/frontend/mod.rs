@@ -1,4 +1,11 @@
 pub mod ast;
+pub mod diagnostics;
+pub mod enums;
 pub mod lexer;
+pub mod limits;
 pub mod main;
+pub mod printer;
+pub mod source;
+pub mod test_runner;
 pub mod typecheck;
+pub mod version;
@@ -0,0 +1,390 @@
+use super::ast::{
+    ColumnAssignmentEnum, Declaration, Expr, LAMBDA_STAGE, Operator, Parameter, Statement,
+    parameter_list,
+};
+
+/*
+ * This file writes a syntax tree back out as wrench source, so programs made or changed by other programs can be saved
+ * and run. Parsing what is written gives the same syntax tree, see to_source
+ */
+
+// Spaces each level of blocks is indented with
+const INDENT: &str = "    ";
+
+// The precedence levels of the grammar, see Expr in grammar.lalrpop. A lower level binds tighter
+const TERM: u8 = 0;
+const EXPONENT: u8 = 1;
+const FACTOR: u8 = 2;
+const SUM: u8 = 3;
+const COMPARISON: u8 = 4;
+const NOT: u8 = 5;
+const AND: u8 = 6;
+const OR: u8 = 7;
+
+// The program as wrench source, with a statement on each line and blocks indented
+// The operators the parser turns into others, e.g. a > b into !(a <= b), are written as they were, so the source reads
+// like a person wrote it. Parsing the source gives a syntax tree equal to the one written
+pub fn to_source(statement: &Statement) -> String {
+    let mut source = String::new();
+    write_statements(statement, 0, &mut source);
+    source
+}
+
+// Helper function to write each statement of a chain of statements on its own line
+// The chain is walked in a loop, so long programs do not grow the stack
+fn write_statements(statement: &Statement, depth: usize, source: &mut String) {
+    let mut rest = statement;
+    loop {
+        match rest {
+            Statement::Compound(first, next) => {
+                write_statement(first, depth, source);
+                rest = next;
+            }
+            last => {
+                write_statement(last, depth, source);
+                return;
+            }
+        }
+    }
+}
+
+fn write_statement(statement: &Statement, depth: usize, source: &mut String) {
+    let line = match statement {
+        Statement::Skip => return,
+        Statement::Compound(_, _) => return write_statements(statement, depth, source),
+        Statement::Counted(_, statement) | Statement::Located(_, statement) => {
+            return write_statement(statement, depth, source);
+        }
+        Statement::Expr(expr) => format!("{};", expr_source(expr, depth)),
+        Statement::VariableAssignment(name, expr) => {
+            format!("{} = {};", name, expr_source(expr, depth))
+        }
+        Statement::FieldAssignment(name, column, expr) => {
+            format!("{}.{} = {};", name, column, expr_source(expr, depth))
+        }
+        Statement::IndexAssignment(name, index, expr) => format!(
+            "{}[{}] = {};",
+            name,
+            expr_source(index, depth),
+            expr_source(expr, depth)
+        ),
+        Statement::Declaration(Declaration::Variable(t, name, expr)) => {
+            format!("var {} {} = {};", t, name, expr_source(expr, depth))
+        }
+        Statement::Declaration(Declaration::Constant(t, name, expr)) => {
+            format!("const {} {} = {};", t, name, expr_source(expr, depth))
+        }
+        Statement::Declaration(Declaration::Function(t, name, params, body)) => format!(
+            "fn {} {}({}) {};",
+            t,
+            name,
+            parameter_list(params),
+            block(body, depth)
+        ),
+        // return; is parsed as a return of null, and written back the way it is usually written
+        Statement::Return(expr) if **expr == Expr::Null => "return;".to_string(),
+        Statement::Return(expr) => format!("return {};", expr_source(expr, depth)),
+        Statement::Break => "break;".to_string(),
+        Statement::Continue => "continue;".to_string(),
+        Statement::If(condition, body, else_body) => {
+            let mut line = format!(
+                "if ({}) {}",
+                expr_source(condition, depth),
+                block(body, depth)
+            );
+            // An if with no else has a skip in its place, as does one with an empty else
+            if **else_body != Statement::Skip {
+                line.push_str(" else ");
+                line.push_str(&block(else_body, depth));
+            }
+            line
+        }
+        Statement::For(index, Parameter::Parameter(t, name), iterable, body) => {
+            let index = match index {
+                Some(Parameter::Parameter(index_type, index_name)) => {
+                    format!("{} {}, ", index_type, index_name)
+                }
+                None => String::new(),
+            };
+            format!(
+                "for ({}{} {} in {}) {}",
+                index,
+                t,
+                name,
+                expr_source(iterable, depth),
+                block(body, depth)
+            )
+        }
+        Statement::While(condition, body) => format!(
+            "while ({}) {}",
+            expr_source(condition, depth),
+            block(body, depth)
+        ),
+        Statement::Defer(body) => format!("defer {}", block(body, depth)),
+        Statement::EnumDeclaration(name, values) => {
+            let values: Vec<String> = values
+                .iter()
+                .map(|value| format!("\"{}\"", value))
+                .collect();
+            format!("enum {} = [{}];", name, values.join(", "))
+        }
+    };
+    source.push_str(&INDENT.repeat(depth));
+    source.push_str(&line);
+    source.push('\n');
+}
+
+// Helper function to write a block in braces, with its statements indented one level deeper than the line it starts on
+fn block(body: &Statement, depth: usize) -> String {
+    let mut statements = String::new();
+    write_statements(body, depth + 1, &mut statements);
+    if statements.is_empty() {
+        return "{}".to_string();
+    }
+    format!("{{\n{}{}}}", statements, INDENT.repeat(depth))
+}
+
+// The source of an expression, where depth is how far the line it is on is indented, for the blocks of lambdas in it
+fn expr_source(expr: &Expr, depth: usize) -> String {
+    expr_with_level(expr, depth).0
+}
+
+// Helper function to write an expression that must bind at least as tightly as the level, in parentheses if it does not
+fn operand(expr: &Expr, level: u8, depth: usize) -> String {
+    let (source, expr_level) = expr_with_level(expr, depth);
+    if expr_level > level {
+        format!("({})", source)
+    } else {
+        source
+    }
+}
+
+// Helper function to write a list of expressions separated by commas, e.g. the arguments of a call
+fn expr_list(exprs: &[Box<Expr>], depth: usize) -> String {
+    exprs
+        .iter()
+        .map(|expr| expr_source(expr, depth))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// The source of an expression with the precedence level of its outermost operator
+fn expr_with_level(expr: &Expr, depth: usize) -> (String, u8) {
+    let binary = |left: &Expr, symbol: &str, right: &Expr, level: u8| {
+        let source = format!(
+            "{} {} {}",
+            operand(left, level, depth),
+            symbol,
+            operand(right, level - 1, depth)
+        );
+        (source, level)
+    };
+    match expr {
+        Expr::Number(n) => (n.to_string(), TERM),
+        // A double is always written with a decimal point, so it is not read back as an int
+        Expr::Double(d) if d.fract() == 0.0 => (format!("{:.1}", d), TERM),
+        Expr::Double(d) => (d.to_string(), TERM),
+        Expr::Null => ("null".to_string(), TERM),
+        // The escapes of a string literal are kept as they were written, see parse_string
+        Expr::StringLiteral(s) => (format!("\"{}\"", s), TERM),
+        Expr::Char(c) => (char_literal(*c), TERM),
+        Expr::Identifier(name) => (name.clone(), TERM),
+        Expr::Bool(b) => (b.to_string(), TERM),
+        // Sugar the parser desugared, see ast_and, ast_greater_than, ast_greater_than_or_equal and ast_not_equals
+        Expr::Not(inner) => match &**inner {
+            Expr::Operation(left, Operator::Or, right) => match (&**left, &**right) {
+                (Expr::Not(left), Expr::Not(right)) => binary(left, "and", right, AND),
+                _ => (format!("!{}", operand(inner, NOT, depth)), NOT),
+            },
+            Expr::Operation(left, Operator::LessThan, right) => {
+                binary(left, ">=", right, COMPARISON)
+            }
+            Expr::Operation(left, Operator::LessThanOrEqual, right) => {
+                binary(left, ">", right, COMPARISON)
+            }
+            Expr::Operation(left, Operator::Equals, right) => binary(left, "!=", right, COMPARISON),
+            _ => (format!("!{}", operand(inner, NOT, depth)), NOT),
+        },
+        // ** is the one operator grouping to the right, so 2 ** 3 ** 2 is 2 ** (3 ** 2)
+        Expr::Operation(left, Operator::Exponent, right) => (
+            format!(
+                "{} ** {}",
+                operand(left, TERM, depth),
+                operand(right, EXPONENT, depth)
+            ),
+            EXPONENT,
+        ),
+        Expr::Operation(left, op, right) => {
+            let level = match op {
+                Operator::Multiplication | Operator::Division | Operator::Modulo => FACTOR,
+                Operator::Addition | Operator::Subtraction => SUM,
+                Operator::Equals | Operator::LessThan | Operator::LessThanOrEqual => COMPARISON,
+                Operator::Or => OR,
+                Operator::Exponent => EXPONENT,
+            };
+            binary(left, op.symbol(), right, level)
+        }
+        Expr::Negation(inner) => (format!("-{}", operand(inner, EXPONENT, depth)), EXPONENT),
+        Expr::Table(columns, defaults, rows) => {
+            let columns: Vec<String> = columns
+                .iter()
+                .map(|column| {
+                    let Parameter::Parameter(t, name) = column;
+                    match defaults
+                        .iter()
+                        .find(|(default_name, _)| default_name == name)
+                    {
+                        Some((_, default)) => {
+                            format!("{} {} = {}", t, name, expr_source(default, depth))
+                        }
+                        None => parameter_list(std::slice::from_ref(column)),
+                    }
+                })
+                .collect();
+            let rows = if rows.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", expr_list(rows, depth))
+            };
+            (format!("table({}){}", columns.join(", "), rows), TERM)
+        }
+        Expr::Row(columns) => {
+            let columns: Vec<String> = columns
+                .iter()
+                .map(|ColumnAssignmentEnum::ColumnAssignment(t, name, value)| {
+                    format!("{} {} = {}", t, name, expr_source(value, depth))
+                })
+                .collect();
+            (format!("row({})", columns.join(", ")), TERM)
+        }
+        // Brackets right after a table expression hold its rows, so it is put in parentheses to be indexed
+        Expr::Indexing(array, index) => {
+            let array = match **array {
+                Expr::Table(..) => format!("({})", expr_source(array, depth)),
+                _ => operand(array, TERM, depth),
+            };
+            (format!("{}[{}]", array, expr_source(index, depth)), TERM)
+        }
+        Expr::Array(elements) => (format!("[{}]", expr_list(elements, depth)), TERM),
+        Expr::Pipe(left, name, args) => match args.as_slice() {
+            [lambda] if name == LAMBDA_STAGE => (
+                format!(
+                    "{} pipe ({})",
+                    operand(left, TERM, depth),
+                    expr_source(lambda, depth)
+                ),
+                TERM,
+            ),
+            _ => (
+                format!(
+                    "{} pipe {}({})",
+                    operand(left, TERM, depth),
+                    name,
+                    expr_list(args, depth)
+                ),
+                TERM,
+            ),
+        },
+        Expr::FunctionCall(name, args) => (format!("{}({})", name, expr_list(args, depth)), TERM),
+        Expr::ColumnIndexing(row, column) => {
+            (format!("{}.{}", operand(row, TERM, depth), column), TERM)
+        }
+        // Ranges are only parsed as what a for-loop iterates, where they are the whole expression
+        Expr::Range(start, end, inclusive) => (
+            format!(
+                "{}{}{}",
+                expr_source(start, depth),
+                if *inclusive { "..=" } else { ".." },
+                expr_source(end, depth)
+            ),
+            OR,
+        ),
+        Expr::Lambda(params, return_type, body) => (
+            format!(
+                "fn ({}) {} {}",
+                parameter_list(params),
+                return_type,
+                block(body, depth)
+            ),
+            TERM,
+        ),
+    }
+}
+
+// Helper function to write a char literal, with the characters the lexer reads escaped escaped again, see parse_char
+fn char_literal(c: char) -> String {
+    match c {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        '\0' => "'\\0'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        c => format!("'{}'", c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::main::create_syntax_tree;
+
+    // Parses the program, writes it back out and checks the source parses to the same syntax tree, returning the source
+    fn round_trip(program: &str) -> String {
+        let syntax_tree = create_syntax_tree(program).unwrap();
+        let source = to_source(&syntax_tree);
+        assert_eq!(
+            create_syntax_tree(&source).unwrap(),
+            syntax_tree,
+            "{}",
+            source
+        );
+        source
+    }
+
+    #[test]
+    fn test_blocks_are_indented() {
+        assert_eq!(
+            round_trip(
+                "fn int f(int x) { if (x < 0) { return 0; } else { while (x > 9) { x = x / 10; } } return x; };"
+            ),
+            "fn int f(int x) {\n    if (x < 0) {\n        return 0;\n    } else {\n        while (x > 9) {\n            x = x / 10;\n        }\n    }\n    return x;\n};\n"
+        );
+        assert_eq!(
+            round_trip("while (true) {} defer {}"),
+            "while (true) {}\ndefer {}\n"
+        );
+    }
+
+    #[test]
+    fn test_desugared_operators_are_written_as_they_were() {
+        assert_eq!(
+            round_trip("a and b or !c; x > 1; x >= 1; x != 1;"),
+            "a and b or !c;\nx > 1;\nx >= 1;\nx != 1;\n"
+        );
+    }
+
+    #[test]
+    fn test_parentheses_only_where_needed() {
+        assert_eq!(
+            round_trip(
+                "(1 + 2) * 3 - (4 - 5); 2 ** 3 ** 2; (2 ** 3) ** 2; (-x) ** 2; -x ** 2; !(a == b and c);"
+            ),
+            "(1 + 2) * 3 - (4 - 5);\n2 ** 3 ** 2;\n(2 ** 3) ** 2;\n(-x) ** 2;\n-x ** 2;\n!(a == b and c);\n"
+        );
+        assert_eq!(
+            round_trip("(a or b) and c; (x + 1).id; (t pipe filter(f))[0];"),
+            "(a or b) and c;\n(x + 1).id;\nt pipe filter(f)[0];\n"
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(
+            round_trip(
+                "var double d = 2.0; print(\"a \\\"b\\\"\\n\", '\\n', '\\'', 'x', 0.25, null, [1, 2]);"
+            ),
+            "var double d = 2.0;\nprint(\"a \\\"b\\\"\\n\", '\\n', '\\'', 'x', 0.25, null, [1, 2]);\n"
+        );
+    }
+}
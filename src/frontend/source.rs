@@ -0,0 +1,160 @@
+/*
+ * This file deals with mapping byte offsets in the source code, as reported by the lexer and parser, to lines and columns
+ */
+
+// Characters of a line a snippet shows, and how many it shows after the offset marked on longer lines
+const SNIPPET_WIDTH: usize = 120;
+const SNIPPET_CONTEXT: usize = 40;
+
+// A position in the source code. Lines and columns start at 1, and columns count characters, so a tab is one column
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+// Built once from the input, and used to look up the line and column of any byte offset in it
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    source: String,
+    // Byte offset of the first character of each line
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        for (i, byte) in source.bytes().enumerate() {
+            if byte == b'\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        SourceMap {
+            source: source.to_string(),
+            line_starts,
+        }
+    }
+
+    // The location of a byte offset. Offsets past the end are placed at the end of the source,
+    // and offsets inside a multi-byte character at the start of it
+    pub fn location(&self, offset: usize) -> SourceLocation {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line_index = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line_index];
+        SourceLocation {
+            line: line_index + 1,
+            column: self.source[line_start..offset].chars().count() + 1,
+        }
+    }
+
+    // The text of a line, without its line ending. Lines start at 1
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.source.len());
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    // The location of an offset as written in error messages, e.g. line 3, column 5
+    pub fn describe(&self, offset: usize) -> String {
+        let location = self.location(offset);
+        format!("line {}, column {}", location.line, location.column)
+    }
+
+    // The line an offset is on, with a ^ under the character at the offset
+    // Tabs before the offset are repeated in the marker line, so it lines up however wide tabs are shown
+    // A long line is cut off a little after the offset, so a line holding e.g. a huge string literal is not shown whole
+    pub fn snippet(&self, offset: usize) -> String {
+        let location = self.location(offset);
+        let mut text = self.line_text(location.line).to_string();
+        let shown = SNIPPET_WIDTH.max(location.column + SNIPPET_CONTEXT);
+        if let Some((cut, _)) = text.char_indices().nth(shown) {
+            text.truncate(cut);
+            text.push_str("...");
+        }
+        let marker: String = text
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!("{}\n{}^", text, marker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lines end in CRLF, the second line is indented with a tab, and the third has multi-byte characters
+    const FIXTURE: &str =
+        "var int x = 5;\r\n\tx = x + 1;\r\nvar string s = \"æøå\"; print(s);\nreturn x;";
+
+    fn location(line: usize, column: usize) -> SourceLocation {
+        SourceLocation { line, column }
+    }
+
+    #[test]
+    fn test_locations_on_first_line() {
+        let map = SourceMap::new(FIXTURE);
+        assert_eq!(map.location(0), location(1, 1));
+        assert_eq!(map.location(4), location(1, 5));
+        // The carriage return is still part of the first line
+        assert_eq!(map.location(14), location(1, 15));
+    }
+
+    #[test]
+    fn test_tab_counts_as_one_column() {
+        let map = SourceMap::new(FIXTURE);
+        let x = FIXTURE.find("\tx").unwrap() + 1;
+        assert_eq!(map.location(x), location(2, 2));
+    }
+
+    #[test]
+    fn test_columns_count_characters_not_bytes() {
+        let map = SourceMap::new(FIXTURE);
+        let print = FIXTURE.find("print").unwrap();
+        assert_eq!(map.location(print), location(3, 23));
+        // An offset inside 'ø' is placed at its start
+        let o = FIXTURE.find('ø').unwrap();
+        assert_eq!(map.location(o + 1), map.location(o));
+    }
+
+    #[test]
+    fn test_line_text_without_line_endings() {
+        let map = SourceMap::new(FIXTURE);
+        assert_eq!(map.line_text(1), "var int x = 5;");
+        assert_eq!(map.line_text(2), "\tx = x + 1;");
+        assert_eq!(map.line_text(4), "return x;");
+    }
+
+    #[test]
+    fn test_offset_past_end() {
+        let map = SourceMap::new(FIXTURE);
+        assert_eq!(map.location(FIXTURE.len() + 10), location(4, 10));
+        assert_eq!(SourceMap::new("").location(3), location(1, 1));
+    }
+
+    #[test]
+    fn test_snippet_marks_column() {
+        let map = SourceMap::new(FIXTURE);
+        let plus = FIXTURE.find('+').unwrap();
+        assert_eq!(map.snippet(plus), "\tx = x + 1;\n\t      ^");
+        assert_eq!(map.describe(plus), "line 2, column 8");
+    }
+
+    #[test]
+    fn test_snippet_cuts_off_long_lines() {
+        let line = format!("var string s = \"{}\";", "a".repeat(1000));
+        let snippet = SourceMap::new(&line).snippet(15);
+        let (text, marker) = snippet.split_once('\n').unwrap();
+        assert_eq!(text.chars().count(), 123);
+        assert!(text.ends_with("aaa..."));
+        assert_eq!(marker, format!("{}^", " ".repeat(15)));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+};
+
+use crate::backend::{evaluate::interpret, library::record_assertion_failures};
+
+use super::main::{create_syntax_tree, human_messages, type_errors};
+
+//Test scripts are the files in the directory, or a directory below it, whose names end with this
+pub const TEST_FILE_SUFFIX: &str = "_test.wr";
+
+//What running one test script came to
+#[derive(Debug, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    //The script ran, but asserts or fails in it failed, or it exited with a code other than 0
+    Failed(Vec<String>),
+    //The script could not be read, parsed or type checked, or it stopped with a runtime error
+    Error(String),
+}
+
+//The outcome of a test script, with the path it was read from
+#[derive(Debug)]
+pub struct TestReport {
+    pub file: String,
+    pub outcome: TestOutcome,
+}
+
+//Finds the test scripts in a directory and the directories below it, sorted by path so runs are reported in the same order
+pub fn find_test_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| format!("Cannot read directory '{}': {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Cannot read directory '{}': {}", dir.display(), e))?
+                .path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(TEST_FILE_SUFFIX))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+//Runs a test script in a fresh environment. A failing assert or fail is recorded and the script goes on,
+//while anything stopping it, from a parse error to a runtime error, makes the outcome an error
+pub fn run_test_file(path: &Path) -> TestOutcome {
+    let input = match fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(e) => return TestOutcome::Error(format!("Error reading file: {}", e)),
+    };
+    let syntax_tree = match create_syntax_tree(&input) {
+        Ok(syntax_tree) => syntax_tree,
+        Err(diagnostics) => return TestOutcome::Error(human_messages(&diagnostics)),
+    };
+    let errors = type_errors(&syntax_tree, &[]);
+    if !errors.is_empty() {
+        return TestOutcome::Error(format!("Type checking failed: {}", errors.join("\n")));
+    }
+    let (result, mut failures) =
+        record_assertion_failures(AssertUnwindSafe(|| interpret(syntax_tree, vec![], false)));
+    match result {
+        Err(message) => TestOutcome::Error(message),
+        Ok(code) => {
+            if code != 0 {
+                failures.push(format!("Exited with code {}", code));
+            }
+            if failures.is_empty() {
+                TestOutcome::Passed
+            } else {
+                TestOutcome::Failed(failures)
+            }
+        }
+    }
+}
+
+//Runs every test script found in the directory, one after another
+pub fn run_test_dir(dir: &Path) -> Result<Vec<TestReport>, String> {
+    let files = find_test_files(dir)?;
+    if files.is_empty() {
+        return Err(format!(
+            "No test scripts ending with '{}' in '{}'",
+            TEST_FILE_SUFFIX,
+            dir.display()
+        ));
+    }
+    Ok(files
+        .into_iter()
+        .map(|file| TestReport {
+            outcome: run_test_file(&file),
+            file: file.display().to_string(),
+        })
+        .collect())
+}
+
+//A line for each script with its failures below it, and a line counting the outcomes
+pub fn test_summary(reports: &[TestReport]) -> String {
+    let mut lines = Vec::new();
+    let (mut passed, mut failed, mut errors) = (0, 0, 0);
+    for report in reports {
+        match &report.outcome {
+            TestOutcome::Passed => {
+                passed += 1;
+                lines.push(format!("PASS  {}", report.file));
+            }
+            TestOutcome::Failed(failures) => {
+                failed += 1;
+                lines.push(format!("FAIL  {}", report.file));
+                lines.extend(failures.iter().map(|failure| format!("      {}", failure)));
+            }
+            TestOutcome::Error(message) => {
+                errors += 1;
+                lines.push(format!("ERROR {}", report.file));
+                lines.extend(message.lines().map(|line| format!("      {}", line)));
+            }
+        }
+    }
+    lines.push(format!(
+        "{} passed, {} failed, {} errors",
+        passed, failed, errors
+    ));
+    lines.join("\n")
+}
+
+//The exit code of a test run, 1 if any script failed or had an error
+pub fn test_exit_code(reports: &[TestReport]) -> i32 {
+    if reports
+        .iter()
+        .all(|report| report.outcome == TestOutcome::Passed)
+    {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory with a passing, a failing and an unparsable test script, and a script that is not a test
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            (
+                "sums_test.wr",
+                "var int total = 1 + 2;\nassert(total == 3, \"1 + 2 is 3\");\n",
+            ),
+            (
+                "strings_test.wr",
+                "assert(str_len(\"abc\") == 2, \"abc has 2 characters\");\nfail(\"not done\");\nassert(true, \"still checked\");\n",
+            ),
+            ("broken_test.wr", "var int x = ;\n"),
+            ("helper.wr", "fail(\"not a test\");\n"),
+        ];
+        for (name, source) in files {
+            fs::write(dir.path().join(name), source).unwrap();
+        }
+        dir
+    }
+
+    fn outcome<'a>(reports: &'a [TestReport], name: &str) -> &'a TestOutcome {
+        &reports
+            .iter()
+            .find(|report| report.file.ends_with(name))
+            .unwrap()
+            .outcome
+    }
+
+    #[test]
+    fn test_passing_and_failing_scripts() {
+        let dir = fixture();
+        let reports = run_test_dir(dir.path()).unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(outcome(&reports, "sums_test.wr"), &TestOutcome::Passed);
+        assert_eq!(
+            outcome(&reports, "strings_test.wr"),
+            &TestOutcome::Failed(vec![
+                "Assertion failed: abc has 2 characters".to_string(),
+                "Failed: not done".to_string(),
+            ])
+        );
+        assert_eq!(test_exit_code(&reports), 1);
+        let summary = test_summary(&reports);
+        assert!(
+            summary.ends_with("1 passed, 1 failed, 1 errors"),
+            "{}",
+            summary
+        );
+        assert!(summary.contains("      Failed: not done"), "{}", summary);
+    }
+
+    #[test]
+    fn test_parse_error_is_reported_as_an_error() {
+        let dir = fixture();
+        let reports = run_test_dir(dir.path()).unwrap();
+        let TestOutcome::Error(message) = outcome(&reports, "broken_test.wr") else {
+            panic!("the script does not parse");
+        };
+        assert!(message.contains("line 1, column 13"), "{}", message);
+    }
+
+    #[test]
+    fn test_all_passing_exits_with_zero() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(
+            dir.path().join("nested").join("ok_test.wr"),
+            "assert(true, \"true\");",
+        )
+        .unwrap();
+        let reports = run_test_dir(dir.path()).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(test_exit_code(&reports), 0);
+        assert!(run_test_dir(&dir.path().join("nested").join("none")).is_err());
+    }
+}
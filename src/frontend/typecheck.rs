@@ -1,18 +1,367 @@
 // Import HashMap to keep track of variable types and their types
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+};
+// Import the builtin registry, for the optional parameters and reserved names of builtins
+use crate::backend::library::lookup_builtin;
+// Import the error codes and the suggestions for names that are not found
+use super::diagnostics::{
+    ErrorCode, did_you_mean, pipe_left_is_function_message, pipe_right_is_table_message,
+    unknown_column_message,
+};
+// Import the limits on what a program declares
+use super::limits::{over_limit_message, source_limits};
 // Import the AST types
 use super::ast::{
-    ColumnAssignmentEnum, Declaration, Expr, Operator, Parameter, Statement, TypeConstruct,
-    TypedExpr,
+    ColumnAssignmentEnum, Declaration, Expr, LAMBDA_STAGE, Operator, Parameter, Span, Statement,
+    TypeConstruct, TypedExpr,
 };
 
+thread_local! {
+    // Warnings found while type checking. They do not stop the check, and are collected until taken with take_warnings
+    static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    // How many loops the statement being checked is in, so a break outside of them is found
+    static LOOP_DEPTH: Cell<usize> = const { Cell::new(0) };
+    // The span of the innermost statement the error being returned was found in, see TypeError
+    static ERROR_SPAN: Cell<Option<Span>> = const { Cell::new(None) };
+}
+
+// An error found type checking a program, with the span of the innermost statement it was found in
+// Errors of a kind explained by wrench --explain have its code, given where the error is made
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    pub code: Option<ErrorCode>,
+    pub span: Option<Span>,
+}
+
+impl TypeError {
+    fn coded(code: ErrorCode, message: String) -> Self {
+        TypeError {
+            code: Some(code),
+            ..TypeError::from(message)
+        }
+    }
+}
+
+// An error without a code, e.g. about the variables given by the host
+impl From<String> for TypeError {
+    fn from(message: String) -> Self {
+        TypeError {
+            message,
+            code: None,
+            span: None,
+        }
+    }
+}
+
+// Helper function to record a warning found while type checking
+fn warn(message: String) {
+    WARNINGS.with(|warnings| warnings.borrow_mut().push(message));
+}
+
+// Returns the warnings found since they were last taken
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS.with(|warnings| warnings.take())
+}
+
+// Helper function to type check a statement at a loop depth, e.g. 0 for a function body, as a break there cannot leave
+// a loop the function is called in. The depth is restored afterwards, also when the check fails
+fn type_check_at_loop_depth(
+    depth: usize,
+    body: &Statement,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<(), TypeError> {
+    let outer = LOOP_DEPTH.with(|current| current.replace(depth));
+    let result = type_check(body, scope_stack);
+    LOOP_DEPTH.with(|current| current.set(outer));
+    result
+}
+
+// Helper function to type check the body of a function or lambda, which sees its parameters and the functions
+// declared before it in its own scope or one around it, but none of the variables besides those captured, see lambda_captures
+fn type_check_function_body(
+    return_type: &TypeConstruct,
+    params: &[Parameter],
+    body: &Statement,
+    scope_stack: &[HashMap<String, VariableInfo>],
+    captured: HashMap<String, VariableInfo>,
+) -> Result<(), TypeError> {
+    // Create a scope for the function parameters
+    let mut param_scope = HashMap::new();
+    for Parameter::Parameter(param_type, param_name) in params {
+        check_not_builtin(param_name)?;
+        param_scope.insert(
+            param_name.clone(),
+            VariableInfo {
+                var_type: param_type.clone(),
+                is_constant: false,
+                value: None,
+                is_table_loop_row: false,
+            },
+        );
+    }
+
+    // Preserve previously declared functions, where one in an inner scope hides one of the same name further out
+    let mut function_scope = HashMap::new();
+    for (k, v) in scope_stack.iter().flatten() {
+        if matches!(v.var_type, TypeConstruct::Function(_, _)) {
+            function_scope.insert(k.clone(), v.clone());
+        }
+    }
+    function_scope.extend(captured);
+
+    let mut function_scope_stack = vec![function_scope, param_scope];
+    type_check_at_loop_depth(0, body, &mut function_scope_stack)?;
+
+    // A function returning null may end without a return, but any other has to return its value
+    if *return_type != TypeConstruct::Null && !always_returns(body) {
+        return Err(TypeError::coded(
+            ErrorCode::ReturnTypeMismatch,
+            format!(
+                "Return type mismatch: expected {:?}, but the end of the function can be reached without a return",
+                return_type
+            ),
+        ));
+    }
+
+    // Validate return type
+    validate_return_type(body, return_type, &mut function_scope_stack)
+}
+
+// The variables a lambda captures: the ints, doubles, strings and bools in scope where it is made, as constants.
+// They are captured by value, like the interpreter does in capture_values, so the lambda cannot assign them
+fn lambda_captures(scope_stack: &[HashMap<String, VariableInfo>]) -> HashMap<String, VariableInfo> {
+    let mut captured = HashMap::new();
+    for (name, info) in scope_stack.iter().flatten() {
+        match info.var_type {
+            TypeConstruct::Int
+            | TypeConstruct::Double
+            | TypeConstruct::String
+            | TypeConstruct::Bool => {
+                captured.insert(
+                    name.clone(),
+                    VariableInfo {
+                        is_constant: true,
+                        ..info.clone()
+                    },
+                );
+            }
+            // A variable of another type hides one of the same name further out
+            _ => {
+                captured.remove(name);
+            }
+        }
+    }
+    captured
+}
+
+// Helper function to type check the body of a loop, where break and continue may be used
+fn type_check_loop_body(
+    body: &Statement,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<(), TypeError> {
+    type_check_at_loop_depth(LOOP_DEPTH.with(Cell::get) + 1, body, scope_stack)
+}
+
+// Builtins called for what they do rather than what they return, so their result may be thrown away
+const SIDE_EFFECT_BUILTINS: [&str; 7] = [
+    "print",
+    "print_full",
+    "import",
+    "import_glob",
+    "table_add_row",
+    "table_update_row",
+    "assert",
+];
+
+// Helper function to warn about an expression statement whose value is thrown away, which is almost always
+// a forgotten assignment or print
+fn warn_discarded_value(typed_expr: &TypedExpr) {
+    if typed_expr.expr_type == TypeConstruct::Null {
+        return;
+    }
+    match &typed_expr.expr {
+        Expr::FunctionCall(name, _)
+            if SIDE_EFFECT_BUILTINS.contains(&name.as_str()) || name.starts_with("export_") => {}
+        Expr::Pipe(_, name, _) if name == "print" => {}
+        Expr::FunctionCall(name, _) => warn(format!(
+            "Warning: the {:?} returned by '{}' is discarded. Assign it to a variable or print it",
+            typed_expr.expr_type, name
+        )),
+        _ => warn(format!(
+            "Warning: the {:?} computed by an expression statement is discarded. Assign it to a variable or print it",
+            typed_expr.expr_type
+        )),
+    }
+}
+
+// Type check a statement and the statements it is compounded of, returning every error found, in program order
+// The statements are taken from a worklist rather than by recursion, so long programs do not grow the stack.
+// A statement that fails does not stop the check: its declaration is still made, so later statements using it are checked as well
+pub fn type_check_all(
+    statement: &Statement,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Vec<TypeError> {
+    check_sequence(statement, scope_stack, false)
+}
+
+// Type check a program like type_check_all, where the value of a last expression statement is echoed, as with wrench -e
+// That value is shown rather than discarded, so it is not warned about
+pub fn type_check_echoing(
+    statement: &Statement,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Vec<TypeError> {
+    check_sequence(statement, scope_stack, true)
+}
+
+fn check_sequence(
+    statement: &Statement,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+    echo_last_value: bool,
+) -> Vec<TypeError> {
+    let mut errors = Vec::new();
+    let mut statements = vec![statement];
+    while let Some(statement) = statements.pop() {
+        if let Statement::Compound(first, rest) = statement {
+            statements.push(rest);
+            statements.push(first);
+            continue;
+        }
+        let depth = scope_stack.len();
+        let is_last = statements.iter().all(|rest| **rest == Statement::Skip);
+        ERROR_SPAN.with(|span| span.set(None));
+        let result = match statement.unlocated() {
+            Statement::Expr(expr) if echo_last_value && is_last => {
+                infer_type(expr, scope_stack).map(|_| ())
+            }
+            _ => type_check(statement, scope_stack),
+        };
+        if let Err(error) = result {
+            // An echoed expression is checked without its statement, which still has the span to give it
+            let span = ERROR_SPAN.with(|span| span.take()).or(match statement {
+                Statement::Located(span, _) => Some(*span),
+                _ => None,
+            });
+            errors.push(TypeError { span, ..error });
+            // A block that failed may have left its scope on the stack
+            scope_stack.truncate(depth);
+            declare_despite_error(statement, scope_stack);
+        }
+    }
+    errors
+}
+
+// Helper function to check that a name a program declares, e.g. of a variable or a parameter, is not that of a builtin
+// Builtins are called before the names a program declares are looked up, so a function with the name of one would never be called
+pub fn check_not_builtin(name: &str) -> Result<(), TypeError> {
+    if lookup_builtin(name).is_some() {
+        return Err(TypeError::coded(
+            ErrorCode::BuiltinRedefined,
+            format!("cannot redefine builtin '{}'", name),
+        ));
+    }
+    Ok(())
+}
+
+// Helper function to type check a for-loop over a range of ints, e.g. for (int i in 0..10) {...}
+// The bounds are ints, and the loop variable is declared as an int. A range has no index, as the loop variable is one
+fn type_check_range_loop(
+    index: &Option<Parameter>,
+    Parameter::Parameter(param_type, param_name): &Parameter,
+    bounds: [&Expr; 2],
+    body: &Statement,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<(), TypeError> {
+    for bound in bounds {
+        let bound_type = infer_type(bound, scope_stack)?.expr_type;
+        if bound_type != TypeConstruct::Int {
+            return Err(
+                format!("The bounds of a range must be ints, found {:?}", bound_type).into(),
+            );
+        }
+    }
+    if index.is_some() {
+        return Err(
+            "Only a for-loop over an array or a table can have an index, found a range"
+                .to_string()
+                .into(),
+        );
+    }
+    if *param_type != TypeConstruct::Int {
+        return Err(TypeError::coded(
+            ErrorCode::TypeMismatch,
+            format!(
+                "Type mismatch in for-loop: expected Int, found {:?} for iterator '{}' over a range",
+                param_type, param_name
+            ),
+        ));
+    }
+    push_scope(scope_stack);
+    scope_stack.last_mut().unwrap().insert(
+        param_name.clone(),
+        VariableInfo {
+            var_type: TypeConstruct::Int,
+            is_constant: false,
+            value: None,
+            is_table_loop_row: false,
+        },
+    );
+    type_check_loop_body(body, scope_stack)?;
+    pop_scope(scope_stack);
+    Ok(())
+}
+
+// Helper function to declare the index of a for-loop, if it has one, in the scope of the loop
+fn declare_loop_index(
+    index: &Option<Parameter>,
+    scope_stack: &mut [HashMap<String, VariableInfo>],
+) {
+    if let Some(Parameter::Parameter(_, index_name)) = index {
+        scope_stack.last_mut().unwrap().insert(
+            index_name.clone(),
+            VariableInfo {
+                var_type: TypeConstruct::Int,
+                is_constant: false,
+                value: None,
+                is_table_loop_row: false,
+            },
+        );
+    }
+}
+
+// Helper function to make the declaration of a statement that failed the check, with the type it was declared with,
+// so later uses of the name are not reported as errors too
+fn declare_despite_error(statement: &Statement, scope_stack: &mut [HashMap<String, VariableInfo>]) {
+    let (var_type, name, is_constant) = match statement.unlocated() {
+        Statement::Declaration(Declaration::Variable(var_type, name, _)) => (var_type, name, false),
+        Statement::Declaration(Declaration::Constant(var_type, name, _)) => (var_type, name, true),
+        _ => return,
+    };
+    scope_stack
+        .last_mut()
+        .unwrap()
+        .entry(name.clone())
+        .or_insert(VariableInfo {
+            var_type: var_type.clone(),
+            is_constant,
+            value: None,
+            is_table_loop_row: false,
+        });
+}
+
 /// Structure to hold information about a variable
 /// - `var_type`: The declared type of the variable
 /// - `is_constant`: Whether the variable is immutable
+/// - `value`: The literal a constant was declared with, substituted wherever the constant is used
+/// - `is_table_loop_row`: Whether the variable is the row of a for-loop over a table without an index, a copy that cannot be written back
 #[derive(PartialEq, Debug, Clone)]
 pub struct VariableInfo {
     pub var_type: TypeConstruct,
     pub is_constant: bool,
+    pub value: Option<Expr>,
+    pub is_table_loop_row: bool,
 }
 
 // Main function to perform type checking on a statement
@@ -21,7 +370,7 @@ pub struct VariableInfo {
 pub fn type_check(
     statement: &Statement,
     scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
-) -> Result<(), String> {
+) -> Result<(), TypeError> {
     // Match on the type of statement to handle different cases
     match statement {
         // Case: Skip statement (no operation)
@@ -29,10 +378,16 @@ pub fn type_check(
             // Skip statement, do nothing
         }
 
+        // Case: Enum declaration. The enum types in the program are given their values before it is checked
+        Statement::EnumDeclaration(_, _) => {}
+
         // Case: Compound statement - Check both parts of a compound statement
-        Statement::Compound(stmt1, stmt2) => {
-            type_check(stmt1, scope_stack)?;
-            type_check(stmt2, scope_stack)?;
+        // The statements are checked in order by type_check_all, and the earliest error is the one reported
+        Statement::Compound(_, _) => {
+            if let Some(error) = type_check_all(statement, scope_stack).into_iter().next() {
+                ERROR_SPAN.with(|span| span.set(error.span));
+                return Err(error);
+            }
         }
 
         // Case: Variable declaration - Handle different types of declarations
@@ -40,11 +395,22 @@ pub fn type_check(
             match declaration {
                 // Case: Variable declaration with a type, name, and expression
                 Declaration::Variable(var_type, name, expr) => {
+                    check_not_builtin(name)?;
+                    // A function made with bind is declared once and cannot be reassigned
+                    if matches!(&**expr, Expr::FunctionCall(f, _) if f == "bind") {
+                        return Err(format!(
+                            "the function '{}' made with bind must be declared with const",
+                            name
+                        )
+                        .into());
+                    }
                     // Check and cast the type of the expression
                     check_and_cast_type(
                         &(VariableInfo {
                             var_type: var_type.clone(),
                             is_constant: false,
+                            value: None,
+                            is_table_loop_row: false,
                         }),
                         expr,
                         scope_stack,
@@ -55,36 +421,75 @@ pub fn type_check(
                         VariableInfo {
                             var_type: var_type.clone(),
                             is_constant: false,
+                            value: None,
+                            is_table_loop_row: false,
                         },
                     );
                 }
                 // Case: Constant declaration with a type, name, and expression
                 Declaration::Constant(const_type, name, expr) => {
+                    check_not_builtin(name)?;
                     // Check and cast the type of the expression
                     let typed_expr = infer_type(expr, scope_stack)?;
-                    if *const_type != typed_expr.expr_type {
-                        return Err(format!(
-                            "Type mismatch: expected {:?}, found {:?} for constant '{}'",
-                            const_type, typed_expr.expr_type, name
+                    if !value_matches(const_type, &typed_expr)? {
+                        return Err(TypeError::coded(
+                            ErrorCode::TypeMismatch,
+                            format!(
+                                "Type mismatch: expected {:?}, found {:?} for constant '{}'",
+                                const_type, typed_expr.expr_type, name
+                            ),
                         ));
                     }
+                    // A constant of a literal, or of another such constant, is replaced by the literal where it is used,
+                    // so builtins that need literal arguments accept the constant too
+                    let value = match typed_expr.expr {
+                        literal @ (Expr::Number(_)
+                        | Expr::Double(_)
+                        | Expr::StringLiteral(_)
+                        | Expr::Bool(_)) => Some(literal),
+                        _ => None,
+                    };
                     // Add the constant to the current scope
                     scope_stack.last_mut().unwrap().insert(
                         name.clone(),
                         VariableInfo {
                             var_type: const_type.clone(),
                             is_constant: true,
+                            value,
+                            is_table_loop_row: false,
                         },
                     );
                 }
                 // Case: Function declaration with a return type, name, parameters, and body
                 Declaration::Function(return_type, name, params, body) => {
+                    check_not_builtin(name)?;
+                    // main is called when the program starts, and what it returns is the exit code
+                    if name == "main"
+                        && (!params.is_empty()
+                            || !matches!(return_type, TypeConstruct::Int | TypeConstruct::Null))
+                    {
+                        return Err(format!(
+                            "main must take no parameters and return int or null, found {} parameters and {:?}",
+                            params.len(),
+                            return_type
+                        ).into());
+                    }
+                    let limit = source_limits().max_parameters;
+                    if params.len() > limit {
+                        return Err(over_limit_message(
+                            &format!("function '{}' declares {} parameters", name, params.len()),
+                            limit,
+                            "--max-parameters",
+                        )
+                        .into());
+                    }
                     let param_types: Vec<TypeConstruct> = params
                         .iter()
                         .map(|Parameter::Parameter(param_type, _)| param_type.clone())
                         .collect();
 
-                    scope_stack[0].insert(
+                    // A function declared in the body of another is only seen in that body
+                    scope_stack.last_mut().unwrap().insert(
                         name.clone(),
                         VariableInfo {
                             var_type: TypeConstruct::Function(
@@ -92,57 +497,67 @@ pub fn type_check(
                                 param_types,
                             ),
                             is_constant: true,
+                            value: None,
+                            is_table_loop_row: false,
                         },
                     );
 
-                    // Create a scope for the function parameters
-                    let mut param_scope = HashMap::new();
-                    for Parameter::Parameter(param_type, param_name) in params {
-                        param_scope.insert(
-                            param_name.clone(),
-                            VariableInfo {
-                                var_type: param_type.clone(),
-                                is_constant: false,
-                            },
-                        );
-                    }
-
-                    // Preserve previously declared functions
-                    let mut function_scope = HashMap::new();
-                    for (k, v) in scope_stack[0].iter() {
-                        if matches!(v.var_type, TypeConstruct::Function(_, _)) {
-                            function_scope.insert(k.clone(), v.clone());
-                        }
-                    }
-
-                    let mut function_scope_stack = Vec::new();
-                    function_scope_stack.push(function_scope);
-                    function_scope_stack.push(param_scope);
-
-                    type_check(body, &mut function_scope_stack)?;
-
-                    // Validate return type
-                    validate_return_type(body, return_type, &mut function_scope_stack)?;
+                    type_check_function_body(
+                        return_type,
+                        params,
+                        body,
+                        scope_stack,
+                        HashMap::new(),
+                    )?;
                 }
             }
         }
 
         // Case: For loop
-        Statement::For(param, iterable_expr, body) => {
+        Statement::For(index, param, iterable_expr, body) => {
+            for Parameter::Parameter(_, name) in index.iter().chain([param]) {
+                check_not_builtin(name)?;
+            }
+            if let Expr::Range(start, end, _) = &**iterable_expr {
+                return type_check_range_loop(index, param, [start, end], body, scope_stack);
+            }
             let typed_iterable = infer_type(iterable_expr, scope_stack)?;
+            if let Some(Parameter::Parameter(index_type, index_name)) = index {
+                if !matches!(
+                    typed_iterable.expr_type,
+                    TypeConstruct::Array(_) | TypeConstruct::Table(_)
+                ) {
+                    return Err(format!(
+                        "Only a for-loop over an array or a table can have an index, found {:?}",
+                        typed_iterable.expr_type
+                    )
+                    .into());
+                }
+                if *index_type != TypeConstruct::Int {
+                    return Err(format!(
+                        "The index '{}' of a for-loop must be an int, found {:?}",
+                        index_name, index_type
+                    )
+                    .into());
+                }
+            }
 
             // Match on the type of the iterable expression
             match &typed_iterable.expr_type {
                 TypeConstruct::Array(element_type) => {
                     push_scope(scope_stack);
+                    declare_loop_index(index, scope_stack);
 
                     // Match on the parameter type
                     match param {
                         Parameter::Parameter(param_type, param_name) => {
-                            if *param_type != **element_type {
-                                return Err(format!(
-                                    "Type mismatch in for-loop: expected {:?}, found {:?} for iterator '{}'",
-                                    param_type, element_type, param_name
+                            if !types_match(param_type, element_type) {
+                                return Err(TypeError::coded(
+                                    ErrorCode::TypeMismatch,
+                                    format!(
+                                        "Type mismatch in for-loop: expected {:?}, found {:?} for iterator '{}'",
+                                        param_type, element_type, param_name
+                                    ),
                                 ));
                             }
                             scope_stack.last_mut().unwrap().insert(
@@ -150,25 +565,31 @@ pub fn type_check(
                                 VariableInfo {
                                     var_type: *element_type.clone(),
                                     is_constant: false,
+                                    value: None,
+                                    is_table_loop_row: false,
                                 },
                             );
                         }
                     }
 
-                    type_check(body, scope_stack)?;
+                    type_check_loop_body(body, scope_stack)?;
 
                     pop_scope(scope_stack);
                 }
                 TypeConstruct::Row(_) => {
                     push_scope(scope_stack);
+                    declare_loop_index(index, scope_stack);
 
                     // Match on the parameter type
                     match param {
                         Parameter::Parameter(param_type, param_name) => {
-                            if *param_type != typed_iterable.expr_type {
-                                return Err(format!(
-                                    "Type mismatch in for-loop: expected {:?}, found {:?} for iterator '{}'",
-                                    param_type, typed_iterable.expr_type, param_name
+                            if !types_match(param_type, &typed_iterable.expr_type) {
+                                return Err(TypeError::coded(
+                                    ErrorCode::TypeMismatch,
+                                    format!(
+                                        "Type mismatch in for-loop: expected {:?}, found {:?} for iterator '{}'",
+                                        param_type, typed_iterable.expr_type, param_name
+                                    ),
                                 ));
                             }
                             scope_stack.last_mut().unwrap().insert(
@@ -176,30 +597,39 @@ pub fn type_check(
                                 VariableInfo {
                                     var_type: typed_iterable.expr_type.clone(),
                                     is_constant: false,
+                                    value: None,
+                                    is_table_loop_row: false,
                                 },
                             );
                         }
                     }
 
-                    type_check(body, scope_stack)?;
+                    type_check_loop_body(body, scope_stack)?;
 
                     pop_scope(scope_stack);
                 }
                 TypeConstruct::Table(table_params) => {
                     push_scope(scope_stack);
+                    declare_loop_index(index, scope_stack);
                     match param {
                         Parameter::Parameter(param_type, param_name) => {
                             if let TypeConstruct::Row(row_params) = param_type {
-                                if row_params != table_params {
-                                    return Err(format!(
-                                        "Type mismatch in for-loop: expected Row({:?}), found Table({:?}) for iterator '{}'",
-                                        row_params, table_params, param_name
+                                if column_difference(row_params, table_params).is_some() {
+                                    return Err(TypeError::coded(
+                                        ErrorCode::TypeMismatch,
+                                        format!(
+                                            "Type mismatch in for-loop: expected Row({:?}), found Table({:?}) for iterator '{}'",
+                                            row_params, table_params, param_name
+                                        ),
                                     ));
                                 }
                             } else {
-                                return Err(format!(
-                                    "Type mismatch in for-loop: expected Row(...), found Table({:?}) for iterator '{}'",
-                                    table_params, param_name
+                                return Err(TypeError::coded(
+                                    ErrorCode::TypeMismatch,
+                                    format!(
+                                        "Type mismatch in for-loop: expected Row(...), found Table({:?}) for iterator '{}'",
+                                        table_params, param_name
+                                    ),
                                 ));
                             }
                             scope_stack.last_mut().unwrap().insert(
@@ -207,18 +637,21 @@ pub fn type_check(
                                 VariableInfo {
                                     var_type: param_type.clone(),
                                     is_constant: false,
+                                    value: None,
+                                    is_table_loop_row: index.is_none(),
                                 },
                             );
                         }
                     }
-                    type_check(body, scope_stack)?;
+                    type_check_loop_body(body, scope_stack)?;
                     pop_scope(scope_stack);
                 }
                 _ => {
                     return Err(format!(
                         "For-loop iterable must be an array, found {:?}",
                         typed_iterable.expr_type
-                    ));
+                    )
+                    .into());
                 }
             }
         }
@@ -227,7 +660,10 @@ pub fn type_check(
         Statement::VariableAssignment(name, expr) => {
             if let Some(var_type) = lookup_variable(name, scope_stack) {
                 if var_type.is_constant {
-                    return Err(format!("Cannot assign to constant variable '{}'", name));
+                    return Err(TypeError::coded(
+                        ErrorCode::ConstantAssignment,
+                        format!("Cannot assign to constant variable '{}'", name),
+                    ));
                 }
 
                 check_and_cast_type(&var_type, expr, scope_stack)?;
@@ -237,20 +673,129 @@ pub fn type_check(
                     .unwrap()
                     .insert(name.clone(), var_type.clone());
             } else {
-                return Err(format!("Undefined variable '{}'", name));
+                return Err(TypeError::coded(
+                    ErrorCode::UndefinedName,
+                    format!(
+                        "Undefined variable '{}'.{}",
+                        name,
+                        did_you_mean_in_scope(name, scope_stack)
+                    ),
+                ));
+            }
+        }
+
+        // Case: Assignment to an element of an array variable
+        Statement::IndexAssignment(name, index, expr) => {
+            let Some(var_info) = lookup_variable(name, scope_stack) else {
+                return Err(TypeError::coded(
+                    ErrorCode::UndefinedName,
+                    format!(
+                        "Undefined variable '{}'.{}",
+                        name,
+                        did_you_mean_in_scope(name, scope_stack)
+                    ),
+                ));
+            };
+            let TypeConstruct::Array(element_type) = &var_info.var_type else {
+                return Err(format!(
+                    "Cannot assign to an element of '{}', '{}' is {:?}, not an array",
+                    name, name, var_info.var_type
+                )
+                .into());
+            };
+            if var_info.is_constant {
+                return Err(TypeError::coded(
+                    ErrorCode::ConstantAssignment,
+                    format!("Cannot assign to constant variable '{}'", name),
+                ));
+            }
+            let index_type = infer_type(index, scope_stack)?.expr_type;
+            if index_type != TypeConstruct::Int {
+                return Err(format!("Index must be an integer, found {:?}", index_type).into());
+            }
+            let element_info = VariableInfo {
+                var_type: *element_type.clone(),
+                is_constant: false,
+                value: None,
+                is_table_loop_row: false,
+            };
+            check_and_cast_type(&element_info, expr, scope_stack).map_err(|e| TypeError {
+                message: format!(
+                    "In the assignment to an element of '{}': {}",
+                    name, e.message
+                ),
+                ..e
+            })?;
+        }
+
+        // Case: Assignment to a column of a row variable
+        // Rows are values, so this changes the variable only. A row of a table is written back with table_update_row
+        Statement::FieldAssignment(name, column, expr) => {
+            let Some(var_info) = lookup_variable(name, scope_stack) else {
+                return Err(TypeError::coded(
+                    ErrorCode::UndefinedName,
+                    format!(
+                        "Undefined variable '{}'.{}",
+                        name,
+                        did_you_mean_in_scope(name, scope_stack)
+                    ),
+                ));
+            };
+            let TypeConstruct::Row(row_params) = &var_info.var_type else {
+                return Err(format!(
+                    "Cannot assign to '{}.{}', '{}' is {:?}, not a row. Columns can only be assigned in row variables",
+                    name, column, name, var_info.var_type
+                ).into());
+            };
+            if var_info.is_constant {
+                return Err(TypeError::coded(
+                    ErrorCode::ConstantAssignment,
+                    format!("Cannot assign to constant variable '{}'", name),
+                ));
+            }
+            if var_info.is_table_loop_row {
+                return Err(format!(
+                    "Cannot assign to '{}.{}', the rows of a for-loop over a table are copies, so the table would not change. \
+                     Loop with an index, e.g. for (int i, row(...) {} in t), and write the row back with table_update_row(t, i, {})",
+                    name, column, name, name
+                ).into());
             }
+            let Some(column_type) = row_params
+                .iter()
+                .find(|Parameter::Parameter(_, param_name)| param_name == column)
+                .map(|Parameter::Parameter(param_type, _)| param_type.clone())
+            else {
+                return Err(TypeError::coded(
+                    ErrorCode::UnknownColumn,
+                    unknown_column_message(column, &param_columns(row_params)),
+                ));
+            };
+            let column_info = VariableInfo {
+                var_type: column_type,
+                is_constant: false,
+                value: None,
+                is_table_loop_row: false,
+            };
+            check_and_cast_type(&column_info, expr, scope_stack).map_err(|e| TypeError {
+                message: format!("In the assignment to '{}.{}': {}", name, column, e.message),
+                ..e
+            })?;
         }
 
         // Case: Constant assignment
         Statement::Expr(expr) => {
-            infer_type(expr, scope_stack)?;
+            let typed_expr = infer_type(expr, scope_stack)?;
+            warn_discarded_value(&typed_expr);
         }
 
         // Case: If statement
         Statement::If(condition, body, else_body) => {
             let typed_condition = infer_type(condition, scope_stack)?;
             if typed_condition.expr_type != TypeConstruct::Bool {
-                return Err("If condition must be a boolean".to_string());
+                return Err(TypeError::coded(
+                    ErrorCode::ConditionNotBool,
+                    "If condition must be a boolean".to_string(),
+                ));
             }
 
             // Push a new scope for the if body
@@ -268,12 +813,15 @@ pub fn type_check(
         Statement::While(condition, body) => {
             let typed_condition = infer_type(condition, scope_stack)?;
             if typed_condition.expr_type != TypeConstruct::Bool {
-                return Err("While condition must be a boolean".to_string());
+                return Err(TypeError::coded(
+                    ErrorCode::ConditionNotBool,
+                    "While condition must be a boolean".to_string(),
+                ));
             }
 
             // Push a new scope for the while body
             push_scope(scope_stack);
-            type_check(body, scope_stack)?;
+            type_check_loop_body(body, scope_stack)?;
             pop_scope(scope_stack);
         }
 
@@ -281,6 +829,46 @@ pub fn type_check(
         Statement::Return(expr) => {
             infer_type(expr, scope_stack)?;
         }
+
+        // Case: break or continue, which stop the loop they are in or go on with its next iteration
+        Statement::Break | Statement::Continue => {
+            if LOOP_DEPTH.with(Cell::get) == 0 {
+                let keyword = if *statement == Statement::Break {
+                    "break"
+                } else {
+                    "continue"
+                };
+                return Err(
+                    format!("{} can only be used inside a while or for loop", keyword).into(),
+                );
+            }
+        }
+
+        // Case: a block deferred until the scope exits, checked in a scope of its own
+        // A return in it would have nowhere to go, as the scope has already given its value, and neither would a break or continue
+        Statement::Defer(body) => {
+            if contains_return(body) {
+                return Err("A deferred block cannot return".to_string().into());
+            }
+            push_scope(scope_stack);
+            type_check_at_loop_depth(0, body, scope_stack)?;
+            pop_scope(scope_stack);
+        }
+
+        // Case: a statement counted for --coverage, checked like the statement itself
+        Statement::Counted(_, statement) => type_check(statement, scope_stack)?,
+
+        // Case: A statement with its span. An error found in it is given the span, unless a statement in it already has one
+        Statement::Located(span, statement) => {
+            ERROR_SPAN.with(|error_span| error_span.set(None));
+            type_check(statement, scope_stack).inspect_err(|_| {
+                ERROR_SPAN.with(|error_span| {
+                    if error_span.get().is_none() {
+                        error_span.set(Some(*span));
+                    }
+                })
+            })?
+        }
     }
 
     Ok(())
@@ -290,7 +878,7 @@ pub fn type_check(
 fn infer_type(
     expr: &Expr,
     scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
-) -> Result<TypedExpr, String> {
+) -> Result<TypedExpr, TypeError> {
     match expr {
         // Case: Integer literal (e.g., `5`)
         Expr::Number(value) => Ok(TypedExpr {
@@ -307,6 +895,11 @@ fn infer_type(
             expr: Expr::Double(*value),
             expr_type: TypeConstruct::Double,
         }),
+        // Case: Character literal (e.g., `'a'`)
+        Expr::Char(value) => Ok(TypedExpr {
+            expr: Expr::Char(*value),
+            expr_type: TypeConstruct::Char,
+        }),
         // Case: String literal (e.g., `"hello"`)
         Expr::StringLiteral(value) => Ok(TypedExpr {
             expr: Expr::StringLiteral(value.clone()),
@@ -323,116 +916,39 @@ fn infer_type(
         Expr::Identifier(name) => {
             if let Some(var_info) = lookup_variable(name, scope_stack) {
                 Ok(TypedExpr {
-                    expr: Expr::Identifier(name.clone()),
+                    expr: var_info
+                        .value
+                        .clone()
+                        .unwrap_or_else(|| Expr::Identifier(name.clone())),
                     expr_type: var_info.var_type.clone(),
                 })
             } else {
-                Err(format!("Undefined variable '{}'", name))
+                Err(TypeError::coded(
+                    ErrorCode::UndefinedName,
+                    format!(
+                        "Undefined variable '{}'.{}",
+                        name,
+                        did_you_mean_in_scope(name, scope_stack)
+                    ),
+                ))
             }
         }
 
         // Case: Binary operation (e.g., `x + y`)
-        Expr::Operation(left, op, right) => {
-            let left_typed = infer_type(left, scope_stack)?;
-            let right_typed = infer_type(right, scope_stack)?;
-
-            // Check if the operator is valid for the types
-            let widened_left = check_and_cast_type(
-                &VariableInfo {
-                    var_type: right_typed.expr_type.clone(),
-                    is_constant: false,
-                },
-                &left_typed.expr,
-                scope_stack,
-            )?;
-            let widened_right = check_and_cast_type(
-                &VariableInfo {
-                    var_type: left_typed.expr_type.clone(),
-                    is_constant: false,
-                },
-                &right_typed.expr,
-                scope_stack,
-            )?;
-
-            if matches!(left_typed.expr_type, TypeConstruct::Row(_))
-                || matches!(right_typed.expr_type, TypeConstruct::Row(_))
-                || matches!(left_typed.expr_type, TypeConstruct::Table(_))
-                || matches!(right_typed.expr_type, TypeConstruct::Table(_))
-            {
-                return Err("Operation on Row or Table types is not allowed".to_string());
+        // Left leaning chains like 1 + 1 + 1 + ... are checked in a loop, so long chains do not grow the stack
+        Expr::Operation(..) => {
+            let mut operations = Vec::new();
+            let mut base = expr;
+            while let Expr::Operation(left, op, right) = base {
+                operations.push((op, right));
+                base = left;
             }
-
-            // Determine the result type based on the operator and operand types
-            let result_type = match (&left_typed.expr_type, &right_typed.expr_type) {
-                (TypeConstruct::Int, TypeConstruct::Double)
-                | (TypeConstruct::Double, TypeConstruct::Int)
-                | (TypeConstruct::Double, TypeConstruct::Double) => TypeConstruct::Double,
-                (TypeConstruct::Int, TypeConstruct::Int) => TypeConstruct::Int,
-                _ => {
-                    return Err(format!(
-                        "Operation on incompatible types. Left-hand side is {:?} and right-hand side is {:?}",
-                        left_typed.expr_type, right_typed.expr_type
-                    ));
-                }
-            };
-
-            // Only allow arithmetic operations on Int or Double
-            match op {
-                Operator::Equals | Operator::LessThan | Operator::LessThanOrEqual => {
-                    Ok(TypedExpr {
-                        expr: Expr::Operation(
-                            Box::new(widened_left),
-                            (*op).clone(),
-                            Box::new(widened_right),
-                        ),
-                        expr_type: TypeConstruct::Bool,
-                    })
-                }
-                Operator::Addition
-                | Operator::Subtraction
-                | Operator::Multiplication
-                | Operator::Division
-                | Operator::Modulo
-                | Operator::Exponent => {
-                    if result_type == TypeConstruct::Int || result_type == TypeConstruct::Double {
-                        // Check for division by zero
-                        if let Operator::Division = op {
-                            match &right_typed.expr {
-                                Expr::Number(0) | Expr::Double(0.0) => {
-                                    return Err("Division by zero is not allowed".to_string());
-                                }
-                                _ => {}
-                            }
-                        }
-                        Ok(TypedExpr {
-                            expr: Expr::Operation(
-                                Box::new(widened_left),
-                                (*op).clone(),
-                                Box::new(widened_right),
-                            ),
-                            expr_type: result_type,
-                        })
-                    } else {
-                        Err(format!("Invalid operation for type {:?}", result_type))
-                    }
-                }
-                Operator::Or => {
-                    if left_typed.expr_type == TypeConstruct::Bool
-                        && right_typed.expr_type == TypeConstruct::Bool
-                    {
-                        Ok(TypedExpr {
-                            expr: Expr::Operation(
-                                Box::new(widened_left),
-                                (*op).clone(),
-                                Box::new(widened_right),
-                            ),
-                            expr_type: TypeConstruct::Bool,
-                        })
-                    } else {
-                        Err("Logical operators require boolean operands".to_string())
-                    }
-                }
+            let mut left_typed = infer_type(base, scope_stack)?;
+            for (op, right) in operations.into_iter().rev() {
+                let right_typed = infer_type(right, scope_stack)?;
+                left_typed = infer_operation_type(left_typed, op, right_typed)?;
             }
+            Ok(left_typed)
         }
         // Case: Logical NOT (e.g., `!true`)
         Expr::Not(inner) => {
@@ -443,34 +959,63 @@ fn infer_type(
                     expr_type: TypeConstruct::Bool,
                 })
             } else {
-                Err("Logical NOT requires a boolean".to_string())
+                Err(TypeError::coded(
+                    ErrorCode::ConditionNotBool,
+                    "Logical NOT requires a boolean".to_string(),
+                ))
+            }
+        }
+
+        // Case: Unary minus (e.g., `-x`). Ints stay ints and doubles stay doubles, and arrays of them are negated element-wise.
+        // The negation of a number literal is the negative literal, so constants declared with one are literals too
+        Expr::Negation(inner) => {
+            let inner_typed = infer_type(inner, scope_stack)?;
+            let expr = match inner_typed.expr {
+                Expr::Number(n) if n != i64::MIN => Expr::Number(-n),
+                Expr::Double(d) => Expr::Double(-d),
+                other => Expr::Negation(Box::new(other)),
+            };
+            match &inner_typed.expr_type {
+                TypeConstruct::Int | TypeConstruct::Double => Ok(TypedExpr {
+                    expr,
+                    expr_type: inner_typed.expr_type,
+                }),
+                TypeConstruct::Array(element)
+                    if matches!(**element, TypeConstruct::Int | TypeConstruct::Double) =>
+                {
+                    Ok(TypedExpr {
+                        expr,
+                        expr_type: inner_typed.expr_type,
+                    })
+                }
+                TypeConstruct::Bool => Err(TypeError::coded(
+                    ErrorCode::IncompatibleOperands,
+                    "Unary minus requires an int or double, found bool. Use ! to negate a bool"
+                        .to_string(),
+                )),
+                TypeConstruct::String => Err(TypeError::coded(
+                    ErrorCode::IncompatibleOperands,
+                    "Unary minus requires an int or double, found string".to_string(),
+                )),
+                other => Err(TypeError::coded(
+                    ErrorCode::IncompatibleOperands,
+                    format!("Unary minus requires an int or double, found {:?}", other),
+                )),
             }
         }
 
         // Case: Array (e.g., `[1, 2, 3]`)
         Expr::Array(elements) => {
             if elements.is_empty() {
-                return Err("Cannot infer type of empty array".to_string());
+                return Err("Cannot infer type of empty array".to_string().into());
             }
 
-            let first_typed = infer_type(&elements[0], scope_stack)?;
-            // Ensure all elements in the array have the same type
-            for e in elements.iter().skip(1) {
-                let t = infer_type(e, scope_stack)?;
-                if t.expr_type != first_typed.expr_type {
-                    return Err("Array elements must have the same type".to_string());
-                }
-            }
-            // Build the array expression with typed elements
-            Ok(TypedExpr {
-                expr: Expr::Array(
-                    elements
-                        .iter()
-                        .map(|e| infer_type(e, scope_stack).map(|typed| Box::new(typed.expr)))
-                        .collect::<Result<Vec<_>, _>>()?,
-                ),
-                expr_type: TypeConstruct::Array(Box::new(first_typed.expr_type)),
-            })
+            // Each element is inferred once, left to right
+            let typed_elements = elements
+                .iter()
+                .map(|e| infer_type(e, scope_stack))
+                .collect::<Result<Vec<_>, _>>()?;
+            typed_array(typed_elements)
         }
 
         // Case: Indexing (e.g., `arr[0]`)
@@ -479,7 +1024,7 @@ fn infer_type(
             let index_typed = infer_type(index_expr, scope_stack)?;
 
             if index_typed.expr_type != TypeConstruct::Int {
-                return Err("Index must be an integer".to_string());
+                return Err("Index must be an integer".to_string().into());
             }
 
             // Make sure we're indexing into an array
@@ -498,41 +1043,87 @@ fn infer_type(
                     expr: Expr::Indexing(Box::new(array_typed.expr), Box::new(index_typed.expr)),
                     expr_type: array_typed.expr_type.clone(),
                 }),
-                _ => Err("Cannot index into non-array type".to_string()),
+
+                // A string is indexed by character, giving a string of that character
+                TypeConstruct::String => Ok(TypedExpr {
+                    expr: Expr::Indexing(Box::new(array_typed.expr), Box::new(index_typed.expr)),
+                    expr_type: TypeConstruct::String,
+                }),
+                _ => Err("Cannot index into non-array type".to_string().into()),
             }
         }
 
         // Case for function call (e.g., `f(x, y)`)
         Expr::FunctionCall(name, args) => {
+            if name == "bind" {
+                return infer_bind_type(args, scope_stack);
+            }
+            if name == "table_with_column" {
+                return infer_table_with_column_type(args, scope_stack);
+            }
+            if name == "to_table" || name == "to_table_column" {
+                return infer_to_table_type(name, args, scope_stack);
+            }
+            if name == "get_or" || name == "row_get_or" {
+                return infer_get_or_type(name, args, scope_stack);
+            }
             if let Some(func_type) = lookup_variable(name, scope_stack) {
                 if let TypeConstruct::Function(return_type, param_types) = &func_type.var_type {
-                    if args.len() != param_types.len() {
-                        return Err(format!(
-                            "Function '{}' expected {} arguments, found {}",
-                            name,
-                            param_types.len(),
-                            args.len()
+                    // Builtins may take optional parameters after those in their signature, e.g. the import options row
+                    let builtin = lookup_builtin(name);
+                    let optional_types: Vec<TypeConstruct> = builtin
+                        .iter()
+                        .flat_map(|b| b.optional_parameters.iter().map(|(_, t)| t.clone()))
+                        .collect();
+                    let max_args = param_types.len() + optional_types.len();
+                    if args.len() < param_types.len() || args.len() > max_args {
+                        let expected = if optional_types.is_empty() {
+                            param_types.len().to_string()
+                        } else {
+                            format!("{} to {}", param_types.len(), max_args)
+                        };
+                        return Err(TypeError::coded(
+                            ErrorCode::ArgumentCount,
+                            format!(
+                                "Function '{}' expected {} arguments, found {}",
+                                name,
+                                expected,
+                                args.len()
+                            ),
                         ));
                     }
 
-                    for (i, (arg, param_type)) in args.iter().zip(param_types.iter()).enumerate() {
+                    for (arg, param_type) in
+                        args.iter().zip(param_types.iter().chain(&optional_types))
+                    {
                         let arg_typed = infer_type(arg, scope_stack)?;
-                        if (name == "import" || name == "async_import") && i == 1 {
-                            if let (TypeConstruct::Table(_), TypeConstruct::Table(_)) =
-                                (param_type, &arg_typed.expr_type)
-                            {
-                                continue;
-                            }
+                        // A builtin parameter of type table() or row() accepts any table or row
+                        if builtin.is_some()
+                            && matches!(
+                                (param_type, &arg_typed.expr_type),
+                                (TypeConstruct::Table(p), TypeConstruct::Table(_))
+                                    | (TypeConstruct::Row(p), TypeConstruct::Row(_)) if p.is_empty()
+                            )
+                        {
+                            continue;
                         }
-                        if *param_type != TypeConstruct::Any && arg_typed.expr_type != *param_type {
-                            return Err(format!(
-                                "Type mismatch in function call: expected {:?}, found {:?}",
-                                param_type, arg_typed.expr_type
+                        if *param_type != TypeConstruct::Any
+                            && !value_matches(param_type, &arg_typed)?
+                        {
+                            return Err(TypeError::coded(
+                                ErrorCode::TypeMismatch,
+                                format!(
+                                    "Type mismatch in function call: expected {:?}, found {:?}",
+                                    param_type, arg_typed.expr_type
+                                ),
                             ));
                         }
                     }
 
-                    if name == "import" || name == "async_import" {
+                    if matches!(
+                        name.as_str(),
+                        "import" | "async_import" | "import_glob" | "async_import_glob"
+                    ) {
                         if let Some(arg) = args.get(1) {
                             let arg_type = infer_type(arg, scope_stack)?;
                             if let TypeConstruct::Table(params) = arg_type.expr_type.clone() {
@@ -545,37 +1136,238 @@ fn infer_type(
                         return Err(format!(
                             "Second argument to '{}' must be a table declaration or variable with table type",
                             name
-                        ));
+                        ).into());
                     }
 
-                    Ok(TypedExpr {
-                        expr: Expr::FunctionCall(name.clone(), args.clone()),
-                        expr_type: *return_type.clone(),
-                    })
-                } else {
-                    Err(format!("'{}' is not a function", name))
-                }
-            } else {
-                Err(format!("Undefined function '{}'", name))
-            }
-        }
-
-        // Case: pipe operation (e.g., `x pipe f`)
-        Expr::Pipe(left, pipe_name, args) => {
-            let left_typed = infer_type(left, scope_stack)?;
+                    // The rows generated have the columns of the row returned by the maker function
+                    if name == "rows_from_range" {
+                        let maker_typed = infer_type(&args[1], scope_stack)?;
+                        let maker = match &maker_typed.expr {
+                            Expr::StringLiteral(maker) => maker,
+                            _ => {
+                                return Err(
+                                    "Second argument to 'rows_from_range' must be the name of a function as a string literal or constant"
+                                        .to_string().into(),
+                                );
+                            }
+                        };
+                        let maker_type = lookup_variable(maker, scope_stack)
+                            .ok_or_else(|| {
+                                TypeError::coded(
+                                    ErrorCode::UndefinedName,
+                                    format!("Undefined function '{}'", maker),
+                                )
+                            })?
+                            .var_type;
+                        if let TypeConstruct::Function(maker_return, maker_params) = &maker_type
+                            && let TypeConstruct::Row(params) = &**maker_return
+                            && *maker_params == vec![TypeConstruct::Int]
+                        {
+                            return Ok(TypedExpr {
+                                expr: Expr::FunctionCall(name.clone(), args.clone()),
+                                expr_type: TypeConstruct::Table(params.clone()),
+                            });
+                        }
+                        return Err(format!(
+                            "Maker '{}' given to 'rows_from_range' must be a function from int to row, found {:?}",
+                            maker, maker_type
+                        ).into());
+                    }
 
-            // Check is the left side is a pipe
+                    // describe takes any table, and returns the statistics table in its signature
+                    if name == "describe" {
+                        let arg_type = infer_type(&args[0], scope_stack)?;
+                        if !matches!(arg_type.expr_type, TypeConstruct::Table(_)) {
+                            return Err(format!(
+                                "Argument to 'describe' must be a table, found {:?}",
+                                arg_type.expr_type
+                            )
+                            .into());
+                        }
+                    }
+
+                    // histogram needs a numeric column, which is checked here when the column is named by a literal
+                    if name == "histogram" {
+                        let arg_type = infer_type(&args[0], scope_stack)?;
+                        let TypeConstruct::Table(params) = arg_type.expr_type else {
+                            return Err(format!(
+                                "First argument to 'histogram' must be a table, found {:?}",
+                                arg_type.expr_type
+                            )
+                            .into());
+                        };
+                        if let Expr::StringLiteral(column_name) =
+                            infer_type(&args[1], scope_stack)?.expr
+                            && !params.is_empty()
+                        {
+                            let column_type = params.iter().find_map(
+                                |Parameter::Parameter(column_type, name)| {
+                                    (**name == *column_name).then_some(column_type)
+                                },
+                            );
+                            match column_type {
+                                None => {
+                                    return Err(TypeError::coded(
+                                        ErrorCode::UnknownColumn,
+                                        format!("Table has no column named '{}'", column_name),
+                                    ));
+                                }
+                                Some(TypeConstruct::Int | TypeConstruct::Double) => {}
+                                Some(TypeConstruct::Nullable(inner))
+                                    if matches!(
+                                        **inner,
+                                        TypeConstruct::Int | TypeConstruct::Double
+                                    ) => {}
+                                Some(column_type) => {
+                                    return Err(format!(
+                                        "'histogram' needs an int or double column, but '{}' is {:?}",
+                                        column_name, column_type
+                                    ).into());
+                                }
+                            }
+                        }
+                    }
+
+                    // A cloned table has the same columns as the table it is cloned from
+                    if name == "table_clone" {
+                        let arg_type = infer_type(&args[0], scope_stack)?;
+                        if let TypeConstruct::Table(_) = arg_type.expr_type {
+                            return Ok(TypedExpr {
+                                expr: Expr::FunctionCall(name.clone(), args.clone()),
+                                expr_type: arg_type.expr_type,
+                            });
+                        }
+                        return Err(format!(
+                            "Argument to 'table_clone' must be a table, found {:?}",
+                            arg_type.expr_type
+                        )
+                        .into());
+                    }
+
+                    // The table returned has the columns of the table given, except the one named
+                    if name == "table_without_column" {
+                        let arg_type = infer_type(&args[0], scope_stack)?;
+                        let TypeConstruct::Table(mut params) = arg_type.expr_type else {
+                            return Err(format!(
+                                "First argument to 'table_without_column' must be a table, found {:?}",
+                                arg_type.expr_type
+                            ).into());
+                        };
+                        let Expr::StringLiteral(column_name) =
+                            infer_type(&args[1], scope_stack)?.expr
+                        else {
+                            return Err(
+                                "Second argument to 'table_without_column' must be the column name as a string literal or constant"
+                                    .to_string().into(),
+                            );
+                        };
+                        let column_name = column_name.to_string();
+                        let column_count = params.len();
+                        params.retain(|Parameter::Parameter(_, name)| *name != column_name);
+                        if params.len() == column_count {
+                            return Err(TypeError::coded(
+                                ErrorCode::UnknownColumn,
+                                format!("Table has no column named '{}'", column_name),
+                            ));
+                        }
+                        return Ok(TypedExpr {
+                            expr: Expr::FunctionCall(name.clone(), args.clone()),
+                            expr_type: TypeConstruct::Table(params),
+                        });
+                    }
+
+                    Ok(TypedExpr {
+                        expr: Expr::FunctionCall(name.clone(), args.clone()),
+                        expr_type: *return_type.clone(),
+                    })
+                } else {
+                    Err(format!("'{}' is not a function", name).into())
+                }
+            } else {
+                Err(TypeError::coded(
+                    ErrorCode::UndefinedName,
+                    format!(
+                        "Undefined function '{}'.{}",
+                        name,
+                        did_you_mean_in_scope(name, scope_stack)
+                    ),
+                ))
+            }
+        }
+
+        // Case: pipe operation (e.g., `x pipe f`)
+        Expr::Pipe(left, pipe_name, args) => {
+            let left_typed = infer_type(left, scope_stack)?;
+
+            // Check is the left side is a pipe
             let is_left_pipe = matches!(**left, Expr::Pipe(_, _, _));
 
+            // A function on the left is almost always a pipe written the wrong way around
+            if let (Expr::Identifier(function_name), TypeConstruct::Function(_, _)) =
+                (&**left, &left_typed.expr_type)
+            {
+                return Err(TypeError::coded(
+                    ErrorCode::InvalidPipeFunction,
+                    pipe_left_is_function_message(function_name, pipe_name),
+                ));
+            }
+
             // If the left side is not a pipe, check if it is a type that can be piped
             // The only type that can be piped is a table
             if !is_left_pipe && !matches!(left_typed.expr_type, TypeConstruct::Table(_)) {
-                return Err(format!(
-                    "A pipeline must start with a Table, but got: {:?}",
-                    left_typed.expr_type
+                return Err(TypeError::coded(
+                    ErrorCode::InvalidPipeFunction,
+                    format!(
+                        "A pipeline must start with a Table, but got: {:?}",
+                        left_typed.expr_type
+                    ),
                 ));
             }
 
+            // A bound stage, e.g. pipe bind("at_least", 21), is the same as the function with the bound arguments after the rows
+            if pipe_name == "bind" {
+                if let TypeConstruct::Function(_, unbound) =
+                    infer_bind_type(args, scope_stack)?.expr_type
+                    && unbound.len() != 1
+                {
+                    return Err(format!(
+                        "A function bound in a pipe must have one unbound parameter for the rows, found {}",
+                        unbound.len()
+                    ).into());
+                }
+                let (function_name, bound) = bound_function_name(args, scope_stack)?;
+                return infer_type(
+                    &Expr::Pipe(left.clone(), function_name, bound.to_vec()),
+                    scope_stack,
+                );
+            }
+
+            // A stage into a lambda is checked as a stage, with no arguments, into a function of the type of the lambda
+            if pipe_name == LAMBDA_STAGE
+                && let [lambda] = args.as_slice()
+            {
+                let lambda_type = infer_type(lambda, scope_stack)?.expr_type;
+                push_scope(scope_stack);
+                scope_stack.last_mut().unwrap().insert(
+                    LAMBDA_STAGE.to_string(),
+                    VariableInfo {
+                        var_type: lambda_type,
+                        is_constant: true,
+                        value: None,
+                        is_table_loop_row: false,
+                    },
+                );
+                let typed = infer_type(
+                    &Expr::Pipe(left.clone(), pipe_name.clone(), Vec::new()),
+                    scope_stack,
+                );
+                pop_scope(scope_stack);
+                return Ok(TypedExpr {
+                    expr: expr.clone(),
+                    expr_type: typed?.expr_type,
+                });
+            }
+
             // Check if the pipe function is defined
             if let Some(func_type) = lookup_variable(pipe_name, scope_stack) {
                 if let TypeConstruct::Function(return_type, param_types) = &func_type.var_type {
@@ -593,14 +1385,35 @@ fn infer_type(
                     // If the function is a pipe function, we need to check if the number of arguments matches
                     // the number of parameters
                     if effective_args.len() != param_types.len() {
-                        return Err(format!(
-                            "Pipe function '{}' expected {} arguments, found {}",
-                            pipe_name,
-                            param_types.len(),
-                            effective_args.len()
+                        return Err(TypeError::coded(
+                            ErrorCode::ArgumentCount,
+                            format!(
+                                "Pipe function '{}' expected {} arguments, found {}",
+                                pipe_name,
+                                param_types.len(),
+                                effective_args.len()
+                            ),
                         ));
                     }
 
+                    // The arguments given after the piped rows or table are checked as in a function call
+                    if args.len() + 1 == param_types.len() {
+                        for (arg, param_type) in args.iter().zip(&param_types[1..]) {
+                            let arg_typed = infer_type(arg, scope_stack)?;
+                            if *param_type != TypeConstruct::Any
+                                && !value_matches(param_type, &arg_typed)?
+                            {
+                                return Err(TypeError::coded(
+                                    ErrorCode::PipeSchemaMismatch,
+                                    format!(
+                                        "Type mismatch in argument to pipe function '{}': expected {:?}, found {:?}",
+                                        pipe_name, param_type, arg_typed.expr_type
+                                    ),
+                                ));
+                            }
+                        }
+                    }
+
                     let allowed = matches!(
                         (&param_types[0], &**return_type),
                         (TypeConstruct::Row(_), TypeConstruct::Row(_))
@@ -613,13 +1426,14 @@ fn infer_type(
                     if pipe_name == "print" {
                         // Check if the left side is a pipe
                         // Print must be the last pipe
-                        if let Expr::Pipe(_boxed_left, left_pipe_name, _) = &left_typed.expr {
-                            if left_pipe_name == "print" {
-                                return Err("You cannot use the result of print() in another pipe. 'print' must be the last pipe.".to_string());
-                            }
+                        if let Expr::Pipe(_boxed_left, left_pipe_name, _) = &left_typed.expr
+                            && left_pipe_name == "print"
+                        {
+                            return Err("You cannot use the result of print() in another pipe. 'print' must be the last pipe.".to_string().into());
                         }
 
                         // Check if the left side is a table when using print
+                        // The pipe gives an empty table with the columns of the rows printed
                         if let TypeConstruct::Table(_) = left_typed.expr_type {
                             return Ok(TypedExpr {
                                 expr: Expr::Pipe(
@@ -627,41 +1441,88 @@ fn infer_type(
                                     pipe_name.clone(),
                                     args.clone(),
                                 ),
-                                expr_type: TypeConstruct::Table(vec![]), // Return a empty table type
+                                expr_type: left_typed.expr_type,
                             });
                         } else {
                             return Err(format!(
                                 "Pipe function 'print' must be used with a table. Got: {:?}",
                                 left_typed.expr_type
-                            ));
+                            )
+                            .into());
                         }
                     }
 
                     if !allowed {
-                        return Err(format!(
-                            "Pipe function '{}' must be one of: Row->Row (map), Row->Bool (filter), Table->Table (reduce) with matching columns. Got: {:?} -> {:?}",
-                            pipe_name, param_types[0], return_type
+                        return Err(TypeError::coded(
+                            ErrorCode::PipeSchemaMismatch,
+                            format!(
+                                "Pipe function '{}' must be one of: Row->Row (map), Row->Bool (filter), Table->Table (reduce) with matching columns. Got: {:?} -> {:?}",
+                                pipe_name, param_types[0], return_type
+                            ),
                         ));
                     }
 
+                    // The pipe gives a table of the rows sent by its last stage.
+                    // A map sends the rows it returns, and a filter the rows it receives
+                    let expr_type = match (&param_types[0], &**return_type) {
+                        (TypeConstruct::Row(_), TypeConstruct::Row(params)) => {
+                            TypeConstruct::Table(params.clone())
+                        }
+                        (TypeConstruct::Row(_), TypeConstruct::Bool) => left_typed.expr_type,
+                        _ => *return_type.clone(),
+                    };
+
                     Ok(TypedExpr {
                         expr: Expr::Pipe(
                             Box::new(left_typed.expr),
                             pipe_name.clone(),
                             args.clone(),
                         ),
-                        expr_type: *return_type.clone(),
+                        expr_type,
                     })
+                } else if let TypeConstruct::Table(_) = func_type.var_type {
+                    Err(TypeError::coded(
+                        ErrorCode::InvalidPipeFunction,
+                        pipe_right_is_table_message(pipe_name),
+                    ))
                 } else {
-                    Err(format!("'{}' is not a valid pipe function", pipe_name))
+                    Err(TypeError::coded(
+                        ErrorCode::InvalidPipeFunction,
+                        format!("'{}' is not a valid pipe function", pipe_name),
+                    ))
                 }
             } else {
-                Err(format!("Undefined pipe function '{}'", pipe_name))
+                Err(TypeError::coded(
+                    ErrorCode::InvalidPipeFunction,
+                    format!("Undefined pipe function '{}'", pipe_name),
+                ))
             }
         }
 
+        // Case: a lambda, which has the type of a function with its parameters and return type
+        Expr::Lambda(params, return_type, body) => {
+            type_check_function_body(
+                return_type,
+                params,
+                body,
+                scope_stack,
+                lambda_captures(scope_stack),
+            )?;
+            Ok(TypedExpr {
+                expr: expr.clone(),
+                expr_type: TypeConstruct::Function(
+                    Box::new(return_type.clone()),
+                    params
+                        .iter()
+                        .map(|Parameter::Parameter(param_type, _)| param_type.clone())
+                        .collect(),
+                ),
+            })
+        }
+
         // Case: table
-        Expr::Table(params) => {
+        Expr::Table(params, defaults, rows) => {
+            check_column_count("table", params.len())?;
             let mut param_types = Vec::new();
             let mut seen_names = HashSet::new();
 
@@ -670,9 +1531,12 @@ fn infer_type(
                     Parameter::Parameter(param_type, param_name) => {
                         // Check for duplicate parameter names
                         if !seen_names.insert(param_name.clone()) {
-                            return Err(format!(
-                                "Duplicate parameter name '{}' in table declaration",
-                                param_name
+                            return Err(TypeError::coded(
+                                ErrorCode::DuplicateColumn,
+                                format!(
+                                    "Duplicate parameter name '{}' in table declaration",
+                                    param_name
+                                ),
                             ));
                         }
                         param_types
@@ -681,24 +1545,89 @@ fn infer_type(
                 }
             }
 
+            // A default is a value of its column, where an int is widened in a double column like in every table
+            let mut typed_defaults = Vec::new();
+            for (name, default) in defaults {
+                let typed_default = infer_type(default, scope_stack)?;
+                let column_type = param_types
+                    .iter()
+                    .find_map(|Parameter::Parameter(t, n)| (n == name).then_some(t))
+                    .unwrap();
+                let widened = *column_type == TypeConstruct::Double
+                    && typed_default.expr_type == TypeConstruct::Int;
+                if *column_type != typed_default.expr_type
+                    && !widened
+                    && !enum_literal_matches(column_type, &typed_default.expr)?
+                {
+                    return Err(TypeError::coded(
+                        ErrorCode::TypeMismatch,
+                        format!(
+                            "Type mismatch: expected {:?}, found {:?} for the default of column '{}'",
+                            column_type, typed_default.expr_type, name
+                        ),
+                    ));
+                }
+                typed_defaults.push((name.clone(), Box::new(typed_default.expr)));
+            }
+
+            // The rows the table starts with have its columns, but may leave out those with a default or declared nullable
+            let mut typed_rows = Vec::new();
+            for (i, row) in rows.iter().enumerate() {
+                let typed_row = infer_type(row, scope_stack)?;
+                let expected = TypeConstruct::Row(
+                    param_types
+                        .iter()
+                        .filter(|Parameter::Parameter(t, name)| {
+                            let filled = matches!(t, TypeConstruct::Nullable(_))
+                                || defaults.iter().any(|(default, _)| default == name);
+                            let given = matches!(&typed_row.expr_type, TypeConstruct::Row(columns)
+                                if columns.iter().any(|Parameter::Parameter(_, n)| n == name));
+                            !filled || given
+                        })
+                        .cloned()
+                        .collect(),
+                );
+                if !types_match(&expected, &typed_row.expr_type) {
+                    return Err(TypeError::coded(
+                        ErrorCode::TypeMismatch,
+                        format!(
+                            "{} for row {} of the table",
+                            type_mismatch(&expected, &typed_row.expr_type),
+                            i + 1
+                        ),
+                    ));
+                }
+                typed_rows.push(Box::new(typed_row.expr));
+            }
+
             Ok(TypedExpr {
-                expr: Expr::Table(params.clone()),
+                expr: Expr::Table(params.clone(), typed_defaults, typed_rows),
                 expr_type: TypeConstruct::Table(param_types),
             })
         }
 
         // Case: row
         Expr::Row(column_assignments) => {
+            check_column_count("row", column_assignments.len())?;
             let mut param_types = Vec::new();
             for column in column_assignments {
                 // Match on the type of column assignment
                 match column {
                     ColumnAssignmentEnum::ColumnAssignment(param_type, param_name, expr) => {
                         let typed_expr = infer_type(expr, scope_stack)?;
-                        if *param_type != typed_expr.expr_type {
-                            return Err(format!(
-                                "Type mismatch: expected {:?}, found {:?} for column '{}'",
-                                param_type, typed_expr.expr_type, param_name
+                        // An int is widened when stored in a double column, like in every table
+                        let widened = *param_type == TypeConstruct::Double
+                            && typed_expr.expr_type == TypeConstruct::Int;
+                        if *param_type != typed_expr.expr_type
+                            && !widened
+                            && !enum_literal_matches(param_type, &typed_expr.expr)?
+                        {
+                            return Err(TypeError::coded(
+                                ErrorCode::TypeMismatch,
+                                format!(
+                                    "Type mismatch: expected {:?}, found {:?} for column '{}'",
+                                    param_type, typed_expr.expr_type, param_name
+                                ),
                             ));
                         }
                         param_types
@@ -713,31 +1642,398 @@ fn infer_type(
         }
 
         // Case: column indexing
+        // Ranges are only parsed as what a for-loop iterates, which checks them, see type_check_range_loop
+        Expr::Range(_, _, _) => Err("A range, e.g. 0..10, can only be iterated by a for-loop"
+            .to_string()
+            .into()),
         Expr::ColumnIndexing(table_expr, column_name) => {
             let table_typed = infer_type(table_expr, scope_stack)?;
 
-            match &table_typed.expr_type {
-                TypeConstruct::Table(params) | TypeConstruct::Row(params) => {
-                    for Parameter::Parameter(col_type, col_name) in params {
-                        if col_name == column_name {
-                            return Ok(TypedExpr {
-                                expr: Expr::ColumnIndexing(
-                                    Box::new(table_typed.expr),
-                                    column_name.clone(),
-                                ),
-                                expr_type: col_type.clone(),
-                            });
-                        }
-                    }
-                    Err(format!(
-                        "Column '{}' not found in {:?}",
-                        column_name, table_typed.expr_type
-                    ))
+            // A column of a row is a single value, a column of a table or an array of rows is an array of values
+            let (params, is_array) = match &table_typed.expr_type {
+                TypeConstruct::Row(params) => (params, false),
+                TypeConstruct::Table(params) => (params, true),
+                TypeConstruct::Array(inner) => match inner.as_ref() {
+                    TypeConstruct::Row(params) => (params, true),
+                    _ => return Err("Cannot index into non-table/row type".to_string().into()),
+                },
+                _ => return Err("Cannot index into non-table/row type".to_string().into()),
+            };
+
+            for Parameter::Parameter(col_type, col_name) in params {
+                if col_name == column_name {
+                    let expr_type = if is_array {
+                        TypeConstruct::Array(Box::new(col_type.clone()))
+                    } else {
+                        col_type.clone()
+                    };
+                    return Ok(TypedExpr {
+                        expr: Expr::ColumnIndexing(Box::new(table_typed.expr), column_name.clone()),
+                        expr_type,
+                    });
+                }
+            }
+            Err(TypeError::coded(
+                ErrorCode::UnknownColumn,
+                unknown_column_message(column_name, &param_columns(params)),
+            ))
+        }
+    }
+}
+
+// The columns of a table or row type as names and types, for unknown_column_message
+fn param_columns(params: &[Parameter]) -> Vec<(String, String)> {
+    params
+        .iter()
+        .map(|Parameter::Parameter(column_type, name)| (name.clone(), column_type.to_string()))
+        .collect()
+}
+
+// The name of the function given to bind, and the arguments bound to it
+fn bound_function_name<'a>(
+    args: &'a [Box<Expr>],
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<(String, &'a [Box<Expr>]), TypeError> {
+    let Some((function_name, bound)) = args.split_first() else {
+        return Err("bind expects the name of a function".to_string().into());
+    };
+    match infer_type(function_name, scope_stack)?.expr {
+        Expr::StringLiteral(function_name) => Ok((function_name.to_string(), bound)),
+        _ => Err(
+            "First argument to 'bind' must be the name of a function as a string literal or constant"
+                .to_string().into(),
+        ),
+    }
+}
+
+// bind fixes the last parameters of a user function, e.g. bind("at_least", 21) on fn bool at_least(row(int age) r, int age)
+// gives fn bool(row(int age)). The bound arguments are fixed when bind is evaluated, so they must be plain values
+fn infer_bind_type(
+    args: &[Box<Expr>],
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<TypedExpr, TypeError> {
+    let (function_name, bound) = bound_function_name(args, scope_stack)?;
+    if lookup_builtin(&function_name).is_some() {
+        return Err(format!(
+            "bind expects a user function, but '{}' is a builtin",
+            function_name
+        )
+        .into());
+    }
+    let Some(TypeConstruct::Function(return_type, param_types)) =
+        lookup_variable(&function_name, scope_stack).map(|info| info.var_type)
+    else {
+        return Err(TypeError::coded(
+            ErrorCode::UndefinedName,
+            format!("Undefined function '{}'", function_name),
+        ));
+    };
+    if bound.len() > param_types.len() {
+        return Err(TypeError::coded(
+            ErrorCode::ArgumentCount,
+            format!(
+                "Function '{}' takes {} arguments, but {} were bound",
+                function_name,
+                param_types.len(),
+                bound.len()
+            ),
+        ));
+    }
+    let unbound = param_types.len() - bound.len();
+    for (arg, param_type) in bound.iter().zip(&param_types[unbound..]) {
+        let arg_typed = infer_type(arg, scope_stack)?;
+        if !matches!(
+            param_type,
+            TypeConstruct::Int
+                | TypeConstruct::Double
+                | TypeConstruct::String
+                | TypeConstruct::Bool
+        ) {
+            return Err(format!(
+                "bind can only fix int, double, string or bool parameters, found {:?}",
+                param_type
+            )
+            .into());
+        }
+        cast_typed_expr(
+            &VariableInfo {
+                var_type: param_type.clone(),
+                is_constant: true,
+                value: None,
+                is_table_loop_row: false,
+            },
+            arg_typed,
+        )?;
+    }
+    Ok(TypedExpr {
+        expr: Expr::FunctionCall("bind".to_string(), args.to_vec()),
+        expr_type: TypeConstruct::Function(return_type, param_types[..unbound].to_vec()),
+    })
+}
+
+// table_with_column(t, "name", values) gives a copy of t with a column more, of the element type of the values
+// The builtin takes the column type from the values, so it can be given as a fourth argument for arrays that may be empty,
+// e.g. table_with_column(t, "half", t.id / 2.0, "double")
+fn infer_table_with_column_type(
+    args: &[Box<Expr>],
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<TypedExpr, TypeError> {
+    let (table, column_name, values, given_type) = match args {
+        [table, column_name, values] => (table, column_name, values, None),
+        [table, column_name, values, given_type] => (table, column_name, values, Some(given_type)),
+        _ => {
+            return Err(TypeError::coded(
+                ErrorCode::ArgumentCount,
+                format!(
+                    "Function 'table_with_column' expected 3 to 4 arguments, found {}",
+                    args.len()
+                ),
+            ));
+        }
+    };
+    let table_type = infer_type(table, scope_stack)?.expr_type;
+    let TypeConstruct::Table(mut params) = table_type else {
+        return Err(format!(
+            "First argument to 'table_with_column' must be a table, found {:?}",
+            table_type
+        )
+        .into());
+    };
+    let Expr::StringLiteral(column_name) = infer_type(column_name, scope_stack)?.expr else {
+        return Err(
+            "Second argument to 'table_with_column' must be the column name as a string literal or constant"
+                .to_string().into(),
+        );
+    };
+    let column_name = column_name.to_string();
+    if params
+        .iter()
+        .any(|Parameter::Parameter(_, name)| *name == column_name)
+    {
+        return Err(TypeError::coded(
+            ErrorCode::DuplicateColumn,
+            format!("Table already has a column named '{}'", column_name),
+        ));
+    }
+    let values_type = infer_type(values, scope_stack)?.expr_type;
+    let element_type = match &values_type {
+        TypeConstruct::Array(element)
+            if matches!(
+                **element,
+                TypeConstruct::Int
+                    | TypeConstruct::Double
+                    | TypeConstruct::String
+                    | TypeConstruct::Bool
+            ) =>
+        {
+            (**element).clone()
+        }
+        _ => {
+            return Err(format!(
+                "Third argument to 'table_with_column' must be an array of int, double, string or bool, found {}",
+                values_type
+            ).into());
+        }
+    };
+    let column_type = match given_type {
+        None => element_type,
+        Some(given_type) => {
+            let column_type = match infer_type(given_type, scope_stack)?.expr {
+                Expr::StringLiteral(t) if &*t == "int" => TypeConstruct::Int,
+                Expr::StringLiteral(t) if &*t == "double" => TypeConstruct::Double,
+                Expr::StringLiteral(t) if &*t == "string" => TypeConstruct::String,
+                Expr::StringLiteral(t) if &*t == "bool" => TypeConstruct::Bool,
+                _ => {
+                    return Err(
+                        "Fourth argument to 'table_with_column' must be \"int\", \"double\", \"string\" or \"bool\""
+                            .to_string().into(),
+                    );
                 }
-                _ => Err("Cannot index into non-table/row type".to_string()),
+            };
+            // Ints are widened to doubles, like in any double column
+            if column_type != element_type
+                && !(column_type == TypeConstruct::Double && element_type == TypeConstruct::Int)
+            {
+                return Err(format!(
+                    "Column '{}' is declared {}, but the values are {}",
+                    column_name, column_type, values_type
+                )
+                .into());
             }
+            column_type
         }
+    };
+    params.push(Parameter::Parameter(column_type, column_name));
+    Ok(TypedExpr {
+        expr: Expr::FunctionCall("table_with_column".to_string(), args.to_vec()),
+        expr_type: TypeConstruct::Table(params),
+    })
+}
+
+// to_table(rows) gives a table with the columns of the rows, and to_table_column(xs, "name") a table with the
+// single column. When the rows or the column name are not known here, the call is typed as a table of any columns
+fn infer_to_table_type(
+    name: &str,
+    args: &[Box<Expr>],
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<TypedExpr, TypeError> {
+    let expected = if name == "to_table" { 1 } else { 2 };
+    if args.len() != expected {
+        return Err(TypeError::coded(
+            ErrorCode::ArgumentCount,
+            format!(
+                "Function '{}' expected {} arguments, found {}",
+                name,
+                expected,
+                args.len()
+            ),
+        ));
     }
+    let values_type = infer_type(&args[0], scope_stack)?.expr_type;
+    let TypeConstruct::Array(element) = &values_type else {
+        return Err(format!(
+            "First argument to '{}' must be an array, found {}",
+            name, values_type
+        )
+        .into());
+    };
+    let params = match (name, &**element) {
+        ("to_table", TypeConstruct::Row(params)) => params.clone(),
+        ("to_table", TypeConstruct::Any) => vec![],
+        ("to_table", _) => {
+            return Err(format!(
+                "First argument to 'to_table' must be an array of rows, found {}",
+                values_type
+            )
+            .into());
+        }
+        (
+            _,
+            TypeConstruct::Int
+            | TypeConstruct::Double
+            | TypeConstruct::String
+            | TypeConstruct::Bool,
+        ) => {
+            let column_name = infer_type(&args[1], scope_stack)?;
+            match column_name.expr {
+                Expr::StringLiteral(column_name) => {
+                    vec![Parameter::Parameter(
+                        (**element).clone(),
+                        column_name.to_string(),
+                    )]
+                }
+                _ if column_name.expr_type == TypeConstruct::String => vec![],
+                _ => {
+                    return Err(format!(
+                        "Second argument to 'to_table_column' must be a column name, found {}",
+                        column_name.expr_type
+                    )
+                    .into());
+                }
+            }
+        }
+        (_, TypeConstruct::Any) => vec![],
+        _ => {
+            return Err(format!(
+                "First argument to 'to_table_column' must be an array of int, double, string or bool, found {}",
+                values_type
+            ).into());
+        }
+    };
+    Ok(TypedExpr {
+        expr: Expr::FunctionCall(name.to_string(), args.to_vec()),
+        expr_type: TypeConstruct::Table(params),
+    })
+}
+
+// get_or(xs, i, default) has the element type of xs, and row_get_or(r, "column", default) the type of the column,
+// or of the default if the row is not known to have the column. The default must have the type of the value it replaces
+fn infer_get_or_type(
+    name: &str,
+    args: &[Box<Expr>],
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<TypedExpr, TypeError> {
+    let [container, key, default] = args else {
+        return Err(TypeError::coded(
+            ErrorCode::ArgumentCount,
+            format!(
+                "Function '{}' expected 3 arguments, found {}",
+                name,
+                args.len()
+            ),
+        ));
+    };
+    let container_type = infer_type(container, scope_stack)?.expr_type;
+    let key_typed = infer_type(key, scope_stack)?;
+    let default_type = infer_type(default, scope_stack)?.expr_type;
+    let value_type = match (name, &container_type, &key_typed.expr_type) {
+        ("get_or", TypeConstruct::Array(element), TypeConstruct::Int) => Some((**element).clone()),
+        ("get_or", TypeConstruct::Array(_), key_type) => {
+            return Err(format!(
+                "Second argument to 'get_or' must be an int index, found {}",
+                key_type
+            )
+            .into());
+        }
+        ("get_or", _, _) => {
+            return Err(format!(
+                "First argument to 'get_or' must be an array, found {}",
+                container_type
+            )
+            .into());
+        }
+        (_, TypeConstruct::Row(params), TypeConstruct::String) => match &key_typed.expr {
+            Expr::StringLiteral(column_name) => params
+                .iter()
+                .find(|Parameter::Parameter(_, name)| name.as_str() == &**column_name)
+                .map(|Parameter::Parameter(column_type, _)| column_type.clone()),
+            _ => None,
+        },
+        (_, TypeConstruct::Row(_), key_type) => {
+            return Err(format!(
+                "Second argument to 'row_get_or' must be a column name, found {}",
+                key_type
+            )
+            .into());
+        }
+        _ => {
+            return Err(format!(
+                "First argument to 'row_get_or' must be a row, found {}",
+                container_type
+            )
+            .into());
+        }
+    };
+    let expr_type = match value_type {
+        None => default_type,
+        Some(value_type)
+            if types_match(&value_type, &default_type)
+                || (value_type == TypeConstruct::Double && default_type == TypeConstruct::Int) =>
+        {
+            value_type
+        }
+        Some(value_type) => {
+            return Err(format!(
+                "Default for '{}' must be {}, found {}",
+                name, value_type, default_type
+            )
+            .into());
+        }
+    };
+    Ok(TypedExpr {
+        expr: Expr::FunctionCall(name.to_string(), args.to_vec()),
+        expr_type,
+    })
+}
+
+// Helper function to suggest a declared name for a name that is not declared, see did_you_mean
+fn did_you_mean_in_scope(name: &str, scope_stack: &[HashMap<String, VariableInfo>]) -> String {
+    did_you_mean(
+        name,
+        scope_stack
+            .iter()
+            .flat_map(|scope| scope.keys().map(String::as_str)),
+    )
 }
 
 // Helper function to look up a variable in the scope stack
@@ -765,57 +2061,504 @@ fn pop_scope(scope_stack: &mut Vec<HashMap<String, VariableInfo>>) {
     scope_stack.pop();
 }
 
+// Helper function to type a binary operation, given the already typed operands
+// Int and Double operands can be mixed on either side, the Int is then widened to a Double when evaluated.
+// Comparisons are typed the same way, also when > and >= are desugared to ! with <= and <
+fn infer_operation_type(
+    left_typed: TypedExpr,
+    op: &Operator,
+    right_typed: TypedExpr,
+) -> Result<TypedExpr, TypeError> {
+    // The value of a nullable column can be compared with null. Otherwise it is used as a value of its type,
+    // and the operation stops the program if it is null
+    let is_nullable = |t: &TypeConstruct| matches!(t, TypeConstruct::Nullable(_));
+    if is_nullable(&left_typed.expr_type) || is_nullable(&right_typed.expr_type) {
+        let compared_with_null = left_typed.expr_type == TypeConstruct::Null
+            || right_typed.expr_type == TypeConstruct::Null;
+        if *op == Operator::Equals && compared_with_null {
+            return Ok(TypedExpr {
+                expr: Expr::Operation(
+                    Box::new(left_typed.expr),
+                    (*op).clone(),
+                    Box::new(right_typed.expr),
+                ),
+                expr_type: TypeConstruct::Bool,
+            });
+        }
+        let non_null = |t: TypeConstruct| match t {
+            TypeConstruct::Nullable(inner) => *inner,
+            t => t,
+        };
+        return infer_operation_type(
+            TypedExpr {
+                expr_type: non_null(left_typed.expr_type),
+                ..left_typed
+            },
+            op,
+            TypedExpr {
+                expr_type: non_null(right_typed.expr_type),
+                ..right_typed
+            },
+        );
+    }
+
+    let left_type = &left_typed.expr_type;
+    let right_type = &right_typed.expr_type;
+
+    if matches!(left_type, TypeConstruct::Row(_) | TypeConstruct::Table(_))
+        || matches!(right_type, TypeConstruct::Row(_) | TypeConstruct::Table(_))
+    {
+        return Err(TypeError::coded(
+            ErrorCode::IncompatibleOperands,
+            "Operation on Row or Table types is not allowed".to_string(),
+        ));
+    }
+
+    let expr_type = match op {
+        Operator::Or => {
+            if *left_type != TypeConstruct::Bool || *right_type != TypeConstruct::Bool {
+                return Err(TypeError::coded(
+                    ErrorCode::IncompatibleOperands,
+                    "Logical operators require boolean operands".to_string(),
+                ));
+            }
+            TypeConstruct::Bool
+        }
+        // The value of an enum can be compared with a string or a value of the same enum. A string literal it is
+        // compared with must be one of the values of the enum, which catches typos like r.status == "actve"
+        Operator::Equals
+            if matches!(left_type, TypeConstruct::Enum(_, _))
+                || matches!(right_type, TypeConstruct::Enum(_, _)) =>
+        {
+            let (enum_type, other_type, other_expr) =
+                if matches!(left_type, TypeConstruct::Enum(_, _)) {
+                    (left_type, right_type, &right_typed.expr)
+                } else {
+                    (right_type, left_type, &left_typed.expr)
+                };
+            if !types_match(enum_type, other_type) && *other_type != TypeConstruct::String {
+                return Err(format!("Cannot compare {} with {}", enum_type, other_type).into());
+            }
+            enum_literal_matches(enum_type, other_expr)?;
+            TypeConstruct::Bool
+        }
+        // Booleans and strings can also be compared for equality, with a value of the same type
+        Operator::Equals
+            if left_type == right_type
+                && matches!(left_type, TypeConstruct::Bool | TypeConstruct::String) =>
+        {
+            TypeConstruct::Bool
+        }
+        // Characters are compared with characters, by their code point
+        Operator::Equals | Operator::LessThan | Operator::LessThanOrEqual
+            if *left_type == TypeConstruct::Char && *right_type == TypeConstruct::Char =>
+        {
+            TypeConstruct::Bool
+        }
+        Operator::Equals | Operator::LessThan | Operator::LessThanOrEqual => {
+            numeric_operation_type(left_type, right_type)?;
+            warn_lossy_widening(&left_typed, &right_typed);
+            warn_lossy_widening(&right_typed, &left_typed);
+            TypeConstruct::Bool
+        }
+        Operator::Addition
+        | Operator::Subtraction
+        | Operator::Multiplication
+        | Operator::Division
+        | Operator::Modulo
+        | Operator::Exponent => {
+            let result_type = arithmetic_operation_type(left_type, right_type)?;
+            // Check for division by zero
+            if let Operator::Division = op
+                && matches!(right_typed.expr, Expr::Number(0) | Expr::Double(0.0))
+            {
+                return Err(TypeError::coded(
+                    ErrorCode::DivisionByZero,
+                    "Division by zero is not allowed".to_string(),
+                ));
+            }
+            result_type
+        }
+    };
+
+    Ok(TypedExpr {
+        expr: Expr::Operation(
+            Box::new(left_typed.expr),
+            (*op).clone(),
+            Box::new(right_typed.expr),
+        ),
+        expr_type,
+    })
+}
+
+// Ints up to this size are exactly the same number when widened to a double
+const EXACT_DOUBLE_INT_LIMIT: i64 = 1 << 53;
+
+// Helper function to warn when an int literal is compared with a double and too large to widen exactly,
+// as the comparison is then done on a nearby double, e.g. 9007199254740993 == 9007199254740992.0 is true
+fn warn_lossy_widening(int_side: &TypedExpr, double_side: &TypedExpr) {
+    if let Expr::Number(n) = int_side.expr
+        && double_side.expr_type == TypeConstruct::Double
+        && n.unsigned_abs() > EXACT_DOUBLE_INT_LIMIT as u64
+    {
+        warn(format!(
+            "Warning: the int {} is compared with a double, which it cannot be widened to exactly. Ints above 2^53 may compare equal to doubles near them",
+            n
+        ));
+    }
+}
+
+// Helper function to find the type of an arithmetic operation. With an array of numbers on either side,
+// e.g. a table column, the operation is done element-wise and gives an array, so t.score * 1.1 is a double[]
+fn arithmetic_operation_type(
+    left_type: &TypeConstruct,
+    right_type: &TypeConstruct,
+) -> Result<TypeConstruct, TypeError> {
+    let element_type = |t: &TypeConstruct| match t {
+        TypeConstruct::Array(element) => (**element).clone(),
+        other => other.clone(),
+    };
+    if !matches!(left_type, TypeConstruct::Array(_))
+        && !matches!(right_type, TypeConstruct::Array(_))
+    {
+        return numeric_operation_type(left_type, right_type);
+    }
+    numeric_operation_type(&element_type(left_type), &element_type(right_type))
+        .map(|element| TypeConstruct::Array(Box::new(element)))
+        .map_err(|_| {
+            TypeError::coded(
+                ErrorCode::IncompatibleOperands,
+                format!(
+                    "Element-wise arithmetic needs arrays of int or double, and numbers. Left-hand side is {} and right-hand side is {}",
+                    left_type, right_type
+                ),
+            )
+        })
+}
+
+// Helper function to find the type of an arithmetic operation on two numbers. Double if either side is a Double
+fn numeric_operation_type(
+    left_type: &TypeConstruct,
+    right_type: &TypeConstruct,
+) -> Result<TypeConstruct, TypeError> {
+    match (left_type, right_type) {
+        (TypeConstruct::Int, TypeConstruct::Int) => Ok(TypeConstruct::Int),
+        (
+            TypeConstruct::Int | TypeConstruct::Double,
+            TypeConstruct::Int | TypeConstruct::Double,
+        ) => Ok(TypeConstruct::Double),
+        _ => Err(TypeError::coded(
+            ErrorCode::IncompatibleOperands,
+            format!(
+                "Operation on incompatible types. Left-hand side is {:?} and right-hand side is {:?}",
+                left_type, right_type
+            ),
+        )),
+    }
+}
+
 // Helper function to check and cast types
 fn check_and_cast_type(
     expected_type: &VariableInfo,
     expr: &Expr,
     scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
-) -> Result<Expr, String> {
-    let typed_expr = infer_type(expr, scope_stack)?;
+) -> Result<Expr, TypeError> {
+    let typed_expr = infer_type_expecting(&expected_type.var_type, expr, scope_stack)?;
+    cast_typed_expr(expected_type, typed_expr)
+}
+
+// Helper function to infer the type of an expression where a type is expected, like infer_type. An empty array literal
+// has no element to take its type from, so it takes the expected type, e.g. in var int[] xs = []; or var int[][] m = [[], [1]];
+fn infer_type_expecting(
+    expected: &TypeConstruct,
+    expr: &Expr,
+    scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
+) -> Result<TypedExpr, TypeError> {
+    match (expected, expr) {
+        (TypeConstruct::Array(_), Expr::Array(elements)) if elements.is_empty() => Ok(TypedExpr {
+            expr: expr.clone(),
+            expr_type: expected.clone(),
+        }),
+        (TypeConstruct::Array(element_type), Expr::Array(elements)) => {
+            let typed_elements = elements
+                .iter()
+                .map(|e| infer_type_expecting(element_type, e, scope_stack))
+                .collect::<Result<Vec<_>, _>>()?;
+            typed_array(typed_elements)
+        }
+        _ => infer_type(expr, scope_stack),
+    }
+}
+
+// Helper function to build an array literal of typed elements, which must all have the type of the first
+fn typed_array(typed_elements: Vec<TypedExpr>) -> Result<TypedExpr, TypeError> {
+    let element_type = typed_elements[0].expr_type.clone();
+    // Ensure all elements in the array have the same type
+    if typed_elements
+        .iter()
+        .skip(1)
+        .any(|t| !types_match(&element_type, &t.expr_type))
+    {
+        return Err("Array elements must have the same type".to_string().into());
+    }
+    // Build the array expression with typed elements
+    Ok(TypedExpr {
+        expr: Expr::Array(
+            typed_elements
+                .into_iter()
+                .map(|typed| Box::new(typed.expr))
+                .collect(),
+        ),
+        expr_type: TypeConstruct::Array(Box::new(element_type)),
+    })
+}
 
+// Helper function to cast an already typed expression to the expected type, without inferring its type again
+fn cast_typed_expr(expected_type: &VariableInfo, typed_expr: TypedExpr) -> Result<Expr, TypeError> {
+    if enum_literal_matches(&expected_type.var_type, &typed_expr.expr)? {
+        return Ok(typed_expr.expr);
+    }
     match (&expected_type.var_type, &typed_expr.expr_type) {
         // Implicit cast from Int to Double allowed
         (TypeConstruct::Double, TypeConstruct::Int) => Ok(typed_expr.expr.clone()),
         // Implicit cast from Double to Int not allowed
-        (TypeConstruct::Int, TypeConstruct::Double) => Err(format!(
-            "Cannot implicitly cast Double to Int. Expected {:?}, found {:?}",
-            expected_type, typed_expr.expr_type
+        (TypeConstruct::Int, TypeConstruct::Double) => Err(TypeError::coded(
+            ErrorCode::DoubleToInt,
+            format!(
+                "Cannot implicitly cast Double to Int. Expected {:?}, found {:?}",
+                expected_type, typed_expr.expr_type
+            ),
         )),
 
         // If the expected type matches the inferred type
-        _ if expected_type.var_type == typed_expr.expr_type => Ok(typed_expr.expr),
+        _ if types_match(&expected_type.var_type, &typed_expr.expr_type) => Ok(typed_expr.expr),
         // If the types do not match, return an error
-        _ => Err(format!(
-            "Type mismatch: expected {:?}, found {:?}",
-            expected_type, typed_expr.expr_type
+        _ => Err(TypeError::coded(
+            ErrorCode::TypeMismatch,
+            type_mismatch(&expected_type.var_type, &typed_expr.expr_type),
         )),
     }
 }
 
+// Helper function to check that a table or row declares no more columns than the limit, see SourceLimits
+fn check_column_count(kind: &str, columns: usize) -> Result<(), TypeError> {
+    let limit = source_limits().max_columns;
+    if columns > limit {
+        return Err(over_limit_message(
+            &format!("{} declares {} columns", kind, columns),
+            limit,
+            "--max-columns",
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// Helper function to compare the type of a value with the type it is expected to be, like types_match.
+// A string literal is also accepted where an enum is expected, if it is one of the values of the enum
+fn value_matches(expected: &TypeConstruct, found: &TypedExpr) -> Result<bool, TypeError> {
+    Ok(enum_literal_matches(expected, &found.expr)? || types_match(expected, &found.expr_type))
+}
+
+// Helper function to check a string literal given where an enum, or a nullable enum, is expected
+// Returns whether the expression is such a literal, or an error if the literal is not one of the values of the enum
+fn enum_literal_matches(expected: &TypeConstruct, expr: &Expr) -> Result<bool, TypeError> {
+    let expected = match expected {
+        TypeConstruct::Nullable(inner) => inner,
+        expected => expected,
+    };
+    match (expected, expr) {
+        (TypeConstruct::Enum(name, values), Expr::StringLiteral(value)) => {
+            if values.iter().any(|v| **v == **value) {
+                Ok(true)
+            } else {
+                Err(not_an_enum_value_message(name, values, value).into())
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+// The error for a string literal that is not one of the values of an enum, suggesting the value closest to it
+fn not_an_enum_value_message(name: &str, values: &[String], value: &str) -> String {
+    format!(
+        "\"{}\" is not a value of enum {}, which is one of {}.{}",
+        value,
+        name,
+        values
+            .iter()
+            .map(|v| format!("\"{}\"", v))
+            .collect::<Vec<_>>()
+            .join(", "),
+        did_you_mean(value, values.iter().map(String::as_str))
+    )
+}
+
+// Helper function to compare a type with the type it is expected to be
+// Columns of tables and rows are compared by name, so the order they are declared in does not matter.
+// A table of unknown columns, e.g. returned by print, can be given where any table is expected
+fn types_match(expected: &TypeConstruct, found: &TypeConstruct) -> bool {
+    match (expected, found) {
+        (TypeConstruct::Table(_), TypeConstruct::Table(found_params))
+            if found_params.is_empty() =>
+        {
+            true
+        }
+        (TypeConstruct::Table(expected_params), TypeConstruct::Table(found_params))
+        | (TypeConstruct::Row(expected_params), TypeConstruct::Row(found_params)) => {
+            column_difference(expected_params, found_params).is_none()
+        }
+        (TypeConstruct::Array(expected_element), TypeConstruct::Array(found_element)) => {
+            types_match(expected_element, found_element)
+        }
+        // A column that is never null can be given where a nullable column is expected, but not the other way around
+        (TypeConstruct::Nullable(expected_inner), found) => match found {
+            TypeConstruct::Nullable(found_inner) => types_match(expected_inner, found_inner),
+            found => types_match(expected_inner, found),
+        },
+        // Enums are told apart by name. The value of an enum can be used as a string, but not the other way around
+        (TypeConstruct::Enum(expected_name, _), TypeConstruct::Enum(found_name, _)) => {
+            expected_name == found_name
+        }
+        (TypeConstruct::String, TypeConstruct::Enum(_, _)) => true,
+        (
+            TypeConstruct::Function(expected_return, expected_params),
+            TypeConstruct::Function(found_return, found_params),
+        ) => {
+            types_match(expected_return, found_return)
+                && expected_params.len() == found_params.len()
+                && expected_params
+                    .iter()
+                    .zip(found_params)
+                    .all(|(e, f)| types_match(e, f))
+        }
+        _ => expected == found,
+    }
+}
+
+// Helper function to describe the first column, by name, that differs between two tables or rows
+fn column_difference(expected: &[Parameter], found: &[Parameter]) -> Option<String> {
+    let expected_columns: HashMap<&String, &TypeConstruct> = expected
+        .iter()
+        .map(|Parameter::Parameter(t, name)| (name, t))
+        .collect();
+    let found_columns: HashMap<&String, &TypeConstruct> = found
+        .iter()
+        .map(|Parameter::Parameter(t, name)| (name, t))
+        .collect();
+
+    let mut names: Vec<&String> = expected_columns
+        .keys()
+        .chain(found_columns.keys())
+        .copied()
+        .collect();
+    names.sort();
+    names.dedup();
+    names.into_iter().find_map(
+        |name| match (expected_columns.get(name), found_columns.get(name)) {
+            (Some(_), None) => Some(format!("column '{}' is missing", name)),
+            (None, Some(_)) => Some(format!("column '{}' is not declared", name)),
+            (Some(e), Some(f)) if !types_match(e, f) => Some(format!(
+                "column '{}' is declared {:?}, but found {:?}",
+                name, e, f
+            )),
+            _ => None,
+        },
+    )
+}
+
+// Helper function for the error when a type is not the expected one
+// For tables and rows the error names the column that differs
+fn type_mismatch(expected: &TypeConstruct, found: &TypeConstruct) -> String {
+    match (expected, found) {
+        (TypeConstruct::Table(expected_params), TypeConstruct::Table(found_params))
+        | (TypeConstruct::Row(expected_params), TypeConstruct::Row(found_params)) => {
+            if let Some(difference) = column_difference(expected_params, found_params) {
+                return format!(
+                    "Type mismatch: {}, expected {:?}, found {:?}",
+                    difference, expected, found
+                );
+            }
+        }
+        _ => {}
+    }
+    format!("Type mismatch: expected {:?}, found {:?}", expected, found)
+}
+
+// Whether a return statement is in the block, not counting those in the functions declared in it
+fn contains_return(body: &Statement) -> bool {
+    let mut statements = vec![body];
+    while let Some(statement) = statements.pop() {
+        match statement {
+            Statement::Return(_) => return true,
+            Statement::Compound(first, rest) => statements.extend([&**first, &**rest]),
+            Statement::If(_, body, else_body) => statements.extend([&**body, &**else_body]),
+            Statement::For(_, _, _, body)
+            | Statement::While(_, body)
+            | Statement::Defer(body)
+            | Statement::Counted(_, body)
+            | Statement::Located(_, body) => statements.push(body),
+            _ => {}
+        }
+    }
+    false
+}
+
+// Whether every way through the statement ends in a return. A loop may run no times, so it is not counted on
+fn always_returns(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return(_) => true,
+        Statement::Compound(first, rest) => {
+            let mut statements = vec![&**first];
+            let mut rest = &**rest;
+            while let Statement::Compound(next, next_rest) = rest {
+                statements.push(next);
+                rest = next_rest;
+            }
+            statements.push(rest);
+            statements.into_iter().any(always_returns)
+        }
+        Statement::If(_, body, else_body) => always_returns(body) && always_returns(else_body),
+        Statement::Counted(_, statement) | Statement::Located(_, statement) => {
+            always_returns(statement)
+        }
+        _ => false,
+    }
+}
+
 fn validate_return_type(
     body: &Statement,
     expected_return_type: &TypeConstruct,
     scope_stack: &mut Vec<HashMap<String, VariableInfo>>,
-) -> Result<(), String> {
+) -> Result<(), TypeError> {
     match body {
         Statement::Return(expr) => {
             let typed_expr = infer_type(expr, scope_stack)?;
-            if typed_expr.expr_type != *expected_return_type {
-                return Err(format!(
-                    "Return type mismatch: expected {:?}, found {:?}",
-                    expected_return_type, typed_expr.expr_type
+            if !value_matches(expected_return_type, &typed_expr)? {
+                return Err(TypeError::coded(
+                    ErrorCode::ReturnTypeMismatch,
+                    format!(
+                        "Return type mismatch: expected {:?}, found {:?}",
+                        expected_return_type, typed_expr.expr_type
+                    ),
                 ));
             }
         }
+        // The chain of statements in a function body is walked in a loop, so long functions do not grow the stack
         Statement::Compound(stmt1, stmt2) => {
             validate_return_type(stmt1, expected_return_type, scope_stack)?;
-            validate_return_type(stmt2, expected_return_type, scope_stack)?;
+            let mut rest = stmt2;
+            while let Statement::Compound(next, next_rest) = &**rest {
+                validate_return_type(next, expected_return_type, scope_stack)?;
+                rest = next_rest;
+            }
+            validate_return_type(rest, expected_return_type, scope_stack)?;
         }
         Statement::If(_, body, else_body) => {
             validate_return_type(body, expected_return_type, scope_stack)?;
             validate_return_type(else_body, expected_return_type, scope_stack)?;
         }
-        Statement::While(_, body) => {
+        Statement::While(_, body) | Statement::Located(_, body) => {
             validate_return_type(body, expected_return_type, scope_stack)?;
         }
         _ => {}
@@ -828,13 +2571,14 @@ fn validate_return_type(
 mod tests {
 
     use super::*;
-    use crate::frontend::main::create_syntax_tree;
+    use crate::frontend::diagnostics::ErrorCode;
+    use crate::frontend::main::{create_global_environment, create_syntax_tree};
 
     //type casting unit tests
     #[test]
     fn test_illegal_double_to_int_shallowing() {
         let statement = "var int a = 5; var double b = 4.5; a = b;";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -847,7 +2591,7 @@ mod tests {
     fn test_legal_double_plus_int_implicit() {
         let statement =
             "var double a = 3.5; var int b = 4; var double c = b; var double result = a + c;";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -859,7 +2603,7 @@ mod tests {
     #[test]
     fn test_illegal_operation_between_incompatible_types() {
         let statement = "var string a = \"hello\"; var int b = 5; var string result = a + b;";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -871,7 +2615,7 @@ mod tests {
     #[test]
     fn test_illegal_scope_in_with_functions() {
         let statement = "var int a = 5; fn int f() { var int b = 10; return a + b; };";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -888,7 +2632,7 @@ mod tests {
             };
             var double result = add(3.5, 4); 
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -905,7 +2649,7 @@ mod tests {
             };
             var int result = add(3, 4); 
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -918,13 +2662,13 @@ mod tests {
     fn test_variable_shadowing_in_nested_scopes() {
         let statement = "
             var int a = 5;
-            fn int f() {
+            fn null f() {
                 var int a = 10; 
                 a = a + 1;
             };
             a = a + 2; 
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -941,7 +2685,7 @@ mod tests {
             };
             var int result = add(3,3); 
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -957,7 +2701,7 @@ mod tests {
                 return a + 0.5;
             };
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -974,7 +2718,7 @@ mod tests {
             };
             var int result = add(3);
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -991,7 +2735,7 @@ mod tests {
             };
             var int result = add(3, 4, 5);
         ";
-        let tree = create_syntax_tree(statement);
+        let tree = create_syntax_tree(statement).unwrap();
         let mut scope_stack = vec![HashMap::new()];
         let result = type_check(&tree, &mut scope_stack);
         assert!(
@@ -1007,7 +2751,7 @@ mod tests {
     #[test]
     fn test_legal_explicit_double_to_int() {
         let source = "var double num1 = 5.4; var int num2 = (int) num1;";
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
         assert!(
             result.is_ok(),
@@ -1018,7 +2762,7 @@ mod tests {
     #[test]
     fn test_legal_explicit_int_to_double() {
         let source = "var int num1 = 5; var double num2 = (double) num1;";
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
         assert!(
             result.is_ok(),
@@ -1031,7 +2775,7 @@ mod tests {
     #[test]
     fn test_illegal_implicit_narrowing() {
         let code = "var double a = 7.35; var int b = a;";
-        let tree = create_syntax_tree(code);
+        let tree = create_syntax_tree(code).unwrap();
         let result = type_check(&tree);
         assert!(result.is_err(), "You cannot implicitly narrow a double!"); //assert will get a bool, not an option
     }
@@ -1041,7 +2785,7 @@ mod tests {
     #[test]
     fn test_illegal_string_plus_string() {
         let source = "var string mystring1 = \"Hello\"; var string mystring2 = \"World\"; var string result = mystring1 + mystring2;";
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
         assert!(result.is_err(), "String concatenation is not allowed!");
     }
@@ -1053,7 +2797,7 @@ mod tests {
         var string mystring = "Hello?";
         var int result = myinteger + mystring;
     "#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
 
         assert!(result.is_err(), "You cannot perform int + string");
@@ -1062,7 +2806,7 @@ mod tests {
     #[test]
     fn test_illegal_assign_string_to_int() {
         let source = r#"var int x = "Hello World";"#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
 
         assert!(
@@ -1081,7 +2825,7 @@ mod tests {
         var bool c = x or y;
         var bool d = x and y;
         "#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
 
         assert!(
@@ -1093,7 +2837,7 @@ mod tests {
     #[test]
     fn test_illegal_array_index() {
         let source = r#" var bool index = true; var string array[] myfruits = ["apple", "banana", "strawberry"]; var string lastfruit = myfruits[index];"#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
 
         assert!(
@@ -1105,7 +2849,7 @@ mod tests {
     #[test]
     fn test_illegal_if_branch() {
         let source = r#" var int x = 1 ; var string mystring = "candy"; if (mystring) {x + 1} "#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
         assert!(
             result.is_err(),
@@ -1117,7 +2861,7 @@ mod tests {
     fn test_illegal_if_branch_2() {
         let source =
             r#" var bool condition = true ; var int myint = 100 ; if (condition) {x = "Hi"} "#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
         assert!(
             result.is_err(),
@@ -1133,9 +2877,1235 @@ mod tests {
             count = count + 1;
         }
         "#;
-        let tree = create_syntax_tree(source);
+        let tree = create_syntax_tree(source).unwrap();
         let result = type_check(&tree);
         assert!(result.is_err(), "Cannot change value of const!")
     }
      */
+
+    #[test]
+    fn test_table_clone_keeps_columns() {
+        let statement = "
+            var table(int id) t = table(int id);
+            var table(int id) c = table_clone(t);
+        ";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let result = type_check(&tree, &mut scope_stack);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_table_clone_requires_table() {
+        let statement = "var table(int id) c = table_clone(5);";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let result = type_check(&tree, &mut scope_stack);
+        assert!(result.is_err(), "Only tables can be cloned");
+    }
+
+    #[test]
+    fn test_import_accepts_options_row() {
+        let statement =
+            "var table(int id) t = import(\"a.csv\", table(int id), row(bool strict = true));";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert!(type_check(&tree, &mut scope_stack).is_ok());
+    }
+
+    #[test]
+    fn test_import_options_must_be_row() {
+        let statement = "var table(int id) t = import(\"a.csv\", table(int id), true);";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let result = type_check(&tree, &mut scope_stack);
+        assert!(result.is_err(), "Import options must be a row");
+    }
+
+    #[test]
+    fn test_column_access_on_array_of_rows() {
+        let statement = "
+            var row(int id, string name)[] rows = [row(int id = 1, string name = \"a\")];
+            var string first = rows[0].name;
+            var int[] ids = rows.id;
+        ";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert!(type_check(&tree, &mut scope_stack).is_ok());
+    }
+
+    #[test]
+    fn test_column_access_on_array_of_rows_unknown_column() {
+        let statement = "
+            var row(int id)[] rows = [row(int id = 1)];
+            var int[] prices = rows.price;
+        ";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let result = type_check(&tree, &mut scope_stack);
+        assert!(
+            result
+                .unwrap_err()
+                .message
+                .contains("Column 'price' not found")
+        );
+    }
+
+    #[test]
+    fn test_rows_from_range_takes_structure_from_maker() {
+        let statement = "
+            fn row(int id) make(int i) { return row(int id = i); };
+            var table(int id) t = rows_from_range(10, \"make\");
+        ";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert!(type_check(&tree, &mut scope_stack).is_ok());
+    }
+
+    #[test]
+    fn test_rows_from_range_maker_must_return_row() {
+        let statement = "
+            fn int make(int i) { return i; };
+            var table(int id) t = rows_from_range(10, \"make\");
+        ";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let result = type_check(&tree, &mut scope_stack);
+        assert!(
+            result
+                .unwrap_err()
+                .message
+                .contains("must be a function from int to row")
+        );
+    }
+
+    #[test]
+    fn test_describe_returns_statistics_table() {
+        let statement = "
+            var table(int id) t = table(int id);
+            var table(string column, string type, int count, int nulls, double min, double max, double mean) d = describe(t);
+        ";
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert!(type_check(&tree, &mut scope_stack).is_ok());
+
+        let tree = create_syntax_tree("describe(5);").unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert!(type_check(&tree, &mut scope_stack).is_err());
+    }
+
+    // Type checks a program with the builtins in scope, giving the message of the error found
+    fn check_program(statement: &str) -> Result<(), String> {
+        check_program_coded(statement).map_err(|error| error.message)
+    }
+
+    // Type checks a program like check_program, giving the error found with its code
+    fn check_program_coded(statement: &str) -> Result<(), TypeError> {
+        let tree = create_syntax_tree(statement).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        type_check(&tree, &mut scope_stack)
+    }
+
+    #[test]
+    fn test_schema_annotated_variable_from_import() {
+        let statement = "var table(int id, string name) people = import(\"p.csv\", table(int id, string name));
+            var table(int id, string name) reordered = import(\"p.csv\", table(string name, int id));";
+        assert_eq!(check_program(statement), Ok(()));
+    }
+
+    #[test]
+    fn test_schema_annotated_row_and_constant_ignore_column_order() {
+        let statement = "var row(int id, string name) r = row(string name = \"a\", int id = 1);
+            const row(string name, int id) c = r;";
+        assert_eq!(check_program(statement), Ok(()));
+    }
+
+    #[test]
+    fn test_schema_annotated_function_return_and_parameter() {
+        let statement =
+            "fn table(int id, string name) keep(table(string name, int id) t) { return t; };
+            var table(int id, string name) t = table(int id, string name);
+            var table(string name, int id) kept = keep(t);";
+        assert_eq!(check_program(statement), Ok(()));
+    }
+
+    #[test]
+    fn test_schema_annotation_gives_generic_table_columns() {
+        // print returns a table of unknown columns. The annotation gives the for-loop and the pipe the columns
+        let statement = "var table(int id) t = print(5);
+            for (row(int id) r in t) {
+                var int x = r.id;
+            }
+            fn bool positive(row(int id) r) { return r.id > 0; };
+            var table(int id) kept = t pipe positive();
+            var int[] ids = kept.id;";
+        assert_eq!(check_program(statement), Ok(()));
+    }
+
+    #[test]
+    fn test_increment_needs_a_numeric_variable() {
+        assert_eq!(check_program("var int i = 0; i++; i--;"), Ok(()));
+        assert_eq!(check_program("var double d = 0.5; d++;"), Ok(()));
+        assert!(check_program("var string s = \"a\"; s++;").is_err());
+        assert!(check_program("var bool b = true; b--;").is_err());
+        assert_eq!(
+            check_program("const int c = 0; c++;"),
+            Err("Cannot assign to constant variable 'c'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_defer_cannot_return() {
+        assert_eq!(
+            check_program("fn int f() { defer { print(1); } return 0; };"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("fn int f() { defer { if (true) { return 1; } } return 0; };"),
+            Err("A deferred block cannot return".to_string())
+        );
+    }
+
+    #[test]
+    fn test_chars_are_only_compared() {
+        assert_eq!(
+            check_program("var char c = 'a'; var bool b = c < 'b' and c != '\\n';"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("var int i = 'a';"),
+            Err("Type mismatch: expected Int, found Char".to_string())
+        );
+        assert!(check_program("var char c = 'a' + 'b';").is_err());
+        assert!(check_program("var bool b = 'a' == \"a\";").is_err());
+    }
+
+    #[test]
+    fn test_schema_mismatch_names_differing_column() {
+        let result = check_program("var table(int id, string name) t = table(int id, int name);");
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|e| e
+                    .starts_with("Type mismatch: column 'name' is declared String, but found Int")),
+            "{:?}",
+            result
+        );
+        let result = check_program("var row(int id, string name) r = row(int id = 1);");
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|e| e.starts_with("Type mismatch: column 'name' is missing")),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_redefining_builtins_rejected() {
+        assert_eq!(
+            check_program("fn null print(int x) { };"),
+            Err("cannot redefine builtin 'print'".to_string())
+        );
+        assert_eq!(
+            check_program("fn table(int id) import(string s, table(int id) t) { return t; };"),
+            Err("cannot redefine builtin 'import'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_every_declared_name_of_a_builtin_rejected() {
+        let rejected = |program: &str| {
+            assert_eq!(
+                check_program_coded(program).map_err(|error| (error.message, error.code)),
+                Err((
+                    "cannot redefine builtin 'print'".to_string(),
+                    Some(ErrorCode::BuiltinRedefined)
+                )),
+                "{}",
+                program
+            )
+        };
+        rejected("var fn int(int) print = fn (int x) int { return x; }; print(3);");
+        rejected("const int print = 1;");
+        rejected("for (int print in 0..3) { }");
+        rejected("for (int print, int x in [1, 2]) { }");
+        rejected("var fn int(int) f = fn (int print) int { return print; };");
+        assert_eq!(
+            check_program("fn int f(table(int id) import) { return 1; };"),
+            Err("cannot redefine builtin 'import'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_function_with_other_name_unaffected() {
+        assert_eq!(
+            check_program("fn table(int id) my_import(string s, table(int id) t) { return t; };"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_int_and_double_mix_on_either_side() {
+        let statement = "var double a = 1.5 + 2; var double b = 2 * 1.5;
+            var bool c = 2.5 >= 1; var bool d = 1 >= 2.5; var bool e = 2 > 1.5; var bool f = 1.5 == 1;";
+        assert_eq!(check_program(statement), Ok(()));
+        assert_eq!(
+            check_program("var int a = 1.5 + 2;")
+                .map_err(|e| e.starts_with("Cannot implicitly cast Double to Int")),
+            Err(true)
+        );
+    }
+
+    #[test]
+    fn test_logical_and_equality_of_booleans_and_strings() {
+        let statement = "var bool a = true or false; var bool b = true and false;
+            var bool c = a == b; var bool d = \"x\" == \"y\";";
+        assert_eq!(check_program(statement), Ok(()));
+        assert!(check_program("var bool a = \"x\" == 1;").is_err());
+    }
+
+    #[test]
+    fn test_main_signature_checked() {
+        assert_eq!(check_program("fn int main() { return 0; };"), Ok(()));
+        assert_eq!(
+            check_program("fn int main(int argc) { return 0; };"),
+            Err(
+                "main must take no parameters and return int or null, found 1 parameters and Int"
+                    .to_string()
+            )
+        );
+        assert!(check_program("fn string main() { return \"a\"; };").is_err());
+    }
+
+    // Type checks a program, and returns the warnings found
+    fn warnings_for(statement: &str) -> Vec<String> {
+        take_warnings();
+        check_program(statement).unwrap();
+        take_warnings()
+    }
+
+    #[test]
+    fn test_discarded_arithmetic_warns() {
+        assert_eq!(
+            warnings_for("var int x = 1; x + 1;"),
+            vec![
+                "Warning: the Int computed by an expression statement is discarded. Assign it to a variable or print it"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_large_int_compared_with_double_warns() {
+        assert_eq!(
+            warnings_for("var double d = 1.0; print(9007199254740993 < d);"),
+            vec![
+                "Warning: the int 9007199254740993 is compared with a double, which it cannot be widened to exactly. Ints above 2^53 may compare equal to doubles near them"
+                    .to_string()
+            ]
+        );
+        assert_eq!(
+            warnings_for("var double d = 1.0; print(d == -9007199254740993);").len(),
+            1
+        );
+        assert!(warnings_for("var double d = 1.0; print(d == 9007199254740992);").is_empty());
+        assert!(warnings_for("var int i = 1; print(i == 9007199254740993);").is_empty());
+    }
+
+    #[test]
+    fn test_discarded_function_result_warns_with_type() {
+        let warnings = warnings_for(
+            "var table(int id) t = table(int id); describe(t); fn double half(double x) { return x / 2.0; }; half(3.0);",
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(
+            warnings[0].starts_with("Warning: the Table("),
+            "{}",
+            warnings[0]
+        );
+        assert!(warnings[0].contains("returned by 'describe' is discarded"));
+        assert!(warnings[1].starts_with("Warning: the Double returned by 'half' is discarded"));
+    }
+
+    #[test]
+    fn test_side_effecting_calls_do_not_warn() {
+        let statement = "var table(int id) t = table(int id);
+            print(5);
+            table_add_row(t, row(int id = 1));
+            t pipe print();
+            fn null log(int x) { print(x); };
+            log(1);";
+        assert_eq!(warnings_for(statement), Vec::<String>::new());
+    }
+
+    // Builds 1 + 1 + ... + 1 with the given number of operations, leaning left like the parser builds it
+    fn long_sum(operations: usize) -> Expr {
+        let mut expr = Expr::Number(1);
+        for _ in 0..operations {
+            expr = Expr::Operation(
+                Box::new(expr),
+                Operator::Addition,
+                Box::new(Expr::Number(1)),
+            );
+        }
+        expr
+    }
+
+    #[test]
+    fn test_long_operation_chain_type_checks() {
+        // Dropping a tree this deep is recursive in Rust, so the test thread needs more than the default stack
+        // Checking the chain recursively would still need several times this
+        let result = std::thread::Builder::new()
+            .stack_size(32 * 1024 * 1024)
+            .spawn(|| {
+                let expr = long_sum(50_000);
+                let mut scope_stack = vec![HashMap::new()];
+                infer_type(&expr, &mut scope_stack).map(|typed| typed.expr_type)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(result, Ok(TypeConstruct::Int));
+    }
+
+    #[test]
+    fn test_long_program_type_checks() {
+        let source = "var int x = 0;\n".to_string() + &"x = x + 1;\n".repeat(20_000);
+        let tree = create_syntax_tree(&source).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert!(type_check(&tree, &mut scope_stack).is_ok());
+    }
+
+    #[test]
+    fn test_fifty_thousand_statement_program_type_checks() {
+        // The tree is parsed and dropped on a thread with a larger stack, dropping it is recursive in Rust
+        let source = "var int x = 0;\n".to_string() + &"x = x + 1;\n".repeat(50_000);
+        let errors = std::thread::Builder::new()
+            .stack_size(32 * 1024 * 1024)
+            .spawn(move || {
+                let tree = create_syntax_tree(&source).unwrap();
+                let mut scope_stack = vec![create_global_environment()];
+                type_check_all(&tree, &mut scope_stack)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(errors, Vec::<TypeError>::new());
+    }
+
+    #[test]
+    fn test_statements_after_an_error_are_checked() {
+        let tree = create_syntax_tree(
+            "var int x = \"one\";
+            var int y = x + 1;
+            y = true;
+            var string z = y;",
+        )
+        .unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let errors = type_check_all(&tree, &mut scope_stack);
+        // x is still declared an int, so its use in y is not reported
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].message.contains("String"), "{:?}", errors[0]);
+        assert!(errors[1].message.contains("Bool"), "{:?}", errors[1]);
+        assert!(errors[2].message.contains("String"), "{:?}", errors[2]);
+        // type_check reports the earliest of them
+        let mut scope_stack = vec![create_global_environment()];
+        assert_eq!(type_check(&tree, &mut scope_stack), Err(errors[0].clone()));
+    }
+
+    #[test]
+    fn test_errors_have_the_span_of_the_innermost_statement() {
+        let source = "var int x = 1;
+            while (x < 3) {
+                x = x + 1;
+                var int y = z;
+            }
+            fn int f() { return \"one\"; };";
+        let tree = create_syntax_tree(source).unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        let errors = type_check_all(&tree, &mut scope_stack);
+        let span_of = |statement: &str| {
+            let start = source.find(statement).unwrap();
+            Some(Span {
+                start,
+                end: start + statement.len(),
+            })
+        };
+        assert_eq!(
+            errors,
+            vec![
+                TypeError {
+                    message: "Undefined variable 'z'. Did you mean 'x'?".to_string(),
+                    code: Some(ErrorCode::UndefinedName),
+                    span: span_of("var int y = z;"),
+                },
+                TypeError {
+                    message: "Return type mismatch: expected Int, found String".to_string(),
+                    code: Some(ErrorCode::ReturnTypeMismatch),
+                    span: span_of("fn int f() { return \"one\"; };"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_block_leaves_no_scope_behind() {
+        let tree = create_syntax_tree(
+            "var int[] xs = [1, 2];
+            for (string s in xs) { var int inner = 1; }
+            var int after = 2;",
+        )
+        .unwrap();
+        let mut scope_stack = vec![create_global_environment()];
+        assert_eq!(type_check_all(&tree, &mut scope_stack).len(), 1);
+        assert_eq!(scope_stack.len(), 1);
+        assert!(scope_stack[0].contains_key("after"));
+    }
+
+    #[test]
+    fn test_bind_gives_function_of_unbound_parameters() {
+        let program = "
+            fn bool between(row(int age) r, int low, int high) {
+                return low <= r.age or r.age <= high;
+            };
+            const fn bool(row(int age)) teen = bind(\"between\", 13, 19);
+            const fn bool(row(int age), int) from_ten = bind(\"between\", 10);";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program("fn int f(int a) { return a; }; const fn int(int) g = bind(\"f\", 1);"),
+            Err("Type mismatch: expected Function(Int, [Int]), found Function(Int, []) for constant 'g'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bind_over_binding_rejected() {
+        assert_eq!(
+            check_program(
+                "fn int add(int a, int b) { return a + b; };
+                const fn int() f = bind(\"add\", 1, 2, 3);"
+            ),
+            Err("Function 'add' takes 2 arguments, but 3 were bound".to_string())
+        );
+        assert_eq!(
+            check_program_coded(
+                "fn int add(int a, int b) { return a + b; };
+                const fn int() f = bind(\"add\", 1, 2, 3);"
+            )
+            .unwrap_err()
+            .code,
+            Some(ErrorCode::ArgumentCount)
+        );
+        assert_eq!(
+            check_program(
+                "fn bool at_least(row(int age) r, int age) { return age <= r.age; };
+                var table(int age) t = table(int age) pipe bind(\"at_least\", 1, 2, 3);"
+            ),
+            Err("Function 'at_least' takes 2 arguments, but 3 were bound".to_string())
+        );
+        assert_eq!(
+            check_program(
+                "fn bool at_least(row(int age) r, int age) { return age <= r.age; };
+                var table(int age) t = table(int age) pipe bind(\"at_least\", 1);
+                var table(int age) u = t pipe bind(\"at_least\");"
+            ),
+            Err(
+                "A function bound in a pipe must have one unbound parameter for the rows, found 2"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_bind_argument_types_checked() {
+        assert_eq!(
+            check_program(
+                "fn int add(int a, int b) { return a + b; };
+                const fn int(int) f = bind(\"add\", \"one\");"
+            ),
+            Err("Type mismatch: expected Int, found String".to_string())
+        );
+        assert_eq!(
+            check_program(
+                "fn int add(int a, int b) { return a + b; };
+                var fn int(int) f = bind(\"add\", 1);"
+            ),
+            Err("the function 'f' made with bind must be declared with const".to_string())
+        );
+    }
+
+    #[test]
+    fn test_row_literal_widens_int_in_double_column() {
+        assert_eq!(
+            check_program("var row(double x) r = row(double x = 1);"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("var row(string x) r = row(string x = 1);"),
+            Err("Type mismatch: expected String, found Int for column 'x'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_column_defaults_and_nullable_columns() {
+        let program = "
+            var table(int id, double score, string note?) t = table(int id, double score = 0, string note?);
+            for (row(int id, double score, string note?) r in t) {
+                if (r.note == null) { print(r.id); }
+            }";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program("var table(int id, string note?) t = table(int id, string note = 1);"),
+            Err(
+                "Type mismatch: expected String, found Int for the default of column 'note'"
+                    .to_string()
+            )
+        );
+        assert!(
+            check_program(
+                "var table(string note?) t = table(string note?);
+                for (row(string note?) r in t) { var string s = r.note; }"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_table_rows_have_its_columns() {
+        assert_eq!(
+            check_program(
+                "var table(int id, double score, string note?) t = table(int id, double score = 0, string note?) [
+                    row(int id = 1, double score = 2.5, string note = \"a\"),
+                    row(int id = 2),
+                ];"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("table(int id, string name) [row(int id = 1, string name = \"a\"), row(int id = 2)];"),
+            Err("Type mismatch: column 'name' is missing, expected Row([Parameter(Int, \"id\"), Parameter(String, \"name\")]), found Row([Parameter(Int, \"id\")]) for row 2 of the table".to_string())
+        );
+        assert_eq!(
+            check_program("table(int id) [row(string id = \"1\")];"),
+            Err("Type mismatch: column 'id' is declared Int, but found String, expected Row([Parameter(Int, \"id\")]), found Row([Parameter(String, \"id\")]) for row 1 of the table".to_string())
+        );
+        assert_eq!(
+            check_program("table(int id) [row(int id = 1, int age = 2)];"),
+            Err("Type mismatch: column 'age' is not declared, expected Row([Parameter(Int, \"id\")]), found Row([Parameter(Int, \"id\"), Parameter(Int, \"age\")]) for row 1 of the table".to_string())
+        );
+        assert_eq!(
+            check_program("table(int id) [1];"),
+            Err("Type mismatch: expected Row([Parameter(Int, \"id\")]), found Int for row 1 of the table".to_string())
+        );
+    }
+
+    #[test]
+    fn test_histogram_needs_a_numeric_column() {
+        let program = "
+            var table(int id, string name) t = table(int id, string name);
+            var table(double bucket_low, double bucket_high, int count) h = histogram(t, \"id\", 10);";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program(
+                "var table(int id, string name) t = table(int id, string name);
+                print(histogram(t, \"name\", 10));"
+            ),
+            Err("'histogram' needs an int or double column, but 'name' is String".to_string())
+        );
+    }
+
+    #[test]
+    fn test_column_arithmetic_is_element_wise() {
+        let program = "
+            var table(int id, double score) t = table(int id, double score);
+            var double[] scaled = t.score * 1.1;
+            var int[] doubled = 2 * t.id;
+            var double[] summed = t.score + t.id;";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program(
+                "var table(int id) t = table(int id);
+                var int[] scaled = t.id * 1.5;"
+            ),
+            Err("Type mismatch: expected Array(Int), found Array(Double)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_element_wise_arithmetic_needs_numbers() {
+        assert_eq!(
+            check_program(
+                "var table(string name) t = table(string name);
+                var string[] longer = t.name + 1;"
+            ),
+            Err(
+                "Element-wise arithmetic needs arrays of int or double, and numbers. Left-hand side is string[] and right-hand side is int"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_program("var int[] a = [1, 2]; var bool b = a < 3;"),
+            Err(
+                "Operation on incompatible types. Left-hand side is Array(Int) and right-hand side is Int"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_table_with_column_type() {
+        let program = "
+            var table(int id) t = table(int id);
+            const string name = \"half\";
+            var table(int id, double half) h = table_with_column(t, name, t.id / 2.0);
+            var table(int id, double copy) c = table_with_column(t, \"copy\", t.id, \"double\");";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program(
+                "var table(int id) t = table(int id);
+                var table(int id) u = table_with_column(t, \"id\", t.id);"
+            ),
+            Err("Table already has a column named 'id'".to_string())
+        );
+        assert_eq!(
+            check_program(
+                "var table(int id) t = table(int id);
+                var table(int id) u = table_with_column(t, \"rows\", [t]);"
+            ),
+            Err(
+                "Third argument to 'table_with_column' must be an array of int, double, string or bool, found table(int id)[]"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_program(
+                "var table(int id) t = table(int id);
+                var table(int id) u = table_with_column(t, \"flag\", t.id, \"bool\");"
+            ),
+            Err("Column 'flag' is declared bool, but the values are int[]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_column_suggests_the_closest() {
+        assert_eq!(
+            check_program(
+                "var row(int id, string name) r = row(int id = 1, string name = \"a\");
+                var string n = r.nmae;"
+            ),
+            Err(
+                "Column 'nmae' not found, the columns are id: int, name: string. Did you mean 'name'?"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_program(
+                "var table(int id) t = table(int id);
+                var int[] totals = t.total;"
+            ),
+            Err("Column 'total' not found, the columns are id: int.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_undefined_variable_suggests_the_closest() {
+        assert_eq!(
+            check_program("var int count = 1; var int y = conut + 1;"),
+            Err("Undefined variable 'conut'. Did you mean 'count'?".to_string())
+        );
+    }
+
+    #[test]
+    fn test_string_builtins_and_indexing_types() {
+        let program = "
+            var string s = \"blåbær\";
+            var string first = s[0];
+            var int n = str_len(s) + str_bytes_len(s);
+            var string[] parts = split(to_upper(substring(s, 0, 3)), \"\");";
+        assert_eq!(check_program(program), Ok(()));
+        assert!(check_program("var int c = \"abc\"[0];").is_err());
+    }
+
+    #[test]
+    fn test_to_table_type() {
+        let program = "
+            var row(int id, string name)[] rows = [row(int id = 1, string name = \"a\")];
+            var table(int id, string name) t = to_table(rows);
+            var table(double score) s = to_table_column([1.5, 2.5], \"score\");";
+        assert_eq!(check_program(program), Ok(()));
+        assert!(
+            check_program(
+                "var row(int id)[] rows = [row(int id = 1)];
+                var table(string id) t = to_table(rows);"
+            )
+            .is_err()
+        );
+        assert_eq!(
+            check_program("var table(int id) t = to_table([1, 2]);"),
+            Err("First argument to 'to_table' must be an array of rows, found int[]".to_string())
+        );
+        assert_eq!(
+            check_program(
+                "var row(int id)[] rows = [row(int id = 1)];
+                var table(int id) t = to_table_column(rows, \"id\");"
+            ),
+            Err(
+                "First argument to 'to_table_column' must be an array of int, double, string or bool, found row(int id)[]"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_table_without_column_type() {
+        let program = "
+            var table(int id, string name) t = table(int id, string name);
+            var table(int id) ids = table_without_column(t, \"name\");
+            var table(int id, string name) same = table_with_column(ids, \"name\", t.name);";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program(
+                "var table(int id) t = table(int id);
+                var table(int id) u = table_without_column(t, \"name\");"
+            ),
+            Err("Table has no column named 'name'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_or_has_element_type() {
+        let program = "
+            var int[] xs = [1, 2];
+            var int x = get_or(xs, 10, 0);
+            var double[] ds = [0.5];
+            var double d = get_or(ds, 0 - 1, 0);";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program("var int[] xs = [1, 2]; var int x = get_or(xs, 10, \"none\");"),
+            Err("Default for 'get_or' must be int, found string".to_string())
+        );
+        assert_eq!(
+            check_program("var int[] xs = [1, 2]; var int x = get_or(xs, \"first\", 0);"),
+            Err("Second argument to 'get_or' must be an int index, found string".to_string())
+        );
+    }
+
+    #[test]
+    fn test_row_get_or_has_column_or_default_type() {
+        let program = "
+            var row(string name) r = row(string name = \"Ann\");
+            var string name = row_get_or(r, \"name\", \"\");
+            var int age = row_get_or(r, \"maybe_col\", 0);";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program(
+                "var row(string name) r = row(string name = \"Ann\");
+                var int name = row_get_or(r, \"name\", 0);"
+            ),
+            Err("Default for 'row_get_or' must be string, found int".to_string())
+        );
+    }
+
+    #[test]
+    fn test_field_assignment_in_table_loop_needs_an_index() {
+        let table = "var table(int id, double salary) t = table(int id, double salary);";
+        assert_eq!(
+            check_program(&format!(
+                "{} for (int i, row(int id, double salary) r in t) {{ r.salary = r.salary * 2; table_update_row(t, i, r); }}",
+                table
+            )),
+            Ok(())
+        );
+        let error = check_program(&format!(
+            "{} for (row(int id, double salary) r in t) {{ r.salary = 1; }}",
+            table
+        ))
+        .unwrap_err();
+        assert!(error.contains("table_update_row(t, i, r)"), "{}", error);
+        assert_eq!(
+            check_program(&format!(
+                "{} for (string i, row(int id, double salary) r in t) {{ }}",
+                table
+            )),
+            Err("The index 'i' of a for-loop must be an int, found String".to_string())
+        );
+    }
+
+    #[test]
+    fn test_field_assignment_checks_column_and_type() {
+        let row = "var row(int id, double salary) r = row(int id = 1, double salary = 2.0);";
+        assert_eq!(check_program(&format!("{} r.salary = 3;", row)), Ok(()));
+        let error = check_program(&format!("{} r.id = 1.5;", row)).unwrap_err();
+        assert!(
+            error.starts_with("In the assignment to 'r.id': Cannot implicitly cast Double to Int"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            check_program(&format!("{} r.salry = 3;", row)),
+            Err("Column 'salry' not found, the columns are id: int, salary: double. Did you mean 'salary'?".to_string())
+        );
+        assert!(check_program("var int x = 1; x.id = 2;").is_err());
+        assert_eq!(
+            check_program(&format!("{} r.id = \"one\";", row)),
+            Err(
+                "In the assignment to 'r.id': Type mismatch: expected Int, found String"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_index_assignment_checks_array_index_and_element() {
+        assert_eq!(
+            check_program("var int[][] m = [[1]]; m[0] = [2, 3]; var int i = 0; m[i] = [];"),
+            Ok(())
+        );
+        let error = check_program("var int[] xs = [1]; xs[0] = 1.5;").unwrap_err();
+        assert!(
+            error.starts_with(
+                "In the assignment to an element of 'xs': Cannot implicitly cast Double to Int"
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(
+            check_program("var int[] xs = [1]; xs[\"a\"] = 1;"),
+            Err("Index must be an integer, found String".to_string())
+        );
+        assert_eq!(
+            check_program("var int x = 1; x[0] = 1;"),
+            Err("Cannot assign to an element of 'x', 'x' is Int, not an array".to_string())
+        );
+        assert_eq!(
+            check_program("const int[] XS = [1]; XS[0] = 2;"),
+            Err("Cannot assign to constant variable 'XS'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_swapped_pipe_sides_are_explained() {
+        let declarations = "
+            fn bool adults(row(int age) r) { return r.age > 17; };
+            var table(int age) data = table(int age);";
+        assert_eq!(
+            check_program(&format!("{} var table(int age) t = adults pipe data();", declarations)),
+            Err("The left side of 'pipe' must be a table or import, but 'adults' is a function; did you mean 'data pipe adults(...)'?".to_string())
+        );
+        assert_eq!(
+            check_program(&format!("{} var table(int age) t = data pipe data();", declarations)),
+            Err("The right side of 'pipe' must be a function, but 'data' is a table; a pipe starts with the table, e.g. 'data pipe f(...)'".to_string())
+        );
+        assert_eq!(
+            check_program_coded(&format!("{} data pipe data();", declarations))
+                .unwrap_err()
+                .code,
+            Some(ErrorCode::InvalidPipeFunction)
+        );
+    }
+
+    #[test]
+    fn test_not_equals_on_values_but_not_rows() {
+        assert_eq!(
+            check_program(
+                "var bool a = 1 != 2; var bool b = 1.5 != 2; var bool c = \"x\" != \"y\"; var bool d = true != false;"
+            ),
+            Ok(())
+        );
+        // Rejected like == is, with the same message
+        let rows = "var row(int id) r = row(int id = 1); var bool same = r != r;";
+        assert!(check_program(rows).is_err());
+        assert_eq!(
+            check_program(rows),
+            check_program(&rows.replace("!=", "=="))
+        );
+    }
+
+    #[test]
+    fn test_unary_minus_types() {
+        let program = "
+            fn double half(int x) { return x / 2.0; };
+            var row(int id, double score) r = row(int id = 1, double score = 2.5);
+            var int a = 3;
+            var int literal = -5;
+            var double literal_double = -2.5;
+            var int identifier = -a;
+            var double column = -r.score;
+            var int int_column = -r.id;
+            var double call = -half(a);
+            var double widened = -(a - 2.5);
+            var int nested = --a;
+            var int power = -a ** 2;
+            var double[] scores = -[1.5, 2.5];";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program("var double d = 1.5; var int x = -d;").unwrap_err(),
+            "Cannot implicitly cast Double to Int. Expected VariableInfo { var_type: Int, is_constant: false, value: None, is_table_loop_row: false }, found Double"
+        );
+        assert_eq!(
+            check_program("var string s = \"a\"; var string t = -s;"),
+            Err("Unary minus requires an int or double, found string".to_string())
+        );
+        assert_eq!(
+            check_program("var bool b = -true;"),
+            Err(
+                "Unary minus requires an int or double, found bool. Use ! to negate a bool"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_program_coded("var string s = \"a\"; var string t = -s;")
+                .unwrap_err()
+                .code,
+            Some(ErrorCode::IncompatibleOperands)
+        );
+    }
+
+    #[test]
+    fn test_negative_literal_constant_is_a_literal() {
+        let mut scope_stack = vec![create_global_environment()];
+        let tree = create_syntax_tree("const int low = -3; const double d = -0.5;").unwrap();
+        assert_eq!(type_check(&tree, &mut scope_stack), Ok(()));
+        let value = |name: &str| scope_stack[0].get(name).unwrap().value.clone();
+        assert_eq!(value("low"), Some(Expr::Number(-3)));
+        assert_eq!(value("d"), Some(Expr::Double(-0.5)));
+    }
+
+    #[test]
+    fn test_for_loop_over_a_range() {
+        assert_eq!(
+            check_program("var int n = 3; for (int i in 0..n) { var int j = i * 2; }"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("for (int i in 0..2.5) {}"),
+            Err("The bounds of a range must be ints, found Double".to_string())
+        );
+        assert_eq!(
+            check_program("for (string s in 0..=3) {}"),
+            Err("Type mismatch in for-loop: expected Int, found String for iterator 's' over a range".to_string())
+        );
+        assert!(check_program("for (int i in 0..3) {} print(i);").is_err());
+    }
+
+    #[test]
+    fn test_break_only_inside_loops() {
+        assert_eq!(
+            check_program("var int i = 0; while (true) { i++; if (i == 5) { break; } }"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("for (int i in 0..3) { for (int j in [1, 2]) { break; } break; }"),
+            Ok(())
+        );
+        let outside = Err("break can only be used inside a while or for loop".to_string());
+        assert_eq!(check_program("break;"), outside);
+        assert_eq!(check_program("if (true) { break; }"), outside);
+        // A function or a deferred block in a loop cannot break out of it
+        assert_eq!(
+            check_program("while (true) { fn null f() { break; }; f(); }"),
+            outside
+        );
+        assert_eq!(check_program("while (true) { defer { break; } }"), outside);
+        // A failed check inside a loop does not leave the loop depth behind
+        assert!(check_program("while (true) { var int x = true; }").is_err());
+        assert_eq!(check_program("break;"), outside);
+    }
+
+    #[test]
+    fn test_null_functions_return_no_value() {
+        assert_eq!(
+            check_program(
+                "fn null log_positive(int x) { if (x < 0) { return; } print(x); }; log_positive(1);"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("fn null f() { return 5; };"),
+            Err("Return type mismatch: expected Null, found Int".to_string())
+        );
+        assert_eq!(
+            check_program("fn int f() { return; };"),
+            Err("Return type mismatch: expected Int, found Null".to_string())
+        );
+    }
+
+    #[test]
+    fn test_functions_with_a_value_return_on_every_path() {
+        let missing = Err(
+            "Return type mismatch: expected Int, but the end of the function can be reached without a return"
+                .to_string(),
+        );
+        assert_eq!(check_program("fn int f() { print(1); };"), missing);
+        assert_eq!(
+            check_program("fn int f(int x) { if (x > 0) { return 1; } };"),
+            missing
+        );
+        assert_eq!(
+            check_program("fn int f(int x) { while (x > 0) { return 1; } };"),
+            missing
+        );
+        assert_eq!(
+            check_program("var fn int(int) f = fn (int x) int { x = x + 1; };"),
+            missing
+        );
+        assert_eq!(
+            check_program(
+                "fn int f(int x) { if (x > 0) { return 1; } else { if (x == 0) { return 0; } else { return -1; } } };"
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_nested_functions_are_seen_only_in_their_body() {
+        let outer = "fn int outer(int x) {
+            fn int twice(int y) { return y * 2; };
+            fn int quadruple(int y) { return twice(twice(y)); };
+            return quadruple(x);
+        };";
+        assert_eq!(check_program(&format!("{} outer(1);", outer)), Ok(()));
+        assert_eq!(
+            check_program(&format!("{} twice(1);", outer)),
+            Err("Undefined function 'twice'.".to_string())
+        );
+        // A helper is not seen before it is declared
+        assert_eq!(
+            check_program(
+                "fn int outer() { fn int first() { return second(); }; fn int second() { return 1; }; return first(); };"
+            ),
+            Err("Undefined function 'second'.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_array_type_annotations() {
+        // The annotation gives the element type of an empty array
+        assert_eq!(check_program("var int[] xs = []; xs = [];"), Ok(()));
+        assert_eq!(
+            check_program("var int[][] m = [[1, 2], [], [3]]; var string[][] e = [[]];"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("var int[] xs = [1.5];"),
+            Err("Type mismatch: expected Array(Int), found Array(Double)".to_string())
+        );
+        assert_eq!(
+            check_program("var int[][] m = [[1], [\"a\"]];"),
+            Err("Array elements must have the same type".to_string())
+        );
+        assert_eq!(
+            check_program("var int[] xs = [[]];"),
+            Err("Cannot infer type of empty array".to_string())
+        );
+        // Without an annotation there is nothing to take the type from
+        assert_eq!(
+            check_program("print([]);"),
+            Err("Cannot infer type of empty array".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lambda_types() {
+        assert_eq!(
+            check_program(
+                "var fn bool(row(int id)) small = fn (row(int id) r) bool { return r.id < 10; };"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("var fn int(int) f = fn (int x) bool { return true; };"),
+            Err(
+                "Type mismatch: expected Function(Int, [Int]), found Function(Bool, [Int])"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_program("var fn int(int) f = fn (int x) int { return \"one\"; };"),
+            Err("Return type mismatch: expected Int, found String".to_string())
+        );
+        // A lambda captures the ints, doubles, strings and bools around it by value, so it cannot assign them
+        assert_eq!(
+            check_program("var int n = 1; var fn int(int) f = fn (int x) int { return x + n; };"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program(
+                "var int n = 1; var fn int(int) f = fn (int x) int { n = x; return n; };"
+            ),
+            Err("Cannot assign to constant variable 'n'".to_string())
+        );
+        // Its parameters hide them, and it does not see tables, like a declared function does not see variables
+        assert_eq!(
+            check_program(
+                "var string x = \"a\"; var fn int(int) f = fn (int x) int { return x; };"
+            ),
+            Ok(())
+        );
+        assert!(
+            check_program(
+                "var table(int id) t = table(int id); var fn int(int) f = fn (int x) int { var table(int id) u = t; return x; };"
+            )
+            .unwrap_err()
+            .starts_with("Undefined variable 't'")
+        );
+        assert!(
+            check_program(
+                "var table(int id) t = table(int id); var table(int id) small = t pipe (fn (row(int id) r) int { return r.id; });"
+            )
+            .unwrap_err()
+            .starts_with("Pipe function 'fn' must be one of")
+        );
+    }
+
+    #[test]
+    fn test_continue_only_inside_loops() {
+        assert_eq!(
+            check_program("for (int x in [1, 2]) { if (x == 1) { continue; } print(x); }"),
+            Ok(())
+        );
+        assert_eq!(
+            check_program("continue;"),
+            Err("continue can only be used inside a while or for loop".to_string())
+        );
+    }
+
+    #[test]
+    fn test_enum_values_are_checked() {
+        let program = "
+            enum Status = [\"active\", \"inactive\"];
+            var Status s = \"active\";
+            var string name = s;
+            var table(int id, Status status) t = table(int id, Status status = \"inactive\");
+            table_add_row(t, row(int id = 1, Status status = \"active\"));
+            for (row(int id, Status status) r in t) {
+                if (r.status == \"inactive\" or r.status == s) { print(r.id); }
+            }";
+        assert_eq!(check_program(program), Ok(()));
+        assert_eq!(
+            check_program(
+                "enum Status = [\"active\", \"inactive\"];
+                var table(int id, Status status) t = table(int id, Status status);
+                for (row(int id, Status status) r in t) {
+                    if (r.status == \"actve\") { print(r.id); }
+                }"
+            ),
+            Err("\"actve\" is not a value of enum Status, which is one of \"active\", \"inactive\". Did you mean 'active'?".to_string())
+        );
+        assert_eq!(
+            check_program("enum Status = [\"active\"]; var Status s = \"pending\";"),
+            Err(
+                "\"pending\" is not a value of enum Status, which is one of \"active\"."
+                    .to_string()
+            )
+        );
+        // A string that is not a literal may hold any value, so it cannot be used as an enum
+        assert!(
+            check_program(
+                "enum Status = [\"active\"]; var string name = \"active\"; var Status s = name;"
+            )
+            .is_err()
+        );
+    }
 }
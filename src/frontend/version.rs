@@ -0,0 +1,49 @@
+//The version of wrench and how it was built, reported by --version and below runtime errors so bug reports carry it.
+//The git hash, build date and features are set by build.rs
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_HASH: &str = env!("WRENCH_GIT_HASH");
+pub const BUILD_DATE: &str = env!("WRENCH_BUILD_DATE");
+//The enabled cargo features, comma separated, empty if there are none
+pub const FEATURES: &str = env!("WRENCH_FEATURES");
+
+//One line describing the build, e.g. wrench 0.1.0 (git 986ac0a, built 2026-10-16, features: none)
+pub fn version_info() -> String {
+    format!(
+        "wrench {} (git {}, built {}, features: {})",
+        VERSION,
+        GIT_HASH,
+        BUILD_DATE,
+        if FEATURES.is_empty() {
+            "none"
+        } else {
+            FEATURES
+        }
+    )
+}
+
+//The line written below an error that stops a program
+pub fn error_footer() -> String {
+    format!(
+        "{} - please include this line when reporting the error",
+        version_info()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_info_names_the_build() {
+        let info = version_info();
+        assert!(
+            info.starts_with(&format!("wrench {} (git ", VERSION)),
+            "{}",
+            info
+        );
+        assert!(info.contains(&format!("built {}", BUILD_DATE)), "{}", info);
+        assert_eq!(BUILD_DATE.len(), "2026-10-16".len());
+        assert!(error_footer().starts_with(&info));
+    }
+}
@@ -0,0 +1,3 @@
+// The wrench interpreter as a library, so host programs can embed it and register their own builtins
+pub mod backend;
+pub mod frontend;
@@ -1,27 +1,85 @@
-use std::{env, fs};
+use std::{env, fs, panic, path::Path, process};
 
-use frontend::main::run;
-
-mod backend;
-mod frontend;
+use wrench::frontend::{
+    diagnostics::explain,
+    lexer::Token,
+    main::{ProgramSource, parse_args, run},
+    test_runner::{run_test_dir, test_exit_code, test_summary},
+    version::{error_footer, version_info},
+};
 
 //#[cfg(not(test))]
 fn main() {
     //Read file_name from command args
     let args: Vec<String> = env::args().collect();
-    let debug_mode = args.contains(&"debug=true".to_string());
-    if args.len() < 2 || (args.len() == 2 && debug_mode) {
-        panic!("Usage: {} <file_name> [debug=true]", args[0]);
+    //Print the version and how this build was made, e.g. for support requests
+    if args.get(1).map(String::as_str) == Some("--version") {
+        println!("{}", version_info());
+        return;
+    }
+    //List the reserved words, one per line, for editor tooling
+    if args.get(1).map(String::as_str) == Some("--keywords") {
+        for keyword in Token::keyword_list() {
+            println!("{}", keyword);
+        }
+        return;
     }
-    let file_name = &args[1];
-    //Read file given as command arg
-    match fs::read_to_string(file_name) {
-        Ok(input) => {
-            //Run wrench interpreter with file content as input
-            run(&input, debug_mode);
+    //Print the longer explanation of an error code, e.g. wrench --explain E014
+    if args.get(1).map(String::as_str) == Some("--explain") {
+        match explain(args.get(2).map(String::as_str).unwrap_or("")) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
+            }
         }
-        Err(e) => {
-            panic!("Error reading file: {}", e)
+        return;
+    }
+    //Run every *_test.wr script in a directory and report each of them, e.g. wrench --test tests/
+    if args.get(1).map(String::as_str) == Some("--test") {
+        //Errors in the scripts are reported in the summary, so the panics carrying them are not printed as well
+        panic::set_hook(Box::new(|_| {}));
+        match run_test_dir(Path::new(args.get(2).map(String::as_str).unwrap_or("."))) {
+            Ok(reports) => {
+                println!("{}", test_summary(&reports));
+                process::exit(test_exit_code(&reports));
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
+            }
         }
     }
+    //Errors that stop the program are panics, and the version is written below them so bug reports carry it
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        eprintln!("{}", error_footer());
+    }));
+    let (source, options) = match parse_args(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(e) => panic!(
+            "{}\nUsage: {} <file_name> | -e <program> [debug=true] [--deny-warnings] [--var name=value] [--var-str name=value] [--max-values n] [--max-print-values n] [--pipe-memory-limit mb] [--max-string-length n] [--max-identifier-length n] [--max-columns n] [--max-parameters n] [--coverage file] [--check] [--dry-run] [--log-level debug|info|warn|error] [--no-fail-on-error-log] [--diagnostics=json]",
+            e, args[0]
+        ),
+    };
+    //Errors are written in the JSON with the other diagnostics, so the panics carrying runtime errors are not printed as well
+    if options.diagnostics_json {
+        panic::set_hook(Box::new(|_| {}));
+    }
+    //Read file given as command arg, or take the program given with -e
+    let input = match source {
+        ProgramSource::File(file_name) => match fs::read_to_string(&file_name) {
+            Ok(input) => input,
+            Err(e) => {
+                panic!("Error reading file: {}", e)
+            }
+        },
+        ProgramSource::Inline(program) => program,
+    };
+    //Run wrench interpreter with the program as input
+    let exit_code = run(&input, &options);
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
 }
@@ -0,0 +1,38 @@
+// Writes the syntax tree of a program as JSON and compares it with ast_json.expected next to this file
+// Set BLESS=1 to write ast_json.expected from the JSON written now
+use std::{env, fs, path::Path};
+
+use wrench::frontend::{ast::ast_to_json, main::create_syntax_tree};
+
+// A function, a table with rows and a pipe through the function
+const PROGRAM: &str = "fn bool small(row(int id) r) { return r.id < 10; }
+var table(int id) t = table(int id) [row(int id = 1), row(int id = 20)];
+t pipe small() pipe print();
+";
+
+#[test]
+fn syntax_tree_is_written_as_expected_json() {
+    let json = ast_to_json(&create_syntax_tree(PROGRAM).unwrap());
+    let expected_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ast_json.expected");
+    if env::var_os("BLESS").is_some() {
+        fs::write(&expected_path, format!("{}\n", json)).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+    assert_eq!(
+        format!("{}\n", json),
+        expected,
+        "the JSON is not as expected, run with BLESS=1 to accept it"
+    );
+}
+
+#[test]
+fn boxes_are_left_out_of_the_json() {
+    let json = ast_to_json(&create_syntax_tree("[1, -x];").unwrap());
+    let compact: String = json.split_whitespace().collect();
+    assert!(
+        compact.contains(r#"{"Expr":{"Array":[{"Number":1},{"Negation":{"Identifier":"x"}}]}}"#),
+        "{}",
+        json
+    );
+}
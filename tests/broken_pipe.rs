@@ -0,0 +1,58 @@
+// Runs the wrench binary with its output read by a reader that stops early, like `wrench big.wr | head -n 1`
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+};
+
+// Runs the program, reads the first line it prints and closes the pipe. Returns the exit code and what went to stderr
+fn run_into_closed_pipe(program: &str) -> (Option<i32>, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.wr");
+    fs::write(&path, program).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first_line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first_line)
+        .unwrap();
+    assert!(!first_line.is_empty());
+    // The reader is dropped here, so the rest of the output has nowhere to go
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    (child.wait().unwrap().code(), stderr)
+}
+
+#[test]
+fn print_into_closed_pipe_exits_quietly() {
+    let (code, stderr) = run_into_closed_pipe(
+        "var int i = 0;
+        while (i < 1000000) {
+            print(i);
+            i = i + 1;
+        }",
+    );
+    assert_eq!(code, Some(0), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn pipe_print_into_closed_pipe_exits_quietly() {
+    let (code, stderr) = run_into_closed_pipe(
+        "fn row(int id) make(int i) {
+            return row(int id = i);
+        };
+        rows_from_range(1000000, \"make\") pipe print();",
+    );
+    assert_eq!(code, Some(0), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
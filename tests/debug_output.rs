@@ -0,0 +1,41 @@
+// Runs the wrench binary twice on the same program with debug=true. Every run has its own HashMap seeds,
+// so output depending on the order of a HashMap would differ between the runs
+use std::{fs, process::Command};
+
+const PROGRAM: &str = "
+fn row(int id, string name, double score, bool active, string city) make(int i) {
+    return row(int id = i, string name = \"n\", double score = 1.5, bool active = true, string city = \"Aalborg\");
+};
+var table(int id, string name, double score, bool active, string city) t =
+    rows_from_range(3, \"make\") pipe print();
+table_add_row(t, make(7));
+print(t);
+print(t[0]);
+print(describe(t));
+debug_env();
+";
+
+fn run_debug(path: &std::path::Path) -> (Vec<u8>, Vec<u8>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .arg(path)
+        .arg("debug=true")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (output.stdout, output.stderr)
+}
+
+#[test]
+fn debug_output_is_the_same_every_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("debug.wr");
+    fs::write(&path, PROGRAM).unwrap();
+    let first = run_debug(&path);
+    for _ in 0..3 {
+        assert_eq!(run_debug(&path), first);
+    }
+}
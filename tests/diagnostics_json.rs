@@ -0,0 +1,210 @@
+// Runs the wrench binary with --diagnostics=json and reads the JSON it writes back, to check its shape
+use std::{collections::HashMap, fs, process::Command};
+
+// The JSON values the diagnostics are written with
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(HashMap<String, Json>),
+}
+
+// A small reader for the JSON wrench writes, so the test does not trust the writer to read its own output
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) {
+        self.skip_whitespace();
+        assert_eq!(self.chars.next(), Some(expected));
+    }
+
+    fn value(&mut self) -> Json {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.chars.next_if_eq(&']').is_none() {
+                    loop {
+                        values.push(self.value());
+                        self.skip_whitespace();
+                        match self.chars.next() {
+                            Some(',') => {}
+                            Some(']') => break,
+                            other => panic!("expected , or ] in array, found {:?}", other),
+                        }
+                    }
+                }
+                Json::Array(values)
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut fields = HashMap::new();
+                loop {
+                    self.skip_whitespace();
+                    let Json::String(key) = self.value() else {
+                        panic!("object keys are strings");
+                    };
+                    self.expect(':');
+                    fields.insert(key, self.value());
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some('}') => break,
+                        other => panic!("expected , or }} in object, found {:?}", other),
+                    }
+                }
+                Json::Object(fields)
+            }
+            Some('"') => {
+                self.chars.next();
+                let mut text = String::new();
+                loop {
+                    match self.chars.next().expect("unterminated string") {
+                        '"' => break,
+                        '\\' => match self.chars.next().unwrap() {
+                            'n' => text.push('\n'),
+                            'r' => text.push('\r'),
+                            't' => text.push('\t'),
+                            'u' => {
+                                let hex: String =
+                                    (0..4).map(|_| self.chars.next().unwrap()).collect();
+                                text.push(
+                                    char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap(),
+                                );
+                            }
+                            c => text.push(c),
+                        },
+                        c => text.push(c),
+                    }
+                }
+                Json::String(text)
+            }
+            Some('n') => {
+                for c in "null".chars() {
+                    assert_eq!(self.chars.next(), Some(c));
+                }
+                Json::Null
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || *c == '-' || *c == '.')
+                {
+                    number.push(c);
+                }
+                Json::Number(number.parse().expect("a JSON value"))
+            }
+        }
+    }
+}
+
+fn parse_json(text: &str) -> Json {
+    let mut reader = Reader {
+        chars: text.chars().peekable(),
+    };
+    let value = reader.value();
+    reader.skip_whitespace();
+    assert_eq!(reader.chars.next(), None, "text after the JSON value");
+    value
+}
+
+// Checks the program in a file with --check --diagnostics=json, returning the exit code and the diagnostics written
+fn diagnostics(program: &str) -> (i32, Vec<HashMap<String, Json>>, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("program.wr");
+    fs::write(&path, program).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .arg(&path)
+        .args(["--check", "--diagnostics=json"])
+        .output()
+        .unwrap();
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let Json::Array(entries) = parse_json(&String::from_utf8(output.stdout).unwrap()) else {
+        panic!("the diagnostics are an array");
+    };
+    let entries = entries
+        .into_iter()
+        .map(|entry| match entry {
+            Json::Object(fields) => fields,
+            other => panic!("each diagnostic is an object, found {:?}", other),
+        })
+        .collect();
+    (
+        output.status.code().unwrap(),
+        entries,
+        path.display().to_string(),
+    )
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_string())
+}
+
+#[test]
+fn test_parse_error_has_position() {
+    let (exit_code, entries, path) = diagnostics("var int x = 1;\nvar int y = );\n");
+    assert_eq!(exit_code, 1);
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(entry["severity"], string("error"));
+    assert_eq!(entry["stage"], string("parse"));
+    assert_eq!(entry["code"], Json::Null);
+    assert_eq!(entry["file"], string(&path));
+    assert_eq!(entry["line"], Json::Number(2.0));
+    assert_eq!(entry["col"], Json::Number(13.0));
+    assert_eq!(entry["end_line"], Json::Number(2.0));
+    assert_eq!(entry["end_col"], Json::Number(14.0));
+    let Json::String(message) = &entry["message"] else {
+        panic!("the message is a string");
+    };
+    assert!(
+        message.starts_with("Unrecognized token Closeparan at line 2, column 13."),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_type_errors_have_codes() {
+    let (exit_code, entries, path) = diagnostics("var int x = 1;\nvar string s = x;\nprint(y);\n");
+    assert_eq!(exit_code, 1);
+    let codes: Vec<&Json> = entries.iter().map(|entry| &entry["code"]).collect();
+    assert_eq!(codes, [&string("E002"), &string("E001")]);
+    for entry in &entries {
+        assert_eq!(entry["severity"], string("error"));
+        assert_eq!(entry["stage"], string("type"));
+        assert_eq!(entry["file"], string(&path));
+        assert_eq!(entry["col"], Json::Number(1.0));
+    }
+    // Type errors are placed at the statement they are found in
+    assert_eq!(entries[0]["line"], Json::Number(2.0));
+    assert_eq!(entries[0]["end_col"], Json::Number(18.0));
+    assert_eq!(entries[1]["line"], Json::Number(3.0));
+    assert_eq!(entries[1]["end_col"], Json::Number(10.0));
+    assert_eq!(
+        entries[0]["message"],
+        string("Type mismatch: expected String, found Int")
+    );
+}
+
+#[test]
+fn test_well_typed_program_has_no_diagnostics() {
+    let (exit_code, entries, _) = diagnostics("var int x = 1;\nprint(x);\n");
+    assert_eq!(exit_code, 0);
+    assert!(entries.is_empty());
+}
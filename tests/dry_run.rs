@@ -0,0 +1,67 @@
+// Runs the wrench binary with --dry-run on a job importing and exporting files in a temporary directory
+use std::{fs, path::Path, process::Command};
+
+// Writes the job into the directory and dry runs it there, returning whether it succeeded and what went to stderr
+fn dry_run(dir: &Path, job: &str) -> (bool, String) {
+    fs::write(dir.join("job.wr"), job).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .current_dir(dir)
+        .args(["job.wr", "--dry-run"])
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+const JOB: &str = "
+fn bool adult(row(int id, int age) r) { return r.age >= 18; };
+var table(int id, int age) adults = async_import(\"people.csv\", table(int id, int age)) pipe adult();
+export_csv(\"adults.csv\", adults);
+";
+
+#[test]
+fn valid_job_checks_files_without_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("people.csv"),
+        "id,age,name\n1,20,a\n2,12,b\n",
+    )
+    .unwrap();
+    let (success, stderr) = dry_run(dir.path(), JOB);
+    assert!(success, "{}", stderr);
+    assert_eq!(
+        stderr,
+        "dry run: 1 import and 1 export checked, no records were read or written
+  import 'people.csv': header has the columns age, id
+  export 'adults.csv': can be written
+"
+    );
+    assert!(!dir.path().join("adults.csv").exists());
+}
+
+#[test]
+fn missing_file_fails_the_dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let (success, stderr) = dry_run(dir.path(), JOB);
+    assert!(!success);
+    assert!(
+        stderr.contains("Failed to open CSV file 'people.csv'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn header_missing_a_column_fails_the_dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("people.csv"), "id,name\n1,a\n").unwrap();
+    let (success, stderr) = dry_run(dir.path(), JOB);
+    assert!(!success);
+    assert!(
+        stderr.contains("CSV file 'people.csv' is missing column 'age'"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,87 @@
+// Runs each program in examples/ and compares what it prints with the .expected file next to it
+// Set BLESS=1 to write the .expected files from what the programs print now
+use std::{
+    env, fs,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+};
+
+use wrench::{
+    backend::{evaluate::ExpressionValue, library::capture_output},
+    frontend::main::{RunOptions, run},
+};
+
+// Copies the CSV files of examples/data into the directory. Each is given to the programs as a variable named after the
+// file, e.g. people for people.csv, holding its path
+fn fixture_vars(dir: &Path) -> Vec<(String, ExpressionValue)> {
+    let mut vars = Vec::new();
+    for entry in sorted_entries(&Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/data")) {
+        let path = dir.join(entry.file_name().unwrap());
+        fs::copy(&entry, &path).unwrap();
+        let name = entry.file_stem().unwrap().to_str().unwrap().to_string();
+        vars.push((name, ExpressionValue::String(path.to_str().unwrap().into())));
+    }
+    vars
+}
+
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+}
+
+// What the program printed, followed by how it ended if it did not end well
+fn golden_output(program: &str, vars: &[(String, ExpressionValue)]) -> String {
+    let options = RunOptions {
+        vars: vars.to_vec(),
+        ..Default::default()
+    };
+    let (result, mut printed) = capture_output(AssertUnwindSafe(|| run(program, &options)));
+    match result {
+        Ok(0) => {}
+        Ok(code) => printed.push_str(&format!("exit code {}\n", code)),
+        Err(message) => printed.push_str(&format!("error: {}\n", message)),
+    }
+    printed
+}
+
+#[test]
+fn examples_print_their_expected_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let vars = fixture_vars(dir.path());
+    let bless = env::var_os("BLESS").is_some();
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut failures = Vec::new();
+    let mut count = 0;
+    for path in sorted_entries(&examples) {
+        if path.extension().is_none_or(|extension| extension != "wr") {
+            continue;
+        }
+        count += 1;
+        let printed = golden_output(&fs::read_to_string(&path).unwrap(), &vars);
+        let expected_path = path.with_extension("expected");
+        if bless {
+            fs::write(&expected_path, &printed).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if printed != expected {
+            failures.push(format!(
+                "{}:\n--- expected\n{}--- printed\n{}",
+                path.file_name().unwrap().to_str().unwrap(),
+                expected,
+                printed
+            ));
+        }
+    }
+    assert!(count > 0, "no examples found in {}", examples.display());
+    assert!(
+        failures.is_empty(),
+        "{} example(s) printed something else than expected, run with BLESS=1 to accept the new output\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,70 @@
+// Runs the wrench binary on programs that log, to check what goes to stderr and stdout and the exit code
+use std::process::{Command, Output};
+
+const PROGRAM: &str = "
+log_debug(\"starting\");
+log_info(\"read 2 rows\");
+print(42);
+log_warn(\"row 2 has no name\");
+";
+
+fn run(program: &str, flags: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .args(["-e", program])
+        .args(flags)
+        .output()
+        .unwrap()
+}
+
+// The levels and messages of the lines logged, after checking each starts with a timestamp like 2024-05-01T12:00:00.123Z
+fn logged(output: &Output) -> Vec<String> {
+    String::from_utf8(output.stderr.clone())
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let (timestamp, rest) = line.split_once(' ').unwrap();
+            assert_eq!(timestamp.len(), 24, "{}", line);
+            assert!(
+                timestamp.ends_with('Z') && timestamp.contains('T'),
+                "{}",
+                line
+            );
+            rest.to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn log_lines_at_the_level_and_above_go_to_stderr() {
+    let output = run(PROGRAM, &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout.clone()).unwrap(), "42\n");
+    assert_eq!(
+        logged(&output),
+        ["INFO read 2 rows", "WARN row 2 has no name"]
+    );
+
+    let output = run(PROGRAM, &["--log-level", "debug"]);
+    assert_eq!(
+        logged(&output),
+        [
+            "DEBUG starting",
+            "INFO read 2 rows",
+            "WARN row 2 has no name"
+        ]
+    );
+    assert_eq!(logged(&run(PROGRAM, &["--log-level", "error"])).len(), 0);
+}
+
+#[test]
+fn logged_error_makes_the_exit_code_non_zero() {
+    let program = "log_error(\"export failed\"); print(1);";
+    let output = run(program, &["--log-level", "error"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout.clone()).unwrap(), "1\n");
+    assert_eq!(logged(&output), ["ERROR export failed"]);
+
+    let output = run(program, &["--no-fail-on-error-log"]);
+    assert!(output.status.success());
+    assert_eq!(logged(&output), ["ERROR export failed"]);
+}
@@ -0,0 +1,84 @@
+// Writes programs back out with to_source and checks that parsing what is written gives the same syntax tree
+use std::{fs, path::Path};
+
+use wrench::frontend::{main::create_syntax_tree, printer::to_source};
+
+// Programs using every kind of statement and expression, next to the programs in examples/
+const PROGRAMS: [&str; 7] = [
+    "enum Status = [\"active\", \"inactive\"];
+    const int limit = 10;
+    var table(int id, Status status, string note ?) t = table(int id, Status status, string note ?, double score = 0.5);
+    table_add_row(t, row(int id = 1, Status status = \"active\", string note = \"first\", double score = 1.0));
+    for (int i, row(int id, Status status, string note ?, double score) r in t) {
+        r.score = r.score * 2;
+        table_update_row(t, i, r);
+    }",
+    "fn int fib(int n) {
+        if (n <= 1) { return n; }
+        return fib(n - 1) + fib(n - 2);
+    };
+    fn int main() {
+        var int[] xs = [];
+        for (int i in 0..10) {
+            if (i % 2 == 0 and i != 4) { continue; }
+            if (i >= 8 or i > 100) { break; }
+            xs = [fib(i), -i, 2 ** -1, -(i + 1)];
+        }
+        for (int i in 1..=3) { xs[i] = 0; }
+        defer { print(\"done\"); }
+        var int k = 3;
+        while (!(k < 0)) { k--; }
+        return 0;
+    };",
+    "var fn int(int) inc = fn (int x) int { return x + 1; };
+    var fn bool(row(int id)) small = fn (row(int id) r) bool { return r.id < 10; };
+    var table(int id) t = table(int id);
+    t pipe filter(small) pipe (fn (row(int id) r) row(int id) { r.id = inc(r.id); return r; }) pipe print();
+    print(t.id[0], t[0].id, 'a', '\\n', \"quoted \\\"text\\\"\", 1.5, t == null);",
+    "var double[][] m = [[1.0, 2.5], []];
+    var bool b = !true or (false and !false);
+    print(m[0][1] * (3 - 1) / 2 % 5, b, 10 - (2 - 3), (2 ** 3) ** 2);",
+    "fn null noop() { if (false) { return; } };
+    if (true) {} else { noop(); }
+    while (false) {}",
+    "var table(int id, string name) t = table(int id, string name = \"none\") [row(int id = 1), row(int id = 2, string name = \"b\")];
+    (table(int id) [row(int id = 1)])[0];
+    table(int id) [row(int id = 3)] pipe print();",
+    "var row(int age) r = row(int age = 5);
+    r.age = r.age + 1;
+    print(r);",
+];
+
+fn assert_round_trip(program: &str) {
+    let syntax_tree = create_syntax_tree(program).unwrap();
+    let source = to_source(&syntax_tree);
+    assert_eq!(
+        create_syntax_tree(&source).unwrap(),
+        syntax_tree,
+        "{}",
+        source
+    );
+    // Writing the source out again gives the same source
+    assert_eq!(to_source(&create_syntax_tree(&source).unwrap()), source);
+}
+
+#[test]
+fn test_programs_round_trip() {
+    for program in PROGRAMS {
+        assert_round_trip(program);
+    }
+}
+
+#[test]
+fn test_examples_round_trip() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut checked = 0;
+    for entry in fs::read_dir(examples).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "wr") {
+            assert_round_trip(&fs::read_to_string(&path).unwrap());
+            checked += 1;
+        }
+    }
+    assert!(checked > 0, "no programs in examples/");
+}
@@ -0,0 +1,28 @@
+// Runs the wrench binary on a program with several syntax errors, which are all written before it stops
+use std::process::Command;
+
+#[test]
+fn every_syntax_error_is_written_and_nothing_runs() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .args([
+            "-e",
+            "print(\"started\");\nvar int y = ;\nprint(1 +);\nvar int = 2;\n",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("Unrecognized token"))
+        .collect();
+    assert_eq!(errors.len(), 3, "{}", stderr);
+    for (error, position) in
+        errors
+            .iter()
+            .zip(["line 2, column 13", "line 3, column 10", "line 4, column 9"])
+    {
+        assert!(error.contains(position), "{}", error);
+    }
+}
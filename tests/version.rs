@@ -0,0 +1,32 @@
+// Runs the wrench binary to check the version it reports, on its own and below runtime errors
+use std::process::Command;
+
+use wrench::frontend::version::version_info;
+
+#[test]
+fn test_version_flag_prints_the_crate_version() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .arg("--version")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(&format!("wrench {} ", env!("CARGO_PKG_VERSION"))),
+        "{}",
+        stdout
+    );
+    assert_eq!(stdout.trim_end(), version_info());
+}
+
+#[test]
+fn test_runtime_error_carries_the_version_footer() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wrench"))
+        .args(["-e", "var int[] xs = [1]; xs[3];"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("out of bounds"), "{}", stderr);
+    assert!(stderr.contains(&version_info()), "{}", stderr);
+}